@@ -0,0 +1,181 @@
+// Checking ahead of a long restore whether the birth time of a path could be
+// set, without setting it. Each check looks at one thing that makes the
+// write fail; a path that passes all of them can still fail for reasons only
+// the write itself finds, such as a server that rejects it
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Something in the way of a birth time write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Obstacle {
+    // The backend cannot set birth times, as on Linux
+    Unsupported,
+    // The volume is mounted read-only
+    ReadOnlyVolume,
+    // The immutable or append-only flag is set
+    #[cfg(unix)]
+    Immutable,
+    // Setting explicit times takes the file's owner or root
+    #[cfg(unix)]
+    NotOwner,
+    // The file cannot be opened with write attributes access
+    #[cfg(target_os = "windows")]
+    AccessDenied,
+}
+
+impl Obstacle {
+    pub fn name(self) -> &'static str {
+        match self {
+            Obstacle::Unsupported => "unsupported",
+            Obstacle::ReadOnlyVolume => "read_only_volume",
+            #[cfg(unix)]
+            Obstacle::Immutable => "immutable",
+            #[cfg(unix)]
+            Obstacle::NotOwner => "not_owner",
+            #[cfg(target_os = "windows")]
+            Obstacle::AccessDenied => "access_denied",
+        }
+    }
+}
+
+// Everything that would make setting the birth time of path fail, empty when
+// nothing was found. Fails when path cannot be looked up
+pub fn check(path: &Path, follow: bool) -> io::Result<Vec<Obstacle>> {
+    let metadata = if follow { fs::metadata(path)? } else { fs::symlink_metadata(path)? };
+    let mut obstacles = Vec::new();
+    if !crate::backend::current().capabilities().set_btime {
+        obstacles.push(Obstacle::Unsupported);
+    }
+    imp::check(path, follow, &metadata, &mut obstacles)?;
+    Ok(obstacles)
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::ffi::CString;
+    use std::fs::Metadata;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+
+    use super::Obstacle;
+
+    pub fn check(path: &Path, follow: bool, metadata: &Metadata, obstacles: &mut Vec<Obstacle>) -> io::Result<()> {
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if stat.f_flag & libc::ST_RDONLY != 0 {
+            obstacles.push(Obstacle::ReadOnlyVolume);
+        }
+        // A link itself has no flags of its own on Linux
+        if (follow || !metadata.file_type().is_symlink()) && immutable(&c_path, metadata) {
+            obstacles.push(Obstacle::Immutable);
+        }
+        // CAP_FOWNER lets other users through as well, which is not checked
+        let euid = unsafe { libc::geteuid() };
+        if euid != 0 && euid != metadata.uid() {
+            obstacles.push(Obstacle::NotOwner);
+        }
+        Ok(())
+    }
+
+    // _IOR('f', 1, long) from linux/fs.h, FS_IMMUTABLE_FL and FS_APPEND_FL.
+    // A file that cannot be opened or has no flags is taken as not immutable
+    #[cfg(target_os = "linux")]
+    fn immutable(c_path: &CString, _metadata: &Metadata) -> bool {
+        const FS_IOC_GETFLAGS: u64 = 0x8008_6601;
+        const FS_IMMUTABLE_FL: i32 = 0x10;
+        const FS_APPEND_FL: i32 = 0x20;
+
+        // O_NONBLOCK keeps FIFOs from blocking the open
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDONLY | libc::O_NONBLOCK | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return false;
+        }
+        let mut flags: i32 = 0;
+        let found = unsafe { libc::ioctl(fd, FS_IOC_GETFLAGS as _, &mut flags) } == 0;
+        unsafe { libc::close(fd) };
+        found && flags & (FS_IMMUTABLE_FL | FS_APPEND_FL) != 0
+    }
+
+    // The user and system immutable and append-only flags of chflags(2)
+    #[cfg(target_os = "macos")]
+    fn immutable(_c_path: &CString, metadata: &Metadata) -> bool {
+        use std::os::macos::fs::MetadataExt;
+
+        let flags = metadata.st_flags();
+        flags & (libc::UF_IMMUTABLE | libc::UF_APPEND | libc::SF_IMMUTABLE | libc::SF_APPEND) != 0
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    fn immutable(_c_path: &CString, _metadata: &Metadata) -> bool {
+        false
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use std::fs::{Metadata, OpenOptions};
+    use std::io;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use std::path::Path;
+
+    use windows::Win32::Foundation::{ERROR_ACCESS_DENIED, ERROR_WRITE_PROTECT, HANDLE};
+    use windows::Win32::Storage::FileSystem::{
+        GetVolumeInformationByHandleW, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_NO_RECALL,
+        FILE_FLAG_OPEN_REPARSE_POINT, FILE_WRITE_ATTRIBUTES,
+    };
+
+    use super::Obstacle;
+    use crate::win;
+
+    // From WinNT.h, the constant lives in a windows crate feature not otherwise needed
+    const FILE_READ_ONLY_VOLUME: u32 = 0x0008_0000;
+
+    // The file is opened the way a write opens it, which changes nothing
+    pub fn check(path: &Path, follow: bool, _metadata: &Metadata, obstacles: &mut Vec<Obstacle>) -> io::Result<()> {
+        let mut flags = FILE_FLAG_BACKUP_SEMANTICS.0 | FILE_FLAG_OPEN_NO_RECALL.0;
+        if !follow {
+            flags |= FILE_FLAG_OPEN_REPARSE_POINT.0;
+        }
+        let query = OpenOptions::new().access_mode(0).custom_flags(flags).open(path)?;
+        let mut volume_flags = 0u32;
+        unsafe {
+            GetVolumeInformationByHandleW(HANDLE(query.as_raw_handle() as isize), None, None, None, Some(&mut volume_flags), None)
+        }
+        .map_err(win::io_error)?;
+        if volume_flags & FILE_READ_ONLY_VOLUME != 0 {
+            obstacles.push(Obstacle::ReadOnlyVolume);
+        }
+
+        match OpenOptions::new().access_mode(FILE_WRITE_ATTRIBUTES.0).custom_flags(flags).open(path) {
+            Ok(_) => {}
+            Err(err) if err.raw_os_error() == Some(ERROR_ACCESS_DENIED.0 as i32) => obstacles.push(Obstacle::AccessDenied),
+            Err(err) if err.raw_os_error() == Some(ERROR_WRITE_PROTECT.0 as i32) => {
+                if !obstacles.contains(&Obstacle::ReadOnlyVolume) {
+                    obstacles.push(Obstacle::ReadOnlyVolume);
+                }
+            }
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(unix, target_os = "windows")))]
+mod imp {
+    use std::fs::Metadata;
+    use std::io;
+    use std::path::Path;
+
+    use super::Obstacle;
+
+    pub fn check(_path: &Path, _follow: bool, _metadata: &Metadata, _obstacles: &mut Vec<Obstacle>) -> io::Result<()> {
+        Ok(())
+    }
+}
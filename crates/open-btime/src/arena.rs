@@ -0,0 +1,115 @@
+// The paths of a batch call stored back to back in one buffer, each followed
+// by a NUL so it can go to the kernel as it is. Decoding a million paths
+// then costs a few growths of one buffer rather than a String, a PathBuf and
+// a CString apiece, and the buffers go back to a per-thread pool afterwards
+// so the next batch starts with them already grown
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+// Arenas that grew past this are freed rather than pooled, so one huge batch
+// does not pin its memory for the life of the process
+const POOL_LIMIT: usize = 16 << 20;
+
+thread_local! {
+    static POOL: RefCell<Option<Buffers>> = const { RefCell::new(None) };
+}
+
+// What the pool keeps of an arena
+struct Buffers {
+    bytes: Vec<u8>,
+    ends: Vec<usize>,
+    scratch: Vec<u8>,
+    joined: PathBuf,
+}
+
+#[derive(Default)]
+pub struct PathArena {
+    bytes: Vec<u8>,
+    // Where each path ends, before its NUL
+    ends: Vec<usize>,
+    // A path being decoded, and its join with the base directory
+    scratch: Vec<u8>,
+    joined: PathBuf,
+}
+
+impl PathArena {
+    // An empty arena, with the buffers of an earlier batch on this thread
+    pub fn take() -> PathArena {
+        match POOL.with(|pool| pool.borrow_mut().take()) {
+            Some(Buffers { bytes, ends, scratch, joined }) => PathArena { bytes, ends, scratch, joined },
+            None => PathArena::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn get(&self, i: usize) -> &Path {
+        let start = if i == 0 { 0 } else { self.ends[i - 1] + 1 };
+        // The bytes came from as_encoded_bytes, or are UTF-8, which is valid
+        // in every platform's encoding
+        Path::new(unsafe { OsStr::from_encoded_bytes_unchecked(&self.bytes[start..self.ends[i]]) })
+    }
+
+    // The path with its NUL, None when it has a NUL of its own
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    pub fn c_str(&self, i: usize) -> Option<&std::ffi::CStr> {
+        let start = if i == 0 { 0 } else { self.ends[i - 1] + 1 };
+        std::ffi::CStr::from_bytes_with_nul(&self.bytes[start..=self.ends[i]]).ok()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Path> {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    // Add a path, resolved against cwd when it is relative
+    pub fn push(&mut self, path: &Path, cwd: Option<&Path>) {
+        match cwd {
+            Some(cwd) if path.is_relative() => {
+                // PathBuf::push joins the way Path::join does, into a buffer kept for the next one
+                self.joined.clear();
+                self.joined.push(cwd);
+                self.joined.push(path);
+                self.bytes.extend_from_slice(self.joined.as_os_str().as_encoded_bytes());
+            }
+            _ => self.bytes.extend_from_slice(path.as_os_str().as_encoded_bytes()),
+        }
+        self.ends.push(self.bytes.len());
+        self.bytes.push(0);
+    }
+
+    // Decode a path into the scratch buffer with write, which gets room for
+    // capacity bytes and returns how many it wrote, then add it like push.
+    // The bytes must be UTF-8, or any bytes on Unix
+    pub fn push_decoded(&mut self, capacity: usize, cwd: Option<&Path>, write: impl FnOnce(&mut [u8]) -> usize) {
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.clear();
+        scratch.resize(capacity, 0);
+        let written = write(&mut scratch);
+        scratch.truncate(written);
+        self.push(Path::new(unsafe { OsStr::from_encoded_bytes_unchecked(&scratch) }), cwd);
+        self.scratch = scratch;
+    }
+}
+
+impl Drop for PathArena {
+    fn drop(&mut self) {
+        if self.bytes.capacity() > POOL_LIMIT {
+            return;
+        }
+        self.bytes.clear();
+        self.ends.clear();
+        let buffers = Buffers {
+            bytes: std::mem::take(&mut self.bytes),
+            ends: std::mem::take(&mut self.ends),
+            scratch: std::mem::take(&mut self.scratch),
+            joined: std::mem::take(&mut self.joined),
+        };
+        // Nothing is pooled once the thread is shutting down
+        let _ = POOL.try_with(|pool| {
+            pool.borrow_mut().get_or_insert(buffers);
+        });
+    }
+}
@@ -0,0 +1,95 @@
+// Replacing a file's contents through a temporary file and a rename, so that
+// readers see either the old or the new contents, while keeping the birth
+// time that a plain rename gives up
+use std::ffi::OsString;
+use std::fs::{self, Metadata, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::backend;
+use crate::copy::{fail, CopyError};
+use crate::times::{self, FileTimes, Timestamp};
+
+static TEMPORARIES: AtomicU64 = AtomicU64::new(0);
+
+// .NAME.PID.N.tmp next to path
+pub fn temporary_path(path: &Path) -> io::Result<PathBuf> {
+    let Some(name) = path.file_name() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"));
+    };
+    let mut temporary = OsString::from(".");
+    temporary.push(name);
+    temporary.push(format!(".{}.{}.tmp", process::id(), TEMPORARIES.fetch_add(1, Ordering::Relaxed)));
+    Ok(path.with_file_name(temporary))
+}
+
+// Write data to path through a temporary file in the same directory. The
+// temporary file gets the permissions of the file it replaces and `times`,
+// whose birth time defaults to that of the replaced file, is flushed to disk
+// and renamed over path. Times left unset are those of the write
+pub fn write_file(path: &Path, data: &[u8], times: FileTimes) -> Result<(), CopyError> {
+    let original = fs::metadata(path).ok();
+    let original_btime = match original {
+        Some(_) => times::get_times(path, true).map_err(fail("stat"))?.btime,
+        None => None,
+    };
+    let times = FileTimes { btime: times.btime.or(original_btime), ctime: None, ..times };
+
+    let temporary = temporary_path(path).map_err(fail("open"))?;
+    if let Err(err) = write_temporary(&temporary, data, original.as_ref(), &times) {
+        let _ = fs::remove_file(&temporary);
+        return Err(err);
+    }
+    rename_into_place(&temporary, path, times.btime)
+}
+
+// Rename a finished temporary file over path and make sure path ends up
+// with btime, removing the temporary file if the rename fails
+pub fn rename_into_place(temporary: &Path, path: &Path, btime: Option<Timestamp>) -> Result<(), CopyError> {
+    if let Err(err) = fs::rename(temporary, path) {
+        let _ = fs::remove_file(temporary);
+        return Err(CopyError { syscall: "rename", error: err });
+    }
+
+    // Tunneling can give a file renamed over a name that was in use moments
+    // ago that name's old creation time, so the birth time is checked again
+    if let Some(btime) = btime.filter(|_| backend::current().capabilities().set_btime) {
+        if times::get_times(path, true).map_err(fail("stat"))?.btime != Some(btime) {
+            crate::set_times(path, &FileTimes { btime: Some(btime), ..FileTimes::default() }).map_err(fail("utimes"))?;
+        }
+    }
+    sync_parent(path)
+}
+
+fn write_temporary(temporary: &Path, data: &[u8], original: Option<&Metadata>, times: &FileTimes) -> Result<(), CopyError> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(temporary).map_err(fail("open"))?;
+    file.write_all(data).map_err(fail("write"))?;
+    if let Some(original) = original {
+        fs::set_permissions(temporary, original.permissions()).map_err(fail("chmod"))?;
+    }
+    // Set before the flush, so the times reach the disk along with the data
+    // and a crash after the rename cannot leave the file with the times of
+    // the write
+    if *times != FileTimes::default() {
+        crate::set_times(temporary, times).map_err(fail("utimes"))?;
+    }
+    file.sync_all().map_err(fail("fsync"))
+}
+
+// Flush the directory entry the rename changed
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> Result<(), CopyError> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::File::open(parent).and_then(|dir| dir.sync_all()).map_err(fail("fsync"))
+}
+
+// NTFS commits the rename through its journal, directories cannot be flushed
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> Result<(), CopyError> {
+    Ok(())
+}
@@ -0,0 +1,438 @@
+// The layer timestamp writes and single-file reads go through; walks take
+// the times that come with directory listings. The platform backend calls
+// the operating system, other backends such as emulations or test doubles
+// can be installed at runtime in its place
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use crate::path;
+use crate::stats;
+use crate::times::FileTimes;
+
+// What a backend can do with birth times
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub set_btime: bool,
+    pub get_btime: bool,
+    // Birth times are kept by the backend rather than by the filesystem
+    pub emulated: bool,
+}
+
+pub trait BtimeBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Set the timestamps of a file, of a symlink or junction itself with
+    // follow unset. Fields that are None are left unchanged, ctime cannot be
+    // set and is ignored
+    fn set_times(&self, path: &Path, times: &FileTimes, follow: bool) -> io::Result<()>;
+
+    // Read all timestamps of a file, of a symlink itself with follow unset
+    fn get_times(&self, path: &Path, follow: bool) -> io::Result<FileTimes>;
+
+    fn capabilities(&self) -> Capabilities;
+
+    // Whether the birth time get_times returns for a path is one the
+    // backend keeps itself rather than the filesystem's
+    fn keeps_btime(&self, _path: &Path, _follow: bool) -> bool {
+        false
+    }
+
+    // set_times, returning the name of the backend that did the write:
+    // itself unless this is a chain
+    fn set_times_by(&self, path: &Path, times: &FileTimes, follow: bool) -> io::Result<&'static str> {
+        self.set_times(path, times, follow).map(|_| self.name())
+    }
+
+    // get_times, returning the name of the backend that did the read
+    fn get_times_by(&self, path: &Path, follow: bool) -> io::Result<(FileTimes, &'static str)> {
+        self.get_times(path, follow).map(|times| (times, self.name()))
+    }
+
+    // The configured names of the backends of a chain, None for others
+    fn chain(&self) -> Option<Vec<&'static str>> {
+        None
+    }
+}
+
+// The operating system's own timestamp calls
+pub struct Platform;
+
+impl BtimeBackend for Platform {
+    fn name(&self) -> &'static str {
+        "platform"
+    }
+
+    fn set_times(&self, path: &Path, times: &FileTimes, follow: bool) -> io::Result<()> {
+        write_times(&path::literal(path), times, follow)
+    }
+
+    fn get_times(&self, path: &Path, follow: bool) -> io::Result<FileTimes> {
+        let path = path::literal(path);
+        let metadata = if follow { std::fs::metadata(&path) } else { std::fs::symlink_metadata(&path) };
+        Ok(FileTimes::from_metadata(&metadata?))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // Linux reports birth times through statx but has no call to set them
+        Capabilities { set_btime: cfg!(any(target_os = "windows", target_os = "macos")), get_btime: true, emulated: false }
+    }
+}
+
+// None until a backend is installed, meaning the platform one
+static BACKEND: RwLock<Option<Arc<dyn BtimeBackend>>> = RwLock::new(None);
+
+pub fn current() -> Arc<dyn BtimeBackend> {
+    match &*BACKEND.read().unwrap() {
+        Some(backend) => Arc::clone(backend),
+        None => Arc::new(Platform),
+    }
+}
+
+// Whether the platform backend is in use, so calls may take
+// platform-specific shortcuts such as batched stats
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub fn is_platform() -> bool {
+    BACKEND.read().unwrap().as_ref().is_none_or(|backend| backend.name() == "platform")
+}
+
+// Install a backend for all following operations
+pub fn install(backend: Arc<dyn BtimeBackend>) {
+    *BACKEND.write().unwrap() = Some(backend);
+}
+
+// The backends configure() can select by name
+pub fn by_name(name: &str) -> Option<Arc<dyn BtimeBackend>> {
+    match name {
+        "platform" | "native" => Some(Arc::new(Platform)),
+        "mock" => Some(Arc::new(crate::mock::Mock)),
+        #[cfg(target_os = "linux")]
+        "xattr" => Some(Arc::new(crate::emulate::Xattr)),
+        #[cfg(feature = "sqlite")]
+        "sidecar" => Some(Arc::new(crate::sidecar::Sidecar)),
+        _ => None,
+    }
+}
+
+// Backends tried in turn: a birth time is written by the first one that can
+// set birth times and does not fail with ENOTSUP for the path, e.g. the
+// platform on Windows and macOS, then extended attributes, then a sidecar
+// database for filesystems without them. Reads go to the first emulating
+// backend that keeps a birth time for the path, otherwise to the first one
+// that does not emulate. A chain ending in "error" fails writes no backend
+// could take, otherwise their birth time is dropped like the platform
+// backend does on Linux
+pub struct Chain {
+    backends: Vec<Arc<dyn BtimeBackend>>,
+    strict: bool,
+}
+
+impl Chain {
+    // Build a chain from backend names, of which only the last may be
+    // "error". Err says what is wrong with them
+    pub fn new(names: &[String]) -> Result<Chain, String> {
+        let (names, strict) = match names.split_last() {
+            Some((last, rest)) if last == "error" => (rest, true),
+            _ => (names, false),
+        };
+        if names.iter().any(|name| name == "error") {
+            return Err("\"error\" can only end the backends".to_string());
+        }
+        let backends = names.iter().map(|name| by_name(name).ok_or_else(|| format!("unknown backend {:?}", name)));
+        let backends = backends.collect::<Result<Vec<_>, _>>()?;
+        if backends.is_empty() {
+            return Err("backends must name at least one backend".to_string());
+        }
+        Ok(Chain { backends, strict })
+    }
+
+    fn reading(&self, path: &Path, follow: bool) -> &Arc<dyn BtimeBackend> {
+        let emulated = |backend: &&Arc<dyn BtimeBackend>| backend.capabilities().emulated;
+        self.backends
+            .iter()
+            .find(|backend| emulated(backend) && backend.keeps_btime(path, follow))
+            .or_else(|| self.backends.iter().find(|backend| !emulated(backend)))
+            .unwrap_or(&self.backends[0])
+    }
+}
+
+// Errors meaning the backend cannot keep birth times for the path, such as a
+// filesystem without user attributes
+fn unsupported(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    // The same code on Linux, not on macOS
+    if err.raw_os_error().is_some_and(|code| code == libc::ENOTSUP || code == libc::EOPNOTSUPP) {
+        return true;
+    }
+    err.kind() == io::ErrorKind::Unsupported
+}
+
+impl BtimeBackend for Chain {
+    fn name(&self) -> &'static str {
+        "chain"
+    }
+
+    fn set_times(&self, path: &Path, times: &FileTimes, follow: bool) -> io::Result<()> {
+        self.set_times_by(path, times, follow).map(|_| ())
+    }
+
+    fn get_times(&self, path: &Path, follow: bool) -> io::Result<FileTimes> {
+        self.reading(path, follow).get_times(path, follow)
+    }
+
+    fn set_times_by(&self, path: &Path, times: &FileTimes, follow: bool) -> io::Result<&'static str> {
+        let mut last_error = None;
+        if times.btime.is_some() {
+            for backend in self.backends.iter().filter(|backend| backend.capabilities().set_btime) {
+                match backend.set_times_by(path, times, follow) {
+                    Ok(name) => {
+                        stats::handled(name);
+                        return Ok(name);
+                    }
+                    Err(err) if unsupported(&err) => last_error = Some(err),
+                    Err(err) => return Err(err),
+                }
+            }
+            if self.strict {
+                return Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "no backend of the chain can set birth times")));
+            }
+        }
+        let name = self.backends[0].set_times_by(path, &FileTimes { btime: None, ..*times }, follow)?;
+        stats::handled(name);
+        Ok(name)
+    }
+
+    fn get_times_by(&self, path: &Path, follow: bool) -> io::Result<(FileTimes, &'static str)> {
+        self.reading(path, follow).get_times_by(path, follow)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let any = |capability: fn(Capabilities) -> bool| self.backends.iter().any(|backend| capability(backend.capabilities()));
+        Capabilities {
+            set_btime: any(|capabilities| capabilities.set_btime),
+            get_btime: any(|capabilities| capabilities.get_btime),
+            emulated: any(|capabilities| capabilities.emulated),
+        }
+    }
+
+    fn keeps_btime(&self, path: &Path, follow: bool) -> bool {
+        self.reading(path, follow).keeps_btime(path, follow)
+    }
+
+    fn chain(&self) -> Option<Vec<&'static str>> {
+        let mut names: Vec<&'static str> = self.backends.iter().map(|backend| backend.name()).collect();
+        if self.strict {
+            names.push("error");
+        }
+        Some(names)
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn write_times(path: &Path, times: &FileTimes, follow: bool) -> io::Result<()> {
+    use std::fs::OpenOptions;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::{
+        GetFileAttributesW, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_NO_RECALL, FILE_FLAG_OPEN_REPARSE_POINT,
+        FILE_WRITE_ATTRIBUTES, INVALID_FILE_ATTRIBUTES,
+    };
+
+    use crate::config::{self, Placeholders};
+    use crate::win;
+    
+    if times.btime.is_none() && times.atime.is_none() && times.mtime.is_none() {
+        return Ok(());
+    }
+    
+    // Open with only write attributes access, backup semantics is needed to
+    // open directories and opening the reparse point stamps a link itself
+    let mut flags = FILE_FLAG_BACKUP_SEMANTICS.0;
+    if !follow {
+        flags |= FILE_FLAG_OPEN_REPARSE_POINT.0;
+    }
+
+    // GetFileAttributesW reads the attributes without opening the file, which
+    // could already fetch a placeholder's data
+    let placeholders = config::get().placeholders;
+    if placeholders != Placeholders::Stamp {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let attributes = unsafe { GetFileAttributesW(PCWSTR(wide.as_ptr())) };
+        if attributes == INVALID_FILE_ATTRIBUTES {
+            return Err(io::Error::last_os_error());
+        }
+        if win::is_placeholder(attributes) {
+            match placeholders {
+                Placeholders::Skip => return Ok(()),
+                // Reading the file through makes the provider download it
+                _ => {
+                    io::copy(&mut std::fs::File::open(path)?, &mut io::sink())?;
+                }
+            }
+        }
+    }
+    if placeholders != Placeholders::Hydrate {
+        flags |= FILE_FLAG_OPEN_NO_RECALL.0;
+    }
+    let file = OpenOptions::new()
+        .access_mode(FILE_WRITE_ATTRIBUTES.0)
+        .custom_flags(flags)
+        .open(path)?;
+    
+    // The file is closed automatically when it goes out of scope
+    win::set_file_times(&file, times)
+}
+
+// The attribute list and packed values setattrlist and fsetattrlist take
+// to write times
+#[cfg(target_os = "macos")]
+pub mod attrlist {
+    use std::os::raw::{c_char, c_int};
+
+    use crate::times::FileTimes;
+
+    #[repr(C)]
+    pub struct Timespec {
+        tv_sec: i64,
+        tv_nsec: i64,
+    }
+
+    #[repr(C)]
+    pub struct AttrList {
+        bitmapcount: u16,
+        reserved: u16,
+        commonattr: u32,
+        volattr: u32,
+        dirattr: u32,
+        fileattr: u32,
+        forkattr: u32,
+    }
+
+    const ATTR_BIT_MAP_COUNT: u16 = 5;
+    const ATTR_CMN_CRTIME: u32 = 0x00000200;
+    const ATTR_CMN_MODTIME: u32 = 0x00000400;
+    const ATTR_CMN_ACCTIME: u32 = 0x00001000;
+    pub const FSOPT_NOFOLLOW: c_int = 0x00000001;
+
+    extern "C" {
+        pub fn setattrlist(
+            path: *const c_char,
+            attrList: *const AttrList,
+            attrBuf: *const libc::c_void,
+            attrBufSize: libc::size_t,
+            options: c_int,
+        ) -> c_int;
+
+        pub fn fsetattrlist(
+            fd: c_int,
+            attrList: *const AttrList,
+            attrBuf: *const libc::c_void,
+            attrBufSize: libc::size_t,
+            options: c_int,
+        ) -> c_int;
+    }
+
+    // The list selecting the times that are set and their values, which go
+    // without a leading length in the order of their attribute bits. None
+    // when no time is set
+    pub fn pack(times: &FileTimes) -> Option<(AttrList, Vec<Timespec>)> {
+        let mut commonattr = 0;
+        let mut attr_buf = Vec::with_capacity(3);
+        for (attr, timestamp) in [
+            (ATTR_CMN_CRTIME, times.btime),
+            (ATTR_CMN_MODTIME, times.mtime),
+            (ATTR_CMN_ACCTIME, times.atime),
+        ] {
+            if let Some(timestamp) = timestamp {
+                commonattr |= attr;
+                attr_buf.push(Timespec {
+                    tv_sec: timestamp.sec,
+                    tv_nsec: timestamp.nsec as i64,
+                });
+            }
+        }
+        if commonattr == 0 {
+            return None;
+        }
+        let attr_list = AttrList {
+            bitmapcount: ATTR_BIT_MAP_COUNT,
+            reserved: 0,
+            commonattr,
+            volattr: 0,
+            dirattr: 0,
+            fileattr: 0,
+            forkattr: 0,
+        };
+        Some((attr_list, attr_buf))
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn write_times(path: &Path, times: &FileTimes, follow: bool) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    
+    use crate::icloud;
+
+    if icloud::is_placeholder(path) {
+        return icloud::evicted();
+    }
+
+    // Create C-compatible path string
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains null bytes"))?;
+    
+    let Some((attr_list, attr_buf)) = attrlist::pack(times) else {
+        return Ok(());
+    };
+    
+    // Call setattrlist
+    let result = unsafe {
+        attrlist::setattrlist(
+            c_path.as_ptr(),
+            &attr_list,
+            attr_buf.as_ptr() as *const libc::c_void,
+            std::mem::size_of::<attrlist::Timespec>() * attr_buf.len(),
+            if follow { 0 } else { attrlist::FSOPT_NOFOLLOW },
+        )
+    };
+    
+    if result != 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ENOENT) && icloud::is_evicted(path) {
+            return icloud::evicted();
+        }
+        return Err(err);
+    }
+    
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn write_times(path: &Path, times: &FileTimes, follow: bool) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    use crate::times::Timestamp;
+    
+    // Linux does not support changing birth time, only mtime and atime are applied
+    if times.mtime.is_none() && times.atime.is_none() {
+        return Ok(());
+    }
+    
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains null bytes"))?;
+    let timespec = |t: Option<Timestamp>| match t {
+        Some(t) => libc::timespec { tv_sec: t.sec as libc::time_t, tv_nsec: t.nsec as _ },
+        None => libc::timespec { tv_sec: 0, tv_nsec: libc::UTIME_OMIT },
+    };
+    let timespecs = [timespec(times.atime), timespec(times.mtime)];
+    
+    let flags = if follow { 0 } else { libc::AT_SYMLINK_NOFOLLOW };
+    if unsafe { libc::utimensat(libc::AT_FDCWD, c_path.as_ptr(), timespecs.as_ptr(), flags) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    
+    Ok(())
+}
@@ -0,0 +1,187 @@
+// Opt-in micro-batching of btime calls, set with configure({ batching }).
+// Each call queues its write and returns, and a background thread applies
+// the queue on `concurrency` workers once it holds `size` writes or the
+// oldest has waited `interval`. Errors are kept until flush(), which also
+// applies whatever is still queued, so callers trade a little latency and
+// per-call errors for far fewer round trips through the filesystem layer
+use std::collections::BTreeMap;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::pool;
+use crate::times::FileTimes;
+use crate::tree::TreeResult;
+use crate::walk::WalkError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Batching {
+    pub interval: Duration,
+    pub size: usize,
+}
+
+pub const DEFAULT: Batching = Batching { interval: Duration::from_millis(10), size: 1024 };
+
+pub struct Write {
+    pub path: PathBuf,
+    pub times: FileTimes,
+    pub follow: bool,
+    pub backup: bool,
+}
+
+struct State {
+    batching: Option<Batching>,
+    queued: Vec<Write>,
+    // When the oldest queued write was queued
+    since: Option<Instant>,
+    // A batch is being applied, by the flusher thread or by flush()
+    applying: bool,
+    result: TreeResult,
+    flusher: Option<thread::JoinHandle<()>>,
+    // Set while stop() waits for the flusher thread to exit
+    stopping: bool,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    batching: None,
+    queued: Vec::new(),
+    since: None,
+    applying: false,
+    result: TreeResult { processed: 0, errors: Vec::new(), truncated: Vec::new(), resumed: 0, backends: BTreeMap::new() },
+    flusher: None,
+    stopping: false,
+});
+// Signalled when writes are queued or the settings change
+static QUEUED: Condvar = Condvar::new();
+// Signalled when a batch has been applied
+static APPLIED: Condvar = Condvar::new();
+
+pub fn get() -> Option<Batching> {
+    STATE.lock().unwrap().batching
+}
+
+// Turning batching off applies the writes still queued
+pub fn set(batching: Option<Batching>) {
+    STATE.lock().unwrap().batching = batching;
+    QUEUED.notify_all();
+    if batching.is_none() {
+        apply_queued();
+    }
+}
+
+// Queue a write, or hand it back when batching is off
+pub fn push(write: Write) -> Option<Write> {
+    let mut state = STATE.lock().unwrap();
+    if state.batching.is_none() {
+        return Some(write);
+    }
+    if state.queued.is_empty() {
+        state.since = Some(Instant::now());
+    }
+    state.queued.push(write);
+    if state.flusher.is_none() {
+        match thread::Builder::new().name("open-btime-batch".to_string()).spawn(flusher) {
+            Ok(thread) => state.flusher = Some(thread),
+            // Without the thread the writes wait for flush()
+            Err(_) => return None,
+        }
+    }
+    QUEUED.notify_all();
+    None
+}
+
+// Apply the queued writes and wait for any batch being applied, then return
+// what all batches since the last flush did
+pub fn flush() -> TreeResult {
+    apply_queued();
+    mem::take(&mut STATE.lock().unwrap().result)
+}
+
+fn apply_queued() {
+    let mut state = STATE.lock().unwrap();
+    while state.applying {
+        state = APPLIED.wait(state).unwrap();
+    }
+    apply(state);
+}
+
+// Stop the thread that applies due batches and wait for it, then apply
+// what is still queued. Called when the last instance of the addon unloads;
+// queuing again starts a new thread
+pub fn stop() {
+    let flusher = {
+        let mut state = STATE.lock().unwrap();
+        state.stopping = true;
+        state.flusher.take()
+    };
+    QUEUED.notify_all();
+    if let Some(flusher) = flusher {
+        let _ = flusher.join();
+    }
+    flush();
+    STATE.lock().unwrap().stopping = false;
+}
+
+fn flusher() {
+    let mut state = STATE.lock().unwrap();
+    loop {
+        if state.stopping {
+            return;
+        }
+        let due = match (state.batching, state.since) {
+            (Some(batching), Some(since)) if !state.applying && !state.queued.is_empty() => {
+                if state.queued.len() >= batching.size {
+                    None
+                } else {
+                    Some((since + batching.interval).saturating_duration_since(Instant::now()))
+                }
+            }
+            _ => {
+                state = QUEUED.wait(state).unwrap();
+                continue;
+            }
+        };
+        match due {
+            Some(wait) if !wait.is_zero() => state = QUEUED.wait_timeout(state, wait).unwrap().0,
+            _ => {
+                apply(state);
+                state = STATE.lock().unwrap();
+            }
+        }
+    }
+}
+
+// Take the queue and apply it without holding the lock
+fn apply(mut state: std::sync::MutexGuard<'static, State>) {
+    let writes = mem::take(&mut state.queued);
+    state.since = None;
+    if writes.is_empty() {
+        return;
+    }
+    state.applying = true;
+    drop(state);
+
+    let applied = Mutex::new(TreeResult::default());
+    pool::for_each_bounded(writes.into_iter(), crate::config::get().concurrency, |write| {
+        let Write { path, times, follow, backup } = write;
+        let result = crate::with_backup_mode(backup, || {
+            if follow {
+                crate::set_times(&path, &times)
+            } else {
+                crate::set_link_times(&path, &times)
+            }
+        });
+        match result {
+            Ok(backend) => applied.lock().unwrap().written(backend),
+            Err(error) => applied.lock().unwrap().errors.push(WalkError { path, error }),
+        }
+    });
+
+    let mut state = STATE.lock().unwrap();
+    state.result.merge(applied.into_inner().unwrap());
+    state.applying = false;
+    APPLIED.notify_all();
+    QUEUED.notify_all();
+}
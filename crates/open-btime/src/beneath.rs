@@ -0,0 +1,250 @@
+// Resolving untrusted paths strictly inside a root directory. A path whose
+// resolution leaves the root, through `..`, an absolute path or a symlink,
+// is rejected instead of being followed. Linux lets the kernel do the
+// resolution with openat2(RESOLVE_BENEATH); elsewhere, and on kernels
+// before 5.6, the path is walked one component at a time
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config;
+use crate::path;
+
+// Symlinks followed before giving up, as MAXSYMLINKS on Linux, unless
+// configure({ symlinkDepth }) sets another limit
+const MAX_LINKS: usize = 40;
+
+#[cfg(unix)]
+fn escapes() -> io::Error {
+    io::Error::from_raw_os_error(libc::EXDEV)
+}
+
+#[cfg(not(unix))]
+fn escapes() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "path resolves outside of the root")
+}
+
+// The path below root that path names: relative paths are taken as they
+// are, absolute ones have to start with root
+fn relative_to(root: &Path, canonical_root: &Path, path: &Path) -> io::Result<PathBuf> {
+    if path.is_relative() {
+        return Ok(path.to_path_buf());
+    }
+    match path.strip_prefix(root).or_else(|_| path.strip_prefix(canonical_root)) {
+        Ok(relative) => Ok(relative.to_path_buf()),
+        Err(_) => Err(escapes()),
+    }
+}
+
+// A path resolved inside a root. Where openat2 did the resolution the file
+// is kept open, and path names that descriptor through /proc/self/fd, so a
+// symlink swapped in after the check cannot redirect the read or write;
+// follow is then the follow to use with path. shown is where the file was
+// found, for results and error messages
+pub struct Resolved {
+    pub path: PathBuf,
+    pub follow: bool,
+    pub shown: PathBuf,
+    #[cfg(target_os = "linux")]
+    _fd: Option<std::os::fd::OwnedFd>,
+}
+
+// Resolve path inside root, with every `..` and symlink resolved. Without
+// follow a symlink in the last component is not resolved, so the link
+// itself is addressed. A file that does not exist fails with ENOENT
+pub fn resolve(root: &Path, path: &Path, follow: bool) -> io::Result<Resolved> {
+    let canonical_root = path::canonicalize(root, true)?;
+    let relative = relative_to(root, &canonical_root, path)?;
+
+    #[cfg(target_os = "linux")]
+    if let Some(resolved) = openat2(&canonical_root, &relative, follow)? {
+        return Ok(resolved);
+    }
+    let found = walk(&canonical_root, &relative, follow)?;
+    Ok(Resolved {
+        path: found.clone(),
+        follow,
+        shown: found,
+        #[cfg(target_os = "linux")]
+        _fd: None,
+    })
+}
+
+// Let the kernel resolve the path, or None where openat2 is not available.
+// The kernel rejects absolute symlinks even when they point inside the root
+#[cfg(target_os = "linux")]
+fn openat2(root: &Path, relative: &Path, follow: bool) -> io::Result<Option<Resolved>> {
+    use std::ffi::CString;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_root = CString::new(root.as_os_str().as_bytes())?;
+    let root_fd = unsafe { libc::open(c_root.as_ptr(), libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC) };
+    if root_fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let root_fd = unsafe { OwnedFd::from_raw_fd(root_fd) };
+
+    let open_beneath = |relative: &Path, flags: i32| -> io::Result<Option<OwnedFd>> {
+        // An empty path would need AT_EMPTY_PATH, "." names the root just as well
+        let relative = if relative.as_os_str().is_empty() { Path::new(".") } else { relative };
+        let c_relative = CString::new(relative.as_os_str().as_bytes())?;
+        let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+        how.flags = (flags | libc::O_PATH | libc::O_CLOEXEC) as u64;
+        how.resolve = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_MAGICLINKS;
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_openat2,
+                root_fd.as_raw_fd(),
+                c_relative.as_ptr(),
+                &how as *const libc::open_how,
+                std::mem::size_of::<libc::open_how>(),
+            )
+        };
+        if fd < 0 {
+            let err = io::Error::last_os_error();
+            // Kernels before 5.6, and seccomp policies that do not know the call
+            return match err.raw_os_error() {
+                Some(libc::ENOSYS) | Some(libc::EPERM) => Ok(None),
+                _ => Err(err),
+            };
+        }
+        Ok(Some(unsafe { OwnedFd::from_raw_fd(fd as i32) }))
+    };
+
+    let Some(fd) = open_beneath(relative, if follow { 0 } else { libc::O_NOFOLLOW })? else {
+        return Ok(None);
+    };
+    let fd_path = PathBuf::from(format!("/proc/self/fd/{}", fd.as_raw_fd()));
+    let shown = fs::read_link(&fd_path).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => io::Error::new(err.kind(), "beneath needs /proc to reach the file it opened"),
+        _ => err,
+    })?;
+
+    // Timestamps cannot be set through an O_PATH descriptor of a symlink,
+    // so a link addressed itself is reached by its name in the directory
+    // that is kept open instead
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if stat.st_mode & libc::S_IFMT == libc::S_IFLNK {
+        let (Some(parent), Some(name)) = (relative.parent(), relative.file_name()) else {
+            return Err(escapes());
+        };
+        let Some(directory) = open_beneath(parent, libc::O_DIRECTORY)? else {
+            return Ok(None);
+        };
+        let path = PathBuf::from(format!("/proc/self/fd/{}", directory.as_raw_fd())).join(name);
+        return Ok(Some(Resolved { path, follow: false, shown, _fd: Some(directory) }));
+    }
+    Ok(Some(Resolved { path: fd_path, follow: true, shown, _fd: Some(fd) }))
+}
+
+// Where a path handed out by resolve was found, for the undo log; other
+// paths are returned as they are
+pub fn found_at(path: &Path) -> PathBuf {
+    let Ok(rest) = path.strip_prefix("/proc/self/fd") else {
+        return path.to_path_buf();
+    };
+    let mut components = rest.components();
+    let Some(fd) = components.next() else {
+        return path.to_path_buf();
+    };
+    match fs::read_link(Path::new("/proc/self/fd").join(fd)) {
+        Ok(found) if components.as_path().as_os_str().is_empty() => found,
+        Ok(found) => found.join(components.as_path()),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+// Walk the path one component at a time, keeping track of where it is below
+// root. Absolute symlink targets are allowed as long as they lie inside root,
+// as Windows junctions are always absolute
+fn walk(root: &Path, relative: &Path, follow: bool) -> io::Result<PathBuf> {
+    let mut pending: Vec<OsString> = Vec::new();
+    push_components(&mut pending, relative)?;
+
+    let mut current = root.to_path_buf();
+    let mut links = 0;
+    while let Some(name) = pending.pop() {
+        if name == ".." {
+            if current == root {
+                return Err(escapes());
+            }
+            current.pop();
+            continue;
+        }
+        let next = current.join(&name);
+        let is_link = fs::symlink_metadata(&next)?.file_type().is_symlink();
+        if !is_link || (pending.is_empty() && !follow) {
+            current = next;
+            continue;
+        }
+
+        links += 1;
+        if links > config::get().symlink_depth.unwrap_or(MAX_LINKS) {
+            return Err(path::too_many_links());
+        }
+        let target = fs::read_link(&next)?;
+        if target.is_absolute() {
+            let target = path::strip_verbatim(target);
+            let Ok(rest) = target.strip_prefix(root) else {
+                return Err(escapes());
+            };
+            push_components(&mut pending, rest)?;
+            current = root.to_path_buf();
+        } else {
+            push_components(&mut pending, &target)?;
+        }
+    }
+    Ok(current)
+}
+
+// Queue the components of a relative path so the first is popped first
+fn push_components(pending: &mut Vec<OsString>, relative: &Path) -> io::Result<()> {
+    let mut components = Vec::new();
+    for component in relative.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => components.push(OsString::from("..")),
+            Component::Normal(name) => components.push(name.to_os_string()),
+            // A drive or root in a link target anchors it outside of the walk
+            Component::Prefix(_) | Component::RootDir => return Err(escapes()),
+        }
+    }
+    pending.extend(components.into_iter().rev());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn keeps_the_file_it_resolved() {
+        use std::os::unix::fs::{symlink, MetadataExt};
+
+        let dir = std::env::temp_dir().join(format!("open-btime-beneath-{}", std::process::id()));
+        let root = dir.join("root");
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::create_dir_all(dir.join("outside")).unwrap();
+        fs::write(root.join("a/f"), b"").unwrap();
+        fs::write(dir.join("outside/f"), b"").unwrap();
+        let inode = fs::metadata(root.join("a/f")).unwrap().ino();
+
+        let resolved = resolve(&root, Path::new("a/f"), true).unwrap();
+        if resolved._fd.is_none() {
+            // Walked, on a kernel without openat2
+            return fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::rename(root.join("a"), root.join("b")).unwrap();
+        symlink(dir.join("outside"), root.join("a")).unwrap();
+        assert_eq!(fs::metadata(&resolved.path).unwrap().ino(), inode);
+        assert_eq!(found_at(&resolved.path), root.join("b/f"));
+        assert_eq!(resolved.shown, root.join("a/f"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+// Progress files that let a long recursive operation resume where it was
+// cut off. Work items are numbered in the operation's deterministic order
+// (manifest order, walk order) and the file records how many leading items
+// are done, along with what identifies the run:
+//
+//   {"operation": "restoreTimes", "manifest": "/backups/nas.ndjson", "completed": 120000}
+//
+// The file is replaced atomically and removed once the operation finishes
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::json::{self, Value};
+use crate::walk::WalkError;
+
+// Rewrite the file after this many items or this much time, whichever is first
+const WRITE_EVERY_ITEMS: usize = 10_000;
+const WRITE_EVERY: Duration = Duration::from_secs(5);
+
+pub struct Checkpoint {
+    path: PathBuf,
+    // ("operation", name) followed by the operation's own identifying fields
+    identity: Vec<(&'static str, String)>,
+    resume_from: usize,
+    written: usize,
+    written_at: Instant,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Checkpoint {
+    // Open or start a checkpoint. An existing file must come from the same
+    // operation with the same identity, so a stale one is not applied to a
+    // different run
+    pub fn open(path: &Path, identity: Vec<(&'static str, String)>) -> io::Result<Checkpoint> {
+        let resume_from = match fs::read_to_string(path) {
+            Ok(text) => {
+                let document = json::parse(&text).map_err(|message| invalid(format!("checkpoint {}", message)))?;
+                for (key, value) in &identity {
+                    if document.get(key).and_then(Value::as_str) != Some(value.as_str()) {
+                        return Err(invalid(format!("checkpoint was written for a different {}", key)));
+                    }
+                }
+                document
+                    .get("completed")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| invalid("checkpoint has no completed count".to_string()))? as usize
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+        Ok(Checkpoint { path: path.to_path_buf(), identity, resume_from, written: resume_from, written_at: Instant::now() })
+    }
+
+    // Items before this index were done by an earlier run
+    pub fn resume_from(&self) -> usize {
+        self.resume_from
+    }
+
+    // Note that the first `completed` items are done, writing the file when due
+    pub fn update(&mut self, completed: usize) -> io::Result<()> {
+        if completed < self.written + WRITE_EVERY_ITEMS && self.written_at.elapsed() < WRITE_EVERY {
+            return Ok(());
+        }
+        self.write(completed)
+    }
+
+    fn write(&mut self, completed: usize) -> io::Result<()> {
+        let mut text = String::from("{");
+        for (key, value) in &self.identity {
+            json::write_string(&mut text, key);
+            text.push_str(": ");
+            json::write_string(&mut text, value);
+            text.push_str(", ");
+        }
+        text.push_str(&format!("\"completed\": {}}}\n", completed));
+
+        // Write next to the final name and rename, so a crash mid-write leaves the previous state
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, &self.path)?;
+
+        self.written = completed;
+        self.written_at = Instant::now();
+        Ok(())
+    }
+
+    // The operation is done, nothing is left to resume
+    pub fn finish(self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Update an optional checkpoint. One that cannot be written is reported once
+// and dropped, the operation itself goes on
+pub fn update(checkpoint: &mut Option<Checkpoint>, completed: usize) -> Option<WalkError> {
+    let current = checkpoint.as_mut()?;
+    let error = current.update(completed).err()?;
+    let path = checkpoint.take()?.path;
+    Some(WalkError { path, error })
+}
+
+pub fn finish(checkpoint: Option<Checkpoint>) -> Option<WalkError> {
+    let checkpoint = checkpoint?;
+    let path = checkpoint.path.clone();
+    checkpoint.finish().err().map(|error| WalkError { path, error })
+}
+
+// The number of leading items done when items finish out of order, as they
+// do on a worker pool
+#[derive(Debug, Default)]
+pub struct Watermark {
+    next: usize,
+    done: BTreeSet<usize>,
+}
+
+impl Watermark {
+    pub fn starting_at(next: usize) -> Watermark {
+        Watermark { next, done: BTreeSet::new() }
+    }
+
+    // Mark item i done and return how many leading items are done now
+    pub fn complete(&mut self, i: usize) -> usize {
+        self.done.insert(i);
+        while self.done.remove(&self.next) {
+            self.next += 1;
+        }
+        self.next
+    }
+}
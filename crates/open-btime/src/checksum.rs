@@ -0,0 +1,244 @@
+// Integrity checks of snapshot manifests. A manifest with the checksum
+// feature ends in a footer holding the CRC-32 and count of its entries:
+//
+//   "checksum": "crc32:8c736521", "count": 2
+//
+// as the last fields of the document, or as a line of its own in NDJSON,
+// where a footer covers the lines since the header or the previous footer so
+// appended runs add footers of their own. The CRC runs over every entry in
+// canonical form, its JSON text without whitespace outside strings, each
+// followed by a newline, so reformatting does not break it. Only entries
+// are covered: the header fields, version, features and root among them,
+// are not, as appended footers could not cover a header written before
+// them. A damaged header is only caught where it no longer parses.
+//
+// An entry may also carry its own CRC as its last field, "crc": "xxxxxxxx",
+// taken over its canonical form up to the comma before that field and a
+// closing brace, which tells which entry of a damaged manifest is wrong
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::json::Value;
+
+const fn table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const TABLE: [u32; 256] = table();
+
+// CRC-32 as used by gzip and zip
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32(u32);
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32(!0)
+    }
+}
+
+impl Crc32 {
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = TABLE[((self.0 ^ byte as u32) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub fn value(self) -> u32 {
+        !self.0
+    }
+}
+
+// JSON text without the whitespace between its tokens
+pub fn canonical(text: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for &byte in text {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+        } else if matches!(byte, b' ' | b'\t' | b'\n' | b'\r') {
+            continue;
+        } else if byte == b'"' {
+            in_string = true;
+        }
+        out.push(byte);
+    }
+    out
+}
+
+// The canonical form of an entry's own CRC field: ,"crc":"xxxxxxxx"}
+const ENTRY_CRC_PREFIX: &[u8] = b",\"crc\":\"";
+const ENTRY_CRC_LENGTH: usize = ENTRY_CRC_PREFIX.len() + 8 + 2;
+
+// The CRC an entry stores for the canonical text of its other fields, given
+// as an object without the closing brace
+pub fn entry_crc(fields: &[u8]) -> u32 {
+    let mut crc = Crc32::default();
+    crc.update(fields);
+    crc.update(b"}");
+    crc.value()
+}
+
+// Check the CRC of a canonical entry that has one
+fn check_entry(entry: &[u8]) -> Result<(), String> {
+    let Some(split) = entry.len().checked_sub(ENTRY_CRC_LENGTH) else {
+        return Ok(());
+    };
+    let (fields, field) = entry.split_at(split);
+    let Some(hex) = field.strip_prefix(ENTRY_CRC_PREFIX).and_then(|rest| rest.strip_suffix(b"\"}")) else {
+        return Ok(());
+    };
+    let stored = std::str::from_utf8(hex).ok().and_then(|hex| u32::from_str_radix(hex, 16).ok());
+    if stored != Some(entry_crc(fields)) {
+        return Err("entry checksum mismatch".to_string());
+    }
+    Ok(())
+}
+
+pub fn footer_checksum(crc: u32) -> String {
+    format!("crc32:{:08x}", crc)
+}
+
+// The checksum of a whole file as stored, in the form of a footer's, which
+// tells a checkpoint whether the manifest it was written for changed
+pub fn file_checksum(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut crc = Crc32::default();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(footer_checksum(crc.value())),
+            read => crc.update(&buffer[..read]),
+        }
+    }
+}
+
+// Runs over the entries of a manifest and checks them against its footers
+#[derive(Debug, Default)]
+pub struct Checker {
+    crc: Crc32,
+    // Entries since the last footer
+    count: u64,
+    // Entries in all, for messages
+    total: u64,
+    pub footers: u64,
+}
+
+impl Checker {
+    // Add an entry in canonical form, checking its own CRC when it has one
+    pub fn add(&mut self, entry: &[u8]) -> Result<(), String> {
+        check_entry(entry).map_err(|message| format!("entry {}: {}", self.total, message))?;
+        self.crc.update(entry);
+        self.crc.update(b"\n");
+        self.count += 1;
+        self.total += 1;
+        Ok(())
+    }
+
+    // Compare the entries since the last footer with a footer
+    pub fn footer(&mut self, footer: &Value) -> Result<(), String> {
+        let checksum = footer.get("checksum").and_then(Value::as_str).ok_or("missing checksum")?;
+        if checksum != footer_checksum(self.crc.value()) {
+            return Err(format!("checksum mismatch, {} recorded, {} computed", checksum, footer_checksum(self.crc.value())));
+        }
+        if footer.get("count").and_then(Value::as_u64) != Some(self.count) {
+            return Err(format!("entry count mismatch, {} entries found", self.count));
+        }
+        self.crc = Crc32::default();
+        self.count = 0;
+        self.footers += 1;
+        Ok(())
+    }
+
+    // Entries after the last footer, as left by a manifest cut short
+    pub fn unchecked(&self) -> u64 {
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+
+    fn crc(bytes: &[u8]) -> u32 {
+        let mut crc = Crc32::default();
+        crc.update(bytes);
+        crc.value()
+    }
+
+    fn with_crc(fields: &str) -> Vec<u8> {
+        format!("{},\"crc\":\"{:08x}\"}}", fields, entry_crc(fields.as_bytes())).into_bytes()
+    }
+
+    #[test]
+    fn computes_crc32() {
+        assert_eq!(crc(b""), 0);
+        assert_eq!(crc(b"123456789"), 0xcbf4_3926);
+        let mut split = Crc32::default();
+        split.update(b"1234");
+        split.update(b"56789");
+        assert_eq!(split.value(), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn canonicalizes_outside_strings() {
+        assert_eq!(canonical(b" { \"a b\" :\t[1, \"\\\" x\"]\n}"), b"{\"a b\":[1,\"\\\" x\"]}");
+    }
+
+    #[test]
+    fn checks_entry_crcs() {
+        let mut checker = Checker::default();
+        checker.add(b"{\"path\":\"a\"}").unwrap();
+        checker.add(&with_crc("{\"path\":\"b\"")).unwrap();
+        let mut damaged = with_crc("{\"path\":\"c\"");
+        damaged[9] = b'd';
+        assert_eq!(checker.add(&damaged).unwrap_err(), "entry 2: entry checksum mismatch");
+    }
+
+    #[test]
+    fn checks_footers() {
+        let entries: [&[u8]; 2] = [b"{\"path\":\"a\"}", b"{\"path\":\"b\"}"];
+        let mut expected = Crc32::default();
+        let mut checker = Checker::default();
+        for entry in entries {
+            checker.add(entry).unwrap();
+            expected.update(entry);
+            expected.update(b"\n");
+        }
+        let footer = |checksum: &str, count: u64| json::parse(&format!("{{\"checksum\":\"{}\",\"count\":{}}}", checksum, count)).unwrap();
+
+        assert!(checker.footer(&footer("crc32:00000000", 2)).unwrap_err().starts_with("checksum mismatch"));
+        assert!(checker.footer(&footer(&footer_checksum(expected.value()), 3)).unwrap_err().starts_with("entry count mismatch"));
+        assert_eq!(checker.unchecked(), 2);
+        checker.footer(&footer(&footer_checksum(expected.value()), 2)).unwrap();
+        assert_eq!((checker.unchecked(), checker.footers), (0, 1));
+
+        // A footer covers the entries since the previous one
+        checker.add(entries[0]).unwrap();
+        let mut next = Crc32::default();
+        next.update(entries[0]);
+        next.update(b"\n");
+        checker.footer(&footer(&footer_checksum(next.value()), 1)).unwrap();
+        assert_eq!(checker.footers, 2);
+    }
+}
@@ -0,0 +1,268 @@
+// Transfer metadata classes from one file to another. Symlinks are followed
+// for every class, so src and dst behave the same whichever is selected
+use std::fs;
+use std::path::Path;
+
+use crate::copy::{fail, CopyError};
+use crate::times::{self, FileTimes};
+
+// The metadata classes cloneMetadata can transfer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Classes {
+    pub times: bool,
+    pub mode: bool,
+    pub owner: bool,
+    pub xattrs: bool,
+    pub flags: bool,
+}
+
+// Copy the selected classes from src to dst and return the selected ones
+// this platform cannot transfer. Ownership goes first as chown clears the
+// setuid bits, flags last as immutable or read-only flags block other writes
+pub fn clone_metadata(src: &Path, dst: &Path, classes: Classes) -> Result<Vec<&'static str>, CopyError> {
+    let metadata = fs::metadata(src).map_err(fail("stat"))?;
+    let source_times = times::get_times(src, true).map_err(fail("stat"))?;
+    let mut skipped = Vec::new();
+
+    if classes.owner {
+        imp::copy_owner(src, dst, &metadata)?;
+    }
+    if classes.mode {
+        fs::set_permissions(dst, metadata.permissions()).map_err(fail("chmod"))?;
+    }
+    if classes.xattrs && !imp::copy_xattrs(src, dst)? {
+        skipped.push("xattrs");
+    }
+    if classes.times {
+        let file_times = FileTimes { ctime: None, ..source_times };
+        crate::set_times(dst, &file_times).map_err(fail("utimes"))?;
+    }
+    if classes.flags && !imp::copy_flags(src, dst, &metadata)? {
+        skipped.push("flags");
+    }
+    Ok(skipped)
+}
+
+#[cfg(unix)]
+mod owner {
+    use std::fs::Metadata;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+
+    use super::{fail, CopyError};
+
+    pub fn copy_owner(_src: &Path, dst: &Path, metadata: &Metadata) -> Result<(), CopyError> {
+        std::os::unix::fs::chown(dst, Some(metadata.uid()), Some(metadata.gid())).map_err(fail("chown"))
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod xattrs {
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    use super::{fail, CopyError};
+    use crate::xattr;
+
+    // Namespaces unprivileged processes cannot write on Linux, skipped
+    // instead of failing the whole clone
+    fn privileged(name: &[u8]) -> bool {
+        cfg!(target_os = "linux") && (name.starts_with(b"security.") || name.starts_with(b"trusted."))
+    }
+
+    // Make dst carry exactly the attributes of src
+    pub fn copy_xattrs(src: &Path, dst: &Path) -> Result<bool, CopyError> {
+        let names = xattr::list(src).map_err(fail("listxattr"))?;
+        for name in &names {
+            let value = xattr::get(src, name).map_err(fail("getxattr"))?;
+            match xattr::set(dst, name, &value) {
+                Ok(_) => {}
+                Err(err) if privileged(name.to_bytes()) && err.raw_os_error() == Some(libc::EPERM) => {}
+                Err(error) => return Err(CopyError { syscall: "setxattr", error }),
+            }
+        }
+
+        let wanted: HashSet<_> = names.iter().collect();
+        for name in xattr::list(dst).map_err(fail("listxattr"))? {
+            if !wanted.contains(&name) && !privileged(name.to_bytes()) {
+                xattr::remove(dst, &name).map_err(fail("removexattr"))?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::ffi::CString;
+    use std::fs::Metadata;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use super::{fail, CopyError};
+    pub use super::owner::copy_owner;
+    pub use super::xattrs::copy_xattrs;
+
+    // _IOR('f', 1, long) and _IOW('f', 2, long) from linux/fs.h; the kernel
+    // reads and writes an int despite the declared type
+    const FS_IOC_GETFLAGS: u64 = 0x8008_6601;
+    const FS_IOC_SETFLAGS: u64 = 0x4008_6602;
+    // The chattr flags a process may change
+    const FS_FL_USER_MODIFIABLE: i32 = 0x0003_80FF;
+
+    fn open(path: &Path) -> io::Result<OwnedFd> {
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        // O_NONBLOCK keeps FIFOs from blocking the open
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDONLY | libc::O_NONBLOCK | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    // None where the filesystem has no flags
+    fn get_flags(fd: &OwnedFd) -> io::Result<Option<i32>> {
+        let mut flags: i32 = 0;
+        if unsafe { libc::ioctl(fd.as_raw_fd(), FS_IOC_GETFLAGS as _, &mut flags) } == 0 {
+            return Ok(Some(flags));
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::ENOTTY | libc::EOPNOTSUPP | libc::EINVAL) => Ok(None),
+            _ => Err(err),
+        }
+    }
+
+    pub fn copy_flags(src: &Path, dst: &Path, _metadata: &Metadata) -> Result<bool, CopyError> {
+        let source = open(src).map_err(fail("open"))?;
+        let Some(flags) = get_flags(&source).map_err(fail("FS_IOC_GETFLAGS"))? else {
+            return Ok(true);
+        };
+        let target = open(dst).map_err(fail("open"))?;
+        let current = get_flags(&target).map_err(fail("FS_IOC_GETFLAGS"))?;
+        let current = match current {
+            Some(current) => current,
+            // Nothing to lose if src has no flags that could be set
+            None if flags & FS_FL_USER_MODIFIABLE == 0 => return Ok(true),
+            None => {
+                let error = io::Error::from_raw_os_error(libc::EOPNOTSUPP);
+                return Err(CopyError { syscall: "FS_IOC_SETFLAGS", error });
+            }
+        };
+
+        // Only the modifiable bits come from src, EXTENTS_FL and the like stay as they are
+        let wanted = (current & !FS_FL_USER_MODIFIABLE) | (flags & FS_FL_USER_MODIFIABLE);
+        if wanted != current && unsafe { libc::ioctl(target.as_raw_fd(), FS_IOC_SETFLAGS as _, &wanted) } != 0 {
+            return Err(CopyError { syscall: "FS_IOC_SETFLAGS", error: io::Error::last_os_error() });
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::ffi::CString;
+    use std::fs::Metadata;
+    use std::io;
+    use std::os::macos::fs::MetadataExt;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use super::{fail, CopyError};
+    pub use super::owner::copy_owner;
+    pub use super::xattrs::copy_xattrs;
+
+    // BSD file flags such as UF_HIDDEN and UF_IMMUTABLE, see chflags(2)
+    pub fn copy_flags(_src: &Path, dst: &Path, metadata: &Metadata) -> Result<bool, CopyError> {
+        let c_dst = CString::new(dst.as_os_str().as_bytes())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains null bytes"))
+            .map_err(fail("chflags"))?;
+        if unsafe { libc::chflags(c_dst.as_ptr(), metadata.st_flags() as _) } != 0 {
+            return Err(CopyError { syscall: "chflags", error: io::Error::last_os_error() });
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use std::fs::Metadata;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::fs::MetadataExt;
+    use std::path::Path;
+
+    use windows::core::PCWSTR;
+    use windows::Win32::Security::{
+        GetFileSecurityW, SetFileSecurityW, GROUP_SECURITY_INFORMATION, OWNER_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR,
+    };
+    use windows::Win32::Storage::FileSystem::{SetFileAttributesW, FILE_FLAGS_AND_ATTRIBUTES};
+
+    use super::CopyError;
+    use crate::win;
+
+    // Attributes SetFileAttributesW accepts: read-only, hidden, system,
+    // archive, temporary, offline and not content indexed
+    const SETTABLE_ATTRIBUTES: u32 = 0x1 | 0x2 | 0x4 | 0x20 | 0x100 | 0x1000 | 0x2000;
+
+    fn wide(path: &Path) -> Vec<u16> {
+        path.as_os_str().encode_wide().chain(Some(0)).collect()
+    }
+
+    // Owner and primary group of the security descriptor. Setting an owner
+    // other than the caller needs SeRestorePrivilege
+    pub fn copy_owner(src: &Path, dst: &Path, _metadata: &Metadata) -> Result<(), CopyError> {
+        let (src, dst) = (wide(src), wide(dst));
+        let information = (OWNER_SECURITY_INFORMATION | GROUP_SECURITY_INFORMATION).0;
+        let mut length = 0u32;
+        let _ = unsafe { GetFileSecurityW(PCWSTR(src.as_ptr()), information, PSECURITY_DESCRIPTOR::default(), 0, &mut length) };
+        // Security descriptors hold pointer-aligned fields
+        let mut buf = vec![0u64; (length as usize).div_ceil(8)];
+        let descriptor = PSECURITY_DESCRIPTOR(buf.as_mut_ptr() as *mut _);
+        if !unsafe { GetFileSecurityW(PCWSTR(src.as_ptr()), information, descriptor, length, &mut length) }.as_bool() {
+            return Err(CopyError { syscall: "GetFileSecurityW", error: io::Error::last_os_error() });
+        }
+        if !unsafe { SetFileSecurityW(PCWSTR(dst.as_ptr()), information, descriptor) }.as_bool() {
+            return Err(CopyError { syscall: "SetFileSecurityW", error: io::Error::last_os_error() });
+        }
+        Ok(())
+    }
+
+    // NTFS extended attributes are not exposed through Win32
+    pub fn copy_xattrs(_src: &Path, _dst: &Path) -> Result<bool, CopyError> {
+        Ok(false)
+    }
+
+    pub fn copy_flags(_src: &Path, dst: &Path, metadata: &Metadata) -> Result<bool, CopyError> {
+        let attributes = metadata.file_attributes() & SETTABLE_ATTRIBUTES;
+        let dst = wide(dst);
+        unsafe { SetFileAttributesW(PCWSTR(dst.as_ptr()), FILE_FLAGS_AND_ATTRIBUTES(attributes)) }
+            .map_err(|err| CopyError { syscall: "SetFileAttributesW", error: win::io_error(err) })?;
+        Ok(true)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+mod imp {
+    use std::fs::Metadata;
+    use std::path::Path;
+
+    use super::CopyError;
+    #[cfg(unix)]
+    pub use super::owner::copy_owner;
+
+    #[cfg(not(unix))]
+    pub fn copy_owner(_src: &Path, _dst: &Path, _metadata: &Metadata) -> Result<(), CopyError> {
+        Ok(())
+    }
+
+    pub fn copy_xattrs(_src: &Path, _dst: &Path) -> Result<bool, CopyError> {
+        Ok(false)
+    }
+
+    pub fn copy_flags(_src: &Path, _dst: &Path, _metadata: &Metadata) -> Result<bool, CopyError> {
+        Ok(false)
+    }
+}
@@ -0,0 +1,161 @@
+// Gzip and zstd compression of snapshot manifests. Writers choose it by
+// format name or extension; readers recognize compressed manifests by their
+// magic bytes, whatever they are called. Built with the compression feature
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+impl Compression {
+    fn from_extension(extension: &str) -> Option<Compression> {
+        match extension {
+            "gz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    // Split a format name such as "json.zst" into the format and compression
+    pub fn split_name(name: &str) -> (&str, Compression) {
+        match name.rsplit_once('.') {
+            Some((format, extension)) => match Compression::from_extension(extension) {
+                Some(compression) => (format, compression),
+                None => (name, Compression::None),
+            },
+            None => (name, Compression::None),
+        }
+    }
+
+    // The compression implied by a path's last extension, and the path
+    // without it for finding the format
+    pub fn split_path(path: &Path) -> (&Path, Compression) {
+        let extension = path.extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase);
+        match extension.as_deref().and_then(Compression::from_extension) {
+            Some(compression) => (Path::new(path.file_stem().unwrap_or_default()), compression),
+            None => (path, Compression::None),
+        }
+    }
+
+    // The compression of a stream going by its first bytes, which are left unread
+    pub fn detect(input: &mut impl BufRead) -> io::Result<Compression> {
+        let head = input.fill_buf()?;
+        if head.starts_with(&ZSTD_MAGIC) {
+            Ok(Compression::Zstd)
+        } else if head.starts_with(&GZIP_MAGIC) {
+            Ok(Compression::Gzip)
+        } else {
+            Ok(Compression::None)
+        }
+    }
+}
+
+fn unavailable(compression: Compression) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{} compressed manifests need open-btime built with the compression feature", compression.name()),
+    )
+}
+
+// A manifest file being written, compressed as it goes
+pub enum ManifestFile {
+    Plain(BufWriter<File>),
+    #[cfg(feature = "compression")]
+    Gzip(flate2::write::GzEncoder<BufWriter<File>>),
+    #[cfg(feature = "compression")]
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl ManifestFile {
+    pub fn create(path: &Path, compression: Compression) -> io::Result<ManifestFile> {
+        ManifestFile::open(path, compression, OpenOptions::new().write(true).create(true).truncate(true))
+    }
+
+    // Add to the end of an existing manifest. Compressed data goes into a new
+    // gzip member or zstd frame, which readers take as a continuation
+    pub fn append(path: &Path, compression: Compression) -> io::Result<ManifestFile> {
+        ManifestFile::open(path, compression, OpenOptions::new().append(true))
+    }
+
+    fn open(path: &Path, compression: Compression, options: &OpenOptions) -> io::Result<ManifestFile> {
+        if compression != Compression::None && !cfg!(feature = "compression") {
+            return Err(unavailable(compression));
+        }
+        let out = BufWriter::new(options.open(path)?);
+        match compression {
+            Compression::None => Ok(ManifestFile::Plain(out)),
+            #[cfg(feature = "compression")]
+            Compression::Gzip => Ok(ManifestFile::Gzip(flate2::write::GzEncoder::new(out, flate2::Compression::default()))),
+            #[cfg(feature = "compression")]
+            Compression::Zstd => Ok(ManifestFile::Zstd(zstd::Encoder::new(out, zstd::DEFAULT_COMPRESSION_LEVEL)?)),
+            #[cfg(not(feature = "compression"))]
+            _ => unreachable!(),
+        }
+    }
+
+    // Write the end of the compressed stream and flush the file
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            ManifestFile::Plain(mut out) => out.flush(),
+            #[cfg(feature = "compression")]
+            ManifestFile::Gzip(encoder) => encoder.finish()?.flush(),
+            #[cfg(feature = "compression")]
+            ManifestFile::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for ManifestFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ManifestFile::Plain(out) => out.write(buf),
+            #[cfg(feature = "compression")]
+            ManifestFile::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "compression")]
+            ManifestFile::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ManifestFile::Plain(out) => out.flush(),
+            #[cfg(feature = "compression")]
+            ManifestFile::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "compression")]
+            ManifestFile::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// Open a manifest for reading, decompressing it when it is compressed
+pub fn open(path: &Path) -> io::Result<(Box<dyn BufRead + Send>, Compression)> {
+    let mut input = BufReader::new(File::open(path)?);
+    let compression = Compression::detect(&mut input)?;
+    let reader: Box<dyn BufRead + Send> = match compression {
+        Compression::None => Box::new(input),
+        #[cfg(feature = "compression")]
+        Compression::Gzip => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(input))),
+        #[cfg(feature = "compression")]
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(input)?)),
+        #[cfg(not(feature = "compression"))]
+        compression => return Err(unavailable(compression)),
+    };
+    Ok((reader, compression))
+}
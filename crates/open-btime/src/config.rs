@@ -0,0 +1,103 @@
+// Process-wide option defaults set with configure(). Every call reads them
+// once when parsing its options, so per-call options still take precedence
+use std::sync::RwLock;
+
+use crate::walk::FollowSymlinks;
+
+// What writes do with cloud files placeholders such as dehydrated OneDrive
+// files, whose data is fetched when they are opened or read, and evicted
+// iCloud Drive items, which can only be skipped or reported
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placeholders {
+    // Leave them untouched
+    Skip,
+    // Set the times without fetching the data
+    Stamp,
+    // Fetch the data, then set the times
+    Hydrate,
+}
+
+impl Placeholders {
+    pub fn from_name(name: &str) -> Option<Placeholders> {
+        match name {
+            "skip" => Some(Placeholders::Skip),
+            "stamp" => Some(Placeholders::Stamp),
+            "hydrate" => Some(Placeholders::Hydrate),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Placeholders::Skip => "skip",
+            Placeholders::Stamp => "stamp",
+            Placeholders::Hydrate => "hydrate",
+        }
+    }
+}
+
+// What writes do with timestamps finer than the platform stores, such as
+// nanoseconds below the 100ns steps of a Windows FILETIME
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quantize {
+    // Drop the remainder, moving the time back
+    Truncate,
+    // Go to the nearest step, halfway cases up
+    Round,
+    // Fail the write
+    Error,
+}
+
+impl Quantize {
+    pub fn from_name(name: &str) -> Option<Quantize> {
+        match name {
+            "truncate" => Some(Quantize::Truncate),
+            "round" => Some(Quantize::Round),
+            "error" => Some(Quantize::Error),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Quantize::Truncate => "truncate",
+            Quantize::Round => "round",
+            Quantize::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub bigint: bool,
+    pub backup: bool,
+    pub follow_symlinks: FollowSymlinks,
+    pub concurrency: usize,
+    pub placeholders: Placeholders,
+    pub quantize: Quantize,
+    // Bytes a manifest may take before it is read through a temporary file
+    pub memory_limit: Option<u64>,
+    // Symlinks a path may resolve through, None to leave it to the OS
+    pub symlink_depth: Option<usize>,
+}
+
+pub const DEFAULT: Config = Config {
+    bigint: true,
+    backup: false,
+    follow_symlinks: FollowSymlinks::Never,
+    concurrency: 4,
+    placeholders: Placeholders::Stamp,
+    quantize: Quantize::Truncate,
+    memory_limit: None,
+    symlink_depth: None,
+};
+
+static CONFIG: RwLock<Config> = RwLock::new(DEFAULT);
+
+pub fn get() -> Config {
+    *CONFIG.read().unwrap()
+}
+
+pub fn set(config: Config) {
+    *CONFIG.write().unwrap() = config;
+}
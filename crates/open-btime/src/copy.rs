@@ -0,0 +1,340 @@
+// File copies that keep the timestamps of the source, using the fastest
+// primitive each platform offers and falling back to plain reads and writes
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::atomic;
+use crate::fault;
+use crate::stats;
+use crate::times::{self, FileTimes};
+
+// How the data got copied, reported back to JS
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    // FICLONE, sharing the source's extents on Btrfs, XFS and bcachefs
+    #[cfg(target_os = "linux")]
+    Reflink,
+    // In-kernel copy with copy_file_range, which may reflink or use server-side copy
+    #[cfg(target_os = "linux")]
+    CopyFileRange,
+    // clonefile on APFS
+    #[cfg(target_os = "macos")]
+    Clonefile,
+    // copyfile(3) when cloning is not possible
+    #[cfg(target_os = "macos")]
+    Copyfile,
+    #[cfg(target_os = "windows")]
+    CopyFileEx,
+    // FSCTL_DUPLICATE_EXTENTS_TO_FILE, block cloning on ReFS and Dev Drive
+    #[cfg(target_os = "windows")]
+    DuplicateExtents,
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    ReadWrite,
+}
+
+impl Strategy {
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(target_os = "linux")]
+            Strategy::Reflink => "reflink",
+            #[cfg(target_os = "linux")]
+            Strategy::CopyFileRange => "copy_file_range",
+            #[cfg(target_os = "macos")]
+            Strategy::Clonefile => "clonefile",
+            #[cfg(target_os = "macos")]
+            Strategy::Copyfile => "copyfile",
+            #[cfg(target_os = "windows")]
+            Strategy::CopyFileEx => "CopyFileExW",
+            #[cfg(target_os = "windows")]
+            Strategy::DuplicateExtents => "FSCTL_DUPLICATE_EXTENTS_TO_FILE",
+            #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+            Strategy::ReadWrite => "read_write",
+        }
+    }
+}
+
+// An error together with the call that failed
+#[derive(Debug)]
+pub struct CopyError {
+    pub syscall: &'static str,
+    pub error: io::Error,
+}
+
+pub fn fail(syscall: &'static str) -> impl FnOnce(io::Error) -> CopyError {
+    move |error| CopyError { syscall, error }
+}
+
+// Copy src to dst, replacing dst, then give dst the birth, modification and
+// access times src had before the copy read it
+pub fn copy_file_with_times(src: &Path, dst: &Path) -> Result<Strategy, CopyError> {
+    let source_times = times::get_times(src, true).map_err(fail("stat"))?;
+    let started = Instant::now();
+    let strategy = fault::check(stats::Op::CopyFile, src).map_err(fail("copy")).and_then(|_| imp::copy_data(src, dst));
+    stats::record_error(stats::Op::CopyFile, started, strategy.as_ref().err().map(|err| &err.error));
+    let strategy = strategy?;
+    let file_times = FileTimes { ctime: None, ..source_times };
+    crate::set_times(dst, &file_times).map_err(fail("utimes"))?;
+    Ok(strategy)
+}
+
+// Clone src to dst so both share their data blocks until one is written,
+// replacing dst, and give the clone the times of src. Fails where the
+// filesystem cannot share blocks instead of falling back to a copy. The
+// clone is made next to dst and renamed over it once it has its times
+pub fn clone_file_with_times(src: &Path, dst: &Path) -> Result<Strategy, CopyError> {
+    let source_times = times::get_times(src, true).map_err(fail("stat"))?;
+    let temporary = atomic::temporary_path(dst).map_err(fail("open"))?;
+    let started = Instant::now();
+    let strategy = fault::check(stats::Op::CopyFile, src).map_err(fail("clone")).and_then(|_| imp::clone_data(src, &temporary));
+    stats::record_error(stats::Op::CopyFile, started, strategy.as_ref().err().map(|err| &err.error));
+    let file_times = FileTimes { ctime: None, ..source_times };
+    let strategy = strategy.and_then(|strategy| {
+        crate::set_times(&temporary, &file_times).map_err(fail("utimes"))?;
+        Ok(strategy)
+    });
+    if strategy.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    let strategy = strategy?;
+    atomic::rename_into_place(&temporary, dst, source_times.btime)?;
+    Ok(strategy)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::fs::{self, File, OpenOptions};
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+    use std::path::Path;
+
+    use super::{fail, CopyError, Strategy};
+
+    // _IOW(0x94, 9, int) from linux/fs.h
+    const FICLONE: u64 = 0x4004_9409;
+
+    pub fn copy_data(src: &Path, dst: &Path) -> Result<Strategy, CopyError> {
+        let mut source = File::open(src).map_err(fail("open"))?;
+        let metadata = source.metadata().map_err(fail("fstat"))?;
+        // Opening dst truncates it, which would destroy src if both are the same file
+        if let Ok(existing) = fs::metadata(dst) {
+            if (existing.dev(), existing.ino()) == (metadata.dev(), metadata.ino()) {
+                let error = io::Error::new(io::ErrorKind::InvalidInput, "source and destination are the same file");
+                return Err(CopyError { syscall: "open", error });
+            }
+        }
+        let mode = metadata.permissions().mode();
+        let mut target = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(mode)
+            .open(dst)
+            .map_err(fail("open"))?;
+        // The mode only applies when dst is created
+        fs::set_permissions(dst, metadata.permissions()).map_err(fail("chmod"))?;
+
+        if unsafe { libc::ioctl(target.as_raw_fd(), FICLONE as _, source.as_raw_fd()) } == 0 {
+            return Ok(Strategy::Reflink);
+        }
+
+        let mut copied = 0u64;
+        loop {
+            let result = unsafe {
+                libc::syscall(
+                    libc::SYS_copy_file_range,
+                    source.as_raw_fd(),
+                    std::ptr::null_mut::<libc::loff_t>(),
+                    target.as_raw_fd(),
+                    std::ptr::null_mut::<libc::loff_t>(),
+                    1usize << 30,
+                    0u32,
+                )
+            };
+            if result > 0 {
+                copied += result as u64;
+                continue;
+            }
+            if result == 0 {
+                return Ok(Strategy::CopyFileRange);
+            }
+
+            let err = io::Error::last_os_error();
+            let unsupported = matches!(
+                err.raw_os_error(),
+                Some(libc::ENOSYS | libc::EXDEV | libc::EINVAL | libc::EOPNOTSUPP | libc::EPERM | libc::EBADF)
+            );
+            // Nothing is written yet, so a plain copy can start from the offsets reached
+            if copied == 0 && unsupported {
+                io::copy(&mut source, &mut target).map_err(fail("write"))?;
+                return Ok(Strategy::ReadWrite);
+            }
+            return Err(CopyError { syscall: "copy_file_range", error: err });
+        }
+    }
+
+    // dst must not exist yet
+    pub fn clone_data(src: &Path, dst: &Path) -> Result<Strategy, CopyError> {
+        let source = File::open(src).map_err(fail("open"))?;
+        let metadata = source.metadata().map_err(fail("fstat"))?;
+        let target = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(metadata.permissions().mode())
+            .open(dst)
+            .map_err(fail("open"))?;
+        fs::set_permissions(dst, metadata.permissions()).map_err(fail("chmod"))?;
+        if unsafe { libc::ioctl(target.as_raw_fd(), FICLONE as _, source.as_raw_fd()) } != 0 {
+            return Err(CopyError { syscall: "FICLONE", error: io::Error::last_os_error() });
+        }
+        Ok(Strategy::Reflink)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use super::{fail, CopyError, Strategy};
+
+    const CLONE_NOFOLLOW: u32 = 0x0001;
+
+    fn c_path(path: &Path) -> io::Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains null bytes"))
+    }
+
+    pub fn copy_data(src: &Path, dst: &Path) -> Result<Strategy, CopyError> {
+        let c_src = c_path(src).map_err(fail("clonefile"))?;
+        let c_dst = c_path(dst).map_err(fail("clonefile"))?;
+
+        // clonefile refuses to replace dst, so only try it for new files
+        if !dst.exists() && unsafe { libc::clonefile(c_src.as_ptr(), c_dst.as_ptr(), CLONE_NOFOLLOW) } == 0 {
+            return Ok(Strategy::Clonefile);
+        }
+
+        let flags = libc::COPYFILE_DATA | libc::COPYFILE_SECURITY | libc::COPYFILE_XATTR;
+        if unsafe { libc::copyfile(c_src.as_ptr(), c_dst.as_ptr(), std::ptr::null_mut(), flags) } != 0 {
+            return Err(CopyError { syscall: "copyfile", error: io::Error::last_os_error() });
+        }
+        Ok(Strategy::Copyfile)
+    }
+
+    // dst must not exist yet, clonefile keeps the permissions
+    pub fn clone_data(src: &Path, dst: &Path) -> Result<Strategy, CopyError> {
+        let c_src = c_path(src).map_err(fail("clonefile"))?;
+        let c_dst = c_path(dst).map_err(fail("clonefile"))?;
+        if unsafe { libc::clonefile(c_src.as_ptr(), c_dst.as_ptr(), CLONE_NOFOLLOW) } != 0 {
+            return Err(CopyError { syscall: "clonefile", error: io::Error::last_os_error() });
+        }
+        Ok(Strategy::Clonefile)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use std::ffi::c_void;
+    use std::fs::{self, File, OpenOptions};
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::fs::MetadataExt;
+    use std::os::windows::io::AsRawHandle;
+    use std::path::Path;
+
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Storage::FileSystem::{
+        CopyFileExW, GetDiskFreeSpaceW, GetVolumePathNameW, FILE_ATTRIBUTE_SPARSE_FILE,
+    };
+    use windows::Win32::System::Ioctl::{DUPLICATE_EXTENTS_DATA, FSCTL_DUPLICATE_EXTENTS_TO_FILE, FSCTL_SET_SPARSE};
+    use windows::Win32::System::IO::DeviceIoControl;
+
+    use super::{fail, CopyError, Strategy};
+    use crate::win;
+
+    // Bytes cloned per call, well below the 4 GiB the call accepts
+    const CHUNK: u64 = 1 << 30;
+
+    // CopyFileExW block-clones on ReFS and Dev Drive volumes by itself
+    pub fn copy_data(src: &Path, dst: &Path) -> Result<Strategy, CopyError> {
+        let wide = |path: &Path| path.as_os_str().encode_wide().chain(Some(0)).collect::<Vec<u16>>();
+        let (src, dst) = (wide(src), wide(dst));
+        unsafe { CopyFileExW(PCWSTR(src.as_ptr()), PCWSTR(dst.as_ptr()), None, None, None, 0) }
+            .map_err(|err| CopyError { syscall: "CopyFileExW", error: win::io_error(err) })?;
+        Ok(Strategy::CopyFileEx)
+    }
+
+    fn handle(file: &File) -> HANDLE {
+        HANDLE(file.as_raw_handle() as isize)
+    }
+
+    fn cluster_size(path: &Path) -> io::Result<u64> {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut root = [0u16; 1024];
+        unsafe { GetVolumePathNameW(PCWSTR(wide.as_ptr()), &mut root) }.map_err(win::io_error)?;
+        let (mut sectors, mut bytes) = (0u32, 0u32);
+        unsafe { GetDiskFreeSpaceW(PCWSTR(root.as_ptr()), Some(&mut sectors), Some(&mut bytes), None, None) }
+            .map_err(win::io_error)?;
+        Ok(sectors as u64 * bytes as u64)
+    }
+
+    fn ioctl(file: &File, code: u32, input: Option<*const c_void>, size: u32) -> windows::core::Result<()> {
+        unsafe { DeviceIoControl(handle(file), code, input, size, None, 0, None, None) }
+    }
+
+    // dst must not exist yet. Both files have to be on the same ReFS volume;
+    // the cloned ranges are whole clusters, so the last one reaches past the
+    // end of the file that was already set
+    pub fn clone_data(src: &Path, dst: &Path) -> Result<Strategy, CopyError> {
+        let source = File::open(src).map_err(fail("open"))?;
+        let metadata = source.metadata().map_err(fail("fstat"))?;
+        let target = OpenOptions::new().write(true).create_new(true).open(dst).map_err(fail("open"))?;
+        let duplicate = |err| CopyError { syscall: "FSCTL_DUPLICATE_EXTENTS_TO_FILE", error: win::io_error(err) };
+
+        if metadata.file_attributes() & FILE_ATTRIBUTE_SPARSE_FILE.0 != 0 {
+            ioctl(&target, FSCTL_SET_SPARSE, None, 0)
+                .map_err(|err| CopyError { syscall: "FSCTL_SET_SPARSE", error: win::io_error(err) })?;
+        }
+        target.set_len(metadata.len()).map_err(fail("SetEndOfFile"))?;
+        let cluster = cluster_size(dst).map_err(fail("GetDiskFreeSpaceW"))?;
+        let length = metadata.len().div_ceil(cluster) * cluster;
+        let mut offset = 0;
+        while offset < length {
+            let count = (length - offset).min(CHUNK);
+            let data = DUPLICATE_EXTENTS_DATA {
+                FileHandle: handle(&source),
+                SourceFileOffset: offset as i64,
+                TargetFileOffset: offset as i64,
+                ByteCount: count as i64,
+            };
+            let input = &data as *const DUPLICATE_EXTENTS_DATA as *const c_void;
+            ioctl(&target, FSCTL_DUPLICATE_EXTENTS_TO_FILE, Some(input), std::mem::size_of::<DUPLICATE_EXTENTS_DATA>() as u32)
+                .map_err(duplicate)?;
+            offset += count;
+        }
+        drop(target);
+        fs::set_permissions(dst, metadata.permissions()).map_err(fail("chmod"))?;
+        Ok(Strategy::DuplicateExtents)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+mod imp {
+    use std::path::Path;
+
+    use super::{fail, CopyError, Strategy};
+
+    pub fn copy_data(src: &Path, dst: &Path) -> Result<Strategy, CopyError> {
+        std::fs::copy(src, dst).map_err(fail("copy"))?;
+        Ok(Strategy::ReadWrite)
+    }
+
+    pub fn clone_data(_src: &Path, _dst: &Path) -> Result<Strategy, CopyError> {
+        let error = std::io::Error::new(std::io::ErrorKind::Unsupported, "cloning is not available");
+        Err(CopyError { syscall: "clone", error })
+    }
+}
@@ -0,0 +1,118 @@
+// Copying a whole tree with its timestamps, e.g. when restoring a backup to
+// another disk where neither a rename nor a clone can carry them over. The
+// directories are created first, the files then streamed over on worker
+// threads, each getting its times as soon as its data is written, and the
+// directories get theirs last, deepest first, since creating the entries in
+// a directory changes its modification time
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::copy;
+use crate::pool;
+use crate::snapshot;
+use crate::times::FileTimes;
+use crate::walk::{Entry, EntryType, WalkError, WalkOptions, Walker};
+
+#[derive(Debug, Default)]
+pub struct CopyTreeResult {
+    pub files: usize,
+    pub directories: usize,
+    // Symlinks that are not followed, recreated with the same target
+    pub links: usize,
+    // Sockets, FIFOs and devices, which are not copied
+    pub skipped: Vec<String>,
+    pub errors: Vec<WalkError>,
+    pub truncated: Vec<PathBuf>,
+}
+
+#[cfg(unix)]
+fn copy_link(src: &Path, dst: &Path, _file_type: EntryType) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(src)?, dst)
+}
+
+#[cfg(windows)]
+fn copy_link(src: &Path, dst: &Path, file_type: EntryType) -> io::Result<()> {
+    let target = fs::read_link(src)?;
+    if file_type == EntryType::Junction || fs::metadata(src).is_ok_and(|metadata| metadata.is_dir()) {
+        std::os::windows::fs::symlink_dir(target, dst)
+    } else {
+        std::os::windows::fs::symlink_file(target, dst)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn copy_link(_src: &Path, _dst: &Path, _file_type: EntryType) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "symlinks not supported"))
+}
+
+// Copy the tree below source to destination, creating destination and
+// replacing files that already exist there, and give every copied entry the
+// birth, modification and access times of its source. Files are copied on
+// `concurrency` workers. Entries that fail are reported and the copy goes on
+pub fn copy_tree(source: &Path, destination: &Path, options: WalkOptions, concurrency: usize) -> io::Result<CopyTreeResult> {
+    let mut result = CopyTreeResult::default();
+    let root_times = FileTimes { ctime: None, ..FileTimes::from_metadata(&fs::metadata(source)?) };
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut walker = Walker::new(source, options);
+    for item in walker.by_ref() {
+        match item {
+            Ok(entry) => entries.push(entry),
+            Err(err) => result.errors.push(err),
+        }
+    }
+    result.truncated.extend_from_slice(walker.truncated());
+    fs::create_dir_all(destination)?;
+
+    let target = |entry: &Entry| destination.join(snapshot::relative_path(source, &entry.path));
+    let mut files = Vec::new();
+    let mut dirs = vec![(destination.to_path_buf(), root_times)];
+    for entry in &entries {
+        let path = target(entry);
+        let times = FileTimes { ctime: None, ..entry.times };
+        let made = match entry.file_type {
+            EntryType::Dir => fs::create_dir_all(&path).map(|_| dirs.push((path.clone(), times))),
+            EntryType::File => {
+                files.push((entry.path.clone(), path.clone()));
+                Ok(())
+            }
+            EntryType::Symlink | EntryType::Junction => {
+                let link = copy_link(&entry.path, &path, entry.file_type).and_then(|_| crate::set_link_times(&path, &times)).map(|_| ());
+                if link.is_ok() {
+                    result.links += 1;
+                }
+                link
+            }
+            EntryType::Other => {
+                result.skipped.push(snapshot::relative_path(source, &entry.path));
+                Ok(())
+            }
+        };
+        if let Err(error) = made {
+            result.errors.push(WalkError { path, error });
+        }
+    }
+
+    let copied = AtomicUsize::new(0);
+    let errors = Mutex::new(Vec::new());
+    pool::for_each_bounded(files.into_iter(), concurrency, |(src, dst)| match copy::copy_file_with_times(&src, &dst) {
+        Ok(_) => {
+            copied.fetch_add(1, Ordering::Relaxed);
+        }
+        Err(err) => errors.lock().unwrap().push(WalkError { path: dst, error: err.error }),
+    });
+    result.files = copied.into_inner();
+    result.errors.extend(errors.into_inner().unwrap());
+
+    // Children come after their parents in the walk, so in reverse every
+    // directory is finalized after everything below it
+    for (path, times) in dirs.into_iter().rev() {
+        match crate::set_times(&path, &times) {
+            Ok(_) => result.directories += 1,
+            Err(error) => result.errors.push(WalkError { path, error }),
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,456 @@
+// Directory enumeration that returns each entry's type and timestamps along
+// with its name, using the bulk listing primitive of each platform
+// (getdents64 + statx, getattrlistbulk, FindFirstFileEx) instead of a
+// readdir followed by a full-path stat per entry
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+
+use crate::times::FileTimes;
+use crate::walk::EntryType;
+
+// What the walker needs to know about an entry, without following symlinks
+#[derive(Clone, Copy, Debug)]
+pub struct Stat {
+    pub file_type: EntryType,
+    pub times: FileTimes,
+    // Length in bytes as reported by the filesystem
+    pub size: u64,
+    // (device, inode) when the listing provides it for free
+    pub id: Option<(u64, u64)>,
+}
+
+impl Stat {
+    pub fn from_metadata(metadata: &fs::Metadata) -> Stat {
+        Stat {
+            file_type: EntryType::from_file_type(metadata.file_type()),
+            times: FileTimes::from_metadata(metadata),
+            size: metadata.len(),
+            id: metadata_id(metadata),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn metadata_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn metadata_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[derive(Debug)]
+pub struct DirEntry {
+    pub name: OsString,
+    // Entries can vanish between listing and stat
+    pub stat: io::Result<Stat>,
+}
+
+// List a directory, counted as one readDir operation in getStats()
+pub fn read_dir(dir: &std::path::Path) -> io::Result<Vec<DirEntry>> {
+    let started = std::time::Instant::now();
+    let result = crate::fault::check(crate::stats::Op::ReadDir, dir).and_then(|_| {
+        let dir = dir.to_path_buf();
+        crate::timeout::run(move || imp::read_dir(&dir))
+    });
+    crate::stats::record(crate::stats::Op::ReadDir, started, &result);
+    result
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub use imp::stat_paths;
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::ffi::{CStr, CString, OsString};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::{DirEntry, Stat};
+    use crate::times::{FileTimes, Timestamp};
+    use crate::walk::EntryType;
+
+    // Set once statx turns out to be missing (kernels before 4.11)
+    static NO_STATX: AtomicBool = AtomicBool::new(false);
+
+    const STATX_MASK: u32 = libc::STATX_BASIC_STATS | libc::STATX_BTIME;
+    const STATX_FLAGS: i32 = libc::AT_SYMLINK_NOFOLLOW | libc::AT_STATX_SYNC_AS_STAT;
+
+    pub fn read_dir(dir: &Path) -> io::Result<Vec<DirEntry>> {
+        let c_dir = CString::new(dir.as_os_str().as_bytes())?;
+        let fd = unsafe { libc::open(c_dir.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // Collect the names first so their stats can go to the kernel as one batch
+        let mut names = Vec::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let read = unsafe { libc::syscall(libc::SYS_getdents64, fd.as_raw_fd(), buf.as_mut_ptr(), buf.len()) };
+            if read < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if read == 0 {
+                break;
+            }
+
+            // struct linux_dirent64 { u64 d_ino; i64 d_off; u16 d_reclen; u8 d_type; char d_name[]; }
+            let mut offset = 0;
+            while offset < read as usize {
+                let record = &buf[offset..];
+                let reclen = u16::from_ne_bytes([record[16], record[17]]) as usize;
+                let name = CStr::from_bytes_until_nul(&record[19..reclen])
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed directory entry"))?;
+                offset += reclen;
+
+                if name.to_bytes() == b"." || name.to_bytes() == b".." {
+                    continue;
+                }
+                names.push(name.to_owned());
+            }
+        }
+
+        let stats = stat_all(&fd, &names);
+        Ok(names
+            .into_iter()
+            .zip(stats)
+            .map(|(name, stat)| DirEntry { name: OsString::from_vec(name.into_bytes()), stat })
+            .collect())
+    }
+
+    #[cfg(feature = "io-uring")]
+    fn stat_all(dir: &OwnedFd, names: &[CString]) -> Vec<io::Result<Stat>> {
+        if !NO_STATX.load(Ordering::Relaxed) {
+            if let Some(results) = crate::uring::statx_many(dir.as_raw_fd(), names, STATX_FLAGS, STATX_MASK) {
+                return results.into_iter().map(|result| result.map(|stx| from_statx(&stx))).collect();
+            }
+        }
+        names.iter().map(|name| stat_at(dir, name)).collect()
+    }
+
+    #[cfg(not(feature = "io-uring"))]
+    fn stat_all(dir: &OwnedFd, names: &[CString]) -> Vec<io::Result<Stat>> {
+        names.iter().map(|name| stat_at(dir, name)).collect()
+    }
+
+    // Stat full paths in one io_uring batch, None when io_uring is unavailable
+    #[cfg(feature = "io-uring")]
+    pub fn stat_paths(paths: &crate::arena::PathArena, follow: bool) -> Option<Vec<io::Result<Stat>>> {
+        // The arena already holds the paths NUL-terminated
+        let names: Vec<&CStr> = (0..paths.len()).map(|i| paths.c_str(i)).collect::<Option<_>>()?;
+        let flags = if follow { libc::AT_STATX_SYNC_AS_STAT } else { STATX_FLAGS };
+        let results = crate::uring::statx_many(libc::AT_FDCWD, &names, flags, STATX_MASK)?;
+        Some(results.into_iter().map(|result| result.map(|stx| from_statx(&stx))).collect())
+    }
+
+    fn from_statx(stx: &libc::statx) -> Stat {
+        let time = |t: libc::statx_timestamp| Some(Timestamp { sec: t.tv_sec, nsec: t.tv_nsec });
+        Stat {
+            file_type: mode_type(stx.stx_mode as u32),
+            times: FileTimes {
+                btime: (stx.stx_mask & libc::STATX_BTIME != 0).then(|| time(stx.stx_btime)).flatten(),
+                mtime: time(stx.stx_mtime),
+                atime: time(stx.stx_atime),
+                ctime: time(stx.stx_ctime),
+            },
+            size: stx.stx_size,
+            id: Some((libc::makedev(stx.stx_dev_major, stx.stx_dev_minor), stx.stx_ino)),
+        }
+    }
+
+    // Stat an entry relative to its directory, so the kernel does not walk the full path again
+    fn stat_at(dir: &OwnedFd, name: &CStr) -> io::Result<Stat> {
+        if !NO_STATX.load(Ordering::Relaxed) {
+            let mut stx: libc::statx = unsafe { std::mem::zeroed() };
+            let result = unsafe {
+                libc::syscall(
+                    libc::SYS_statx,
+                    dir.as_raw_fd(),
+                    name.as_ptr(),
+                    STATX_FLAGS,
+                    STATX_MASK,
+                    &mut stx as *mut libc::statx,
+                )
+            };
+            if result == 0 {
+                return Ok(from_statx(&stx));
+            }
+
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::ENOSYS) {
+                return Err(err);
+            }
+            NO_STATX.store(true, Ordering::Relaxed);
+        }
+
+        let mut st: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstatat(dir.as_raw_fd(), name.as_ptr(), &mut st, libc::AT_SYMLINK_NOFOLLOW) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let time = |sec: i64, nsec: i64| Some(Timestamp { sec, nsec: nsec as u32 });
+        Ok(Stat {
+            file_type: mode_type(st.st_mode),
+            times: FileTimes {
+                btime: None,
+                mtime: time(st.st_mtime, st.st_mtime_nsec),
+                atime: time(st.st_atime, st.st_atime_nsec),
+                ctime: time(st.st_ctime, st.st_ctime_nsec),
+            },
+            size: st.st_size as u64,
+            id: Some((st.st_dev, st.st_ino)),
+        })
+    }
+
+    fn mode_type(mode: u32) -> EntryType {
+        match mode & libc::S_IFMT {
+            libc::S_IFREG => EntryType::File,
+            libc::S_IFDIR => EntryType::Dir,
+            libc::S_IFLNK => EntryType::Symlink,
+            _ => EntryType::Other,
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::ffi::{CString, OsStr};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use super::{DirEntry, Stat};
+    use crate::times::{FileTimes, Timestamp};
+    use crate::walk::EntryType;
+
+    const VREG: u32 = 1;
+    const VDIR: u32 = 2;
+    const VLNK: u32 = 5;
+
+    pub fn read_dir(dir: &Path) -> io::Result<Vec<DirEntry>> {
+        let c_dir = CString::new(dir.as_os_str().as_bytes())?;
+        let fd = unsafe { libc::open(c_dir.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut attr_list: libc::attrlist = unsafe { std::mem::zeroed() };
+        attr_list.bitmapcount = libc::ATTR_BIT_MAP_COUNT;
+        attr_list.commonattr = libc::ATTR_CMN_RETURNED_ATTRS
+            | libc::ATTR_CMN_NAME
+            | libc::ATTR_CMN_DEVID
+            | libc::ATTR_CMN_OBJTYPE
+            | libc::ATTR_CMN_CRTIME
+            | libc::ATTR_CMN_MODTIME
+            | libc::ATTR_CMN_CHGTIME
+            | libc::ATTR_CMN_ACCTIME
+            | libc::ATTR_CMN_FILEID;
+        attr_list.fileattr = libc::ATTR_FILE_DATALENGTH;
+
+        let mut entries = Vec::new();
+        let mut buf = vec![0u8; 256 * 1024];
+        loop {
+            let count = unsafe {
+                libc::getattrlistbulk(
+                    fd.as_raw_fd(),
+                    &mut attr_list as *mut libc::attrlist as *mut libc::c_void,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    libc::FSOPT_PACK_INVAL_ATTRS as u64,
+                )
+            };
+            if count < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if count == 0 {
+                break;
+            }
+
+            let mut offset = 0;
+            for _ in 0..count {
+                let (entry, length) = parse_entry(&buf[offset..]);
+                entries.push(entry);
+                offset += length;
+            }
+        }
+
+        Ok(entries)
+    }
+
+    // Unpack one record. With FSOPT_PACK_INVAL_ATTRS every requested attribute
+    // is present in bit order, the returned set says which ones are valid
+    fn parse_entry(record: &[u8]) -> (DirEntry, usize) {
+        let read_u32 = |at: usize| u32::from_ne_bytes(record[at..at + 4].try_into().unwrap());
+        let read_i64 = |at: usize| i64::from_ne_bytes(record[at..at + 8].try_into().unwrap());
+        let read_time = |at: usize| Timestamp { sec: read_i64(at), nsec: read_i64(at + 8) as u32 };
+
+        let length = read_u32(0) as usize;
+        let returned = read_u32(4);
+        let returned_file = read_u32(16);
+
+        // attribute_set_t is five u32 groups, the name is an attrreference_t
+        let name_ref = 24;
+        let name_offset = name_ref + read_u32(name_ref) as i32 as usize;
+        let name_length = read_u32(name_ref + 4) as usize;
+        let name = &record[name_offset..name_offset + name_length.saturating_sub(1)];
+
+        let dev = read_u32(32) as i32 as u64;
+        let obj_type = read_u32(36);
+        let valid = |attr: u32| returned & attr != 0;
+        let time_at = |at: usize, attr: u32| valid(attr).then(|| read_time(at));
+
+        let file_type = match obj_type {
+            VREG => EntryType::File,
+            VDIR => EntryType::Dir,
+            VLNK => EntryType::Symlink,
+            _ => EntryType::Other,
+        };
+        let times = FileTimes {
+            btime: time_at(40, libc::ATTR_CMN_CRTIME),
+            mtime: time_at(56, libc::ATTR_CMN_MODTIME),
+            ctime: time_at(72, libc::ATTR_CMN_CHGTIME),
+            atime: time_at(88, libc::ATTR_CMN_ACCTIME),
+        };
+        let id = valid(libc::ATTR_CMN_FILEID).then(|| (dev, read_i64(104) as u64));
+        // File attributes follow the common ones and are invalid for directories
+        let size = if returned_file & libc::ATTR_FILE_DATALENGTH != 0 { read_i64(112) as u64 } else { 0 };
+
+        let entry = DirEntry {
+            name: OsStr::from_bytes(name).to_os_string(),
+            stat: Ok(Stat { file_type, times, size, id }),
+        };
+        (entry, length)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use std::ffi::OsString;
+    use std::io;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::Path;
+
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Storage::FileSystem::{
+        FindClose, FindExInfoBasic, FindExSearchNameMatch, FindFirstFileExW, FindNextFileW,
+        FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_REPARSE_POINT, FIND_FIRST_EX_LARGE_FETCH, WIN32_FIND_DATAW,
+    };
+
+    use super::{DirEntry, Stat};
+    use crate::times::FileTimes;
+    use crate::walk::EntryType;
+    use crate::win::{filetime_to_timestamp, io_error};
+
+    const ERROR_FILE_NOT_FOUND: i32 = 2;
+    const ERROR_NO_MORE_FILES: i32 = 18;
+    // Reparse tags with this bit (symlinks, junctions) point at another name
+    const NAME_SURROGATE_BIT: u32 = 0x2000_0000;
+    // The tag of junctions and volume mount points
+    const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+
+    struct FindHandle(HANDLE);
+
+    impl Drop for FindHandle {
+        fn drop(&mut self) {
+            let _ = unsafe { FindClose(self.0) };
+        }
+    }
+
+    pub fn read_dir(dir: &Path) -> io::Result<Vec<DirEntry>> {
+        let pattern: Vec<u16> = dir.join("*").as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut data = WIN32_FIND_DATAW::default();
+
+        // Basic info skips the 8.3 short names, large fetch asks for bigger batches per call
+        let handle = unsafe {
+            FindFirstFileExW(
+                PCWSTR(pattern.as_ptr()),
+                FindExInfoBasic,
+                &mut data as *mut WIN32_FIND_DATAW as *mut core::ffi::c_void,
+                FindExSearchNameMatch,
+                None,
+                FIND_FIRST_EX_LARGE_FETCH,
+            )
+        };
+        let handle = match handle.map_err(io_error) {
+            Ok(handle) => FindHandle(handle),
+            Err(err) if err.raw_os_error() == Some(ERROR_FILE_NOT_FOUND) => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut entries = Vec::new();
+        loop {
+            let name_length = data.cFileName.iter().position(|&c| c == 0).unwrap_or(data.cFileName.len());
+            let name = OsString::from_wide(&data.cFileName[..name_length]);
+            if name != "." && name != ".." {
+                entries.push(DirEntry { name, stat: Ok(find_data_stat(&data)) });
+            }
+
+            if let Err(err) = unsafe { FindNextFileW(handle.0, &mut data) } {
+                let err = io_error(err);
+                if err.raw_os_error() == Some(ERROR_NO_MORE_FILES) {
+                    break;
+                }
+                return Err(err);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn find_data_stat(data: &WIN32_FIND_DATAW) -> Stat {
+        let attributes = data.dwFileAttributes;
+        // dwReserved0 holds the reparse tag of reparse points
+        let reparse_tag = if attributes & FILE_ATTRIBUTE_REPARSE_POINT.0 != 0 { data.dwReserved0 } else { 0 };
+        let file_type = if reparse_tag == IO_REPARSE_TAG_MOUNT_POINT {
+            EntryType::Junction
+        } else if reparse_tag & NAME_SURROGATE_BIT != 0 {
+            EntryType::Symlink
+        } else if attributes & FILE_ATTRIBUTE_DIRECTORY.0 != 0 {
+            EntryType::Dir
+        } else {
+            EntryType::File
+        };
+
+        Stat {
+            file_type,
+            times: FileTimes {
+                btime: filetime_to_timestamp(data.ftCreationTime),
+                mtime: filetime_to_timestamp(data.ftLastWriteTime),
+                atime: filetime_to_timestamp(data.ftLastAccessTime),
+                ctime: None,
+            },
+            size: ((data.nFileSizeHigh as u64) << 32) | data.nFileSizeLow as u64,
+            id: None,
+        }
+    }
+}
+
+// Other platforms list with std and stat each entry
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+mod imp {
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    use super::{DirEntry, Stat};
+
+    pub fn read_dir(dir: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let stat = fs::symlink_metadata(entry.path()).map(|metadata| Stat::from_metadata(&metadata));
+            entries.push(DirEntry { name: entry.file_name(), stat });
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,119 @@
+// MS-DOS dates and times as FAT directory entries and ZIP headers store
+// them: two 16-bit words of local time with 2-second steps, from 1980-01-01
+// to 2107-12-31. Local time is given as an offset from UTC in minutes, since
+// the words carry no timezone of their own
+use crate::times::{self, Timestamp};
+
+const MIN_YEAR: i64 = 1980;
+const MAX_YEAR: i64 = 2107;
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// The time a date and time word stand for, None when a field is out of range
+pub fn to_timestamp(date: u16, time: u16, utc_offset_minutes: i32) -> Option<Timestamp> {
+    let year = MIN_YEAR + i64::from(date >> 9);
+    let month = u32::from((date >> 5) & 0x0f);
+    let day = u32::from(date & 0x1f);
+    let (hour, minute, second) = (i64::from(time >> 11), i64::from((time >> 5) & 0x3f), i64::from(time & 0x1f) * 2);
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) || hour > 23 || minute > 59 || second > 58 {
+        return None;
+    }
+    let local = times::days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(Timestamp { sec: local - i64::from(utc_offset_minutes) * 60, nsec: 0 })
+}
+
+// The (date, time) words of a time, rounded down to 2 seconds. Errors for
+// times outside what the words can hold
+pub fn from_timestamp(timestamp: Timestamp, utc_offset_minutes: i32) -> Result<(u16, u16), String> {
+    let out_of_range = || format!("{} is outside the DOS date range 1980-2107", timestamp.to_iso8601());
+    // Times near the ends of the i64 range are out of range in any timezone
+    let local = i64::from(utc_offset_minutes)
+        .checked_mul(60)
+        .and_then(|offset| timestamp.sec.checked_add(offset))
+        .ok_or_else(out_of_range)?;
+    let (year, month, day) = times::civil_from_days(local.div_euclid(86_400));
+    if !(MIN_YEAR..=MAX_YEAR).contains(&year) {
+        return Err(out_of_range());
+    }
+    let seconds = local.rem_euclid(86_400);
+    let date = ((year - MIN_YEAR) as u16) << 9 | (month as u16) << 5 | day as u16;
+    let time = ((seconds / 3600) as u16) << 11 | ((seconds / 60 % 60) as u16) << 5 | (seconds % 60 / 2) as u16;
+    Ok((date, time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1980-01-01 00:00:00 UTC
+    const DOS_EPOCH: i64 = 315_532_800;
+
+    fn words(year: u16, month: u16, day: u16, hour: u16, minute: u16, second: u16) -> (u16, u16) {
+        ((year - 1980) << 9 | month << 5 | day, hour << 11 | minute << 5 | (second / 2))
+    }
+
+    #[test]
+    fn reads_dates_and_times() {
+        let (date, time) = words(1980, 1, 1, 0, 0, 0);
+        assert_eq!(to_timestamp(date, time, 0), Some(Timestamp { sec: DOS_EPOCH, nsec: 0 }));
+        assert_eq!(to_timestamp(date, time, 60), Some(Timestamp { sec: DOS_EPOCH - 3600, nsec: 0 }));
+        let (date, time) = words(2023, 11, 14, 22, 13, 20);
+        assert_eq!(to_timestamp(date, time, 0), Some(Timestamp { sec: 1_700_000_000, nsec: 0 }));
+        let (date, time) = words(2024, 2, 29, 23, 59, 58);
+        assert!(to_timestamp(date, time, 0).is_some());
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        for (date, time) in [
+            words(1981, 2, 29, 0, 0, 0),
+            words(1980, 13, 1, 0, 0, 0),
+            words(1980, 0, 1, 0, 0, 0),
+            words(1980, 4, 31, 0, 0, 0),
+            words(1980, 1, 0, 0, 0, 0),
+            words(1980, 1, 1, 24, 0, 0),
+            words(1980, 1, 1, 0, 60, 0),
+        ] {
+            assert_eq!(to_timestamp(date, time, 0), None, "{:x} {:x}", date, time);
+        }
+        assert_eq!(to_timestamp(0x21, 30, 0), None);
+    }
+
+    #[test]
+    fn writes_dates_and_times() {
+        assert_eq!(from_timestamp(Timestamp { sec: 1_700_000_000, nsec: 5 }, 0), Ok(words(2023, 11, 14, 22, 13, 20)));
+        // Odd seconds round down
+        assert_eq!(from_timestamp(Timestamp { sec: 1_700_000_001, nsec: 0 }, 0), Ok(words(2023, 11, 14, 22, 13, 20)));
+        assert_eq!(from_timestamp(Timestamp { sec: 1_700_000_000, nsec: 0 }, -300), Ok(words(2023, 11, 14, 17, 13, 20)));
+        assert_eq!(from_timestamp(Timestamp { sec: DOS_EPOCH, nsec: 0 }, 0), Ok(words(1980, 1, 1, 0, 0, 0)));
+        for offset in [-720, 0, 90, 840] {
+            let timestamp = Timestamp { sec: 1_234_567_890, nsec: 0 };
+            let (date, time) = from_timestamp(timestamp, offset).unwrap();
+            assert_eq!(to_timestamp(date, time, offset), Some(timestamp));
+        }
+    }
+
+    #[test]
+    fn rejects_times_out_of_range() {
+        assert!(from_timestamp(Timestamp { sec: DOS_EPOCH - 1, nsec: 0 }, 0).is_err());
+        assert!(from_timestamp(Timestamp { sec: DOS_EPOCH, nsec: 0 }, -1).is_err());
+        let last = Timestamp { sec: days_after_2107() - 2, nsec: 0 };
+        assert_eq!(from_timestamp(last, 0), Ok(words(2107, 12, 31, 23, 59, 58)));
+        assert!(from_timestamp(Timestamp { sec: days_after_2107(), nsec: 0 }, 0).is_err());
+        assert!(from_timestamp(Timestamp { sec: i64::MAX, nsec: 0 }, 1).is_err());
+        assert!(from_timestamp(Timestamp { sec: i64::MIN, nsec: 0 }, -1).is_err());
+        assert!(from_timestamp(Timestamp { sec: i64::MAX, nsec: 0 }, i32::MAX).is_err());
+    }
+
+    // 2108-01-01 00:00:00 UTC
+    fn days_after_2107() -> i64 {
+        times::days_from_civil(2108, 1, 1) * 86_400
+    }
+}
@@ -0,0 +1,69 @@
+// A backend for Linux, which cannot set birth times, that keeps a birth
+// time written by this library in the `user.btime` extended attribute
+// instead. The attribute holds the nanoseconds since the Unix epoch as
+// decimal text, so `getfattr -n user.btime` shows it and other tools can
+// read it. Reads prefer the attribute over the birth time the filesystem
+// reports, modification and access times are written and read as usual.
+// The time is emulated: it travels with the file only as far as extended
+// attributes do, and copies, archives and other programs ignore it
+use std::ffi::CStr;
+use std::io;
+use std::path::Path;
+
+use crate::backend::{self, BtimeBackend, Capabilities};
+use crate::times::{FileTimes, Timestamp};
+use crate::xattr;
+
+const NAME: &CStr = c"user.btime";
+
+// The birth time kept in the attribute, None when there is none or it does
+// not hold a time
+pub fn stored(path: &Path) -> io::Result<Option<Timestamp>> {
+    let value = match xattr::get(path, NAME) {
+        Ok(value) => value,
+        Err(err) if matches!(err.raw_os_error(), Some(libc::ENODATA) | Some(libc::ENOTSUP)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let nanos = std::str::from_utf8(&value).ok().and_then(|text| text.trim().parse::<i128>().ok());
+    Ok(nanos.and_then(Timestamp::from_nanos))
+}
+
+// Linux keeps user attributes off symlinks, so a link itself has no
+// emulated birth time
+fn is_link(path: &Path, follow: bool) -> io::Result<bool> {
+    Ok(!follow && std::fs::symlink_metadata(path)?.file_type().is_symlink())
+}
+
+pub struct Xattr;
+
+impl BtimeBackend for Xattr {
+    fn name(&self) -> &'static str {
+        "xattr"
+    }
+
+    fn set_times(&self, path: &Path, times: &FileTimes, follow: bool) -> io::Result<()> {
+        if let Some(btime) = times.btime {
+            if is_link(path, follow)? {
+                return Err(io::Error::from_raw_os_error(libc::EPERM));
+            }
+            xattr::set(path, NAME, btime.as_nanos().to_string().as_bytes())?;
+        }
+        backend::Platform.set_times(path, &FileTimes { btime: None, ..*times }, follow)
+    }
+
+    fn get_times(&self, path: &Path, follow: bool) -> io::Result<FileTimes> {
+        let times = backend::Platform.get_times(path, follow)?;
+        if is_link(path, follow)? {
+            return Ok(times);
+        }
+        Ok(FileTimes { btime: stored(path)?.or(times.btime), ..times })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { set_btime: true, get_btime: true, emulated: true }
+    }
+
+    fn keeps_btime(&self, path: &Path, follow: bool) -> bool {
+        !is_link(path, follow).unwrap_or(true) && stored(path).is_ok_and(|stored| stored.is_some())
+    }
+}
@@ -0,0 +1,61 @@
+// Injected failures for testing how callers handle errors. A rule makes
+// matching calls fail with a chosen OS error code before the call is made,
+// so nothing on disk changes. Rule matching is skipped entirely while no
+// rules are installed
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use crate::glob::Glob;
+use crate::stats::Op;
+
+pub struct Rule {
+    // Any operation when None
+    pub op: Option<Op>,
+    // Matched like an exclude pattern against the whole path, any path when None
+    pub path: Option<Glob>,
+    // Fail every nth matching call, starting with the nth
+    pub every: u64,
+    pub errno: i32,
+    pub calls: AtomicU64,
+}
+
+static RULES: RwLock<Vec<Rule>> = RwLock::new(Vec::new());
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+pub fn install(rules: Vec<Rule>) {
+    let mut installed = RULES.write().unwrap();
+    ACTIVE.store(!rules.is_empty(), Ordering::Relaxed);
+    *installed = rules;
+}
+
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+// The error the first matching rule injects into this call, if any. Every
+// matching rule counts the call
+pub fn check(op: Op, path: &Path) -> io::Result<()> {
+    if !active() {
+        return Ok(());
+    }
+    let subject = path.to_string_lossy().replace('\\', "/");
+    let subject = subject.trim_start_matches('/');
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+
+    let mut injected = None;
+    for rule in RULES.read().unwrap().iter() {
+        if rule.op.is_some_and(|rule_op| rule_op != op) || rule.path.as_ref().is_some_and(|glob| !glob.matches(subject, &name)) {
+            continue;
+        }
+        let call = rule.calls.fetch_add(1, Ordering::Relaxed) + 1;
+        if injected.is_none() && call % rule.every == 0 {
+            injected = Some(rule.errno);
+        }
+    }
+    match injected {
+        Some(errno) => Err(io::Error::from_raw_os_error(errno)),
+        None => Ok(()),
+    }
+}
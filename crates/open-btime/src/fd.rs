@@ -0,0 +1,83 @@
+// Timestamps of files open in Node, addressed by file descriptor instead of
+// by path. A descriptor keeps naming the same file when it is renamed or a
+// directory above it is replaced, so there is no window in which another
+// file could be written, and within the macOS sandbox it works for files
+// the process may no longer look up by path. These calls go to the OS
+// directly rather than through the installed backend, which takes paths
+use std::io;
+
+use crate::times::FileTimes;
+
+#[cfg(unix)]
+fn borrow(fd: i32) -> std::mem::ManuallyDrop<std::fs::File> {
+    use std::os::fd::FromRawFd;
+
+    // Node owns the descriptor and closes it, not the File
+    std::mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+#[cfg(unix)]
+pub fn get_times(fd: i32) -> io::Result<FileTimes> {
+    Ok(FileTimes::from_metadata(&borrow(fd).metadata()?))
+}
+
+// Set the times of the open file with fsetattrlist. Fields that are None
+// are left unchanged
+#[cfg(target_os = "macos")]
+pub fn set_times(fd: i32, times: &FileTimes) -> io::Result<()> {
+    use crate::backend::attrlist;
+
+    let Some((attr_list, attr_buf)) = attrlist::pack(times) else {
+        return Ok(());
+    };
+    let result = unsafe {
+        attrlist::fsetattrlist(
+            fd,
+            &attr_list,
+            attr_buf.as_ptr() as *const libc::c_void,
+            std::mem::size_of::<attrlist::Timespec>() * attr_buf.len(),
+            0,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// futimens, which like the path write leaves the birth time alone as Linux
+// cannot set it
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn set_times(fd: i32, times: &FileTimes) -> io::Result<()> {
+    use crate::times::Timestamp;
+
+    if times.mtime.is_none() && times.atime.is_none() {
+        return Ok(());
+    }
+    let timespec = |t: Option<Timestamp>| match t {
+        Some(t) => libc::timespec { tv_sec: t.sec as libc::time_t, tv_nsec: t.nsec as _ },
+        None => libc::timespec { tv_sec: 0, tv_nsec: libc::UTIME_OMIT },
+    };
+    let timespecs = [timespec(times.atime), timespec(times.mtime)];
+    if unsafe { libc::futimens(fd, timespecs.as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Node's descriptors on Windows belong to libuv's C runtime, whose handle
+// table an addon cannot reach
+#[cfg(not(unix))]
+pub fn get_times(_fd: i32) -> io::Result<FileTimes> {
+    Err(unsupported())
+}
+
+#[cfg(not(unix))]
+pub fn set_times(_fd: i32, _times: &FileTimes) -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(not(unix))]
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "file descriptors are not supported on this platform, pass the path")
+}
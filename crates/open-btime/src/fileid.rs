@@ -0,0 +1,49 @@
+// Files opened by their NTFS or ReFS file ID instead of a path, for scanners
+// that read the MFT or the change journal and never resolve names
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::mem;
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::{AsRawHandle, FromRawHandle, RawHandle};
+use std::path::Path;
+
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Storage::FileSystem::{
+    ExtendedFileIdType, FileIdType, OpenFileById, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT,
+    FILE_ID_128, FILE_ID_DESCRIPTOR, FILE_ID_DESCRIPTOR_0, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+    FILE_WRITE_ATTRIBUTES,
+};
+
+use crate::times::FileTimes;
+use crate::win;
+
+// IDs that fit in 64 bits are FileReferenceNumbers as NTFS reports them,
+// wider ones are the 128-bit IDs of ReFS
+fn descriptor(id: u128) -> FILE_ID_DESCRIPTOR {
+    let (id_type, anonymous) = match u64::try_from(id) {
+        Ok(id) => (FileIdType, FILE_ID_DESCRIPTOR_0 { FileId: id as i64 }),
+        Err(_) => (ExtendedFileIdType, FILE_ID_DESCRIPTOR_0 { ExtendedFileId: FILE_ID_128 { Identifier: id.to_le_bytes() } }),
+    };
+    FILE_ID_DESCRIPTOR { dwSize: mem::size_of::<FILE_ID_DESCRIPTOR>() as u32, Type: id_type, Anonymous: anonymous }
+}
+
+// Set the timestamps of the file with this ID on the volume that `volume`,
+// any path on it, belongs to. Fields that are None are left unchanged and a
+// reparse point is stamped itself
+pub fn set_times(volume: &Path, id: u128, times: &FileTimes) -> io::Result<()> {
+    // The hint only tells OpenFileById which volume is meant
+    let hint = OpenOptions::new().access_mode(0).custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0).open(volume)?;
+    let handle = unsafe {
+        OpenFileById(
+            HANDLE(hint.as_raw_handle() as isize),
+            &descriptor(id),
+            FILE_WRITE_ATTRIBUTES.0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            None,
+            FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT,
+        )
+    }
+    .map_err(win::io_error)?;
+    let file = unsafe { File::from_raw_handle(handle.0 as RawHandle) };
+    win::set_file_times(&file, times)
+}
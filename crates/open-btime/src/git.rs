@@ -0,0 +1,98 @@
+// First-commit times of the files in a git work tree, read with gitoxide
+use std::collections::HashMap;
+use std::path::Path;
+
+use gix::bstr::{BString, ByteSlice};
+use gix::{ObjectId, Repository};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+struct TreeEntry {
+    name: BString,
+    is_tree: bool,
+    id: ObjectId,
+}
+
+fn tree_entries(repo: &Repository, id: ObjectId) -> Result<Vec<TreeEntry>, Error> {
+    let tree = repo.find_tree(id)?;
+    let decoded = tree.decode()?;
+    Ok(decoded
+        .entries
+        .iter()
+        // Submodules are checked out as directories with their own history
+        .filter(|entry| !entry.mode.is_commit())
+        .map(|entry| TreeEntry {
+            name: entry.filename.to_owned(),
+            is_tree: entry.mode.is_tree(),
+            id: entry.oid.to_owned(),
+        })
+        .collect())
+}
+
+// Call added with the /-separated path of every file in tree `new` that is not
+// at the same place in tree `old`. Subtrees with equal ids are skipped, so
+// comparing a commit with its parent only reads what changed
+fn added_files(
+    repo: &Repository,
+    old: Option<ObjectId>,
+    new: ObjectId,
+    prefix: &str,
+    added: &mut dyn FnMut(String),
+) -> Result<(), Error> {
+    if old == Some(new) {
+        return Ok(());
+    }
+    let old_entries: HashMap<BString, (bool, ObjectId)> = match old {
+        Some(old) => tree_entries(repo, old)?.into_iter().map(|e| (e.name, (e.is_tree, e.id))).collect(),
+        None => HashMap::new(),
+    };
+
+    for entry in tree_entries(repo, new)? {
+        let path = format!("{}{}", prefix, entry.name.to_str_lossy());
+        let previous = old_entries.get(&entry.name);
+        if entry.is_tree {
+            let old_tree = previous.filter(|(is_tree, _)| *is_tree).map(|(_, id)| *id);
+            added_files(repo, old_tree, entry.id, &format!("{}/", path), added)?;
+        } else if !matches!(previous, Some((false, _))) {
+            added(path);
+        }
+    }
+    Ok(())
+}
+
+fn first_commit_times_of(repo: &Repository) -> Result<Vec<(String, i64)>, Error> {
+    let head = repo.head_commit()?;
+
+    // Only files in the checked out commit matter
+    let mut times: HashMap<String, i64> = HashMap::new();
+    added_files(repo, None, head.tree_id()?.detach(), "", &mut |path| {
+        times.insert(path, i64::MAX);
+    })?;
+
+    // A file can be added more than once (deleted and re-added, or on two
+    // branches), the earliest commit adding it wins
+    for info in repo.rev_walk([head.id]).all()? {
+        let commit = info?.object()?;
+        let seconds = commit.time()?.seconds;
+        let parent_tree = match commit.parent_ids().next() {
+            Some(parent) => Some(repo.find_commit(parent)?.tree_id()?.detach()),
+            None => None,
+        };
+        added_files(repo, parent_tree, commit.tree_id()?.detach(), "", &mut |path| {
+            if let Some(time) = times.get_mut(&path) {
+                *time = (*time).min(seconds);
+            }
+        })?;
+    }
+
+    let mut times: Vec<_> = times.into_iter().filter(|(_, time)| *time != i64::MAX).collect();
+    times.sort();
+    Ok(times)
+}
+
+// (path relative to the work tree, committer time in seconds) of the commit
+// that first added each file of HEAD
+pub fn first_commit_times(repo_path: &Path) -> Result<Vec<(String, i64)>, String> {
+    let repo = gix::open(repo_path).map_err(|err| format!("Not a git repository {}: {}", repo_path.display(), err))?;
+    first_commit_times_of(&repo).map_err(|err| format!("Reading git history of {} failed: {}", repo_path.display(), err))
+}
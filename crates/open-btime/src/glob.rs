@@ -0,0 +1,157 @@
+// Minimal glob patterns for excluding paths from recursive operations.
+//
+// `*` matches within one path component, `**` across components, `?` one
+// character and `[abc]`/`[a-z]`/`[!a]` a character class. Patterns without a
+// `/` match an entry's name at any depth (".git", "*.tmp"); patterns with a `/`
+// match the path relative to the walk root ("build/**", "src/*.bak").
+#[derive(Clone, Debug)]
+pub struct Glob {
+    pattern: Vec<char>,
+    match_name: bool,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, String> {
+        let pattern = pattern.replace('\\', "/");
+        let trimmed = pattern.trim_start_matches('/').trim_end_matches('/');
+        if trimmed.is_empty() {
+            return Err(format!("invalid exclude pattern: {:?}", pattern));
+        }
+
+        let chars: Vec<char> = trimmed.chars().collect();
+        if let Some(open) = chars.iter().position(|&c| c == '[') {
+            if !chars[open..].contains(&']') {
+                return Err(format!("unterminated character class in pattern: {:?}", pattern));
+            }
+        }
+
+        Ok(Glob {
+            match_name: !pattern.contains('/'),
+            pattern: chars,
+        })
+    }
+
+    // relative is the /-separated path below the walk root, name its last component
+    pub fn matches(&self, relative: &str, name: &str) -> bool {
+        let subject: Vec<char> = if self.match_name { name.chars() } else { relative.chars() }.collect();
+        match_here(&self.pattern, &subject)
+    }
+}
+
+fn match_here(pattern: &[char], subject: &[char]) -> bool {
+    match pattern.first() {
+        None => subject.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            // "**/" also matches zero directories
+            let rest = &pattern[2..];
+            let rest_after_slash = rest.strip_prefix(&['/']).unwrap_or(rest);
+            if match_here(rest_after_slash, subject) {
+                return true;
+            }
+            (0..subject.len()).any(|i| match_here(rest, &subject[i + 1..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for i in 0..=subject.len() {
+                if match_here(rest, &subject[i..]) {
+                    return true;
+                }
+                if subject.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => matches!(subject.first(), Some(c) if *c != '/') && match_here(&pattern[1..], &subject[1..]),
+        Some('[') => {
+            let Some(&c) = subject.first() else {
+                return false;
+            };
+            match match_class(&pattern[1..], c) {
+                Some((true, rest)) => match_here(rest, &subject[1..]),
+                _ => false,
+            }
+        }
+        Some(&p) => subject.first() == Some(&p) && match_here(&pattern[1..], &subject[1..]),
+    }
+}
+
+// Match c against the class starting after '[', returning whether it matched
+// and the pattern following the closing ']'
+fn match_class(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, mut i) = match pattern.first() {
+        Some('!') | Some('^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut matched = false;
+    let mut first = true;
+
+    while i < pattern.len() {
+        let start = pattern[i];
+        if start == ']' && !first {
+            return Some((matched != negated && c != '/', &pattern[i + 1..]));
+        }
+        first = false;
+
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&end| end != ']') {
+            matched |= start <= c && c <= pattern[i + 2];
+            i += 3;
+        } else {
+            matched |= start == c;
+            i += 1;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, relative: &str) -> bool {
+        let name = relative.rsplit('/').next().unwrap();
+        Glob::new(pattern).unwrap().matches(relative, name)
+    }
+
+    #[test]
+    fn matches_names_at_any_depth() {
+        assert!(matches(".git", ".git"));
+        assert!(matches(".git", "a/b/.git"));
+        assert!(matches("*.tmp", "a/b.tmp"));
+        assert!(!matches("*.tmp", "a.tmp/b"));
+        assert!(matches("?.md", "docs/a.md"));
+        assert!(!matches("?.md", "docs/ab.md"));
+    }
+
+    #[test]
+    fn matches_relative_paths() {
+        assert!(matches("build/**", "build/x/y.o"));
+        assert!(!matches("build/**", "src/build/x"));
+        assert!(matches("**/cache", "cache"));
+        assert!(matches("**/cache", "a/b/cache"));
+        assert!(matches("src/*.bak", "src/a.bak"));
+        assert!(!matches("src/*.bak", "src/a/b.bak"));
+        // Leading and trailing separators are dropped, and \ is one too
+        assert!(matches("\\src\\*.bak\\", "src/a.bak"));
+    }
+
+    #[test]
+    fn matches_classes() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(!matches("[abc].txt", "d.txt"));
+        assert!(matches("[a-c]1", "c1"));
+        assert!(matches("[!a-c]1", "d1"));
+        assert!(!matches("[!a-c]1", "a1"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(!matches("x[!a]y", "x/y"));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(Glob::new("").is_err());
+        assert!(Glob::new("//").is_err());
+        assert!(Glob::new("[abc").is_err());
+    }
+}
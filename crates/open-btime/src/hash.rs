@@ -0,0 +1,40 @@
+// Content hashes recorded in snapshots, by which restores recognize files
+// that were moved or renamed since. XXH3 is fast enough to hash whole trees
+// and is not meant to resist deliberate collisions
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use xxhash_rust::xxh3::Xxh3;
+
+const PREFIX: &str = "xxh3:";
+
+pub fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        hasher.update(&buf[..read]);
+    }
+    Ok(hasher.digest())
+}
+
+// As stored in manifests, "xxh3:" and 16 hex digits
+pub fn format(hash: u64) -> String {
+    format!("{}{:016x}", PREFIX, hash)
+}
+
+// A hash of another algorithm is not one this version can compare
+pub fn parse(text: &str) -> Option<u64> {
+    let hex = text.strip_prefix(PREFIX)?;
+    if hex.len() != 16 {
+        return None;
+    }
+    u64::from_str_radix(hex, 16).ok()
+}
@@ -0,0 +1,47 @@
+// iCloud Drive placeholders. An item evicted from the disk is replaced in
+// its directory by a small `.NAME.icloud` file until it is downloaded
+// again, so the path NAME does not exist in the meantime
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, Placeholders};
+
+// NAME for the placeholder file name `.NAME.icloud`
+pub fn item_name(name: &OsStr) -> Option<&OsStr> {
+    let inner = name.as_bytes().strip_prefix(b".")?.strip_suffix(b".icloud")?;
+    (!inner.is_empty()).then(|| OsStr::from_bytes(inner))
+}
+
+// The path of the item a placeholder stands in for
+pub fn item_path(placeholder: &Path) -> Option<PathBuf> {
+    let name = item_name(placeholder.file_name()?)?;
+    Some(placeholder.with_file_name(name))
+}
+
+// The path of the placeholder standing in for an item
+pub fn placeholder_path(item: &Path) -> Option<PathBuf> {
+    let mut name = OsString::from(".");
+    name.push(item.file_name()?);
+    name.push(".icloud");
+    Some(item.with_file_name(name))
+}
+
+pub fn is_placeholder(path: &Path) -> bool {
+    path.file_name().and_then(item_name).is_some()
+}
+
+// Whether an item is missing because iCloud evicted it
+pub fn is_evicted(item: &Path) -> bool {
+    placeholder_path(item).is_some_and(|placeholder| placeholder.symlink_metadata().is_ok_and(|m| m.is_file()))
+}
+
+// The outcome of a write to an evicted item or to its placeholder, which
+// only a download brings back: skipped or reported with a distinct error
+pub fn evicted() -> io::Result<()> {
+    match config::get().placeholders {
+        Placeholders::Skip => Ok(()),
+        _ => Err(io::Error::other("iCloud item is not downloaded")),
+    }
+}
@@ -1,175 +1,3549 @@
 use neon::prelude::*;
 use neon::types::buffer::TypedArray;
+use neon::types::{JsBigInt, JsDate};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+mod access;
+mod arena;
+mod atomic;
+mod backend;
+mod batch;
+mod beneath;
+mod checkpoint;
+mod checksum;
+mod clone;
+mod compress;
+mod config;
+mod copy;
+mod copytree;
+mod dir;
+mod dos;
+#[cfg(target_os = "linux")]
+mod emulate;
+#[cfg(feature = "git")]
+mod git;
+mod fault;
+mod fd;
+#[cfg(target_os = "windows")]
+mod fileid;
+mod glob;
+mod hash;
+#[cfg(target_os = "macos")]
+mod icloud;
+mod json;
+mod links;
+mod mirror;
+mod mock;
+mod mounts;
+mod oserror;
+mod path;
+mod predicate;
+mod pool;
+#[cfg(target_os = "windows")]
+mod privilege;
+mod probecache;
+mod progress;
+mod queue;
+mod ratelimit;
+#[cfg(feature = "sqlite")]
+mod sidecar;
+mod simulate;
+mod snapshot;
+mod stat;
+mod stats;
+#[cfg(target_os = "windows")]
+mod streams;
+mod teardown;
+#[cfg(target_os = "macos")]
+mod spotlight;
+mod timeout;
+mod times;
+mod transaction;
+mod tree;
+mod undo;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+#[cfg(target_os = "windows")]
+mod usn;
+mod volume;
+mod walk;
+#[cfg(target_os = "windows")]
+mod win;
+mod workers;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod xattr;
+
+use arena::PathArena;
+use times::{FileTimes, Timestamp};
+use predicate::{Predicate, TimeField};
+use walk::{EntryType, Filter, FollowSymlinks, WalkOptions, Walker};
+
+// Extract a path argument, accepting a null-terminated Buffer, a string or a file: URL object
+fn path_argument(cx: &mut FunctionContext, i: usize) -> NeonResult<PathBuf> {
+    let value = cx.argument::<JsValue>(i)?;
+    path_value(cx, value)
+}
+
+fn path_value<'a>(cx: &mut impl Context<'a>, value: Handle<'a, JsValue>) -> NeonResult<PathBuf> {
+    if let Ok(path_buffer) = value.downcast::<JsBuffer, _>(cx) {
+        let path_bytes = path_buffer.as_slice(cx);
+
+        // Find the null terminator
+        let null_pos = path_bytes.iter().position(|&b| b == 0)
+            .unwrap_or(path_bytes.len());
+
+        return match buffer_path(&path_bytes[0..null_pos]) {
+            Some(path) => Ok(path.to_path_buf()),
+            None => cx.throw_error("Invalid UTF-8 in path"),
+        };
+    }
+
+    if let Ok(path_string) = value.downcast::<JsString, _>(cx) {
+        return Ok(PathBuf::from(path_string.value(cx)));
+    }
+
+    // URL objects are recognised by their href, like Node's fs APIs do
+    if let Ok(url) = value.downcast::<JsObject, _>(cx) {
+        if let Some(href) = url.get_opt::<JsString, _, _>(cx, "href")? {
+            let href = href.value(cx);
+            return match path::file_url_to_path(&href) {
+                Ok(p) => Ok(p),
+                Err(err) => cx.throw_type_error(err.to_string()),
+            };
+        }
+    }
+
+    cx.throw_type_error("path must be a Buffer, string or file: URL")
+}
+
+// Add a path of a batch call to an arena, resolved against cwd. Strings are
+// decoded into the arena's buffer through Node-API rather than into a String
+// each, and Buffers copied from where they are
+fn push_path_value<'a>(
+    cx: &mut impl Context<'a>,
+    arena: &mut PathArena,
+    value: Handle<'a, JsValue>,
+    cwd: Option<&Path>,
+) -> NeonResult<()> {
+    if let Ok(string) = value.downcast::<JsString, _>(cx) {
+        let capacity = string.size(cx) + 1;
+        let (env, raw) = (cx.to_raw(), string.to_raw());
+        arena.push_decoded(capacity, cwd, |buffer| {
+            let mut written = 0;
+            let status = unsafe {
+                neon::sys::bindings::get_value_string_utf8(env, raw, buffer.as_mut_ptr().cast(), buffer.len(), &mut written)
+            };
+            if status == neon::sys::bindings::Status::Ok { written } else { 0 }
+        });
+        return Ok(());
+    }
+    if let Ok(path_buffer) = value.downcast::<JsBuffer, _>(cx) {
+        let path_bytes = path_buffer.as_slice(cx);
+        let null_pos = path_bytes.iter().position(|&b| b == 0).unwrap_or(path_bytes.len());
+        return match buffer_path(&path_bytes[0..null_pos]) {
+            Some(path) => {
+                arena.push(path, cwd);
+                Ok(())
+            }
+            None => cx.throw_error("Invalid UTF-8 in path"),
+        };
+    }
+    let path = path_value(cx, value)?;
+    arena.push(&path, cwd);
+    Ok(())
+}
+
+// Unix paths are byte strings and are taken as they are, so names in legacy
+// encodings can be addressed
+#[cfg(not(target_os = "windows"))]
+fn buffer_path(bytes: &[u8]) -> Option<&Path> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(Path::new(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+// Windows paths are UTF-16, a Buffer has to hold them as UTF-8
+#[cfg(target_os = "windows")]
+fn buffer_path(bytes: &[u8]) -> Option<&Path> {
+    std::str::from_utf8(bytes).ok().map(Path::new)
+}
+
+// Extract the path argument of a single-path call whose options may mark a
+// Buffer path as UTF-16LE with pathEncoding, for callers that hold wide
+// strings from Win32 APIs
+fn encoded_path_argument<'a>(
+    cx: &mut FunctionContext<'a>,
+    i: usize,
+    options: Option<Handle<'a, JsObject>>,
+) -> NeonResult<PathBuf> {
+    let encoding = match options {
+        Some(options) => options.get_opt::<JsString, _, _>(cx, "pathEncoding")?.map(|encoding| encoding.value(cx)),
+        None => None,
+    };
+    match encoding.as_deref() {
+        None | Some("utf8") => path_argument(cx, i),
+        Some("utf16le") => {
+            let Ok(buffer) = cx.argument::<JsValue>(i)?.downcast::<JsBuffer, _>(cx) else {
+                return cx.throw_type_error("pathEncoding 'utf16le' needs the path as a Buffer");
+            };
+            let bytes = buffer.as_slice(cx);
+            if bytes.len() % 2 != 0 {
+                return cx.throw_error("UTF-16LE path has an odd number of bytes");
+            }
+            // Up to the null terminator, if any
+            let units: Vec<u16> =
+                bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).take_while(|&unit| unit != 0).collect();
+            match wide_path(&units) {
+                Some(path) => Ok(path),
+                None => cx.throw_error("Invalid UTF-16 in path"),
+            }
+        }
+        Some(other) => cx.throw_type_error(format!("pathEncoding must be 'utf8' or 'utf16le', got {:?}", other)),
+    }
+}
+
+// Windows takes the units as they are, unpaired surrogates included
+#[cfg(target_os = "windows")]
+fn wide_path(units: &[u16]) -> Option<PathBuf> {
+    use std::os::windows::ffi::OsStringExt;
+    Some(PathBuf::from(std::ffi::OsString::from_wide(units)))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn wide_path(units: &[u16]) -> Option<PathBuf> {
+    String::from_utf16(units).ok().map(PathBuf::from)
+}
+
+// Get the optional options object passed at position i
+fn options_argument<'a>(cx: &mut FunctionContext<'a>, i: usize) -> NeonResult<Option<Handle<'a, JsObject>>> {
+    match cx.argument_opt(i) {
+        Some(value) if !value.is_a::<JsUndefined, _>(cx) && !value.is_a::<JsNull, _>(cx) => {
+            Ok(Some(value.downcast_or_throw(cx)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Read the `cwd` option that relative paths are resolved against
+fn cwd_option<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>) -> NeonResult<Option<PathBuf>> {
+    let Some(options) = options else {
+        return Ok(None);
+    };
+    match options.get_opt::<JsValue, _, _>(cx, "cwd")? {
+        Some(value) if !value.is_a::<JsUndefined, _>(cx) => Ok(Some(path_value(cx, value)?)),
+        _ => Ok(None),
+    }
+}
+
+// Read the `backup` option, which asks for writes with backup privileges
+fn backup_option<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>) -> NeonResult<bool> {
+    let Some(options) = options else {
+        return Ok(config::get().backup);
+    };
+    match options.get_opt::<JsBoolean, _, _>(cx, "backup")? {
+        Some(backup) => Ok(backup.value(cx)),
+        None => Ok(config::get().backup),
+    }
+}
+
+// Run a timestamp write with SeBackupPrivilege enabled when backup is set.
+// Other platforms have no equivalent and run it as is
+#[cfg(target_os = "windows")]
+fn with_backup_mode<T>(backup: bool, f: impl FnOnce() -> std::io::Result<T>) -> std::io::Result<T> {
+    if backup {
+        privilege::with_backup_privileges(f)
+    } else {
+        f()
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn with_backup_mode<T>(_backup: bool, f: impl FnOnce() -> std::io::Result<T>) -> std::io::Result<T> {
+    f()
+}
+
+// Why a write failed with access denied, if the privilege state explains it
+#[cfg(target_os = "windows")]
+fn access_denied_hint(err: &std::io::Error) -> Option<&'static str> {
+    privilege::access_denied_hint(err)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn access_denied_hint(_err: &std::io::Error) -> Option<&'static str> {
+    None
+}
+
+// The message thrown for a failed OS call: "(errno) call: what went wrong",
+// e.g. "(5) utimes(C:\x): Access is denied"
+fn os_error_message(err: &std::io::Error, call: impl std::fmt::Display) -> String {
+    format!("({}) {}: {}", err.raw_os_error().unwrap_or(-1), call, oserror::describe(err))
+}
+
+// Give an error object the errno and the stable code of an OS error, e.g.
+// { errno: 32, code: 'EBUSY' } for a sharing violation on Windows
+fn set_error_code<'a>(cx: &mut impl Context<'a>, object: Handle<'a, impl Object>, err: &std::io::Error) -> NeonResult<()> {
+    let errno = cx.number(err.raw_os_error().unwrap_or(-1));
+    let code = cx.string(oserror::code(err));
+    object.set(cx, "errno", errno)?;
+    object.set(cx, "code", code)?;
+    Ok(())
+}
+
+// Throw an Error for a failed OS call, with errno and code
+fn throw_os_error<'a, T>(cx: &mut impl Context<'a>, err: &std::io::Error, message: String) -> NeonResult<T> {
+    let error = cx.error(message)?;
+    set_error_code(cx, error, err)?;
+    cx.throw(error)
+}
+
+// An error on its way to JS from code that cannot throw itself, such as
+// work on another thread. Failed OS calls keep their error for the errno
+// and code of the thrown Error
+struct Failure {
+    message: String,
+    error: Option<std::io::Error>,
+}
+
+impl Failure {
+    fn os(message: String, error: std::io::Error) -> Failure {
+        Failure { message, error: Some(error) }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Failure {
+        Failure { message, error: None }
+    }
+}
+
+fn failure_to_js<'a>(cx: &mut impl Context<'a>, failure: &Failure) -> JsResult<'a, JsError> {
+    let error = cx.error(&failure.message)?;
+    if let Some(err) = &failure.error {
+        set_error_code(cx, error, err)?;
+    }
+    Ok(error)
+}
+
+fn throw_failure<'a, T>(cx: &mut impl Context<'a>, failure: Failure) -> NeonResult<T> {
+    let error = failure_to_js(cx, &failure)?;
+    cx.throw(error)
+}
+
+// Append the access denied hint to a message, e.g. "(5) utimes(C:\x): Access
+// is denied: the account does not hold SeRestorePrivilege"
+fn with_hint(message: String, err: &std::io::Error) -> String {
+    match access_denied_hint(err) {
+        Some(hint) => format!("{}: {}", message, hint),
+        None => message,
+    }
+}
+
+// The onlyIf option of btime: which new birth times replace the current one.
+// A file without a recorded birth time is always written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OnlyIf {
+    Older,
+    Newer,
+    Different,
+}
+
+impl OnlyIf {
+    fn allows(self, current: Option<Timestamp>, new: Timestamp) -> bool {
+        let Some(current) = current else {
+            return true;
+        };
+        match self {
+            OnlyIf::Older => new < current,
+            OnlyIf::Newer => new > current,
+            OnlyIf::Different => new != current,
+        }
+    }
+}
+
+fn only_if_option<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>) -> NeonResult<Option<OnlyIf>> {
+    let Some(options) = options else {
+        return Ok(None);
+    };
+    let Some(only_if) = options.get_opt::<JsString, _, _>(cx, "onlyIf")? else {
+        return Ok(None);
+    };
+    match only_if.value(cx).as_str() {
+        "older" => Ok(Some(OnlyIf::Older)),
+        "newer" => Ok(Some(OnlyIf::Newer)),
+        "different" => Ok(Some(OnlyIf::Different)),
+        other => cx.throw_type_error(format!("onlyIf must be 'older', 'newer' or 'different', got {:?}", other)),
+    }
+}
+
+// The mtimeGuard option of btime: what to do with a birth time later than
+// the file's modification time, which downstream tools find suspicious
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MtimeGuard {
+    Reject,
+    Warn,
+}
+
+fn mtime_guard_option<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>) -> NeonResult<Option<MtimeGuard>> {
+    let Some(options) = options else {
+        return Ok(None);
+    };
+    let Some(guard) = options.get_opt::<JsString, _, _>(cx, "mtimeGuard")? else {
+        return Ok(None);
+    };
+    match guard.value(cx).as_str() {
+        "reject" => Ok(Some(MtimeGuard::Reject)),
+        "warn" => Ok(Some(MtimeGuard::Warn)),
+        other => cx.throw_type_error(format!("mtimeGuard must be 'reject' or 'warn', got {:?}", other)),
+    }
+}
+
+// Read the `quantize` option, the policy for timestamps finer than the
+// platform stores
+fn quantize_option<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>) -> NeonResult<config::Quantize> {
+    let Some(options) = options else {
+        return Ok(config::get().quantize);
+    };
+    let Some(name) = options.get_opt::<JsString, _, _>(cx, "quantize")? else {
+        return Ok(config::get().quantize);
+    };
+    let name = name.value(cx);
+    match config::Quantize::from_name(&name) {
+        Some(quantize) => Ok(quantize),
+        None => cx.throw_type_error(format!("quantize must be 'truncate', 'round' or 'error', got {:?}", name)),
+    }
+}
+
+// The value a write stores for a timestamp: (time, [{ quantize, bigint }]).
+// Times finer than the platform's resolution are moved by the policy
+fn quantize_time(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let time = cx.argument::<JsValue>(0)?;
+    let time = timestamp_value(&mut cx, time)?;
+    let options = options_argument(&mut cx, 1)?;
+    let quantize = quantize_option(&mut cx, options)?;
+    let bigint = bigint_option(&mut cx, options)?;
+    match time.quantize(quantize) {
+        Some(applied) => Ok(timestamp_to_js(&mut cx, Some(applied), bigint)),
+        None => cx.throw_range_error(format!("{} is finer than the platform's 100ns resolution", time.to_iso8601())),
+    }
+}
+
+// The value each time would be stored as if written to path, for the
+// platform's resolution under the quantize policy and the steps the
+// filesystem keeps: (path, time, [{ cwd, quantize, bigint }]) ->
+// { filesystem, btime, mtime, atime }, null for a time outside its range
+fn quantize_preview(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let path = path_argument(&mut cx, 0)?;
+    let time = cx.argument::<JsValue>(1)?;
+    let time = timestamp_value(&mut cx, time)?;
+    let options = options_argument(&mut cx, 2)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let quantize = quantize_option(&mut cx, options)?;
+    let bigint = bigint_option(&mut cx, options)?;
+    let path = path::resolve(path, cwd.as_deref());
+
+    let Some(applied) = time.quantize(quantize) else {
+        return cx.throw_range_error(format!("{} is finer than the platform's 100ns resolution", time.to_iso8601()));
+    };
+    let filesystem = match volume::filesystem(&path) {
+        Ok(filesystem) => filesystem,
+        Err(err) => return throw_os_error(&mut cx, &err, os_error_message(&err, format!("statfs({})", path.display()))),
+    };
+    let stored = volume::stored_times(&filesystem, &FileTimes { btime: Some(applied), mtime: Some(applied), atime: Some(applied), ctime: None });
+    let in_range = |time: Option<Timestamp>| match volume::limits(&filesystem) {
+        Some(limits) => time.filter(|time| (limits.earliest..=limits.latest).contains(time)),
+        None => time,
+    };
+
+    let result = cx.empty_object();
+    let name = cx.string(&filesystem.name);
+    result.set(&mut cx, "filesystem", name)?;
+    for (field, time) in [("btime", stored.btime), ("mtime", stored.mtime), ("atime", stored.atime)] {
+        let value = timestamp_to_js(&mut cx, in_range(time), bigint);
+        result.set(&mut cx, field, value)?;
+    }
+    Ok(result)
+}
+
+// Read the utcOffset option of the DOS time helpers, in minutes east of UTC
+fn utc_offset_option<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>) -> NeonResult<i32> {
+    let Some(options) = options else {
+        return Ok(0);
+    };
+    match options.get_opt::<JsNumber, _, _>(cx, "utcOffset")? {
+        Some(offset) => {
+            let offset = offset.value(cx);
+            if offset.fract() != 0.0 || !(offset > -1440.0 && offset < 1440.0) {
+                return cx.throw_range_error("utcOffset must be a whole number of minutes within a day of UTC");
+            }
+            Ok(offset as i32)
+        }
+        None => Ok(0),
+    }
+}
+
+// Convert MS-DOS date and time words: (date, time, [{ utcOffset, bigint }])
+fn dos_date_time_to_unix(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let word = |cx: &mut FunctionContext, i: usize, name: &str| -> NeonResult<u16> {
+        let value = cx.argument::<JsNumber>(i)?.value(cx);
+        if value.fract() != 0.0 || !(0.0..=65535.0).contains(&value) {
+            return cx.throw_range_error(format!("{} must be a 16-bit unsigned integer", name));
+        }
+        Ok(value as u16)
+    };
+    let date = word(&mut cx, 0, "date")?;
+    let time = word(&mut cx, 1, "time")?;
+    let options = options_argument(&mut cx, 2)?;
+    let utc_offset = utc_offset_option(&mut cx, options)?;
+    let bigint = bigint_option(&mut cx, options)?;
+    match dos::to_timestamp(date, time, utc_offset) {
+        Some(timestamp) => Ok(timestamp_to_js(&mut cx, Some(timestamp), bigint)),
+        None => cx.throw_range_error(format!("invalid DOS date/time 0x{:04x} 0x{:04x}", date, time)),
+    }
+}
+
+// The MS-DOS date and time words of a time: (time, [{ utcOffset }]) -> { date, time }
+fn unix_to_dos_date_time(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let time = cx.argument::<JsValue>(0)?;
+    let time = timestamp_value(&mut cx, time)?;
+    let options = options_argument(&mut cx, 1)?;
+    let utc_offset = utc_offset_option(&mut cx, options)?;
+    let (date, time) = match dos::from_timestamp(time, utc_offset) {
+        Ok(words) => words,
+        Err(message) => return cx.throw_range_error(message),
+    };
+    let result = cx.empty_object();
+    let date = cx.number(date);
+    let time = cx.number(time);
+    result.set(&mut cx, "date", date)?;
+    result.set(&mut cx, "time", time)?;
+    Ok(result)
+}
+
+// Convert a Windows FILETIME: (BigInt of 100ns intervals since 1601) -> BigInt of Unix nanoseconds
+fn filetime_to_unix_ns(mut cx: FunctionContext) -> JsResult<JsBigInt> {
+    let filetime = cx.argument::<JsBigInt>(0)?;
+    match filetime.to_u64(&mut cx) {
+        Ok(intervals) if intervals <= i64::MAX as u64 => Ok(JsBigInt::from_i128(&mut cx, Timestamp::from_filetime(intervals).as_nanos())),
+        _ => cx.throw_range_error("FILETIME must be a BigInt from 0 to 2n ** 63n - 1n"),
+    }
+}
+
+// The Windows FILETIME of a time: (BigInt of Unix nanoseconds) -> BigInt of 100ns intervals since 1601
+fn unix_ns_to_filetime(mut cx: FunctionContext) -> JsResult<JsBigInt> {
+    let nanos = cx.argument::<JsBigInt>(0)?;
+    let intervals = nanos.to_i128(&mut cx).ok().and_then(Timestamp::from_nanos).and_then(Timestamp::to_filetime);
+    match intervals {
+        Some(intervals) => Ok(JsBigInt::from_u64(&mut cx, intervals)),
+        None => cx.throw_range_error("time is not representable as a FILETIME"),
+    }
+}
+
+// Report a problem that does not fail the call through process.emitWarning
+fn emit_warning(cx: &mut FunctionContext, message: &str) -> NeonResult<()> {
+    let process = cx.global::<JsObject>("process")?;
+    let emit = process.get::<JsFunction, _, _>(cx, "emitWarning")?;
+    let message = cx.string(message);
+    let kind = cx.string("OpenBtimeWarning");
+    emit.call_with(cx).this(process).arg(message).arg(kind).exec(cx)
+}
+
+// A symlink or junction passes the write on to its target unless link: 'self'
+fn link_option<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>) -> NeonResult<bool> {
+    let Some(options) = options else {
+        return Ok(true);
+    };
+    match options.get_opt::<JsString, _, _>(cx, "link")? {
+        Some(link) => match link.value(cx).as_str() {
+            "target" => Ok(true),
+            "self" => Ok(false),
+            other => cx.throw_type_error(format!("link must be 'target' or 'self', got {:?}", other)),
+        },
+        None => Ok(true),
+    }
+}
 
 // Set the birth time (creation time) of a file
-fn btime(mut cx: FunctionContext) -> JsResult<JsNumber> {
+fn btime(mut cx: FunctionContext) -> JsResult<JsValue> {
     // Extract parameters
     if cx.len() < 2 {
-        return cx.throw_error("bad arguments, expected: (buffer path, seconds btime)");
+        return cx.throw_error("bad arguments, expected: (buffer path, seconds btime, [options])");
+    }
+    
+    // Get the path from a buffer, string or file: URL, resolving it against the cwd option
+    let options = options_argument(&mut cx, 2)?;
+    let path = encoded_path_argument(&mut cx, 0, options)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let beneath = beneath_option(&mut cx, options, cwd.as_deref())?;
+    let mut path = path::resolve(path, beneath.as_deref().or(cwd.as_deref()));
+    
+    // Get the btime as seconds (number) or nanoseconds (BigInt)
+    let btime_value = cx.argument::<JsValue>(1)?;
+    let btime = timestamp_value(&mut cx, btime_value)?;
+    let quantize = quantize_option(&mut cx, options)?;
+    let Some(btime) = btime.quantize(quantize) else {
+        return cx.throw_range_error(format!("{} is finer than the platform's 100ns resolution", btime.to_iso8601()));
+    };
+    
+    let mut follow = link_option(&mut cx, options)?;
+    // With beneath the file is kept open until the call returns, and path
+    // reaches it through that descriptor; shown is the path for messages
+    let mut resolved = None;
+    if let Some(root) = &beneath {
+        let found = beneath_path(&mut cx, root, &path, follow)?;
+        (path, follow) = (found.path.clone(), found.follow);
+        resolved = Some(found);
+    } else if canonicalize_option(&mut cx, options)? {
+        path = canonical_path(&mut cx, &path, follow)?;
+    }
+    let shown = resolved.as_ref().map_or(path.clone(), |found| found.shown.clone());
+    let mut times = FileTimes { btime: Some(btime), ..FileTimes::default() };
+
+    // A birth time written through one hard link changes it for every name
+    // of the file, which refuseHardLinks turns into an error
+    let refuse_hard_links = match options {
+        Some(options) => options.get_opt::<JsBoolean, _, _>(&mut cx, "refuseHardLinks")?.is_some_and(|refuse| refuse.value(&mut cx)),
+        None => false,
+    };
+    // With report the call returns { written, sharedWithLinks, backend }
+    // instead, noting the other names the write changes the birth time of
+    // and the backend that did it
+    let report = match options {
+        Some(options) => options.get_opt::<JsBoolean, _, _>(&mut cx, "report")?.is_some_and(|report| report.value(&mut cx)),
+        None => false,
+    };
+    let mut shared_with = 0;
+    if refuse_hard_links || report {
+        match links::other_names(&path, follow) {
+            Ok(names @ 1..) if refuse_hard_links => {
+                let err = links::refused(names);
+                return throw_os_error(&mut cx, &err, format!("{}: {}", shown.display(), err));
+            }
+            Ok(names) => shared_with = names,
+            Err(err) => return throw_os_error(&mut cx, &err, stat_error_message(&err, &shown, follow)),
+        }
+    }
+
+    // onlyIf, mtimeGuard and fixMtime compare against the current times, read
+    // once right before the write. With onlyIf the call reports whether it wrote
+    let only_if = only_if_option(&mut cx, options)?;
+    let mtime_guard = mtime_guard_option(&mut cx, options)?;
+    let fix_mtime = match options {
+        Some(options) => options.get_opt::<JsBoolean, _, _>(&mut cx, "fixMtime")?.is_some_and(|fix| fix.value(&mut cx)),
+        None => false,
+    };
+    // A simulation returns what the call would write instead of writing it,
+    // with the warnings it would emit
+    let simulate = match options {
+        Some(options) => options.get_opt::<JsBoolean, _, _>(&mut cx, "simulate")?.is_some_and(|simulate| simulate.value(&mut cx)),
+        None => false,
+    };
+    let mut warnings = Vec::new();
+    if only_if.is_some() || mtime_guard.is_some() || fix_mtime {
+        let current = match times::get_times(&path, follow) {
+            Ok(current) => current,
+            Err(err) => return throw_os_error(&mut cx, &err, stat_error_message(&err, &shown, follow)),
+        };
+        if only_if.is_some_and(|only_if| !only_if.allows(current.btime, btime)) {
+            if simulate {
+                return simulated_btime(&mut cx, options, &path, &times, follow, false, &warnings);
+            }
+            if report {
+                return btime_report(&mut cx, None, shared_with);
+            }
+            return Ok(cx.boolean(false).upcast());
+        }
+        // The raised mtime goes into the same write as the birth time, so
+        // the file is never seen with only one of them changed
+        if fix_mtime && current.mtime.is_some_and(|mtime| btime > mtime) {
+            times.mtime = Some(btime);
+        } else if let (Some(guard), Some(mtime)) = (mtime_guard, current.mtime) {
+            if btime > mtime {
+                let message = format!(
+                    "birth time {} is later than the modification time {} of {}",
+                    btime.to_iso8601(),
+                    mtime.to_iso8601(),
+                    shown.display()
+                );
+                match guard {
+                    MtimeGuard::Reject => return cx.throw_error(message),
+                    MtimeGuard::Warn if simulate => warnings.push(message),
+                    MtimeGuard::Warn => emit_warning(&mut cx, &message)?,
+                }
+            }
+        }
+    }
+
+    // Try to set the birth time, with backup privileges if asked for
+    let backup = backup_option(&mut cx, options)?;
+    let verify_delay = verify_delay_option(&mut cx, options)?;
+    let reapply = match options {
+        Some(options) => options.get_opt::<JsBoolean, _, _>(&mut cx, "reapply")?.is_some_and(|reapply| reapply.value(&mut cx)),
+        None => false,
+    };
+    if simulate {
+        return simulated_btime(&mut cx, options, &path, &times, follow, true, &warnings);
+    }
+
+    // With batching on the write is queued, unless it is to be read back or
+    // reported on, or goes through a descriptor beneath keeps open
+    if verify_delay.is_none() && !report && resolved.is_none() {
+        match batch::push(batch::Write { path, times, follow, backup }) {
+            Some(write) => (path, times) = (write.path, write.times),
+            None if only_if.is_some() => return Ok(cx.boolean(true).upcast()),
+            None => return Ok(cx.number(0).upcast()),
+        }
+    }
+    let set = || with_backup_mode(backup, || if follow { set_times(&path, &times) } else { set_link_times(&path, &times) });
+    let written_by = match set() {
+        Ok(backend) => backend,
+        Err(err) => {
+            let error_message = os_error_message(&err, format!("utimes({})", shown.display()));
+            return throw_os_error(&mut cx, &err, with_hint(error_message, &err));
+        }
+    };
+    if let Some(delay) = verify_delay.filter(|_| backend::current().capabilities().set_btime) {
+        if let Err(failure) = verify_btime(&path, &shown, btime, follow, delay, reapply, || set().map(|_| ())) {
+            return throw_failure(&mut cx, failure);
+        }
+    }
+    if report {
+        return btime_report(&mut cx, Some(written_by), shared_with);
+    }
+    if only_if.is_some() {
+        return Ok(cx.boolean(true).upcast());
+    }
+    Ok(cx.number(0).upcast()) // Return 0 on success (like the original C++ implementation)
+}
+
+// The result of btime with report: { written, sharedWithLinks, backend },
+// backend naming the one that did the write or null when nothing was written
+fn btime_report<'a>(cx: &mut FunctionContext<'a>, written_by: Option<&'static str>, shared_with: u64) -> JsResult<'a, JsValue> {
+    let result = cx.empty_object();
+    let written = cx.boolean(written_by.is_some());
+    let shared_with = cx.number(shared_with as f64);
+    let backend = match written_by {
+        Some(backend) => cx.string(backend).upcast(),
+        None => cx.null().upcast::<JsValue>(),
+    };
+    result.set(cx, "written", written)?;
+    result.set(cx, "sharedWithLinks", shared_with)?;
+    result.set(cx, "backend", backend)?;
+    Ok(result.upcast())
+}
+
+// The result of btime with simulate: { path, write, times, stored,
+// clamped, reasons, warnings }
+fn simulated_btime<'a>(
+    cx: &mut FunctionContext<'a>,
+    options: Option<Handle<'a, JsObject>>,
+    path: &Path,
+    times: &FileTimes,
+    follow: bool,
+    write: bool,
+    warnings: &[String],
+) -> JsResult<'a, JsValue> {
+    let bigint = bigint_option(cx, options)?;
+    // A file beneath keeps open is reported where it was found
+    let shown = beneath::found_at(path);
+    let mut simulation = match simulate::simulate(path, times, follow) {
+        Ok(simulation) => simulation,
+        Err(err) => return throw_os_error(cx, &err, stat_error_message(&err, &shown, follow)),
+    };
+    simulation.path = shown;
+    let result = simulation_to_js(cx, &simulation, write, bigint)?;
+    let warnings = strings_to_js(cx, warnings)?;
+    result.set(cx, "warnings", warnings)?;
+    Ok(result.upcast())
+}
+
+fn simulation_to_js<'a>(cx: &mut impl Context<'a>, simulation: &simulate::Simulation, write: bool, bigint: bool) -> JsResult<'a, JsObject> {
+    let result = cx.empty_object();
+    let path = cx.string(simulation.path.to_string_lossy());
+    let write = cx.boolean(write);
+    let times = times_to_js(cx, &simulation.times, bigint)?;
+    let stored = times_to_js(cx, &simulation.stored, bigint)?;
+    let clamped = cx.boolean(simulation.clamped);
+    let reasons: Vec<String> = simulation.obstacles.iter().map(|obstacle| obstacle.name().to_string()).collect();
+    let reasons = strings_to_js(cx, &reasons)?;
+    result.set(cx, "path", path)?;
+    result.set(cx, "write", write)?;
+    result.set(cx, "times", times)?;
+    result.set(cx, "stored", stored)?;
+    result.set(cx, "clamped", clamped)?;
+    result.set(cx, "reasons", reasons)?;
+    let shared_with = cx.number(simulation.shared_with as f64);
+    result.set(cx, "sharedWithLinks", shared_with)?;
+    Ok(result)
+}
+
+// Read the verifyDelay option of btime, in milliseconds
+fn verify_delay_option<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>) -> NeonResult<Option<std::time::Duration>> {
+    let Some(options) = options else {
+        return Ok(None);
+    };
+    match options.get_opt::<JsNumber, _, _>(cx, "verifyDelay")? {
+        Some(delay) => {
+            let delay = delay.value(cx);
+            if !(delay >= 0.0 && delay.is_finite()) {
+                return cx.throw_range_error("verifyDelay must be a number of milliseconds");
+            }
+            Ok(Some(std::time::Duration::from_secs_f64(delay / 1000.0)))
+        }
+        None => Ok(None),
+    }
+}
+
+// Read the birth time back after delay. Tunneling on NTFS and FAT gives a
+// file created under the name of one deleted or renamed away within the
+// last 15 seconds that file's creation time, so an editor saving through a
+// temporary file and a rename can replace a time just written. With
+// reapply a changed time is written once more and checked right away
+fn verify_btime(
+    path: &Path,
+    shown: &Path,
+    btime: Timestamp,
+    follow: bool,
+    delay: std::time::Duration,
+    reapply: bool,
+    write: impl Fn() -> std::io::Result<()>,
+) -> Result<(), Failure> {
+    std::thread::sleep(delay);
+    // What the filesystem can keep of the time written, which is what reads back
+    let stored = match volume::filesystem(path) {
+        Ok(filesystem) => volume::stored_times(&filesystem, &FileTimes { btime: Some(btime), ..FileTimes::default() }).btime,
+        Err(_) => Some(btime),
+    };
+    let read = || {
+        let read = times::get_times(path, follow).map(|times| times.btime);
+        read.map_err(|err| Failure::os(stat_error_message(&err, shown, follow), err))
+    };
+    let mut current = read()?;
+    if current != stored && reapply {
+        if let Err(err) = write() {
+            let error_message = os_error_message(&err, format!("utimes({})", shown.display()));
+            return Err(Failure::os(with_hint(error_message, &err), err));
+        }
+        current = read()?;
+    }
+    if current == stored {
+        return Ok(());
+    }
+    Err(Failure::from(format!(
+        "birth time of {} reads back as {} instead of {}, it was replaced after the write",
+        shown.display(),
+        current.map_or("nothing".to_string(), |current| current.to_iso8601()),
+        stored.map_or("nothing".to_string(), |stored| stored.to_iso8601())
+    )))
+}
+
+// Convert a JS timestamp: numbers are (fractional) Unix seconds, BigInts are
+// nanoseconds so values read back from getTimes() round-trip exactly
+fn timestamp_value<'a>(cx: &mut impl Context<'a>, value: Handle<'a, JsValue>) -> NeonResult<Timestamp> {
+    let timestamp = if let Ok(nanos) = value.downcast::<JsBigInt, _>(cx) {
+        nanos.to_i128(cx).ok().and_then(Timestamp::from_nanos)
+    } else if let Ok(seconds) = value.downcast::<JsNumber, _>(cx) {
+        Timestamp::from_secs_f64(seconds.value(cx))
+    } else {
+        return cx.throw_type_error("timestamp must be a number of seconds or a BigInt of nanoseconds");
+    };
+
+    match timestamp {
+        Some(timestamp) => Ok(timestamp),
+        None => cx.throw_range_error("timestamp out of range"),
+    }
+}
+
+// Convert a timestamp for JS: BigInt nanoseconds by default, or a number of
+// milliseconds when the caller passed { bigint: false }
+fn timestamp_to_js<'a>(cx: &mut impl Context<'a>, timestamp: Option<Timestamp>, bigint: bool) -> Handle<'a, JsValue> {
+    match timestamp {
+        Some(timestamp) if bigint => JsBigInt::from_i128(cx, timestamp.as_nanos()).upcast(),
+        Some(timestamp) => cx.number(timestamp.as_millis_f64()).upcast(),
+        None => cx.null().upcast(),
+    }
+}
+
+// Read the `bigint` option, true unless disabled here or with configure()
+fn bigint_option<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>) -> NeonResult<bool> {
+    let Some(options) = options else {
+        return Ok(config::get().bigint);
+    };
+    match options.get_opt::<JsBoolean, _, _>(cx, "bigint")? {
+        Some(bigint) => Ok(bigint.value(cx)),
+        None => Ok(config::get().bigint),
+    }
+}
+
+// Read the `canonicalize` option, which resolves symlinks, `.` and `..` in
+// the path before it is used
+fn canonicalize_option<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>) -> NeonResult<bool> {
+    let Some(options) = options else {
+        return Ok(false);
+    };
+    match options.get_opt::<JsBoolean, _, _>(cx, "canonicalize")? {
+        Some(canonicalize) => Ok(canonicalize.value(cx)),
+        None => Ok(false),
+    }
+}
+
+fn canonical_path(cx: &mut FunctionContext, path: &Path, follow: bool) -> NeonResult<PathBuf> {
+    match path::canonicalize(path, follow) {
+        Ok(canonical) => Ok(canonical),
+        Err(err) => throw_os_error(cx, &err, os_error_message(&err, format!("realpath({})", path.display()))),
+    }
+}
+
+// Read the `beneath` option: a root directory, resolved against cwd, that
+// the path is resolved in and must not leave
+fn beneath_option<'a>(
+    cx: &mut FunctionContext<'a>,
+    options: Option<Handle<'a, JsObject>>,
+    cwd: Option<&Path>,
+) -> NeonResult<Option<PathBuf>> {
+    let Some(options) = options else {
+        return Ok(None);
+    };
+    match options.get_opt::<JsValue, _, _>(cx, "beneath")? {
+        Some(value) if !value.is_a::<JsUndefined, _>(cx) => Ok(Some(path::resolve(path_value(cx, value)?, cwd))),
+        _ => Ok(None),
+    }
+}
+
+fn beneath_path(cx: &mut FunctionContext, root: &Path, path: &Path, follow: bool) -> NeonResult<beneath::Resolved> {
+    match beneath::resolve(root, path, follow) {
+        Ok(resolved) => Ok(resolved),
+        Err(err) => {
+            throw_os_error(cx, &err, os_error_message(&err, format!("resolve({})", path.display())))
+        }
+    }
+}
+
+// What the read APIs read: the times, the bigint option, the path read and
+// whether it was resolved with canonicalize or beneath, and the backend that
+// answered
+struct TimesRead {
+    times: FileTimes,
+    bigint: bool,
+    path: PathBuf,
+    resolved: bool,
+    backend: &'static str,
+}
+
+// Shared argument handling of the read APIs: (path, [options])
+fn read_times(cx: &mut FunctionContext, follow: bool) -> NeonResult<TimesRead> {
+    let options = options_argument(cx, 1)?;
+    let path = encoded_path_argument(cx, 0, options)?;
+    let cwd = cwd_option(cx, options)?;
+    let bigint = bigint_option(cx, options)?;
+    let beneath = beneath_option(cx, options, cwd.as_deref())?;
+    let mut path = path::resolve(path, beneath.as_deref().or(cwd.as_deref()));
+    let canonical = canonicalize_option(cx, options)?;
+    if let Some(root) = &beneath {
+        // Read through the descriptor beneath opened, report where it was found
+        let found = beneath_path(cx, root, &path, follow)?;
+        return match times::get_times_by(&found.path, found.follow) {
+            Ok((times, backend)) => Ok(TimesRead { times, bigint, path: found.shown, resolved: true, backend }),
+            Err(err) => throw_os_error(cx, &err, stat_error_message(&err, &found.shown, follow)),
+        };
+    }
+    if canonical {
+        path = canonical_path(cx, &path, follow)?;
+    }
+
+    match times::get_times_by(&path, follow) {
+        Ok((times, backend)) => Ok(TimesRead { times, bigint, path, resolved: canonical, backend }),
+        Err(err) => throw_os_error(cx, &err, stat_error_message(&err, &path, follow)),
+    }
+}
+
+fn stat_error_message(err: &std::io::Error, path: &Path, follow: bool) -> String {
+    let syscall = if follow { "stat" } else { "lstat" };
+    os_error_message(err, format!("{}({})", syscall, path.display()))
+}
+
+// Build the { btime, mtime, atime, ctime } object returned by the read APIs
+fn times_to_js<'a>(cx: &mut impl Context<'a>, file_times: &FileTimes, bigint: bool) -> JsResult<'a, JsObject> {
+    let result = cx.empty_object();
+
+    for (key, timestamp) in [
+        ("btime", file_times.btime),
+        ("mtime", file_times.mtime),
+        ("atime", file_times.atime),
+        ("ctime", file_times.ctime),
+    ] {
+        let value = timestamp_to_js(cx, timestamp, bigint);
+        result.set(cx, key, value)?;
+    }
+
+    Ok(result)
+}
+
+// Get the birth time of a file, or null where it is not recorded
+fn get_btime(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let read = read_times(&mut cx, true)?;
+    Ok(timestamp_to_js(&mut cx, read.times.btime, read.bigint))
+}
+
+// Get the birth time of a symlink itself rather than of its target
+fn lget_btime(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let read = read_times(&mut cx, false)?;
+    Ok(timestamp_to_js(&mut cx, read.times.btime, read.bigint))
+}
+
+// Get all timestamps of a file as { btime, mtime, atime, ctime }, plus the
+// path read with canonicalize: true
+fn get_times(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let read = read_times(&mut cx, true)?;
+    let result = times_to_js(&mut cx, &read.times, read.bigint)?;
+    if read.resolved {
+        let path = cx.string(read.path.to_string_lossy());
+        result.set(&mut cx, "path", path)?;
+    }
+    // A birth time kept by the backend is flagged, so it is not taken for
+    // one the filesystem recorded
+    let backend = backend::current();
+    if backend.capabilities().emulated {
+        let emulated = cx.boolean(backend.keeps_btime(&read.path, true));
+        result.set(&mut cx, "emulated", emulated)?;
+    }
+    // and with a chain, so is the backend that answered
+    if backend.chain().is_some() {
+        let reader = cx.string(read.backend);
+        result.set(&mut cx, "backend", reader)?;
+    }
+    Ok(result)
+}
+
+// The file descriptor argument of fbtime and fgetTimes
+fn fd_argument(cx: &mut FunctionContext, i: usize) -> NeonResult<i32> {
+    let fd = cx.argument::<JsNumber>(i)?.value(cx);
+    if !(fd >= 0.0 && fd <= i32::MAX as f64 && fd.fract() == 0.0) {
+        return cx.throw_range_error(format!("fd must be a non-negative integer, got {}", fd));
+    }
+    Ok(fd as i32)
+}
+
+// Set the birth time of a file open in Node: (fd, btime, [{ quantize }]) -> 0
+fn fbtime(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let fd = fd_argument(&mut cx, 0)?;
+    let btime_value = cx.argument::<JsValue>(1)?;
+    let btime = timestamp_value(&mut cx, btime_value)?;
+    let options = options_argument(&mut cx, 2)?;
+    let quantize = quantize_option(&mut cx, options)?;
+    let Some(btime) = btime.quantize(quantize) else {
+        return cx.throw_range_error(format!("{} is finer than the platform's 100ns resolution", btime.to_iso8601()));
+    };
+
+    ratelimit::acquire();
+    let started = std::time::Instant::now();
+    let result = fd::set_times(fd, &FileTimes { btime: Some(btime), ..FileTimes::default() });
+    stats::record(stats::Op::SetTimes, started, &result);
+    if let Err(err) = result {
+        let error_message = os_error_message(&err, format!("futimes({})", fd));
+        return throw_os_error(&mut cx, &err, with_hint(error_message, &err));
+    }
+    Ok(cx.number(0))
+}
+
+// Get all timestamps of a file open in Node: (fd, [{ bigint }]) ->
+// { btime, mtime, atime, ctime }
+fn fget_times(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let fd = fd_argument(&mut cx, 0)?;
+    let options = options_argument(&mut cx, 1)?;
+    let bigint = bigint_option(&mut cx, options)?;
+
+    let started = std::time::Instant::now();
+    let result = fd::get_times(fd);
+    stats::record(stats::Op::GetTimes, started, &result);
+    match result {
+        Ok(file_times) => times_to_js(&mut cx, &file_times, bigint),
+        Err(err) => throw_os_error(&mut cx, &err, os_error_message(&err, format!("fstat({})", fd))),
+    }
+}
+
+// Get the timestamps of many files in one call. Failed entries are Error
+// objects instead of throwing for the whole batch. With { packed: true } the
+// result is { times, errors }: times holds btime, mtime, atime and ctime per
+// path (BigInt64Array of nanoseconds, or Float64Array of milliseconds with
+// bigint: false) with missing values as -2^63/NaN, and errors holds the
+// OS error code per path (0 on success)
+fn get_times_many(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let paths = cx.argument::<JsArray>(0)?.to_vec(&mut cx)?;
+    let options = options_argument(&mut cx, 1)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let bigint = bigint_option(&mut cx, options)?;
+    let packed = match options {
+        Some(options) => options
+            .get_opt::<JsBoolean, _, _>(&mut cx, "packed")?
+            .map(|packed| packed.value(&mut cx))
+            .unwrap_or(false),
+        None => false,
+    };
+
+    let canonicalize = canonicalize_option(&mut cx, options)?;
+
+    // Decode every path up front so the reads run without touching JS values.
+    // A path that cannot be canonicalized is read as given and fails there
+    let mut resolved = PathArena::take();
+    if canonicalize {
+        for value in paths {
+            let path = path::resolve(path_value(&mut cx, value)?, cwd.as_deref());
+            resolved.push(&path::canonicalize(&path, true).unwrap_or(path), None);
+        }
+    } else {
+        for value in paths {
+            push_path_value(&mut cx, &mut resolved, value, cwd.as_deref())?;
+        }
+    }
+    let results = times::get_times_many(&resolved, true);
+
+    if packed {
+        let mut errors = Vec::with_capacity(results.len());
+        let mut fields = Vec::with_capacity(results.len() * 4);
+        for result in &results {
+            let file_times = match result {
+                Ok(file_times) => {
+                    errors.push(0);
+                    *file_times
+                }
+                Err(err) => {
+                    errors.push(err.raw_os_error().unwrap_or(-1));
+                    FileTimes::default()
+                }
+            };
+            fields.extend([file_times.btime, file_times.mtime, file_times.atime, file_times.ctime]);
+        }
+
+        let packed = cx.empty_object();
+        let times = if bigint {
+            let nanos: Vec<i64> = fields
+                .iter()
+                .map(|t| t.and_then(|t| i64::try_from(t.as_nanos()).ok()).unwrap_or(MISSING_NANOS))
+                .collect();
+            JsTypedArray::<i64>::from_slice(&mut cx, &nanos)?.upcast::<JsValue>()
+        } else {
+            let millis: Vec<f64> = fields.iter().map(|t| t.map_or(f64::NAN, |t| t.as_millis_f64())).collect();
+            JsTypedArray::<f64>::from_slice(&mut cx, &millis)?.upcast::<JsValue>()
+        };
+        let errors = JsTypedArray::<i32>::from_slice(&mut cx, &errors)?;
+        packed.set(&mut cx, "times", times)?;
+        packed.set(&mut cx, "errors", errors)?;
+        return Ok(packed.upcast());
+    }
+
+    let array = cx.empty_array();
+    for (i, (result, path)) in results.iter().zip(resolved.iter()).enumerate() {
+        let entry = match result {
+            Ok(file_times) => times_to_js(&mut cx, file_times, bigint)?.upcast::<JsValue>(),
+            Err(err) => {
+                let error = cx.error(stat_error_message(err, path, true))?;
+                set_error_code(&mut cx, error, err)?;
+                error.upcast()
+            }
+        };
+        array.set(&mut cx, i as u32, entry)?;
+    }
+    Ok(array.upcast())
+}
+
+// Stat a file in one call as { btime, mtime, atime, ctime, available, size,
+// ino, dev, nlink, type, filesystem }. available tells which timestamps the
+// platform and filesystem record; the counts are BigInts unless bigint: false
+fn stat_all(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let options = options_argument(&mut cx, 1)?;
+    let path = encoded_path_argument(&mut cx, 0, options)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let bigint = bigint_option(&mut cx, options)?;
+    let follow = link_option(&mut cx, options)?;
+    let path = path::resolve(path, cwd.as_deref());
+
+    let file_stat = match stat::stat(&path, follow) {
+        Ok(file_stat) => file_stat,
+        Err(err) => return throw_os_error(&mut cx, &err, stat_error_message(&err, &path, follow)),
+    };
+
+    let result = times_to_js(&mut cx, &file_stat.times, bigint)?;
+    let available = cx.empty_object();
+    for (key, timestamp) in [
+        ("btime", file_stat.times.btime),
+        ("mtime", file_stat.times.mtime),
+        ("atime", file_stat.times.atime),
+        ("ctime", file_stat.times.ctime),
+    ] {
+        let value = cx.boolean(timestamp.is_some());
+        available.set(&mut cx, key, value)?;
+    }
+    result.set(&mut cx, "available", available)?;
+
+    for (key, number) in [
+        ("size", file_stat.size),
+        ("ino", file_stat.ino),
+        ("dev", file_stat.dev),
+        ("nlink", file_stat.nlink),
+    ] {
+        let value = if bigint {
+            JsBigInt::from_u64(&mut cx, number).upcast::<JsValue>()
+        } else {
+            cx.number(number as f64).upcast()
+        };
+        result.set(&mut cx, key, value)?;
+    }
+
+    let file_type = cx.string(file_stat.file_type.name());
+    result.set(&mut cx, "type", file_type)?;
+    let filesystem = match &file_stat.filesystem {
+        Some(name) => cx.string(name).upcast::<JsValue>(),
+        None => cx.null().upcast(),
+    };
+    result.set(&mut cx, "filesystem", filesystem)?;
+    Ok(result)
+}
+
+// Placeholder for unrecorded timestamps in packed BigInt64Array results
+const MISSING_NANOS: i64 = i64::MIN;
+
+// Read an optional array of strings option
+fn string_array_option<'a>(cx: &mut FunctionContext<'a>, options: Handle<'a, JsObject>, key: &str) -> NeonResult<Option<Vec<String>>> {
+    let Some(array) = options.get_opt::<JsArray, _, _>(cx, key)? else {
+        return Ok(None);
+    };
+    let mut strings = Vec::new();
+    for value in array.to_vec(cx)? {
+        strings.push(value.downcast_or_throw::<JsString, _>(cx)?.value(cx));
+    }
+    Ok(Some(strings))
+}
+
+// Read an optional timestamp option (seconds or BigInt nanoseconds)
+fn timestamp_option<'a>(cx: &mut FunctionContext<'a>, options: Handle<'a, JsObject>, key: &str) -> NeonResult<Option<Timestamp>> {
+    match options.get_opt::<JsValue, _, _>(cx, key)? {
+        Some(value) if !value.is_a::<JsUndefined, _>(cx) => Ok(Some(timestamp_value(cx, value)?)),
+        _ => Ok(None),
+    }
+}
+
+// A string or an array of them, as filter conditions take either
+fn strings_value<'a>(cx: &mut FunctionContext<'a>, value: Handle<'a, JsValue>) -> NeonResult<Vec<String>> {
+    if let Ok(string) = value.downcast::<JsString, _>(cx) {
+        return Ok(vec![string.value(cx)]);
+    }
+    let mut strings = Vec::new();
+    for value in value.downcast_or_throw::<JsArray, _>(cx)?.to_vec(cx)? {
+        strings.push(value.downcast_or_throw::<JsString, _>(cx)?.value(cx));
+    }
+    Ok(strings)
+}
+
+// Parse the `filter` option: an object whose conditions must all hold, or
+// an array of such objects any of which must
+fn predicate_value<'a>(cx: &mut FunctionContext<'a>, value: Handle<'a, JsValue>) -> NeonResult<Predicate> {
+    if let Ok(array) = value.downcast::<JsArray, _>(cx) {
+        let mut any = Vec::new();
+        for item in array.to_vec(cx)? {
+            any.push(predicate_value(cx, item)?);
+        }
+        return Ok(Predicate::Any(any));
+    }
+    let object = value.downcast_or_throw::<JsObject, _>(cx)?;
+    let mut all = Vec::new();
+    for key in object.get_own_property_names(cx)?.to_vec(cx)? {
+        let key = key.downcast_or_throw::<JsString, _>(cx)?.value(cx);
+        let value = object.get::<JsValue, _, _>(cx, key.as_str())?;
+        if value.is_a::<JsUndefined, _>(cx) {
+            continue;
+        }
+        let time = |key: &str| match &key[..5] {
+            "btime" => TimeField::Btime,
+            "mtime" => TimeField::Mtime,
+            _ => TimeField::Atime,
+        };
+        let predicate = match key.as_str() {
+            "all" => match predicate_value(cx, value)? {
+                Predicate::Any(predicates) => Predicate::All(predicates),
+                _ => return cx.throw_type_error("filter condition all must be an array"),
+            },
+            "any" if value.is_a::<JsArray, _>(cx) => predicate_value(cx, value)?,
+            "any" => return cx.throw_type_error("filter condition any must be an array"),
+            "not" => Predicate::Not(Box::new(predicate_value(cx, value)?)),
+            "ext" => {
+                let extensions = strings_value(cx, value)?;
+                Predicate::Extension(extensions.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect())
+            }
+            "type" => {
+                let mut entry_types = Vec::new();
+                for name in strings_value(cx, value)? {
+                    match EntryType::from_name(&name) {
+                        Some(entry_type) => entry_types.push(entry_type),
+                        None => return cx.throw_type_error(format!("unknown entry type: {}", name)),
+                    }
+                }
+                Predicate::Type(entry_types)
+            }
+            "name" => {
+                let mut globs = Vec::new();
+                for pattern in strings_value(cx, value)? {
+                    match glob::Glob::new(&pattern) {
+                        Ok(glob) => globs.push(glob),
+                        Err(message) => return cx.throw_type_error(message),
+                    }
+                }
+                Predicate::Name(globs)
+            }
+            "btimeBefore" | "mtimeBefore" | "atimeBefore" => Predicate::Before(time(&key), timestamp_value(cx, value)?),
+            "btimeAfter" | "mtimeAfter" | "atimeAfter" => Predicate::After(time(&key), timestamp_value(cx, value)?),
+            "sizeOver" | "sizeUnder" => {
+                let size = if let Ok(size) = value.downcast::<JsBigInt, _>(cx) {
+                    size.to_u64(cx).ok()
+                } else {
+                    let size = value.downcast_or_throw::<JsNumber, _>(cx)?.value(cx);
+                    (size >= 0.0).then_some(size as u64)
+                };
+                let Some(size) = size else {
+                    return cx.throw_range_error(format!("{} must be a non-negative number", key));
+                };
+                if key == "sizeOver" { Predicate::SizeOver(size) } else { Predicate::SizeUnder(size) }
+            }
+            _ => return cx.throw_type_error(format!("unknown filter condition: {}", key)),
+        };
+        all.push(predicate);
+    }
+    Ok(Predicate::All(all))
+}
+
+// Parse the walker options shared by the recursive APIs
+fn walk_options<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>) -> NeonResult<WalkOptions> {
+    let mut walk_options = WalkOptions { follow_symlinks: config::get().follow_symlinks, ..WalkOptions::default() };
+    let Some(options) = options else {
+        return Ok(walk_options);
+    };
+
+    let mut filter = Filter {
+        min_mtime: timestamp_option(cx, options, "minMtime")?,
+        max_mtime: timestamp_option(cx, options, "maxMtime")?,
+        ..Filter::default()
+    };
+    if let Some(extensions) = string_array_option(cx, options, "extensions")? {
+        filter.extensions = Some(extensions.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect());
+    }
+    if let Some(types) = string_array_option(cx, options, "types")? {
+        let mut entry_types = Vec::new();
+        for name in types {
+            match EntryType::from_name(&name) {
+                Some(entry_type) => entry_types.push(entry_type),
+                None => return cx.throw_type_error(format!("unknown entry type: {}", name)),
+            }
+        }
+        filter.types = Some(entry_types);
+    }
+    if let Some(predicate) = options.get_opt::<JsValue, _, _>(cx, "filter")? {
+        filter.predicate = Some(predicate_value(cx, predicate)?);
+    }
+
+    walk_options.filter = filter;
+    if let Some(follow) = options.get_opt::<JsString, _, _>(cx, "followSymlinks")? {
+        walk_options.follow_symlinks = follow_symlinks_value(cx, follow)?;
+    }
+    if let Some(follow) = options.get_opt::<JsBoolean, _, _>(cx, "followJunctions")? {
+        walk_options.follow_junctions = follow.value(cx);
+    }
+    if let Some(icloud_names) = options.get_opt::<JsBoolean, _, _>(cx, "icloudNames")? {
+        walk_options.icloud_names = icloud_names.value(cx);
+    }
+    if let Some(max_depth) = options.get_opt::<JsNumber, _, _>(cx, "maxDepth")? {
+        let max_depth = max_depth.value(cx);
+        if max_depth.is_nan() || max_depth < 0.0 {
+            return cx.throw_range_error("maxDepth must be a non-negative number");
+        }
+        walk_options.max_depth = Some(max_depth as usize);
+    }
+    if let Some(patterns) = string_array_option(cx, options, "exclude")? {
+        for pattern in patterns {
+            match glob::Glob::new(&pattern) {
+                Ok(glob) => walk_options.exclude.push(glob),
+                Err(message) => return cx.throw_type_error(message),
+            }
+        }
+    }
+    Ok(walk_options)
+}
+
+// Walk the tree below a directory and return the matching entries with their
+// timestamps as { entries, errors, truncated }
+fn walk(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let root = path_argument(&mut cx, 0)?;
+    let options = options_argument(&mut cx, 1)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let bigint = bigint_option(&mut cx, options)?;
+    let walk_options = walk_options(&mut cx, options)?;
+    let root = path::resolve(root, cwd.as_deref());
+
+    // A missing or unreadable root fails the whole call
+    if let Err(err) = std::fs::read_dir(&root) {
+        let error_message = os_error_message(&err, format!("scandir({})", root.display()));
+        return throw_os_error(&mut cx, &err, error_message);
+    }
+
+    let entries = cx.empty_array();
+    let errors = cx.empty_array();
+    let mut walker = Walker::new(&root, walk_options);
+    for result in walker.by_ref() {
+        match result {
+            Ok(entry) => {
+                let object = times_to_js(&mut cx, &entry.times, bigint)?;
+                let path = cx.string(entry.path.to_string_lossy());
+                let file_type = cx.string(entry.file_type.name());
+                let depth = cx.number(entry.depth as f64);
+                let symlink = cx.boolean(entry.symlink);
+                let placeholder = cx.boolean(entry.placeholder);
+                object.set(&mut cx, "path", path)?;
+                object.set(&mut cx, "type", file_type)?;
+                object.set(&mut cx, "depth", depth)?;
+                object.set(&mut cx, "symlink", symlink)?;
+                object.set(&mut cx, "placeholder", placeholder)?;
+                let len = entries.len(&mut cx);
+                entries.set(&mut cx, len, object)?;
+            }
+            Err(err) => {
+                let object = walk_error_to_js(&mut cx, &err)?;
+                let len = errors.len(&mut cx);
+                errors.set(&mut cx, len, object)?;
+            }
+        }
+    }
+
+    let truncated = paths_to_js(&mut cx, walker.truncated())?;
+
+    let result = cx.empty_object();
+    result.set(&mut cx, "entries", entries)?;
+    result.set(&mut cx, "errors", errors)?;
+    result.set(&mut cx, "truncated", truncated)?;
+    Ok(result)
+}
+
+// Shared argument handling of the recursive setters: (root, btime, [options])
+struct RecursiveArguments {
+    root: PathBuf,
+    btime: Timestamp,
+    walk_options: WalkOptions,
+    concurrency: usize,
+    checkpoint: Option<PathBuf>,
+}
+
+fn recursive_arguments(cx: &mut FunctionContext) -> NeonResult<RecursiveArguments> {
+    let root = path_argument(cx, 0)?;
+    let btime_value = cx.argument::<JsValue>(1)?;
+    let btime = timestamp_value(cx, btime_value)?;
+    let options = options_argument(cx, 2)?;
+    let cwd = cwd_option(cx, options)?;
+    let walk_options = walk_options(cx, options)?;
+    let concurrency = concurrency_option(cx, options)?;
+    let checkpoint = checkpoint_option(cx, options, cwd.as_deref())?;
+    let root = path::resolve(root, cwd.as_deref());
+    Ok(RecursiveArguments { root, btime, walk_options, concurrency, checkpoint })
+}
+
+// Read the `checkpoint` option, the progress file of a resumable operation
+fn checkpoint_option<'a>(
+    cx: &mut FunctionContext<'a>,
+    options: Option<Handle<'a, JsObject>>,
+    cwd: Option<&Path>,
+) -> NeonResult<Option<PathBuf>> {
+    let Some(options) = options else {
+        return Ok(None);
+    };
+    match options.get_opt::<JsValue, _, _>(cx, "checkpoint")? {
+        Some(value) if !value.is_a::<JsUndefined, _>(cx) => {
+            let checkpoint = path_value(cx, value)?;
+            Ok(Some(path::resolve(checkpoint, cwd)))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Open the checkpoint of a run identified by `identity`, if one was asked for
+fn open_checkpoint(
+    path: Option<&Path>,
+    operation: &str,
+    identity: Vec<(&'static str, String)>,
+) -> Result<Option<checkpoint::Checkpoint>, Failure> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let identity = [("operation", operation.to_string())].into_iter().chain(identity).collect();
+    match checkpoint::Checkpoint::open(path, identity) {
+        Ok(checkpoint) => Ok(Some(checkpoint)),
+        Err(err) => match err.raw_os_error() {
+            Some(_) => Err(Failure::os(os_error_message(&err, format!("open({})", path.display())), err)),
+            None => Err(Failure::from(format!("Invalid checkpoint {}: {}", path.display(), err))),
+        },
+    }
+}
+
+// Start of a btimeRecursive run, failing on a missing root or a checkpoint
+// that does not belong to this run
+fn recursive_checkpoint(arguments: &RecursiveArguments) -> Result<Option<checkpoint::Checkpoint>, Failure> {
+    check_root(&arguments.root)?;
+    let identity = vec![
+        ("root", arguments.root.display().to_string()),
+        ("btime", arguments.btime.as_nanos().to_string()),
+    ];
+    open_checkpoint(arguments.checkpoint.as_deref(), "btimeRecursive", identity)
+}
+
+fn follow_symlinks_value<'a>(cx: &mut impl Context<'a>, value: Handle<'a, JsString>) -> NeonResult<FollowSymlinks> {
+    let follow = value.value(cx);
+    match FollowSymlinks::from_name(&follow) {
+        Some(follow_symlinks) => Ok(follow_symlinks),
+        None => cx.throw_type_error(format!("followSymlinks must be 'never', 'dirs' or 'always', got {:?}", follow)),
+    }
+}
+
+fn concurrency_option<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>) -> NeonResult<usize> {
+    let Some(options) = options else {
+        return Ok(config::get().concurrency);
+    };
+    match options.get_opt::<JsNumber, _, _>(cx, "concurrency")? {
+        Some(concurrency) => {
+            let concurrency = concurrency.value(cx);
+            if concurrency.is_nan() || concurrency < 1.0 {
+                return cx.throw_range_error("concurrency must be at least 1");
+            }
+            Ok(concurrency as usize)
+        }
+        None => Ok(config::get().concurrency),
+    }
+}
+
+// Read the caseFold option: whether relative paths that differ only in case
+// name the same entry. 'auto' folds when the volume holding root matches
+// names case-insensitively, going by the platform when that cannot be told
+fn case_fold_option<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsObject>>, root: &Path) -> NeonResult<bool> {
+    let Some(options) = options else {
+        return Ok(false);
+    };
+    let Some(value) = options.get_opt::<JsValue, _, _>(cx, "caseFold")? else {
+        return Ok(false);
+    };
+    if let Ok(case_fold) = value.downcast::<JsBoolean, _>(cx) {
+        return Ok(case_fold.value(cx));
+    }
+    match value.downcast::<JsString, _>(cx) {
+        Ok(mode) if mode.value(cx) == "auto" => {
+            let default = cfg!(any(target_os = "windows", target_os = "macos"));
+            Ok(volume::case_sensitive(root).map_or(default, |sensitive| !sensitive))
+        }
+        _ => cx.throw_type_error("caseFold must be a boolean or 'auto'"),
+    }
+}
+
+// A missing or unreadable root fails the whole recursive call
+fn check_root(root: &Path) -> Result<(), Failure> {
+    match std::fs::read_dir(root) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(Failure::os(os_error_message(&err, format!("scandir({})", root.display())), err)),
+    }
+}
+
+fn tree_result_to_js<'a>(cx: &mut impl Context<'a>, tree_result: &tree::TreeResult) -> JsResult<'a, JsObject> {
+    let errors = cx.empty_array();
+    for (i, err) in tree_result.errors.iter().enumerate() {
+        let object = walk_error_to_js(cx, err)?;
+        errors.set(cx, i as u32, object)?;
+    }
+    let truncated = paths_to_js(cx, &tree_result.truncated)?;
+    let processed = cx.number(tree_result.processed as f64);
+
+    let result = cx.empty_object();
+    result.set(cx, "processed", processed)?;
+    result.set(cx, "errors", errors)?;
+    result.set(cx, "truncated", truncated)?;
+    // With a fallback chain, how many of the writes each backend did
+    if backend::current().chain().is_some() {
+        let backends = cx.empty_object();
+        for (&backend, &count) in &tree_result.backends {
+            let count = cx.number(count as f64);
+            backends.set(cx, backend, count)?;
+        }
+        result.set(cx, "backends", backends)?;
+    }
+    Ok(result)
+}
+
+// Set the birth time of a directory and everything the walker finds below it,
+// without returning to JS per entry
+fn btime_recursive(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let arguments = recursive_arguments(&mut cx)?;
+    let checkpoint = match recursive_checkpoint(&arguments) {
+        Ok(checkpoint) => checkpoint,
+        Err(failure) => return throw_failure(&mut cx, failure),
+    };
+    let RecursiveArguments { root, btime, walk_options, concurrency, .. } = arguments;
+    let work = teardown::work(&mut cx);
+    let tree_result = tree::stamp_tree(&root, btime, walk_options, concurrency, checkpoint, work.cancelled(), &|_, _| {});
+    recursive_result_to_js(&mut cx, &tree_result, arguments.checkpoint.is_some())
+}
+
+// A checkpointed run also reports how many entries it resumed past
+fn recursive_result_to_js<'a>(
+    cx: &mut impl Context<'a>,
+    tree_result: &tree::TreeResult,
+    checkpointed: bool,
+) -> JsResult<'a, JsObject> {
+    let result = tree_result_to_js(cx, tree_result)?;
+    if checkpointed {
+        let resumed = cx.number(tree_result.resumed as f64);
+        result.set(cx, "resumed", resumed)?;
+    }
+    Ok(result)
+}
+
+// Read the onFile option, a callback for each file as it completes
+fn progress_option<'a>(
+    cx: &mut FunctionContext<'a>,
+    options: Option<Handle<'a, JsObject>>,
+) -> NeonResult<Option<Arc<progress::Progress>>> {
+    let Some(options) = options else {
+        return Ok(None);
+    };
+    match options.get_opt::<JsValue, _, _>(cx, "onFile")? {
+        Some(value) if !value.is_a::<JsUndefined, _>(cx) => match value.downcast::<JsFunction, _>(cx) {
+            Ok(callback) => Ok(Some(progress::Progress::new(cx, callback))),
+            Err(_) => cx.throw_type_error("onFile must be a function"),
+        },
+        _ => Ok(None),
+    }
+}
+
+// Promise-returning btimeRecursive that runs off the JS thread
+fn btime_recursive_async(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let arguments = recursive_arguments(&mut cx)?;
+    let options = options_argument(&mut cx, 2)?;
+    let progress = progress_option(&mut cx, options)?;
+    let checkpointed = arguments.checkpoint.is_some();
+    let work = teardown::work(&mut cx);
+    let running = work.start();
+    let reporter = progress.clone();
+    let promise = workers::promise(
+        &mut cx,
+        move || {
+            let _running = running;
+            let checkpoint = recursive_checkpoint(&arguments)?;
+            let RecursiveArguments { root, btime, walk_options, concurrency, .. } = arguments;
+            let completed = |path: &Path, error: Option<&std::io::Error>| {
+                if let Some(progress) = &reporter {
+                    progress.complete(path, error);
+                }
+            };
+            Ok(tree::stamp_tree(&root, btime, walk_options, concurrency, checkpoint, work.cancelled(), &completed))
+        },
+        move |mut cx, result: Result<tree::TreeResult, Failure>| {
+            // Files completed since the last batch are reported before the promise settles
+            if let Some(progress) = progress {
+                progress.deliver(&mut cx)?;
+            }
+            match result {
+                Ok(tree_result) => recursive_result_to_js(&mut cx, &tree_result, checkpointed),
+                Err(failure) => throw_failure(&mut cx, failure),
+            }
+        },
+    );
+    Ok(promise)
+}
+
+// Errors reading or writing a manifest, as thrown to JS
+fn manifest_error_message(err: &std::io::Error, syscall: &str, path: &Path) -> String {
+    match err.raw_os_error() {
+        Some(_) => os_error_message(err, format!("{}({})", syscall, path.display())),
+        None => format!("Invalid manifest {}: {}", path.display(), err),
+    }
+}
+
+// Record the timestamps of a tree in a manifest file: (root, manifestPath, [options])
+fn snapshot_times(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let root = path_argument(&mut cx, 0)?;
+    let manifest_path = path_argument(&mut cx, 1)?;
+    let options = options_argument(&mut cx, 2)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let walk_options = walk_options(&mut cx, options)?;
+    let root = path::resolve(root, cwd.as_deref());
+    let manifest_path = path::resolve(manifest_path, cwd.as_deref());
+    let format = match options {
+        Some(options) => options.get_opt::<JsString, _, _>(&mut cx, "format")?.map(|format| format.value(&mut cx)),
+        None => None,
+    };
+    // A format name may end in .gz or .zst like the manifest's extension
+    let mut snapshot_options = snapshot::SnapshotOptions::default();
+    (snapshot_options.format, snapshot_options.compression) = match format {
+        Some(name) => {
+            let (format, compression) = compress::Compression::split_name(&name);
+            match snapshot::Format::from_name(format) {
+                Some(format) => (format, compression),
+                None => return cx.throw_type_error("format must be 'json', 'ndjson' or 'csv', optionally with '.gz' or '.zst'"),
+            }
+        }
+        None => (snapshot::Format::from_path(&manifest_path), compress::Compression::split_path(&manifest_path).1),
+    };
+    if let Some(options) = options {
+        let flag = |cx: &mut FunctionContext, key: &str| -> NeonResult<Option<bool>> {
+            Ok(options.get_opt::<JsBoolean, _, _>(cx, key)?.map(|value| value.value(cx)))
+        };
+        snapshot_options.append = flag(&mut cx, "append")?.unwrap_or(false);
+        if let Some(checksum) = flag(&mut cx, "checksum")? {
+            snapshot_options.checksums.footer = checksum;
+        }
+        snapshot_options.checksums.entries = flag(&mut cx, "entryChecksums")?.unwrap_or(false);
+        snapshot_options.hash = flag(&mut cx, "hash")?.unwrap_or(false);
+        snapshot_options.links = flag(&mut cx, "hardLinks")?.unwrap_or(false);
+    }
+
+    if let Err(failure) = check_root(&root) {
+        return throw_failure(&mut cx, failure);
+    }
+    match snapshot::snapshot(&root, &manifest_path, &snapshot_options, walk_options) {
+        Ok(tree_result) => tree_result_to_js(&mut cx, &tree_result),
+        Err(err) => throw_os_error(&mut cx, &err, manifest_error_message(&err, "write", &manifest_path)),
+    }
+}
+
+// Read the `times` option naming which timestamps to look at
+fn time_fields_option<'a>(
+    cx: &mut FunctionContext<'a>,
+    options: Handle<'a, JsObject>,
+    default: snapshot::TimeFields,
+) -> NeonResult<snapshot::TimeFields> {
+    match string_array_option(cx, options, "times")? {
+        Some(names) => match snapshot::TimeFields::from_names(&names) {
+            Ok(fields) => Ok(fields),
+            Err(message) => cx.throw_type_error(message),
+        },
+        None => Ok(default),
+    }
+}
+
+// Apply the timestamps recorded in a manifest: (manifestPath, [options])
+fn restore_times(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let manifest_path = path_argument(&mut cx, 0)?;
+    let options = options_argument(&mut cx, 1)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let manifest_path = path::resolve(manifest_path, cwd.as_deref());
+
+    let mut restore_options = snapshot::RestoreOptions::default();
+    if let Some(options) = options {
+        if let Some(root) = options.get_opt::<JsValue, _, _>(&mut cx, "root")? {
+            let root = path_value(&mut cx, root)?;
+            restore_options.root = Some(path::resolve(root, cwd.as_deref()));
+        }
+        if let Some(remap) = options.get_opt::<JsArray, _, _>(&mut cx, "remap")? {
+            if restore_options.root.is_some() {
+                return cx.throw_type_error("root and remap cannot be combined");
+            }
+            for rule in remap.to_vec(&mut cx)? {
+                let rule = rule.downcast_or_throw::<JsObject, _>(&mut cx)?;
+                let from = rule.get::<JsString, _, _>(&mut cx, "from")?.value(&mut cx);
+                let to = rule.get::<JsValue, _, _>(&mut cx, "to")?;
+                let to = path_value(&mut cx, to)?;
+                match snapshot::Remap::new(&from, path::resolve(to, cwd.as_deref())) {
+                    Ok(rule) => restore_options.remap.push(rule),
+                    Err(message) => return cx.throw_type_error(message),
+                }
+            }
+        }
+        restore_options.fields = time_fields_option(&mut cx, options, snapshot::TimeFields::ALL)?;
+        if let Some(detect_moves) = options.get_opt::<JsBoolean, _, _>(&mut cx, "detectMoves")? {
+            restore_options.detect_moves = detect_moves.value(&mut cx);
+        }
+        if let Some(dry_run) = options.get_opt::<JsBoolean, _, _>(&mut cx, "dryRun")? {
+            restore_options.dry_run = dry_run.value(&mut cx);
+        }
+    }
+    let checkpoint_path = checkpoint_option(&mut cx, options, cwd.as_deref())?;
+    if restore_options.dry_run && checkpoint_path.is_some() {
+        return cx.throw_type_error("dryRun and checkpoint cannot be combined");
+    }
+    let bigint = bigint_option(&mut cx, options)?;
+
+    if let Err(err) = snapshot::verify(&manifest_path, config::get().memory_limit) {
+        return throw_os_error(&mut cx, &err, manifest_error_message(&err, "read", &manifest_path));
+    }
+    let (header, entries) = match snapshot::open_manifest(&manifest_path, config::get().memory_limit) {
+        Ok(manifest) => manifest,
+        Err(err) => return throw_os_error(&mut cx, &err, manifest_error_message(&err, "open", &manifest_path)),
+    };
+    // A checkpoint resumes a restore of the same manifest contents to the
+    // same place, so an edited manifest or other remap rules start over
+    let mut identity = vec![("manifest", manifest_path.display().to_string())];
+    if checkpoint_path.is_some() {
+        match checksum::file_checksum(&manifest_path) {
+            Ok(checksum) => identity.push(("checksum", checksum)),
+            Err(err) => return throw_os_error(&mut cx, &err, manifest_error_message(&err, "read", &manifest_path)),
+        }
+        let root = restore_options.root.as_ref().map_or(String::new(), |root| root.display().to_string());
+        let remap: Vec<String> = restore_options.remap.iter().map(ToString::to_string).collect();
+        identity.extend([("root", root), ("remap", remap.join(", "))]);
+    }
+    let checkpoint = match open_checkpoint(checkpoint_path.as_deref(), "restoreTimes", identity) {
+        Ok(checkpoint) => checkpoint,
+        Err(failure) => return throw_failure(&mut cx, failure),
+    };
+    let backup = backup_option(&mut cx, options)?;
+    let restore = || Ok(snapshot::restore(&header, entries, &restore_options, checkpoint));
+    let restore_result = match with_backup_mode(backup, restore) {
+        Ok(Ok(restore_result)) => restore_result,
+        Ok(Err(err)) => return throw_os_error(&mut cx, &err, manifest_error_message(&err, "read", &manifest_path)),
+        Err(err) => return throw_os_error(&mut cx, &err, os_error_message(&err, "AdjustTokenPrivileges(SeBackupPrivilege)")),
+    };
+    let result = recursive_result_to_js(&mut cx, &restore_result.result, checkpoint_path.is_some())?;
+    let unmatched = strings_to_js(&mut cx, &restore_result.unmatched)?;
+    result.set(&mut cx, "unmatched", unmatched)?;
+    if restore_options.dry_run {
+        let dry_run = &restore_result.dry_run;
+        let changes = cx.empty_array();
+        for (i, change) in dry_run.changes.iter().enumerate() {
+            let object = change_to_js(&mut cx, change, bigint)?;
+            // How far each changed time would move, recorded minus current
+            let delta = cx.empty_object();
+            let (recorded, current) = (&change.recorded, &change.current);
+            for (name, recorded, current) in
+                [("btime", recorded.btime, current.btime), ("mtime", recorded.mtime, current.mtime), ("atime", recorded.atime, current.atime)]
+            {
+                if let (true, Some(recorded), Some(current)) = (change.fields.contains(&name), recorded, current) {
+                    let nanos = recorded.as_nanos() - current.as_nanos();
+                    let value: Handle<JsValue> = if bigint {
+                        JsBigInt::from_i128(&mut cx, nanos).upcast()
+                    } else {
+                        cx.number(nanos as f64 / 1e6).upcast()
+                    };
+                    delta.set(&mut cx, name, value)?;
+                }
+            }
+            object.set(&mut cx, "delta", delta)?;
+            changes.set(&mut cx, i as u32, object)?;
+        }
+        result.set(&mut cx, "changes", changes)?;
+        let missing = strings_to_js(&mut cx, &dry_run.missing)?;
+        result.set(&mut cx, "missing", missing)?;
+        let blocked = cx.empty_array();
+        for (i, (path, obstacles)) in dry_run.blocked.iter().enumerate() {
+            let item = cx.empty_object();
+            let path = cx.string(path);
+            let reasons: Vec<String> = obstacles.iter().map(|obstacle| obstacle.name().to_string()).collect();
+            let reasons = strings_to_js(&mut cx, &reasons)?;
+            item.set(&mut cx, "path", path)?;
+            item.set(&mut cx, "reasons", reasons)?;
+            blocked.set(&mut cx, i as u32, item)?;
+        }
+        result.set(&mut cx, "blocked", blocked)?;
+    }
+    if restore_options.detect_moves {
+        let moved = cx.empty_array();
+        for (i, (from, to)) in restore_result.moved.iter().enumerate() {
+            let item = cx.empty_object();
+            let from = cx.string(from);
+            let to = cx.string(to.to_string_lossy());
+            item.set(&mut cx, "from", from)?;
+            item.set(&mut cx, "to", to)?;
+            moved.set(&mut cx, i as u32, item)?;
+        }
+        result.set(&mut cx, "moved", moved)?;
+    }
+    let links = cx.empty_array();
+    for (i, names) in restore_result.links.iter().enumerate() {
+        let names = strings_to_js(&mut cx, names)?;
+        links.set(&mut cx, i as u32, names)?;
+    }
+    result.set(&mut cx, "links", links)?;
+    Ok(result)
+}
+
+// A changed entry as { path, fields, recorded, current }
+fn change_to_js<'a>(cx: &mut impl Context<'a>, change: &snapshot::Change, bigint: bool) -> JsResult<'a, JsObject> {
+    let object = cx.empty_object();
+    let path = cx.string(&change.path);
+    object.set(cx, "path", path)?;
+    let changed_fields = cx.empty_array();
+    for (j, field) in change.fields.iter().enumerate() {
+        let field = cx.string(field);
+        changed_fields.set(cx, j as u32, field)?;
+    }
+    object.set(cx, "fields", changed_fields)?;
+    let recorded = times_to_js(cx, &FileTimes { ctime: None, ..change.recorded }, bigint)?;
+    object.set(cx, "recorded", recorded)?;
+    let current = times_to_js(cx, &FileTimes { ctime: None, ..change.current }, bigint)?;
+    object.set(cx, "current", current)?;
+    Ok(object)
+}
+
+// Compare a tree with a manifest: (manifestPath, root, [options]). Returns
+// { changed, missing, added, errors, truncated } with manifest-relative paths
+fn diff_times(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let manifest_path = path_argument(&mut cx, 0)?;
+    let root = path_argument(&mut cx, 1)?;
+    let options = options_argument(&mut cx, 2)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let bigint = bigint_option(&mut cx, options)?;
+    let walk_options = walk_options(&mut cx, options)?;
+    // Access times change on every read, so they are only compared on request
+    let default_fields = snapshot::TimeFields { atime: false, ..snapshot::TimeFields::ALL };
+    let fields = match options {
+        Some(options) => time_fields_option(&mut cx, options, default_fields)?,
+        None => default_fields,
+    };
+    let manifest_path = path::resolve(manifest_path, cwd.as_deref());
+    let root = path::resolve(root, cwd.as_deref());
+
+    let manifest = match snapshot::read_manifest(&manifest_path) {
+        Ok(manifest) => manifest,
+        Err(err) => return throw_os_error(&mut cx, &err, manifest_error_message(&err, "open", &manifest_path)),
+    };
+    if let Err(failure) = check_root(&root) {
+        return throw_failure(&mut cx, failure);
+    }
+    let case_fold = case_fold_option(&mut cx, options, &root)?;
+    let diff = match snapshot::diff(&manifest, &root, walk_options, fields, case_fold) {
+        Ok(diff) => diff,
+        Err(err) => return throw_os_error(&mut cx, &err, stat_error_message(&err, &root, true)),
+    };
+
+    let changed = cx.empty_array();
+    for (i, change) in diff.changed.iter().enumerate() {
+        let object = change_to_js(&mut cx, change, bigint)?;
+        changed.set(&mut cx, i as u32, object)?;
+    }
+    let missing = strings_to_js(&mut cx, &diff.missing)?;
+    let added = strings_to_js(&mut cx, &diff.added)?;
+    let errors = cx.empty_array();
+    for (i, err) in diff.errors.iter().enumerate() {
+        let object = walk_error_to_js(&mut cx, err)?;
+        errors.set(&mut cx, i as u32, object)?;
+    }
+    let truncated = paths_to_js(&mut cx, &diff.truncated)?;
+
+    let result = cx.empty_object();
+    result.set(&mut cx, "changed", changed)?;
+    result.set(&mut cx, "missing", missing)?;
+    result.set(&mut cx, "added", added)?;
+    result.set(&mut cx, "errors", errors)?;
+    result.set(&mut cx, "truncated", truncated)?;
+    Ok(result)
+}
+
+// Set individual birth times below a root: (root, [{ path, firstCommitUnixTime }], [options]).
+// Relative paths are resolved against root
+fn apply_birth_times(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let root = path_argument(&mut cx, 0)?;
+    let list = cx.argument::<JsArray>(1)?;
+    let options = options_argument(&mut cx, 2)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let concurrency = concurrency_option(&mut cx, options)?;
+    let transaction = match options {
+        Some(options) => options.get_opt::<JsBoolean, _, _>(&mut cx, "transaction")?.is_some_and(|set| set.value(&mut cx)),
+        None => false,
+    };
+    let simulate = match options {
+        Some(options) => options.get_opt::<JsBoolean, _, _>(&mut cx, "simulate")?.is_some_and(|simulate| simulate.value(&mut cx)),
+        None => false,
+    };
+    let refuse_hard_links = match options {
+        Some(options) => options.get_opt::<JsBoolean, _, _>(&mut cx, "refuseHardLinks")?.is_some_and(|refuse| refuse.value(&mut cx)),
+        None => false,
+    };
+    let bigint = bigint_option(&mut cx, options)?;
+    let root = path::resolve(root, cwd.as_deref());
+
+    let mut items = Vec::new();
+    for item in list.to_vec(&mut cx)? {
+        let item = item.downcast_or_throw::<JsObject, _>(&mut cx)?;
+        let path = item.get::<JsValue, _, _>(&mut cx, "path")?;
+        let path = path_value(&mut cx, path)?;
+        let time = item.get::<JsValue, _, _>(&mut cx, "firstCommitUnixTime")?;
+        let time = timestamp_value(&mut cx, time)?;
+        items.push((path::resolve(path, Some(&root)), time));
+    }
+
+    // Files with other names, whose birth time each write changes for all
+    // of them. Refused ones are left out and reported as errors
+    let paths: Vec<PathBuf> = items.iter().map(|(path, _)| path.clone()).collect();
+    let shared = links::shared(&paths, concurrency);
+    let mut refused = Vec::new();
+    if refuse_hard_links {
+        for &(i, names) in shared.iter().rev() {
+            let (path, _) = items.remove(i);
+            refused.push(walk::WalkError { path, error: links::refused(names) });
+        }
+        refused.reverse();
+    }
+
+    let mut simulated = None;
+    let mut tree_result = if simulate {
+        let (simulations, tree_result) = simulate::simulate_paths(items, concurrency);
+        simulated = Some(simulations);
+        tree_result
+    } else if !transaction {
+        tree::stamp_paths(items, concurrency)
+    } else {
+        let items = items.into_iter().map(|(path, btime)| (path, FileTimes { btime: Some(btime), ..FileTimes::default() }));
+        match transaction::set_paths(items.collect(), concurrency) {
+            Ok(tree_result) => tree_result,
+            Err(aborted) => return throw_aborted(&mut cx, &aborted),
+        }
+    };
+    tree_result.errors.splice(0..0, refused);
+
+    let result = tree_result_to_js(&mut cx, &tree_result)?;
+    let array = cx.empty_array();
+    for (i, &(index, names)) in shared.iter().enumerate() {
+        let object = cx.empty_object();
+        let path = cx.string(paths[index].to_string_lossy());
+        let names = cx.number(names as f64);
+        object.set(&mut cx, "path", path)?;
+        object.set(&mut cx, "sharedWithLinks", names)?;
+        array.set(&mut cx, i as u32, object)?;
+    }
+    result.set(&mut cx, "shared", array)?;
+    if let Some(simulations) = simulated {
+        let array = cx.empty_array();
+        for (i, simulation) in simulations.iter().enumerate() {
+            let object = simulation_to_js(&mut cx, simulation, true, bigint)?;
+            array.set(&mut cx, i as u32, object)?;
+        }
+        result.set(&mut cx, "simulated", array)?;
+    }
+    Ok(result)
+}
+
+// Throw for a transaction that was given up, with its errors and the
+// outcome of the rollback as properties of the error
+fn throw_aborted<'a, T: Value>(cx: &mut FunctionContext<'a>, aborted: &transaction::Aborted) -> JsResult<'a, T> {
+    let message = match aborted.errors.first() {
+        Some(first) => format!(
+            "transaction aborted at {}: {}, {} paths rolled back",
+            first.path.display(),
+            first.error,
+            aborted.rolled_back
+        ),
+        None => "transaction aborted".to_string(),
+    };
+    let error = cx.error(message)?;
+    let errors = cx.empty_array();
+    for (i, err) in aborted.errors.iter().enumerate() {
+        let object = walk_error_to_js(cx, err)?;
+        errors.set(cx, i as u32, object)?;
+    }
+    let rollback_errors = cx.empty_array();
+    for (i, err) in aborted.rollback_errors.iter().enumerate() {
+        let object = walk_error_to_js(cx, err)?;
+        rollback_errors.set(cx, i as u32, object)?;
+    }
+    let rolled_back = cx.number(aborted.rolled_back as f64);
+    error.set(cx, "errors", errors)?;
+    error.set(cx, "rolledBack", rolled_back)?;
+    error.set(cx, "rollbackErrors", rollback_errors)?;
+    cx.throw(error)
+}
+
+// Read when each file of a repository's HEAD was first committed:
+// (repoPath, [options]) => [{ path, firstCommitUnixTime }]
+#[cfg(feature = "git")]
+fn git_birth_times(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let repo_path = path_argument(&mut cx, 0)?;
+    let options = options_argument(&mut cx, 1)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let repo_path = path::resolve(repo_path, cwd.as_deref());
+
+    let times = match git::first_commit_times(&repo_path) {
+        Ok(times) => times,
+        Err(error_message) => return cx.throw_error(error_message),
+    };
+    let result = cx.empty_array();
+    for (i, (path, seconds)) in times.iter().enumerate() {
+        let object = cx.empty_object();
+        let path = cx.string(path);
+        let seconds = cx.number(*seconds as f64);
+        object.set(&mut cx, "path", path)?;
+        object.set(&mut cx, "firstCommitUnixTime", seconds)?;
+        result.set(&mut cx, i as u32, object)?;
+    }
+    Ok(result)
+}
+
+// Copy timestamps from one tree to another by relative path:
+// (srcRoot, dstRoot, [options])
+fn mirror_times(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let source = path_argument(&mut cx, 0)?;
+    let destination = path_argument(&mut cx, 1)?;
+    let options = options_argument(&mut cx, 2)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let walk_options = walk_options(&mut cx, options)?;
+    let concurrency = concurrency_option(&mut cx, options)?;
+    let default_fields = snapshot::TimeFields { atime: false, ..snapshot::TimeFields::ALL };
+    let fields = match options {
+        Some(options) => time_fields_option(&mut cx, options, default_fields)?,
+        None => default_fields,
+    };
+    let source = path::resolve(source, cwd.as_deref());
+    let destination = path::resolve(destination, cwd.as_deref());
+
+    for root in [&source, &destination] {
+        if let Err(failure) = check_root(root) {
+            return throw_failure(&mut cx, failure);
+        }
+    }
+    let case_fold = case_fold_option(&mut cx, options, &destination)?;
+    let mirror_result = match mirror::mirror_times(&source, &destination, walk_options, fields, concurrency, case_fold) {
+        Ok(mirror_result) => mirror_result,
+        Err(err) => return throw_os_error(&mut cx, &err, err.to_string()),
+    };
+
+    let updated = cx.number(mirror_result.updated as f64);
+    let unchanged = cx.number(mirror_result.unchanged as f64);
+    let missing = strings_to_js(&mut cx, &mirror_result.missing)?;
+    let extra = strings_to_js(&mut cx, &mirror_result.extra)?;
+    let mismatched = strings_to_js(&mut cx, &mirror_result.mismatched)?;
+    let errors = cx.empty_array();
+    for (i, err) in mirror_result.errors.iter().enumerate() {
+        let object = walk_error_to_js(&mut cx, err)?;
+        errors.set(&mut cx, i as u32, object)?;
+    }
+    let truncated = paths_to_js(&mut cx, &mirror_result.truncated)?;
+
+    let result = cx.empty_object();
+    result.set(&mut cx, "updated", updated)?;
+    result.set(&mut cx, "unchanged", unchanged)?;
+    result.set(&mut cx, "missing", missing)?;
+    result.set(&mut cx, "extra", extra)?;
+    result.set(&mut cx, "mismatched", mismatched)?;
+    result.set(&mut cx, "errors", errors)?;
+    result.set(&mut cx, "truncated", truncated)?;
+    Ok(result)
+}
+
+// A times queue as handed to JS, with the cwd its paths are resolved against
+struct TimesQueueHandle {
+    queue: RefCell<queue::TimesQueue>,
+    cwd: Option<PathBuf>,
+}
+
+impl Finalize for TimesQueueHandle {}
+
+// Start a queue of timestamps to apply while an archive extracts:
+// ([{ cwd, concurrency, batchSize }]) -> queue
+fn create_times_queue(mut cx: FunctionContext) -> JsResult<JsBox<TimesQueueHandle>> {
+    let options = options_argument(&mut cx, 0)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let concurrency = concurrency_option(&mut cx, options)?;
+    let batch_size = match options {
+        Some(options) => match options.get_opt::<JsNumber, _, _>(&mut cx, "batchSize")? {
+            Some(batch_size) => {
+                let batch_size = batch_size.value(&mut cx);
+                if batch_size.is_nan() || batch_size < 1.0 {
+                    return cx.throw_range_error("batchSize must be at least 1");
+                }
+                batch_size as usize
+            }
+            None => DEFAULT_BATCH_SIZE,
+        },
+        None => DEFAULT_BATCH_SIZE,
+    };
+    let queue = RefCell::new(queue::TimesQueue::new(batch_size, concurrency));
+    Ok(cx.boxed(TimesQueueHandle { queue, cwd }))
+}
+
+// Files a times queue collects before setting them
+const DEFAULT_BATCH_SIZE: usize = 256;
+
+// A timestamp of a queued entry, which may also be a Date as found in tar headers
+fn entry_timestamp<'a>(cx: &mut FunctionContext<'a>, entry: Handle<'a, JsObject>, key: &str) -> NeonResult<Option<Timestamp>> {
+    let Some(value) = entry.get_opt::<JsValue, _, _>(cx, key)? else {
+        return Ok(None);
+    };
+    if value.is_a::<JsNull, _>(cx) || value.is_a::<JsUndefined, _>(cx) {
+        return Ok(None);
+    }
+    if let Ok(date) = value.downcast::<JsDate, _>(cx) {
+        return match Timestamp::from_secs_f64(date.value(cx) / 1000.0) {
+            Some(timestamp) => Ok(Some(timestamp)),
+            None => cx.throw_range_error("timestamp out of range"),
+        };
+    }
+    timestamp_value(cx, value).map(Some)
+}
+
+// Queue the timestamps of an extracted entry:
+// (queue, { path, type, btime, mtime, atime }). Directories wait for
+// finishTimesQueue, symlinks are skipped
+fn queue_times(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let handle = cx.argument::<JsBox<TimesQueueHandle>>(0)?;
+    let entry = cx.argument::<JsObject>(1)?;
+    let path = entry.get::<JsValue, _, _>(&mut cx, "path")?;
+    let path = path::resolve(path_value(&mut cx, path)?, handle.cwd.as_deref());
+    let entry_type = match entry.get_opt::<JsString, _, _>(&mut cx, "type")? {
+        Some(entry_type) => entry_type.value(&mut cx),
+        None => "file".to_string(),
+    };
+    let times = FileTimes {
+        btime: entry_timestamp(&mut cx, entry, "btime")?,
+        mtime: entry_timestamp(&mut cx, entry, "mtime")?,
+        atime: entry_timestamp(&mut cx, entry, "atime")?,
+        ctime: None,
+    };
+
+    let mut queue = handle.queue.borrow_mut();
+    if queue.is_finished() {
+        return cx.throw_error("times queue is already finished");
+    }
+    match entry_type.as_str() {
+        "directory" => queue.push_deferred(path, times),
+        "symlink" => {}
+        _ => queue.push_file(path, times),
+    }
+    Ok(cx.undefined())
+}
+
+// Queue a node-tar ReadEntry as passed to onReadEntry: (queue, entry, [{ btime }]).
+// node-tar writes the entry's data after the hook returns, so the entry is
+// held until finishTimesQueue. Archives carry no birth time node-tar reads,
+// so the one given at extraction is kept unless `btime: 'mtime'` asks for
+// the archived mtime
+fn queue_tar_entry(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let handle = cx.argument::<JsBox<TimesQueueHandle>>(0)?;
+    let entry = cx.argument::<JsObject>(1)?;
+    let options = options_argument(&mut cx, 2)?;
+    let btime_from_mtime = match options {
+        Some(options) => match options.get_opt::<JsString, _, _>(&mut cx, "btime")? {
+            Some(btime) => match btime.value(&mut cx).as_str() {
+                "mtime" => true,
+                "keep" => false,
+                other => return cx.throw_type_error(format!("btime must be 'mtime' or 'keep', got {:?}", other)),
+            },
+            None => false,
+        },
+        None => false,
+    };
+    let path = entry.get::<JsValue, _, _>(&mut cx, "path")?;
+    let path = path::resolve(path_value(&mut cx, path)?, handle.cwd.as_deref());
+    let entry_type = match entry.get_opt::<JsString, _, _>(&mut cx, "type")? {
+        Some(entry_type) => entry_type.value(&mut cx),
+        None => "File".to_string(),
+    };
+    let mtime = entry_timestamp(&mut cx, entry, "mtime")?;
+    let times = FileTimes {
+        btime: mtime.filter(|_| btime_from_mtime),
+        mtime,
+        atime: entry_timestamp(&mut cx, entry, "atime")?,
+        ctime: None,
+    };
+
+    let mut queue = handle.queue.borrow_mut();
+    if queue.is_finished() {
+        return cx.throw_error("times queue is already finished");
+    }
+    // Links are followed by the setters, so a symlink would stamp its target
+    if entry_type != "SymbolicLink" {
+        queue.push_deferred(path, times);
+    }
+    Ok(cx.undefined())
+}
+
+// Apply everything left in the queue, directories last:
+// (queue) -> { processed, errors, truncated }
+fn finish_times_queue(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let handle = cx.argument::<JsBox<TimesQueueHandle>>(0)?;
+    let mut queue = handle.queue.borrow_mut();
+    if queue.is_finished() {
+        return cx.throw_error("times queue is already finished");
+    }
+    let tree_result = queue.finish();
+    drop(queue);
+    tree_result_to_js(&mut cx, &tree_result)
+}
+
+// A time in the conventions of the `utimes` npm package: milliseconds as a
+// number or a Date, undefined or null for a time to leave alone
+fn compat_millis<'a>(cx: &mut FunctionContext<'a>, value: Option<Handle<'a, JsValue>>) -> NeonResult<Option<Timestamp>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    if value.is_a::<JsNull, _>(cx) || value.is_a::<JsUndefined, _>(cx) {
+        return Ok(None);
+    }
+    let millis = if let Ok(date) = value.downcast::<JsDate, _>(cx) {
+        date.value(cx)
+    } else if let Ok(number) = value.downcast::<JsNumber, _>(cx) {
+        number.value(cx)
+    } else {
+        return cx.throw_type_error("times must be milliseconds as numbers or Dates");
+    };
+    match Timestamp::from_secs_f64(millis / 1000.0) {
+        Some(timestamp) => Ok(Some(timestamp)),
+        None => cx.throw_range_error("timestamp out of range"),
+    }
+}
+
+// Drop-in replacement for the `utimes` npm package:
+// (paths, times, [callback]) where paths is one path or an array and times
+// is one millisecond value for all three times or { btime, mtime, atime }.
+// The writes run off the main thread; without a callback a promise is
+// returned. Birth times are skipped where the platform cannot set them
+fn utimes_compat(mut cx: FunctionContext, follow: bool) -> JsResult<JsValue> {
+    let target = cx.argument::<JsValue>(0)?;
+    let mut paths = Vec::new();
+    if let Ok(list) = target.downcast::<JsArray, _>(&mut cx) {
+        for value in list.to_vec(&mut cx)? {
+            paths.push(path_value(&mut cx, value)?);
+        }
+    } else {
+        paths.push(path_value(&mut cx, target)?);
+    }
+
+    let value = cx.argument::<JsValue>(1)?;
+    let is_date = value.is_a::<JsDate, _>(&mut cx);
+    let times = match value.downcast::<JsObject, _>(&mut cx) {
+        Ok(object) if !is_date => {
+            let btime = object.get_opt::<JsValue, _, _>(&mut cx, "btime")?;
+            let mtime = object.get_opt::<JsValue, _, _>(&mut cx, "mtime")?;
+            let atime = object.get_opt::<JsValue, _, _>(&mut cx, "atime")?;
+            FileTimes {
+                btime: compat_millis(&mut cx, btime)?,
+                mtime: compat_millis(&mut cx, mtime)?,
+                atime: compat_millis(&mut cx, atime)?,
+                ctime: None,
+            }
+        }
+        _ => {
+            let time = compat_millis(&mut cx, Some(value))?;
+            FileTimes { btime: time, mtime: time, atime: time, ctime: None }
+        }
+    };
+
+    let callback = cx.argument_opt(2);
+    run_utimes(&mut cx, paths, times, follow, callback)
+}
+
+// Write times to paths on a worker thread for the compatibility exports,
+// then call callback with null or the error, or settle the returned promise
+// when there is no callback
+fn run_utimes<'a>(
+    cx: &mut FunctionContext<'a>,
+    paths: Vec<PathBuf>,
+    times: FileTimes,
+    follow: bool,
+    callback: Option<Handle<'a, JsValue>>,
+) -> JsResult<'a, JsValue> {
+    let write = move || -> Result<(), Failure> {
+        for path in &paths {
+            if let Err(err) = write_times(path, &times, follow) {
+                let syscall = if follow { "utimes" } else { "lutimes" };
+                let message = with_hint(os_error_message(&err, format!("{}({})", syscall, path.display())), &err);
+                return Err(Failure::os(message, err));
+            }
+        }
+        Ok(())
+    };
+    let Some(callback) = callback.filter(|value| !value.is_a::<JsUndefined, _>(cx)) else {
+        let promise = workers::promise(cx, write, |mut cx, result| match result {
+            Ok(()) => Ok(cx.undefined()),
+            Err(failure) => throw_failure(&mut cx, failure),
+        });
+        return Ok(promise.upcast());
+    };
+    let callback = callback.downcast_or_throw::<JsFunction, _>(cx)?.root(cx);
+    workers::and_then(cx, write, move |mut cx, result| {
+        let callback = callback.into_inner(&mut cx);
+        let error = match result {
+            Ok(()) => cx.null().upcast::<JsValue>(),
+            Err(failure) => failure_to_js(&mut cx, &failure)?.upcast(),
+        };
+        callback.call_with(&cx).arg(error).exec(&mut cx)
+    });
+    Ok(cx.undefined().upcast())
+}
+
+fn utimes(cx: FunctionContext) -> JsResult<JsValue> {
+    utimes_compat(cx, true)
+}
+
+// utimes for symlinks themselves
+fn lutimes(cx: FunctionContext) -> JsResult<JsValue> {
+    utimes_compat(cx, false)
+}
+
+// The contract of @ronomon/utimes: (path, btime, mtime, atime, callback) with
+// times in milliseconds, 0 or undefined leaving a time alone
+fn ronomon_compat(mut cx: FunctionContext, follow: bool) -> JsResult<JsValue> {
+    let path = path_argument(&mut cx, 0)?;
+    let mut fields = [None; 3];
+    for (i, field) in fields.iter_mut().enumerate() {
+        let value = cx.argument_opt(i + 1);
+        *field = compat_millis(&mut cx, value)?.filter(|time| *time != Timestamp { sec: 0, nsec: 0 });
     }
-    
-    // Get the buffer containing the path
-    let path_buffer = cx.argument::<JsBuffer>(0)?;
-    let path_bytes = path_buffer.as_slice(&cx).to_vec();
-    
-    // Find the null terminator
-    let null_pos = path_bytes.iter().position(|&b| b == 0)
-        .unwrap_or(path_bytes.len());
-    
-    // Convert to a UTF-8 string up to the null terminator
-    let path_str = match std::str::from_utf8(&path_bytes[0..null_pos]) {
-        Ok(s) => s,
-        Err(_) => return cx.throw_error("Invalid UTF-8 in path"),
+    let [btime, mtime, atime] = fields;
+    let callback = cx.argument::<JsFunction>(4)?.upcast();
+    run_utimes(&mut cx, vec![path], FileTimes { btime, mtime, atime, ctime: None }, follow, Some(callback))
+}
+
+fn ronomon_utimes(cx: FunctionContext) -> JsResult<JsValue> {
+    ronomon_compat(cx, true)
+}
+
+fn ronomon_lutimes(cx: FunctionContext) -> JsResult<JsValue> {
+    ronomon_compat(cx, false)
+}
+
+// Run a callback that rewrites files and put their birth times back once it
+// settles, also when it throws: (paths, callback, [{ cwd }]) -> Promise.
+// Paths that cannot be read beforehand are not restored
+fn preserve_birth_times(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let list = cx.argument::<JsArray>(0)?;
+    let callback = cx.argument::<JsFunction>(1)?;
+    let options = options_argument(&mut cx, 2)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let mut saved = Vec::new();
+    for value in list.to_vec(&mut cx)? {
+        let path = path::resolve(path_value(&mut cx, value)?, cwd.as_deref());
+        if let Ok(FileTimes { btime: Some(btime), .. }) = times::get_times(&path, true) {
+            saved.push((path, FileTimes { btime: Some(btime), ..FileTimes::default() }));
+        }
+    }
+
+    // Errors of the restore replace the callback's outcome, as with finally
+    let restore = JsFunction::new(&mut cx, move |mut cx| {
+        let failed: Vec<_> = saved.iter().filter_map(|(path, times)| set_times(path, times).err().map(|err| (path, err))).collect();
+        match failed.first() {
+            None => Ok(cx.undefined()),
+            Some((path, err)) => {
+                let message = format!(
+                    "could not restore the birth times of {} paths, first {}",
+                    failed.len(),
+                    os_error_message(err, format!("utimes({})", path.display()))
+                );
+                throw_os_error(&mut cx, err, message)
+            }
+        }
+    })?;
+
+    // Promise.resolve().then(callback) also turns a synchronous throw into a rejection
+    let promise_class = cx.global::<JsFunction>("Promise")?;
+    let resolve = promise_class.get::<JsFunction, _, _>(&mut cx, "resolve")?;
+    let settled = resolve.call_with(&cx).this(promise_class).apply::<JsObject, _>(&mut cx)?;
+    let then = settled.get::<JsFunction, _, _>(&mut cx, "then")?;
+    let running = then.call_with(&cx).this(settled).arg(callback).apply::<JsObject, _>(&mut cx)?;
+    let finally = running.get::<JsFunction, _, _>(&mut cx, "finally")?;
+    finally.call_with(&cx).this(running).arg(restore).apply(&mut cx)
+}
+
+// Copy a file and keep its timestamps: (src, dst, [{ cwd }]) -> { strategy }
+fn copy_file_with_times(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let src = path_argument(&mut cx, 0)?;
+    let dst = path_argument(&mut cx, 1)?;
+    let options = options_argument(&mut cx, 2)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let src = path::resolve(src, cwd.as_deref());
+    let dst = path::resolve(dst, cwd.as_deref());
+
+    let strategy = match copy::copy_file_with_times(&src, &dst) {
+        Ok(strategy) => strategy,
+        Err(err) => {
+            let message =
+                os_error_message(&err.error, format!("{}({}, {})", err.syscall, src.display(), dst.display()));
+            return throw_os_error(&mut cx, &err.error, with_hint(message, &err.error));
+        }
     };
-    
-    // Get the btime seconds parameter
-    let btime_seconds = cx.argument::<JsNumber>(1)?.value(&mut cx) as u64;
-    
-    // Try to set the birth time
-    match set_btime(path_str, btime_seconds) {
-        Ok(_) => Ok(cx.number(0)), // Return 0 on success (like the original C++ implementation)
+    let result = cx.empty_object();
+    let strategy = cx.string(strategy.name());
+    result.set(&mut cx, "strategy", strategy)?;
+    Ok(result)
+}
+
+// Copy a tree and keep the timestamps of every entry:
+// (srcRoot, dstRoot, [options]) -> { files, directories, links, skipped, errors, truncated }
+fn copy_tree_with_times(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let source = path_argument(&mut cx, 0)?;
+    let destination = path_argument(&mut cx, 1)?;
+    let options = options_argument(&mut cx, 2)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let walk_options = walk_options(&mut cx, options)?;
+    let concurrency = concurrency_option(&mut cx, options)?;
+    let source = path::resolve(source, cwd.as_deref());
+    let destination = path::resolve(destination, cwd.as_deref());
+
+    if let Err(failure) = check_root(&source) {
+        return throw_failure(&mut cx, failure);
+    }
+    let copy_result = match copytree::copy_tree(&source, &destination, walk_options, concurrency) {
+        Ok(copy_result) => copy_result,
         Err(err) => {
-            let error_message = format!("({}) utimes({})", err.raw_os_error().unwrap_or(-1), path_str);
-            cx.throw_error(error_message)
+            let message = os_error_message(&err, format!("mkdir({})", destination.display()));
+            return throw_os_error(&mut cx, &err, with_hint(message, &err));
         }
+    };
+
+    let files = cx.number(copy_result.files as f64);
+    let directories = cx.number(copy_result.directories as f64);
+    let links = cx.number(copy_result.links as f64);
+    let skipped = strings_to_js(&mut cx, &copy_result.skipped)?;
+    let errors = cx.empty_array();
+    for (i, err) in copy_result.errors.iter().enumerate() {
+        let object = walk_error_to_js(&mut cx, err)?;
+        errors.set(&mut cx, i as u32, object)?;
     }
+    let truncated = paths_to_js(&mut cx, &copy_result.truncated)?;
+
+    let result = cx.empty_object();
+    result.set(&mut cx, "files", files)?;
+    result.set(&mut cx, "directories", directories)?;
+    result.set(&mut cx, "links", links)?;
+    result.set(&mut cx, "skipped", skipped)?;
+    result.set(&mut cx, "errors", errors)?;
+    result.set(&mut cx, "truncated", truncated)?;
+    Ok(result)
 }
 
-// Platform-specific implementation of setting birth time
-#[cfg(target_os = "windows")]
-fn set_btime(path: &str, seconds: u64) -> std::io::Result<()> {
-    use std::fs::OpenOptions;
-    use std::os::windows::fs::OpenOptionsExt;
-    use std::os::windows::io::AsRawHandle;
-    use windows::Win32::Foundation::{CloseHandle, FILETIME, HANDLE};
-    use windows::Win32::Storage::FileSystem::{SetFileTime, FILE_WRITE_ATTRIBUTES};
-    
-    // Convert Unix timestamp to Windows FILETIME
-    let intervals = seconds * 10_000_000 + 116_444_736_000_000_000;
-    let ft = FILETIME {
-        dwLowDateTime: (intervals & 0xFFFFFFFF) as u32,
-        dwHighDateTime: (intervals >> 32) as u32,
+// Clone a file without copying its data and keep its timestamps:
+// (src, dst, [{ cwd }]) -> { strategy }
+fn clone_with_times(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let src = path_argument(&mut cx, 0)?;
+    let dst = path_argument(&mut cx, 1)?;
+    let options = options_argument(&mut cx, 2)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let src = path::resolve(src, cwd.as_deref());
+    let dst = path::resolve(dst, cwd.as_deref());
+
+    let strategy = match copy::clone_file_with_times(&src, &dst) {
+        Ok(strategy) => strategy,
+        Err(err) => {
+            let message =
+                os_error_message(&err.error, format!("{}({}, {})", err.syscall, src.display(), dst.display()));
+            return throw_os_error(&mut cx, &err.error, with_hint(message, &err.error));
+        }
     };
-    
-    // Open the file with write attributes permission
-    let file = OpenOptions::new()
-        .write(true)
-        .custom_flags(FILE_WRITE_ATTRIBUTES.0)
-        .open(path)?;
-    
-    // Get the file handle
-    let handle = HANDLE(file.as_raw_handle() as isize);
-    
-    // Set the creation time (birth time)
-    let result = unsafe { SetFileTime(handle, Some(&ft), None, None) };
-    
-    if !result.as_bool() {
-        return Err(std::io::Error::last_os_error());
+    let result = cx.empty_object();
+    let strategy = cx.string(strategy.name());
+    result.set(&mut cx, "strategy", strategy)?;
+    Ok(result)
+}
+
+// Replace a file's contents through a temporary file and a rename, keeping
+// its birth time: (path, data, [{ btime, mtime, atime }], [{ cwd }])
+fn write_file_atomic_with_times(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let path = path_argument(&mut cx, 0)?;
+    let data = cx.argument::<JsValue>(1)?;
+    let data = if let Ok(string) = data.downcast::<JsString, _>(&mut cx) {
+        string.value(&mut cx).into_bytes()
+    } else if let Ok(bytes) = data.downcast::<JsTypedArray<u8>, _>(&mut cx) {
+        bytes.as_slice(&cx).to_vec()
+    } else {
+        return cx.throw_type_error("data must be a string, Buffer or Uint8Array");
+    };
+    let mut times = FileTimes::default();
+    if let Some(object) = cx.argument_opt(2).and_then(|value| value.downcast::<JsObject, _>(&mut cx).ok()) {
+        times.btime = entry_timestamp(&mut cx, object, "btime")?;
+        times.mtime = entry_timestamp(&mut cx, object, "mtime")?;
+        times.atime = entry_timestamp(&mut cx, object, "atime")?;
     }
-    
-    // The file is closed automatically when it goes out of scope
-    Ok(())
+    let options = options_argument(&mut cx, 3)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let path = path::resolve(path, cwd.as_deref());
+
+    if let Err(err) = atomic::write_file(&path, &data, times) {
+        let message = os_error_message(&err.error, format!("{}({})", err.syscall, path.display()));
+        return throw_os_error(&mut cx, &err.error, with_hint(message, &err.error));
+    }
+    Ok(cx.undefined())
 }
 
-#[cfg(target_os = "macos")]
-fn set_btime(path: &str, seconds: u64) -> std::io::Result<()> {
-    use std::ffi::CString;
-    use std::os::unix::ffi::OsStrExt;
-    use std::os::raw::{c_char, c_int};
-    use std::path::PathBuf;
-    
-    // Create C-compatible path string
-    let path_buf = PathBuf::from(path);
-    let c_path = CString::new(path_buf.as_os_str().as_bytes())
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Path contains null bytes"))?;
-    
-    #[repr(C)]
-    struct Timespec {
-        tv_sec: i64,
-        tv_nsec: i64,
+// Transfer metadata classes between files:
+// (src, dst, [{ cwd, times, mode, owner, xattrs, flags }]) -> { skipped }
+fn clone_metadata(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let src = path_argument(&mut cx, 0)?;
+    let dst = path_argument(&mut cx, 1)?;
+    let options = options_argument(&mut cx, 2)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let src = path::resolve(src, cwd.as_deref());
+    let dst = path::resolve(dst, cwd.as_deref());
+
+    // Everything but ownership unless deselected, as chown mostly needs root
+    let class = |cx: &mut FunctionContext, key: &str, default: bool| -> NeonResult<bool> {
+        let Some(options) = options else {
+            return Ok(default);
+        };
+        Ok(options.get_opt::<JsBoolean, _, _>(cx, key)?.map(|value| value.value(cx)).unwrap_or(default))
+    };
+    let classes = clone::Classes {
+        times: class(&mut cx, "times", true)?,
+        mode: class(&mut cx, "mode", true)?,
+        owner: class(&mut cx, "owner", false)?,
+        xattrs: class(&mut cx, "xattrs", true)?,
+        flags: class(&mut cx, "flags", true)?,
+    };
+
+    let skipped = match clone::clone_metadata(&src, &dst, classes) {
+        Ok(skipped) => skipped,
+        Err(err) => {
+            let message =
+                os_error_message(&err.error, format!("{}({}, {})", err.syscall, src.display(), dst.display()));
+            return throw_os_error(&mut cx, &err.error, with_hint(message, &err.error));
+        }
+    };
+    let skipped: Vec<String> = skipped.into_iter().map(String::from).collect();
+    let skipped = strings_to_js(&mut cx, &skipped)?;
+    let result = cx.empty_object();
+    result.set(&mut cx, "skipped", skipped)?;
+    Ok(result)
+}
+
+// Set process-wide defaults for the bigint, backup, followSymlinks and
+// concurrency options, the placeholder policy, the symlink depth, the
+// backend or a chain of them, the size of the worker pool and batching:
+// ([{ ... }]) -> the resulting settings. Keys left out keep their current
+// value, null restores the built-in default
+fn configure(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let options = options_argument(&mut cx, 0)?;
+    let mut new_config = config::get();
+    let mut new_backend = None;
+    let mut new_undo_log = None;
+    let mut new_rate_limit = None;
+    let mut new_timeout = None;
+    let mut new_threads = None;
+    let mut new_batching = None;
+    if let Some(options) = options {
+        // The option readers return the given value when the key is set
+        if let Some(set) = config_key(&mut cx, options, "bigint")? {
+            new_config.bigint = if set { bigint_option(&mut cx, Some(options))? } else { config::DEFAULT.bigint };
+        }
+        if let Some(set) = config_key(&mut cx, options, "backup")? {
+            new_config.backup = if set { backup_option(&mut cx, Some(options))? } else { config::DEFAULT.backup };
+        }
+        if let Some(set) = config_key(&mut cx, options, "followSymlinks")? {
+            new_config.follow_symlinks = if set {
+                let follow = options.get::<JsString, _, _>(&mut cx, "followSymlinks")?;
+                follow_symlinks_value(&mut cx, follow)?
+            } else {
+                config::DEFAULT.follow_symlinks
+            };
+        }
+        if let Some(set) = config_key(&mut cx, options, "concurrency")? {
+            new_config.concurrency = if set { concurrency_option(&mut cx, Some(options))? } else { config::DEFAULT.concurrency };
+        }
+        if let Some(set) = config_key(&mut cx, options, "placeholders")? {
+            new_config.placeholders = if set {
+                let name = options.get::<JsString, _, _>(&mut cx, "placeholders")?.value(&mut cx);
+                match config::Placeholders::from_name(&name) {
+                    Some(placeholders) => placeholders,
+                    None => return cx.throw_type_error(format!("placeholders must be 'skip', 'stamp' or 'hydrate', got {:?}", name)),
+                }
+            } else {
+                config::DEFAULT.placeholders
+            };
+        }
+        if let Some(set) = config_key(&mut cx, options, "quantize")? {
+            new_config.quantize = if set { quantize_option(&mut cx, Some(options))? } else { config::DEFAULT.quantize };
+        }
+        if let Some(set) = config_key(&mut cx, options, "memoryLimit")? {
+            new_config.memory_limit = if set {
+                let limit = options.get::<JsNumber, _, _>(&mut cx, "memoryLimit")?.value(&mut cx);
+                if !(limit >= 0.0 && limit.is_finite()) {
+                    return cx.throw_range_error("memoryLimit must be a non-negative number of bytes");
+                }
+                Some(limit as u64)
+            } else {
+                config::DEFAULT.memory_limit
+            };
+        }
+        if let Some(set) = config_key(&mut cx, options, "symlinkDepth")? {
+            new_config.symlink_depth = if set {
+                let depth = options.get::<JsNumber, _, _>(&mut cx, "symlinkDepth")?.value(&mut cx);
+                if !(depth >= 0.0 && depth.is_finite()) {
+                    return cx.throw_range_error("symlinkDepth must be a non-negative number of symlinks");
+                }
+                Some(depth as usize)
+            } else {
+                config::DEFAULT.symlink_depth
+            };
+        }
+        if let Some(set) = config_key(&mut cx, options, "undoLog")? {
+            new_undo_log = Some(if set {
+                let log_path = options.get::<JsValue, _, _>(&mut cx, "undoLog")?;
+                Some(path_value(&mut cx, log_path)?)
+            } else {
+                None
+            });
+        }
+        if let Some(set) = config_key(&mut cx, options, "rateLimit")? {
+            new_rate_limit = Some(if set {
+                let rate = options.get::<JsNumber, _, _>(&mut cx, "rateLimit")?.value(&mut cx);
+                if !(rate >= ratelimit::MIN_RATE && rate.is_finite()) {
+                    return cx.throw_range_error("rateLimit must be a number of writes per second, at least one an hour");
+                }
+                Some(rate)
+            } else {
+                None
+            });
+        }
+        if let Some(set) = config_key(&mut cx, options, "timeout")? {
+            new_timeout = Some(if set {
+                let millis = options.get::<JsNumber, _, _>(&mut cx, "timeout")?.value(&mut cx);
+                if !(millis > 0.0 && millis.is_finite()) {
+                    return cx.throw_range_error("timeout must be a positive number of milliseconds");
+                }
+                Some(std::time::Duration::from_millis(millis.ceil() as u64))
+            } else {
+                None
+            });
+        }
+        if let Some(set) = config_key(&mut cx, options, "threads")? {
+            new_threads = Some(if set {
+                let threads = options.get::<JsNumber, _, _>(&mut cx, "threads")?.value(&mut cx);
+                if threads.is_nan() || threads < 1.0 {
+                    return cx.throw_range_error("threads must be at least 1");
+                }
+                threads as usize
+            } else {
+                workers::DEFAULT_THREADS
+            });
+        }
+        if let Some(set) = config_key(&mut cx, options, "batching")? {
+            new_batching = Some(if set {
+                let batching = options.get::<JsObject, _, _>(&mut cx, "batching")?;
+                Some(batching_value(&mut cx, batching)?)
+            } else {
+                None
+            });
+        }
+        if let Some(set) = config_key(&mut cx, options, "backend")? {
+            let name = if set { options.get::<JsString, _, _>(&mut cx, "backend")?.value(&mut cx) } else { "platform".to_string() };
+            match backend::by_name(&name) {
+                Some(selected) => new_backend = Some(selected),
+                None => return cx.throw_type_error(format!("unknown backend {:?}", name)),
+            }
+        }
+        if let Some(set) = config_key(&mut cx, options, "backends")? {
+            if new_backend.is_some() && set {
+                return cx.throw_type_error("backend and backends cannot both be set");
+            }
+            if set {
+                let names = string_array_option(&mut cx, options, "backends")?.unwrap_or_default();
+                match backend::Chain::new(&names) {
+                    Ok(chain) => new_backend = Some(Arc::new(chain)),
+                    Err(message) => return cx.throw_type_error(message),
+                }
+            } else if new_backend.is_none() {
+                new_backend = backend::by_name("platform");
+            }
+        }
     }
-    
-    #[repr(C)]
-    #[derive(Clone, Copy)]
-    struct Timeval {
-        tv_sec: i64,
-        tv_usec: i32,
+    // Only store the settings once every key was valid and the log is open
+    if let Some(log_path) = new_undo_log {
+        if let Err(err) = undo::open(log_path.as_deref()) {
+            let log_path = log_path.unwrap_or_default();
+            return throw_os_error(&mut cx, &err, os_error_message(&err, format!("open({})", log_path.display())));
+        }
     }
-    
-    #[repr(C)]
-    struct AttrList {
-        bitmapcount: u16,
-        reserved: u16,
-        commonattr: u32,
-        volattr: u32,
-        dirattr: u32,
-        fileattr: u32,
-        forkattr: u32,
+    config::set(new_config);
+    if let Some(rate) = new_rate_limit {
+        ratelimit::set(rate);
     }
-    
-    #[repr(C)]
-    struct AttrBuf {
-        ret_length: u32,
-        struct_length: u32,
-        btime: Timespec,
+    if let Some(limit) = new_timeout {
+        timeout::set(limit);
     }
-    
-    const ATTR_BIT_MAP_COUNT: u16 = 5;
-    const ATTR_CMN_CRTIME: u32 = 0x00000200;
-    
-    extern "C" {
-        fn setattrlist(
-            path: *const c_char,
-            attrList: *const AttrList,
-            attrBuf: *const libc::c_void,
-            attrBufSize: libc::size_t,
-            options: c_int,
-        ) -> c_int;
+    if let Some(threads) = new_threads {
+        workers::resize(threads);
     }
-    
-    // Prepare the attribute list
-    let mut attr_list = AttrList {
-        bitmapcount: ATTR_BIT_MAP_COUNT,
-        reserved: 0,
-        commonattr: ATTR_CMN_CRTIME,
-        volattr: 0,
-        dirattr: 0,
-        fileattr: 0,
-        forkattr: 0,
+    if let Some(batching) = new_batching {
+        batch::set(batching);
+    }
+    if let Some(new_backend) = new_backend {
+        backend::install(new_backend);
+    }
+
+    let result = cx.empty_object();
+    let bigint = cx.boolean(new_config.bigint);
+    let backup = cx.boolean(new_config.backup);
+    let follow_symlinks = cx.string(new_config.follow_symlinks.name());
+    let concurrency = cx.number(new_config.concurrency as f64);
+    result.set(&mut cx, "bigint", bigint)?;
+    result.set(&mut cx, "backup", backup)?;
+    result.set(&mut cx, "followSymlinks", follow_symlinks)?;
+    result.set(&mut cx, "concurrency", concurrency)?;
+    let placeholders = cx.string(new_config.placeholders.name());
+    result.set(&mut cx, "placeholders", placeholders)?;
+    let quantize = cx.string(new_config.quantize.name());
+    result.set(&mut cx, "quantize", quantize)?;
+    let memory_limit = match new_config.memory_limit {
+        Some(limit) => cx.number(limit as f64).upcast(),
+        None => cx.null().upcast::<JsValue>(),
     };
-    
-    // Prepare the attribute buffer with the birth time
-    let attr_buf = AttrBuf {
-        ret_length: 0,
-        struct_length: 0,
-        btime: Timespec {
-            tv_sec: seconds as i64,
-            tv_nsec: 0,
+    result.set(&mut cx, "memoryLimit", memory_limit)?;
+    let symlink_depth = match new_config.symlink_depth {
+        Some(depth) => cx.number(depth as f64).upcast(),
+        None => cx.null().upcast::<JsValue>(),
+    };
+    result.set(&mut cx, "symlinkDepth", symlink_depth)?;
+    let backend = backend::current();
+    let backend_name = cx.string(backend.name());
+    result.set(&mut cx, "backend", backend_name)?;
+    let backends = backends_to_js(&mut cx, backend.as_ref())?;
+    result.set(&mut cx, "backends", backends)?;
+    let undo_log = match undo::path() {
+        Some(log_path) => cx.string(log_path.to_string_lossy()).upcast(),
+        None => cx.null().upcast::<JsValue>(),
+    };
+    result.set(&mut cx, "undoLog", undo_log)?;
+    let rate_limit = match ratelimit::rate() {
+        Some(rate) => cx.number(rate).upcast(),
+        None => cx.null().upcast::<JsValue>(),
+    };
+    result.set(&mut cx, "rateLimit", rate_limit)?;
+    let limit = match timeout::get() {
+        Some(limit) => cx.number(limit.as_millis() as f64).upcast(),
+        None => cx.null().upcast::<JsValue>(),
+    };
+    result.set(&mut cx, "timeout", limit)?;
+    let threads = cx.number(workers::size() as f64);
+    result.set(&mut cx, "threads", threads)?;
+    let batching = match batch::get() {
+        Some(batching) => {
+            let object = cx.empty_object();
+            let interval = cx.number(batching.interval.as_secs_f64() * 1000.0);
+            let size = cx.number(batching.size as f64);
+            object.set(&mut cx, "interval", interval)?;
+            object.set(&mut cx, "size", size)?;
+            object.upcast()
+        }
+        None => cx.null().upcast::<JsValue>(),
+    };
+    result.set(&mut cx, "batching", batching)?;
+    Ok(result)
+}
+
+// Put back the times an undo log recorded, the last write first:
+// (logPath, [{ cwd }]) -> { processed, errors, truncated }
+fn undo(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let log_path = path_argument(&mut cx, 0)?;
+    let options = options_argument(&mut cx, 1)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let log_path = path::resolve(log_path, cwd.as_deref());
+
+    match undo::replay(&log_path, apply_times) {
+        Ok(tree_result) => tree_result_to_js(&mut cx, &tree_result),
+        Err(err) => match err.raw_os_error() {
+            Some(_) => throw_os_error(&mut cx, &err, os_error_message(&err, format!("open({})", log_path.display()))),
+            None => cx.throw_error(format!("Invalid undo log {}: {}", log_path.display(), err)),
         },
+    }
+}
+
+// Detect the filesystem below a directory and check with a temporary file
+// whether birth time writes there stick, or take the last probe of the
+// volume: (dir, [{ cwd, fresh }]) ->
+// { filesystem, remote, getBtime, setBtime, caseSensitive, error }
+fn probe_filesystem(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let dir = path_argument(&mut cx, 0)?;
+    let options = options_argument(&mut cx, 1)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let dir = path::resolve(dir, cwd.as_deref());
+    let fresh = match options {
+        Some(options) => options.get_opt::<JsBoolean, _, _>(&mut cx, "fresh")?.is_some_and(|fresh| fresh.value(&mut cx)),
+        None => false,
     };
-    
-    // Call setattrlist
-    let result = unsafe {
-        setattrlist(
-            c_path.as_ptr(),
-            &mut attr_list as *mut AttrList,
-            &attr_buf as *const AttrBuf as *const libc::c_void,
-            std::mem::size_of::<AttrBuf>(),
-            0,
-        )
+
+    let probe = match probecache::probe(&dir, fresh) {
+        Ok(probe) => probe,
+        Err(err) => return throw_os_error(&mut cx, &err, os_error_message(&err, format!("probe({})", dir.display()))),
     };
-    
-    if result != 0 {
-        return Err(std::io::Error::last_os_error());
+    let result = cx.empty_object();
+    let filesystem = cx.string(&probe.filesystem.name);
+    let remote = cx.boolean(probe.filesystem.remote);
+    let get_btime = cx.boolean(probe.get_btime);
+    let set_btime = cx.string(probe.set_btime.name());
+    let case_sensitive = cx.boolean(probe.case_sensitive);
+    result.set(&mut cx, "filesystem", filesystem)?;
+    result.set(&mut cx, "remote", remote)?;
+    result.set(&mut cx, "getBtime", get_btime)?;
+    result.set(&mut cx, "setBtime", set_btime)?;
+    result.set(&mut cx, "caseSensitive", case_sensitive)?;
+    let error = match &probe.error {
+        Some(err) => {
+            let object = cx.empty_object();
+            let message = cx.string(with_hint(oserror::describe(err), err));
+            set_error_code(&mut cx, object, err)?;
+            object.set(&mut cx, "message", message)?;
+            object.upcast()
+        }
+        None => cx.null().upcast::<JsValue>(),
+    };
+    result.set(&mut cx, "error", error)?;
+    Ok(result)
+}
+
+// What the volume a path lives on does with timestamps, from the cached
+// probe of its directory and the limits of its filesystem type:
+// (path, [{ cwd, fresh, bigint }]) -> { filesystem, remote, btime,
+// granularity, earliest, latest, caseSensitive }
+fn get_volume_capabilities(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let path = path_argument(&mut cx, 0)?;
+    let options = options_argument(&mut cx, 1)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let bigint = bigint_option(&mut cx, options)?;
+    let fresh = match options {
+        Some(options) => options.get_opt::<JsBoolean, _, _>(&mut cx, "fresh")?.is_some_and(|fresh| fresh.value(&mut cx)),
+        None => false,
+    };
+    let path = path::resolve(path, cwd.as_deref());
+
+    // The probe needs a directory to create its file in
+    let dir = match std::fs::metadata(&path) {
+        Ok(metadata) if metadata.is_dir() => path.clone(),
+        Ok(_) => path.parent().map_or_else(|| path.clone(), Path::to_path_buf),
+        Err(err) => return throw_os_error(&mut cx, &err, stat_error_message(&err, &path, true)),
+    };
+    let probe = match probecache::probe(&dir, fresh) {
+        Ok(probe) => probe,
+        Err(err) => return throw_os_error(&mut cx, &err, os_error_message(&err, format!("probe({})", dir.display()))),
+    };
+    let limits = volume::limits(&probe.filesystem);
+
+    let result = cx.empty_object();
+    let filesystem = cx.string(&probe.filesystem.name);
+    let remote = cx.boolean(probe.filesystem.remote);
+    result.set(&mut cx, "filesystem", filesystem)?;
+    result.set(&mut cx, "remote", remote)?;
+    let btime = cx.empty_object();
+    let readable = cx.boolean(probe.get_btime);
+    let settable = cx.boolean(probe.set_btime == volume::BtimeWrites::Stored);
+    let writes = cx.string(probe.set_btime.name());
+    btime.set(&mut cx, "readable", readable)?;
+    btime.set(&mut cx, "settable", settable)?;
+    btime.set(&mut cx, "writes", writes)?;
+    result.set(&mut cx, "btime", btime)?;
+    let granularity = match limits {
+        Some(limits) => {
+            let object = cx.empty_object();
+            let volume::Granularity { btime, mtime, atime, ctime } = limits.granularity;
+            for (field, step) in [("btime", btime), ("mtime", mtime), ("atime", atime), ("ctime", ctime)] {
+                let step = cx.number(step as f64);
+                object.set(&mut cx, field, step)?;
+            }
+            object.upcast()
+        }
+        None => cx.null().upcast::<JsValue>(),
+    };
+    result.set(&mut cx, "granularity", granularity)?;
+    let earliest = timestamp_to_js(&mut cx, limits.map(|limits| limits.earliest), bigint);
+    let latest = timestamp_to_js(&mut cx, limits.map(|limits| limits.latest), bigint);
+    result.set(&mut cx, "earliest", earliest)?;
+    result.set(&mut cx, "latest", latest)?;
+    let case_sensitive = cx.boolean(probe.case_sensitive);
+    result.set(&mut cx, "caseSensitive", case_sensitive)?;
+    Ok(result)
+}
+
+// The mounted filesystems: () -> [{ path, device, filesystem, readOnly }]
+fn list_mounts(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let mounts = match mounts::list() {
+        Ok(mounts) => mounts,
+        Err(err) => return throw_os_error(&mut cx, &err, os_error_message(&err, "listMounts()")),
+    };
+    let array = cx.empty_array();
+    for (i, mount) in mounts.iter().enumerate() {
+        let object = cx.empty_object();
+        let path = cx.string(mount.path.to_string_lossy());
+        let device = cx.string(&mount.device);
+        let filesystem = cx.string(&mount.filesystem);
+        let read_only = cx.boolean(mount.read_only);
+        object.set(&mut cx, "path", path)?;
+        object.set(&mut cx, "device", device)?;
+        object.set(&mut cx, "filesystem", filesystem)?;
+        object.set(&mut cx, "readOnly", read_only)?;
+        array.set(&mut cx, i as u32, object)?;
     }
-    
-    Ok(())
+    Ok(array)
 }
 
-#[cfg(not(any(target_os = "windows", target_os = "macos")))]
-fn set_btime(_path: &str, _seconds: u64) -> std::io::Result<()> {
-    // Linux does not support changing birth time
-    Ok(())
+// Check without writing anything whether the birth time of a path could be
+// set: (path, [{ cwd, link }]) -> { ok, reasons }
+fn can_set_btime(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let path = path_argument(&mut cx, 0)?;
+    let options = options_argument(&mut cx, 1)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let follow = link_option(&mut cx, options)?;
+    let path = path::resolve(path, cwd.as_deref());
+
+    let obstacles = match access::check(&path, follow) {
+        Ok(obstacles) => obstacles,
+        Err(err) => return throw_os_error(&mut cx, &err, stat_error_message(&err, &path, follow)),
+    };
+    let result = cx.empty_object();
+    let ok = cx.boolean(obstacles.is_empty());
+    let reasons: Vec<String> = obstacles.iter().map(|obstacle| obstacle.name().to_string()).collect();
+    let reasons = strings_to_js(&mut cx, &reasons)?;
+    result.set(&mut cx, "ok", ok)?;
+    result.set(&mut cx, "reasons", reasons)?;
+    Ok(result)
+}
+
+// The backend in use and what it can do: () -> { name, setBtime, getBtime, emulated }
+fn get_backend(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let backend = backend::current();
+    let capabilities = backend.capabilities();
+    let result = cx.empty_object();
+    let name = cx.string(backend.name());
+    let set_btime = cx.boolean(capabilities.set_btime);
+    let get_btime = cx.boolean(capabilities.get_btime);
+    let emulated = cx.boolean(capabilities.emulated);
+    result.set(&mut cx, "name", name)?;
+    result.set(&mut cx, "setBtime", set_btime)?;
+    result.set(&mut cx, "getBtime", get_btime)?;
+    result.set(&mut cx, "emulated", emulated)?;
+    let backends = backends_to_js(&mut cx, backend.as_ref())?;
+    result.set(&mut cx, "backends", backends)?;
+    Ok(result)
+}
+
+// The names of a chain's backends, or null for a single backend
+fn backends_to_js<'a>(cx: &mut impl Context<'a>, backend: &dyn backend::BtimeBackend) -> JsResult<'a, JsValue> {
+    match backend.chain() {
+        Some(names) => {
+            let names: Vec<String> = names.into_iter().map(String::from).collect();
+            Ok(strings_to_js(cx, &names)?.upcast())
+        }
+        None => Ok(cx.null().upcast()),
+    }
+}
+
+// The birth times the sidecar backend recorded below a directory:
+// (dir, [{ cwd, bigint }]) -> [{ path, inode, btime }]
+#[cfg(feature = "sqlite")]
+fn export_sidecar(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let dir = path_argument(&mut cx, 0)?;
+    let options = options_argument(&mut cx, 1)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let bigint = bigint_option(&mut cx, options)?;
+    let dir = path::resolve(dir, cwd.as_deref());
+
+    let entries = match sidecar::export(&dir) {
+        Ok(entries) => entries,
+        Err(err) => return throw_os_error(&mut cx, &err, os_error_message(&err, format!("exportSidecar({})", dir.display()))),
+    };
+    let array = cx.empty_array();
+    for (i, entry) in entries.iter().enumerate() {
+        let object = cx.empty_object();
+        let path = cx.string(&entry.path);
+        let inode = cx.number(entry.inode as f64);
+        let btime = timestamp_to_js(&mut cx, Some(entry.btime), bigint);
+        object.set(&mut cx, "path", path)?;
+        object.set(&mut cx, "inode", inode)?;
+        object.set(&mut cx, "btime", btime)?;
+        array.set(&mut cx, i as u32, object)?;
+    }
+    Ok(array)
+}
+
+// Record birth times below a directory in the sidecar database of its
+// volume: (dir, [{ path, btime }], [{ cwd }]) -> { imported, missing }
+#[cfg(feature = "sqlite")]
+fn import_sidecar(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let dir = path_argument(&mut cx, 0)?;
+    let list = cx.argument::<JsArray>(1)?;
+    let options = options_argument(&mut cx, 2)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let dir = path::resolve(dir, cwd.as_deref());
+
+    let mut entries = Vec::new();
+    for item in list.to_vec(&mut cx)? {
+        let item = item.downcast_or_throw::<JsObject, _>(&mut cx)?;
+        let path = item.get::<JsString, _, _>(&mut cx, "path")?.value(&mut cx);
+        let btime = item.get::<JsValue, _, _>(&mut cx, "btime")?;
+        let btime = timestamp_value(&mut cx, btime)?;
+        entries.push((path, btime));
+    }
+
+    let missing = match sidecar::import(&dir, &entries) {
+        Ok(missing) => missing,
+        Err(err) => return throw_os_error(&mut cx, &err, os_error_message(&err, format!("importSidecar({})", dir.display()))),
+    };
+    let result = cx.empty_object();
+    let imported = cx.number((entries.len() - missing.len()) as f64);
+    let missing = strings_to_js(&mut cx, &missing)?;
+    result.set(&mut cx, "imported", imported)?;
+    result.set(&mut cx, "missing", missing)?;
+    Ok(result)
+}
+
+// Read the batching setting, { interval, size } with either left out taking
+// its default
+fn batching_value<'a>(cx: &mut FunctionContext<'a>, batching: Handle<'a, JsObject>) -> NeonResult<batch::Batching> {
+    let mut value = batch::DEFAULT;
+    if let Some(interval) = batching.get_opt::<JsNumber, _, _>(cx, "interval")? {
+        let interval = interval.value(cx);
+        if !(interval >= 0.0 && interval.is_finite()) {
+            return cx.throw_range_error("batching.interval must be a number of milliseconds");
+        }
+        value.interval = std::time::Duration::from_secs_f64(interval / 1000.0);
+    }
+    if let Some(size) = batching.get_opt::<JsNumber, _, _>(cx, "size")? {
+        let size = size.value(cx);
+        if size.is_nan() || size < 1.0 {
+            return cx.throw_range_error("batching.size must be at least 1");
+        }
+        value.size = size as usize;
+    }
+    Ok(value)
+}
+
+// Apply the writes batching has queued and report every batch since the
+// last flush: () -> { processed, errors, truncated }
+fn flush(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let tree_result = batch::flush();
+    tree_result_to_js(&mut cx, &tree_result)
+}
+
+// Whether configure() got a key: None when absent, Some(false) for null
+fn config_key<'a>(cx: &mut FunctionContext<'a>, options: Handle<'a, JsObject>, key: &str) -> NeonResult<Option<bool>> {
+    match options.get_opt::<JsValue, _, _>(cx, key)? {
+        Some(value) => Ok(Some(!value.is_a::<JsNull, _>(cx))),
+        None => Ok(None),
+    }
+}
+
+// Make matching operations fail, replacing the rules installed before:
+// ([{ op, path, every, errno }]). An empty list turns injection off
+fn inject_faults(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let list = cx.argument::<JsArray>(0)?;
+    let mut rules = Vec::new();
+    for rule in list.to_vec(&mut cx)? {
+        let rule = rule.downcast_or_throw::<JsObject, _>(&mut cx)?;
+        let op = match rule.get_opt::<JsString, _, _>(&mut cx, "op")? {
+            Some(name) => {
+                let name = name.value(&mut cx);
+                match stats::Op::from_name(&name) {
+                    Some(op) => Some(op),
+                    None => return cx.throw_type_error(format!("unknown operation {:?}", name)),
+                }
+            }
+            None => None,
+        };
+        let path = match rule.get_opt::<JsString, _, _>(&mut cx, "path")? {
+            Some(pattern) => match glob::Glob::new(&pattern.value(&mut cx)) {
+                Ok(glob) => Some(glob),
+                Err(message) => return cx.throw_type_error(message),
+            },
+            None => None,
+        };
+        let every = match rule.get_opt::<JsNumber, _, _>(&mut cx, "every")? {
+            Some(every) => {
+                let every = every.value(&mut cx);
+                if every.is_nan() || every < 1.0 {
+                    return cx.throw_range_error("every must be at least 1");
+                }
+                every as u64
+            }
+            None => 1,
+        };
+        let errno = rule.get::<JsNumber, _, _>(&mut cx, "errno")?.value(&mut cx);
+        if errno.fract() != 0.0 || errno < 1.0 || errno > i32::MAX as f64 {
+            return cx.throw_range_error("errno must be a positive integer");
+        }
+        rules.push(fault::Rule { op, path, every, errno: errno as i32, calls: Default::default() });
+    }
+    fault::install(rules);
+    Ok(cx.undefined())
+}
+
+// Counters of the filesystem operations done so far:
+// () -> { operations: { [name]: { count, failures, totalNs, averageNs } }, errors: { [errno]: count } }
+fn get_stats(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let operations = cx.empty_object();
+    for op in stats::OPS {
+        let op_stats = stats::op_stats(op);
+        let object = cx.empty_object();
+        let count = cx.number(op_stats.count as f64);
+        let failures = cx.number(op_stats.failures as f64);
+        let total = JsBigInt::from_u64(&mut cx, op_stats.total_nanos);
+        let average = JsBigInt::from_u64(&mut cx, op_stats.total_nanos.checked_div(op_stats.count).unwrap_or(0));
+        object.set(&mut cx, "count", count)?;
+        object.set(&mut cx, "failures", failures)?;
+        object.set(&mut cx, "totalNs", total)?;
+        object.set(&mut cx, "averageNs", average)?;
+        operations.set(&mut cx, op.name(), object)?;
+    }
+
+    let errors = cx.empty_object();
+    for (code, count) in stats::errors() {
+        let count = cx.number(count as f64);
+        errors.set(&mut cx, code.to_string().as_str(), count)?;
+    }
+
+    let backends = cx.empty_object();
+    for (backend, count) in stats::handled_by() {
+        let count = cx.number(count as f64);
+        backends.set(&mut cx, backend, count)?;
+    }
+
+    let result = cx.empty_object();
+    result.set(&mut cx, "operations", operations)?;
+    result.set(&mut cx, "errors", errors)?;
+    result.set(&mut cx, "backends", backends)?;
+    Ok(result)
+}
+
+fn reset_stats(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    stats::reset();
+    Ok(cx.undefined())
+}
+
+// The calls the mock backend answered, oldest first:
+// ([{ bigint, clear }]) -> [{ op, path, times }]. times is null for failed
+// reads, clear also forgets them
+fn get_mock_operations(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let options = options_argument(&mut cx, 0)?;
+    let bigint = bigint_option(&mut cx, options)?;
+    let clear = match options {
+        Some(options) => options.get_opt::<JsBoolean, _, _>(&mut cx, "clear")?.map(|clear| clear.value(&mut cx)),
+        None => None,
+    };
+
+    let operations = mock::operations();
+    if clear == Some(true) {
+        mock::clear_operations();
+    }
+    let result = cx.empty_array();
+    for (i, operation) in operations.iter().enumerate() {
+        let object = cx.empty_object();
+        let op = cx.string(operation.kind.name());
+        let path = cx.string(operation.path.to_string_lossy());
+        let times: Handle<JsValue> = match &operation.times {
+            Some(times) => times_to_js(&mut cx, times, bigint)?.upcast(),
+            None => cx.null().upcast(),
+        };
+        object.set(&mut cx, "op", op)?;
+        object.set(&mut cx, "path", path)?;
+        object.set(&mut cx, "times", times)?;
+        result.set(&mut cx, i as u32, object)?;
+    }
+    Ok(result)
+}
+
+// Forget the calls and the times the mock backend holds
+fn reset_mock(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    mock::reset();
+    Ok(cx.undefined())
+}
+
+// Report which watched files had their timestamps or attributes rewritten
+// since a previous call, from the NTFS change journal:
+// (paths, [{ cwd, since: { journalId, nextUsn }, bigint }])
+#[cfg(target_os = "windows")]
+fn read_usn_changes(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let list = cx.argument::<JsArray>(0)?;
+    let options = options_argument(&mut cx, 1)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let bigint = bigint_option(&mut cx, options)?;
+    let mut watched = Vec::new();
+    for value in list.to_vec(&mut cx)? {
+        let path = path_value(&mut cx, value)?;
+        watched.push(path::resolve(path, cwd.as_deref()));
+    }
+
+    let mut since = None;
+    if let Some(options) = options {
+        if let Some(cursor) = options.get_opt::<JsObject, _, _>(&mut cx, "since")? {
+            let journal_id = cursor.get::<JsBigInt, _, _>(&mut cx, "journalId")?;
+            let next_usn = cursor.get::<JsBigInt, _, _>(&mut cx, "nextUsn")?;
+            let (Ok(journal_id), Ok(next_usn)) = (journal_id.to_u64(&mut cx), next_usn.to_i64(&mut cx)) else {
+                return cx.throw_range_error("invalid journal cursor");
+            };
+            since = Some(usn::Cursor { journal_id, next_usn });
+        }
+    }
+
+    let read_result = match usn::read_changes(&watched, since) {
+        Ok(read_result) => read_result,
+        Err(err) => {
+            let first = watched.first();
+            let volume = first.map(|path| path.display().to_string()).unwrap_or_default();
+            let mut message = os_error_message(&err, format!("FSCTL_READ_USN_JOURNAL({})", volume));
+            if first.is_some_and(|path| volume::is_refs(path)) {
+                message.push_str(": the change journal of ReFS volumes is not supported");
+            }
+            return throw_os_error(&mut cx, &err, message);
+        }
+    };
+
+    let changes = cx.empty_array();
+    for (i, change) in read_result.changes.iter().enumerate() {
+        let object = cx.empty_object();
+        let path = cx.string(change.path.to_string_lossy());
+        let usn = JsBigInt::from_i64(&mut cx, change.usn);
+        let time = timestamp_to_js(&mut cx, change.time, bigint);
+        object.set(&mut cx, "path", path)?;
+        object.set(&mut cx, "usn", usn)?;
+        object.set(&mut cx, "time", time)?;
+        changes.set(&mut cx, i as u32, object)?;
+    }
+    let cursor = cx.empty_object();
+    let journal_id = JsBigInt::from_u64(&mut cx, read_result.cursor.journal_id);
+    let next_usn = JsBigInt::from_i64(&mut cx, read_result.cursor.next_usn);
+    cursor.set(&mut cx, "journalId", journal_id)?;
+    cursor.set(&mut cx, "nextUsn", next_usn)?;
+    let lost = cx.boolean(read_result.lost);
+
+    let result = cx.empty_object();
+    result.set(&mut cx, "cursor", cursor)?;
+    result.set(&mut cx, "lost", lost)?;
+    result.set(&mut cx, "changes", changes)?;
+    Ok(result)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn read_usn_changes(mut cx: FunctionContext) -> JsResult<JsObject> {
+    cx.throw_error("readUsnChanges is only available on Windows")
+}
+
+// Set the timestamps of a file identified by its file ID instead of a path:
+// (volume, fileId, { btime, mtime, atime }, [{ cwd, backup }]). volume is
+// any path on the file's volume
+#[cfg(target_os = "windows")]
+fn set_times_by_id(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let volume = path_argument(&mut cx, 0)?;
+    let id = cx.argument::<JsBigInt>(1)?;
+    let Ok(id) = id.to_u128(&mut cx) else {
+        return cx.throw_range_error("fileId must be a non-negative BigInt of at most 128 bits");
+    };
+    let entry = cx.argument::<JsObject>(2)?;
+    let times = FileTimes {
+        btime: entry_timestamp(&mut cx, entry, "btime")?,
+        mtime: entry_timestamp(&mut cx, entry, "mtime")?,
+        atime: entry_timestamp(&mut cx, entry, "atime")?,
+        ctime: None,
+    };
+    let options = options_argument(&mut cx, 3)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let backup = backup_option(&mut cx, options)?;
+    let volume = path::resolve(volume, cwd.as_deref());
+
+    let started = std::time::Instant::now();
+    let result = fault::check(stats::Op::SetTimes, &volume)
+        .and_then(|_| with_backup_mode(backup, || fileid::set_times(&volume, id, &times)));
+    stats::record(stats::Op::SetTimes, started, &result);
+    match result {
+        Ok(_) => Ok(cx.undefined()),
+        Err(err) => {
+            let error_message = os_error_message(&err, format!("OpenFileById({}, {:#x})", volume.display(), id));
+            throw_os_error(&mut cx, &err, with_hint(error_message, &err))
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn set_times_by_id(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    cx.throw_error("setTimesById is only available on Windows")
+}
+
+// List the data streams of a file: (path, [{ cwd }]) -> [{ name, size }],
+// the unnamed stream with an empty name
+#[cfg(target_os = "windows")]
+fn list_streams(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let path = path_argument(&mut cx, 0)?;
+    let options = options_argument(&mut cx, 1)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let path = path::resolve(path, cwd.as_deref());
+
+    let stream_list = match streams::list(&path) {
+        Ok(stream_list) => stream_list,
+        Err(err) => return throw_os_error(&mut cx, &err, os_error_message(&err, format!("FindFirstStreamW({})", path.display()))),
+    };
+    let result = cx.empty_array();
+    for (i, stream) in stream_list.iter().enumerate() {
+        let item = cx.empty_object();
+        let name = cx.string(stream.name.to_string_lossy());
+        let size = cx.number(stream.size as f64);
+        item.set(&mut cx, "name", name)?;
+        item.set(&mut cx, "size", size)?;
+        result.set(&mut cx, i as u32, item)?;
+    }
+    Ok(result)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn list_streams(mut cx: FunctionContext) -> JsResult<JsArray> {
+    cx.throw_error("listStreams is only available on Windows")
+}
+
+// Set timestamps through one stream of a file:
+// (path, stream, { btime, mtime, atime }, [{ cwd, backup }])
+#[cfg(target_os = "windows")]
+fn set_stream_times(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let path = path_argument(&mut cx, 0)?;
+    let name = cx.argument::<JsString>(1)?.value(&mut cx);
+    let entry = cx.argument::<JsObject>(2)?;
+    let times = FileTimes {
+        btime: entry_timestamp(&mut cx, entry, "btime")?,
+        mtime: entry_timestamp(&mut cx, entry, "mtime")?,
+        atime: entry_timestamp(&mut cx, entry, "atime")?,
+        ctime: None,
+    };
+    let options = options_argument(&mut cx, 3)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let backup = backup_option(&mut cx, options)?;
+    let path = path::resolve(path, cwd.as_deref());
+    let stream = match streams::stream_path(&path, &name) {
+        Ok(stream) => PathBuf::from(stream),
+        Err(message) => return cx.throw_type_error(message),
+    };
+
+    let started = std::time::Instant::now();
+    let result = fault::check(stats::Op::SetTimes, &stream)
+        .and_then(|_| with_backup_mode(backup, || streams::set_times(&stream, &times)));
+    stats::record(stats::Op::SetTimes, started, &result);
+    match result {
+        Ok(_) => Ok(cx.undefined()),
+        Err(err) => {
+            let error_message = os_error_message(&err, format!("SetFileTime({})", stream.display()));
+            throw_os_error(&mut cx, &err, with_hint(error_message, &err))
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn set_stream_times(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    cx.throw_error("setStreamTimes is only available on Windows")
+}
+
+// Try to enable SeRestorePrivilege for the process and report the outcome:
+// () -> { held, enabled }
+#[cfg(target_os = "windows")]
+fn enable_restore_privilege(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let state = match privilege::enable_restore_privilege() {
+        Ok(state) => state,
+        Err(err) => {
+            return throw_os_error(&mut cx, &err, os_error_message(&err, "AdjustTokenPrivileges(SeRestorePrivilege)"))
+        }
+    };
+    let held = cx.boolean(state != privilege::PrivilegeState::NotHeld);
+    let enabled = cx.boolean(state == privilege::PrivilegeState::Enabled);
+    let result = cx.empty_object();
+    result.set(&mut cx, "held", held)?;
+    result.set(&mut cx, "enabled", enabled)?;
+    Ok(result)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn enable_restore_privilege(mut cx: FunctionContext) -> JsResult<JsObject> {
+    cx.throw_error("enableRestorePrivilege is only available on Windows")
+}
+
+// Read a Finder metadata date such as kMDItemContentCreationDate:
+// (path, name, [options]), null when the file has none
+#[cfg(target_os = "macos")]
+fn get_metadata_date(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let path = path_argument(&mut cx, 0)?;
+    let name = cx.argument::<JsString>(1)?.value(&mut cx);
+    let options = options_argument(&mut cx, 2)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let bigint = bigint_option(&mut cx, options)?;
+    let path = path::resolve(path, cwd.as_deref());
+
+    match spotlight::get_date(&path, &name) {
+        Ok(date) => Ok(timestamp_to_js(&mut cx, date, bigint)),
+        Err(err) => throw_os_error(&mut cx, &err, os_error_message(&err, format!("getxattr({}, {})", path.display(), name))),
+    }
+}
+
+// Write a Finder metadata date: (path, name, date, [options]). A null date
+// removes the value written before
+#[cfg(target_os = "macos")]
+fn set_metadata_date(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let path = path_argument(&mut cx, 0)?;
+    let name = cx.argument::<JsString>(1)?.value(&mut cx);
+    let date = cx.argument::<JsValue>(2)?;
+    let date = if date.is_a::<JsNull, _>(&mut cx) { None } else { Some(timestamp_value(&mut cx, date)?) };
+    let options = options_argument(&mut cx, 3)?;
+    let cwd = cwd_option(&mut cx, options)?;
+    let path = path::resolve(path, cwd.as_deref());
+
+    let (syscall, result) = match date {
+        Some(date) => ("setxattr", spotlight::set_date(&path, &name, date)),
+        None => ("removexattr", spotlight::remove_date(&path, &name)),
+    };
+    match result {
+        Ok(_) => Ok(cx.undefined()),
+        Err(err) => throw_os_error(&mut cx, &err, os_error_message(&err, format!("{}({}, {})", syscall, path.display(), name))),
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn get_metadata_date(mut cx: FunctionContext) -> JsResult<JsValue> {
+    cx.throw_error("getMetadataDate is only available on macOS")
+}
+
+#[cfg(not(target_os = "macos"))]
+fn set_metadata_date(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    cx.throw_error("setMetadataDate is only available on macOS")
+}
+
+fn paths_to_js<'a>(cx: &mut impl Context<'a>, paths: &[PathBuf]) -> JsResult<'a, JsArray> {
+    let array = cx.empty_array();
+    for (i, path) in paths.iter().enumerate() {
+        let path = cx.string(path.to_string_lossy());
+        array.set(cx, i as u32, path)?;
+    }
+    Ok(array)
+}
+
+fn strings_to_js<'a>(cx: &mut impl Context<'a>, strings: &[String]) -> JsResult<'a, JsArray> {
+    let array = cx.empty_array();
+    for (i, string) in strings.iter().enumerate() {
+        let string = cx.string(string);
+        array.set(cx, i as u32, string)?;
+    }
+    Ok(array)
+}
+
+fn walk_error_to_js<'a>(cx: &mut impl Context<'a>, err: &walk::WalkError) -> JsResult<'a, JsObject> {
+    let object = cx.empty_object();
+    let path = cx.string(err.path.to_string_lossy());
+    let message = cx.string(with_hint(oserror::describe(&err.error), &err.error));
+    object.set(cx, "path", path)?;
+    set_error_code(cx, object, &err.error)?;
+    object.set(cx, "message", message)?;
+    Ok(object)
+}
+
+// Set the birth time (creation time) of a file. The write functions return
+// the name of the backend that did the write
+fn set_btime(path: &Path, btime: Timestamp) -> std::io::Result<&'static str> {
+    set_times(path, &FileTimes { btime: Some(btime), ..FileTimes::default() })
+}
+
+// Set the timestamps of a file through the installed backend, counted in
+// getStats(). Fields that are None are left unchanged, ctime cannot be set
+// and is ignored
+fn set_times(path: &Path, times: &FileTimes) -> std::io::Result<&'static str> {
+    write_times(path, times, true)
+}
+
+// set_times for a symlink or junction itself
+fn set_link_times(path: &Path, times: &FileTimes) -> std::io::Result<&'static str> {
+    write_times(path, times, false)
+}
+
+fn write_times(path: &Path, times: &FileTimes, follow: bool) -> std::io::Result<&'static str> {
+    undo::record(path, times, follow, || apply_times(path, times, follow))
+}
+
+// write_times without the undo log, for undo itself
+fn apply_times(path: &Path, times: &FileTimes, follow: bool) -> std::io::Result<&'static str> {
+    ratelimit::acquire();
+    let started = std::time::Instant::now();
+    let result = fault::check(stats::Op::SetTimes, path)
+        .and_then(|_| times.quantize(config::get().quantize))
+        .and_then(|times| {
+            let (backend, path) = (backend::current(), path.to_path_buf());
+            timeout::run(move || path::check(&path, follow).and_then(|_| backend.set_times_by(&path, &times, follow)))
+        });
+    stats::record(stats::Op::SetTimes, started, &result);
+    result
 }
 
 // Update the Cargo.toml for platform-specific dependencies:
@@ -180,6 +3554,81 @@ fn set_btime(_path: &str, _seconds: u64) -> std::io::Result<()> {
 
 #[neon::main]
 fn main(mut cx: ModuleContext) -> NeonResult<()> {
+    // Lets test runs select a backend before any code calls configure()
+    if let Ok(name) = std::env::var("OPEN_BTIME_BACKEND") {
+        match backend::by_name(&name) {
+            Some(selected) => backend::install(selected),
+            None => return cx.throw_error(format!("unknown backend {:?} in OPEN_BTIME_BACKEND", name)),
+        }
+    }
+    // Stops background work and waits for it when this instance unloads
+    teardown::work(&mut cx);
     cx.export_function("btime", btime)?;
+    cx.export_function("btimeRecursive", btime_recursive)?;
+    cx.export_function("btimeRecursiveAsync", btime_recursive_async)?;
+    cx.export_function("getBtime", get_btime)?;
+    cx.export_function("lgetBtime", lget_btime)?;
+    cx.export_function("getTimes", get_times)?;
+    cx.export_function("fbtime", fbtime)?;
+    cx.export_function("fgetTimes", fget_times)?;
+    cx.export_function("getTimesMany", get_times_many)?;
+    cx.export_function("statAll", stat_all)?;
+    cx.export_function("walk", walk)?;
+    cx.export_function("snapshotTimes", snapshot_times)?;
+    cx.export_function("restoreTimes", restore_times)?;
+    cx.export_function("diffTimes", diff_times)?;
+    cx.export_function("applyBirthTimes", apply_birth_times)?;
+    cx.export_function("mirrorTimes", mirror_times)?;
+    cx.export_function("copyFileWithTimes", copy_file_with_times)?;
+    cx.export_function("copyTreeWithTimes", copy_tree_with_times)?;
+    cx.export_function("cloneWithTimes", clone_with_times)?;
+    cx.export_function("writeFileAtomicWithTimes", write_file_atomic_with_times)?;
+    cx.export_function("preserveBirthTimes", preserve_birth_times)?;
+    cx.export_function("utimes", utimes)?;
+    cx.export_function("lutimes", lutimes)?;
+    // require('open-btime').ronomon stands in for require('@ronomon/utimes')
+    let ronomon = cx.empty_object();
+    let ronomon_utimes = JsFunction::new(&mut cx, ronomon_utimes)?;
+    let ronomon_lutimes = JsFunction::new(&mut cx, ronomon_lutimes)?;
+    ronomon.set(&mut cx, "utimes", ronomon_utimes)?;
+    ronomon.set(&mut cx, "lutimes", ronomon_lutimes)?;
+    cx.export_value("ronomon", ronomon)?;
+    cx.export_function("cloneMetadata", clone_metadata)?;
+    cx.export_function("createTimesQueue", create_times_queue)?;
+    cx.export_function("queueTimes", queue_times)?;
+    cx.export_function("queueTarEntry", queue_tar_entry)?;
+    cx.export_function("finishTimesQueue", finish_times_queue)?;
+    cx.export_function("quantizeTime", quantize_time)?;
+    cx.export_function("quantizePreview", quantize_preview)?;
+    cx.export_function("dosDateTimeToUnix", dos_date_time_to_unix)?;
+    cx.export_function("unixToDosDateTime", unix_to_dos_date_time)?;
+    cx.export_function("filetimeToUnixNs", filetime_to_unix_ns)?;
+    cx.export_function("unixNsToFiletime", unix_ns_to_filetime)?;
+    cx.export_function("configure", configure)?;
+    cx.export_function("flush", flush)?;
+    cx.export_function("undo", undo)?;
+    cx.export_function("getBackend", get_backend)?;
+    cx.export_function("probeFilesystem", probe_filesystem)?;
+    cx.export_function("listMounts", list_mounts)?;
+    cx.export_function("getVolumeCapabilities", get_volume_capabilities)?;
+    cx.export_function("canSetBtime", can_set_btime)?;
+    cx.export_function("getMockOperations", get_mock_operations)?;
+    cx.export_function("resetMock", reset_mock)?;
+    cx.export_function("getStats", get_stats)?;
+    cx.export_function("resetStats", reset_stats)?;
+    cx.export_function("injectFaults", inject_faults)?;
+    cx.export_function("readUsnChanges", read_usn_changes)?;
+    cx.export_function("setTimesById", set_times_by_id)?;
+    cx.export_function("listStreams", list_streams)?;
+    cx.export_function("setStreamTimes", set_stream_times)?;
+    cx.export_function("enableRestorePrivilege", enable_restore_privilege)?;
+    cx.export_function("getMetadataDate", get_metadata_date)?;
+    cx.export_function("setMetadataDate", set_metadata_date)?;
+    #[cfg(feature = "git")]
+    cx.export_function("gitBirthTimes", git_birth_times)?;
+    #[cfg(feature = "sqlite")]
+    cx.export_function("exportSidecar", export_sidecar)?;
+    #[cfg(feature = "sqlite")]
+    cx.export_function("importSidecar", import_sidecar)?;
     Ok(())
 }
@@ -0,0 +1,122 @@
+// Hard links in snapshots. Files with more than one name are recorded with
+// the identity of the file they name, (device, inode) or the volume serial
+// and file index on Windows, as "link": "<dev>:<ino>". A restore sets the
+// times of each physical file once instead of once per name, which matters
+// for backup trees that hard link unchanged files across many generations.
+//
+// The birth time belongs to the file, not to a name, so writing it through
+// one name changes it for all of them. Writes can report the other names a
+// file has, and refuse files that have any
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::pool;
+
+#[cfg(windows)]
+use crate::win;
+
+// The identity of a file with more than one name, None for a file with one
+#[cfg(unix)]
+pub fn link_key(path: &Path) -> io::Result<Option<String>> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::symlink_metadata(path)?;
+    Ok((metadata.nlink() > 1).then(|| format!("{}:{}", metadata.dev(), metadata.ino())))
+}
+
+#[cfg(windows)]
+pub fn link_key(path: &Path) -> io::Result<Option<String>> {
+    let info = win::file_information(&win::open_query(path, false)?)?;
+    Ok((info.nNumberOfLinks > 1).then(|| format!("{}:{}", info.dwVolumeSerialNumber, win::file_index(&info))))
+}
+
+// How many names the file has besides path, 0 for a file with one. Only
+// regular files can be hard linked: the link count of a directory counts
+// its entry in the parent, its own "." and the ".." of each subdirectory
+#[cfg(unix)]
+pub fn other_names(path: &Path, follow: bool) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = if follow { std::fs::metadata(path)? } else { std::fs::symlink_metadata(path)? };
+    if !metadata.is_file() {
+        return Ok(0);
+    }
+    Ok(metadata.nlink().saturating_sub(1))
+}
+
+#[cfg(windows)]
+pub fn other_names(path: &Path, follow: bool) -> io::Result<u64> {
+    use windows::Win32::Storage::FileSystem::FILE_ATTRIBUTE_DIRECTORY;
+
+    let info = win::file_information(&win::open_query(path, follow)?)?;
+    if info.dwFileAttributes & FILE_ATTRIBUTE_DIRECTORY.0 != 0 {
+        return Ok(0);
+    }
+    Ok(u64::from(info.nNumberOfLinks.saturating_sub(1)))
+}
+
+// The indices of the paths whose file has other names, with how many, in
+// the order of paths. Paths that cannot be looked up are left to the write to
+// report
+pub fn shared(paths: &[PathBuf], concurrency: usize) -> Vec<(usize, u64)> {
+    let shared = Mutex::new(Vec::new());
+    pool::for_each_bounded(paths.iter().enumerate(), concurrency, |(i, path)| {
+        if let Ok(names @ 1..) = other_names(path, true) {
+            shared.lock().unwrap().push((i, names));
+        }
+    });
+    let mut shared = shared.into_inner().unwrap();
+    shared.sort_unstable_by_key(|(i, _)| *i);
+    shared
+}
+
+// Why a write to a file with other names was refused, coded EMLINK
+#[derive(Debug)]
+pub struct Shared {
+    pub names: u64,
+}
+
+impl fmt::Display for Shared {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = if self.names == 1 { "" } else { "s" };
+        write!(f, "shares its birth time with {} other hard link{}", self.names, plural)
+    }
+}
+
+impl std::error::Error for Shared {}
+
+pub fn refused(names: u64) -> io::Error {
+    io::Error::other(Shared { names })
+}
+
+// Whether two paths name the same file, so a link whose names were split
+// into separate files since the snapshot still gets its times
+#[cfg(unix)]
+pub fn same_file(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let (a, b) = (std::fs::symlink_metadata(a)?, std::fs::symlink_metadata(b)?);
+    Ok((a.dev(), a.ino()) == (b.dev(), b.ino()))
+}
+
+#[cfg(windows)]
+pub fn same_file(a: &Path, b: &Path) -> io::Result<bool> {
+    let a = win::file_information(&win::open_query(a, false)?)?;
+    let b = win::file_information(&win::open_query(b, false)?)?;
+    Ok((a.dwVolumeSerialNumber, win::file_index(&a)) == (b.dwVolumeSerialNumber, win::file_index(&b)))
+}
+
+// Other platforms have no way to tell links apart, every file counts as one
+#[cfg(not(any(unix, windows)))]
+pub fn link_key(_path: &Path) -> io::Result<Option<String>> {
+    Ok(None)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn other_names(_path: &Path, _follow: bool) -> io::Result<u64> {
+    Ok(0)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn same_file(_a: &Path, _b: &Path) -> io::Result<bool> {
+    Ok(false)
+}
@@ -0,0 +1,132 @@
+// Copying timestamps between two trees with the same layout, e.g. after an
+// rsync or robocopy run that dropped creation times
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::pool;
+use crate::snapshot::{self, TimeFields};
+use crate::times::{FileTimes, Timestamp};
+use crate::volume;
+use crate::walk::{Entry, EntryType, WalkError, WalkOptions, Walker};
+
+#[derive(Debug, Default)]
+pub struct MirrorResult {
+    // Destination entries whose timestamps were changed
+    pub updated: usize,
+    pub unchanged: usize,
+    // Relative paths found only in the source
+    pub missing: Vec<String>,
+    // Relative paths found only in the destination
+    pub extra: Vec<String>,
+    // Relative paths that are a different type of entry in the destination
+    pub mismatched: Vec<String>,
+    pub errors: Vec<WalkError>,
+    pub truncated: Vec<PathBuf>,
+}
+
+// Walk a tree into (relative path, entry), the root itself included as "."
+fn walk_tree(root: &Path, options: WalkOptions, result: &mut MirrorResult) -> io::Result<Vec<(String, Entry)>> {
+    let metadata = std::fs::metadata(root)?;
+    let mut entries = vec![(
+        ".".to_string(),
+        Entry {
+            path: root.to_path_buf(),
+            depth: 0,
+            file_type: EntryType::Dir,
+            times: FileTimes::from_metadata(&metadata),
+            size: metadata.len(),
+            symlink: false,
+            placeholder: false,
+        },
+    )];
+
+    let mut walker = Walker::new(root, options);
+    for item in walker.by_ref() {
+        match item {
+            Ok(entry) => entries.push((snapshot::relative_path(root, &entry.path), entry)),
+            Err(err) => result.errors.push(err),
+        }
+    }
+    result.truncated.extend_from_slice(walker.truncated());
+    Ok(entries)
+}
+
+// The selected source times that differ from the destination's
+fn differing(fields: TimeFields, source: &FileTimes, destination: &FileTimes) -> FileTimes {
+    let pick = |selected: bool, source: Option<Timestamp>, destination: Option<Timestamp>| {
+        source.filter(|_| selected && source != destination)
+    };
+    FileTimes {
+        btime: pick(fields.btime, source.btime, destination.btime),
+        mtime: pick(fields.mtime, source.mtime, destination.mtime),
+        atime: pick(fields.atime, source.atime, destination.atime),
+        ctime: None,
+    }
+}
+
+// Copy the selected timestamps of every source entry to the entry at the same
+// relative path below destination, where they differ. Files are written on
+// `concurrency` workers, directories afterwards, deepest first. Symlinks that
+// are not followed are left alone. With case_fold, relative paths that
+// differ only in case are the same entry
+pub fn mirror_times(
+    source: &Path,
+    destination: &Path,
+    options: WalkOptions,
+    fields: TimeFields,
+    concurrency: usize,
+    case_fold: bool,
+) -> io::Result<MirrorResult> {
+    let mut result = MirrorResult::default();
+    let source_entries = walk_tree(source, options.clone(), &mut result)?;
+    let key = |path: &str| if case_fold { volume::fold_case(path) } else { path.to_string() };
+    let mut destination_entries: HashMap<String, (String, Entry)> = walk_tree(destination, options, &mut result)?
+        .into_iter()
+        .map(|(relative, entry)| (key(&relative), (relative, entry)))
+        .collect();
+
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for (relative, entry) in source_entries {
+        let Some((_, target)) = destination_entries.remove(&key(&relative)) else {
+            result.missing.push(relative);
+            continue;
+        };
+        if target.file_type != entry.file_type {
+            result.mismatched.push(relative);
+            continue;
+        }
+        if entry.file_type.is_link() {
+            continue;
+        }
+
+        let changes = differing(fields, &entry.times, &target.times);
+        if changes == FileTimes::default() {
+            result.unchanged += 1;
+        } else if entry.file_type == EntryType::Dir {
+            dirs.push((target.path, changes));
+        } else {
+            files.push((target.path, changes));
+        }
+    }
+    result.extra = destination_entries.into_values().map(|(relative, _)| relative).collect();
+    result.extra.sort();
+
+    let updated = AtomicUsize::new(0);
+    let errors = Mutex::new(Vec::new());
+    let apply = |(path, times): (PathBuf, FileTimes)| match crate::set_times(&path, &times) {
+        Ok(_) => {
+            updated.fetch_add(1, Ordering::Relaxed);
+        }
+        Err(error) => errors.lock().unwrap().push(WalkError { path, error }),
+    };
+    pool::for_each_bounded(files.into_iter(), concurrency, apply);
+    dirs.into_iter().rev().for_each(apply);
+
+    result.updated = updated.into_inner();
+    result.errors.extend(errors.into_inner().unwrap());
+    Ok(result)
+}
@@ -0,0 +1,106 @@
+// A backend that keeps timestamps in memory instead of writing them, so
+// applications can test against it on any platform. Writes are recorded
+// and stored per path; reads return the stored fields over the file's real
+// times, or the stored fields alone for paths that do not exist
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::backend::{self, BtimeBackend, Capabilities};
+use crate::times::FileTimes;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    SetTimes,
+    GetTimes,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::SetTimes => "setTimes",
+            Kind::GetTimes => "getTimes",
+        }
+    }
+}
+
+// One call the mock answered, in call order
+#[derive(Clone, Debug)]
+pub struct Operation {
+    pub kind: Kind,
+    pub path: PathBuf,
+    // The times written, or those returned by a successful read
+    pub times: Option<FileTimes>,
+}
+
+#[derive(Default)]
+struct State {
+    operations: Vec<Operation>,
+    files: HashMap<PathBuf, FileTimes>,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+fn with_state<T>(f: impl FnOnce(&mut State) -> T) -> T {
+    f(STATE.lock().unwrap().get_or_insert_with(State::default))
+}
+
+// Fields set in `over` replace those of `base`
+fn overlay(base: FileTimes, over: &FileTimes) -> FileTimes {
+    FileTimes {
+        btime: over.btime.or(base.btime),
+        mtime: over.mtime.or(base.mtime),
+        atime: over.atime.or(base.atime),
+        ctime: base.ctime,
+    }
+}
+
+pub struct Mock;
+
+impl BtimeBackend for Mock {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn set_times(&self, path: &Path, times: &FileTimes, _follow: bool) -> io::Result<()> {
+        let times = FileTimes { ctime: None, ..*times };
+        with_state(|state| {
+            let stored = state.files.entry(path.to_path_buf()).or_default();
+            *stored = overlay(*stored, &times);
+            state.operations.push(Operation { kind: Kind::SetTimes, path: path.to_path_buf(), times: Some(times) });
+        });
+        Ok(())
+    }
+
+    fn get_times(&self, path: &Path, follow: bool) -> io::Result<FileTimes> {
+        let real = backend::Platform.get_times(path, follow);
+        with_state(|state| {
+            let times = match (real, state.files.get(path)) {
+                (Ok(real), Some(stored)) => Ok(overlay(real, stored)),
+                (Err(err), Some(stored)) if err.kind() == io::ErrorKind::NotFound => Ok(*stored),
+                (real, _) => real,
+            };
+            let recorded = times.as_ref().ok().copied();
+            state.operations.push(Operation { kind: Kind::GetTimes, path: path.to_path_buf(), times: recorded });
+            times
+        })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { set_btime: true, get_btime: true, emulated: false }
+    }
+}
+
+pub fn operations() -> Vec<Operation> {
+    with_state(|state| state.operations.clone())
+}
+
+pub fn clear_operations() {
+    with_state(|state| state.operations.clear());
+}
+
+// Forget the recorded calls and the stored times
+pub fn reset() {
+    *STATE.lock().unwrap() = None;
+}
@@ -0,0 +1,210 @@
+// The mount table: listed for callers that plan work per volume, and
+// watched so what was learned about a volume is dropped once something is
+// mounted or unmounted. A device number or volume serial can be reused by
+// the next filesystem mounted, so a cache keyed by them alone would
+// describe the wrong volume
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+#[derive(Clone, Debug)]
+pub struct Mount {
+    pub path: PathBuf,
+    // What is mounted: a device such as /dev/sda1, a share such as
+    // //server/share, or the volume GUID path on Windows
+    pub device: String,
+    // The type as the mount table names it, e.g. "ext4", "apfs" or "NTFS"
+    pub filesystem: String,
+    pub read_only: bool,
+}
+
+// Undo the octal escapes mountinfo writes for space, tab, newline and backslash
+#[cfg(target_os = "linux")]
+fn unescape(bytes: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|digits| std::str::from_utf8(digits).ok()).and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match octal {
+            Some(byte) if bytes[i] == b'\\' => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            _ => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    unescaped
+}
+
+// Each line of /proc/self/mountinfo is
+// "id parent major:minor root mountpoint options [tags...] - type source superoptions".
+// Mount points are bytes like any path and need not be UTF-8
+#[cfg(target_os = "linux")]
+pub fn list() -> io::Result<Vec<Mount>> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let mountinfo = std::fs::read("/proc/self/mountinfo")?;
+    let read_only = |options: &[u8]| options.split(|&byte| byte == b',').any(|option| option == b"ro");
+    let mut mounts = Vec::new();
+    for line in mountinfo.split(|&byte| byte == b'\n') {
+        let Some(separator) = line.windows(3).position(|window| window == b" - ") else {
+            continue;
+        };
+        let mount: Vec<&[u8]> = line[..separator].split(|&byte| byte == b' ').collect();
+        let filesystem: Vec<&[u8]> = line[separator + 3..].split(|&byte| byte == b' ').collect();
+        let (Some(path), Some(options), Some(kind), Some(source)) = (mount.get(4), mount.get(5), filesystem.first(), filesystem.get(1)) else {
+            continue;
+        };
+        let super_options = filesystem.get(2).copied().unwrap_or_default();
+        let read_only = read_only(options) || read_only(super_options);
+        mounts.push(Mount {
+            path: PathBuf::from(OsString::from_vec(unescape(path))),
+            device: String::from_utf8_lossy(&unescape(source)).into_owned(),
+            filesystem: String::from_utf8_lossy(&unescape(kind)).into_owned(),
+            read_only,
+        });
+    }
+    Ok(mounts)
+}
+
+// The mounted filesystems as getfsstat reports them. Without MNT_WAIT it
+// reads the kernel's cached table rather than asking every filesystem
+#[cfg(target_os = "macos")]
+fn table() -> io::Result<Vec<libc::statfs>> {
+    let count = unsafe { libc::getfsstat(std::ptr::null_mut(), 0, libc::MNT_NOWAIT) };
+    if count < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut table: Vec<libc::statfs> = vec![unsafe { std::mem::zeroed() }; count as usize];
+    let size = (table.len() * std::mem::size_of::<libc::statfs>()) as libc::c_int;
+    let count = unsafe { libc::getfsstat(table.as_mut_ptr(), size, libc::MNT_NOWAIT) };
+    if count < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    table.truncate(count as usize);
+    Ok(table)
+}
+
+#[cfg(target_os = "macos")]
+pub fn list() -> io::Result<Vec<Mount>> {
+    use std::ffi::{CStr, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+
+    let text = |name: &[libc::c_char]| unsafe { CStr::from_ptr(name.as_ptr()) }.to_string_lossy().into_owned();
+    let mounts = table()?.iter().map(|mount| Mount {
+        path: PathBuf::from(OsStr::from_bytes(unsafe { CStr::from_ptr(mount.f_mntonname.as_ptr()) }.to_bytes())),
+        device: text(&mount.f_mntfromname),
+        filesystem: text(&mount.f_fstypename),
+        read_only: mount.f_flags & libc::MNT_RDONLY as u32 != 0,
+    });
+    Ok(mounts.collect())
+}
+
+// The drive letters in use. Drives without a volume, such as an empty card
+// reader, are left out
+#[cfg(target_os = "windows")]
+pub fn list() -> io::Result<Vec<Mount>> {
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::{GetLogicalDrives, GetVolumeInformationW, GetVolumeNameForVolumeMountPointW};
+
+    // From winnt.h
+    const FILE_READ_ONLY_VOLUME: u32 = 0x0008_0000;
+
+    let drives = unsafe { GetLogicalDrives() };
+    if drives == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let text = |buffer: &[u16]| String::from_utf16_lossy(&buffer[..buffer.iter().position(|&unit| unit == 0).unwrap_or(buffer.len())]);
+    let mut mounts = Vec::new();
+    for letter in (0..26u8).filter(|letter| drives & (1 << letter) != 0) {
+        let root = format!("{}:\\", (b'A' + letter) as char);
+        let wide: Vec<u16> = root.encode_utf16().chain(Some(0)).collect();
+        let mut flags = 0u32;
+        let mut filesystem = [0u16; 64];
+        let volume = unsafe { GetVolumeInformationW(PCWSTR(wide.as_ptr()), None, None, None, Some(&mut flags), Some(&mut filesystem)) };
+        if volume.is_err() {
+            continue;
+        }
+        // Network drives have no volume GUID path
+        let mut device = [0u16; 64];
+        let device = match unsafe { GetVolumeNameForVolumeMountPointW(PCWSTR(wide.as_ptr()), &mut device) } {
+            Ok(()) => text(&device),
+            Err(_) => root.clone(),
+        };
+        mounts.push(Mount { path: PathBuf::from(root), device, filesystem: text(&filesystem), read_only: flags & FILE_READ_ONLY_VOLUME != 0 });
+    }
+    Ok(mounts)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+pub fn list() -> io::Result<Vec<Mount>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "mount table not available"))
+}
+
+// Whether the mount table changed since the last call. The first call
+// starts watching and reports no change
+#[cfg(target_os = "linux")]
+pub fn changed() -> bool {
+    use std::os::fd::AsRawFd;
+
+    // The kernel flags an open mountinfo with POLLPRI when the mount
+    // namespace changes, and clears the flag as poll reports it
+    static MOUNTINFO: Mutex<Option<std::fs::File>> = Mutex::new(None);
+    let mut mountinfo = MOUNTINFO.lock().unwrap();
+    let Some(file) = mountinfo.as_ref() else {
+        *mountinfo = std::fs::File::open("/proc/self/mountinfo").ok();
+        return false;
+    };
+    let mut poll = libc::pollfd { fd: file.as_raw_fd(), events: libc::POLLPRI, revents: 0 };
+    if unsafe { libc::poll(&mut poll, 1, 0) } <= 0 {
+        return false;
+    }
+    poll.revents & (libc::POLLPRI | libc::POLLERR) != 0
+}
+
+// macOS has no notification short of a run loop, so the table itself is
+// compared
+#[cfg(target_os = "macos")]
+pub fn changed() -> bool {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    static SIGNATURE: Mutex<Option<u64>> = Mutex::new(None);
+    let Ok(table) = table() else {
+        return false;
+    };
+    let mut hasher = DefaultHasher::new();
+    for mount in &table {
+        mount.f_mntfromname.hash(&mut hasher);
+        mount.f_mntonname.hash(&mut hasher);
+        mount.f_flags.hash(&mut hasher);
+    }
+    let signature = hasher.finish();
+    let mut last = SIGNATURE.lock().unwrap();
+    let changed = last.is_some_and(|last| last != signature);
+    *last = Some(signature);
+    changed
+}
+
+// Drive letters coming and going. A disk swapped under the same letter has
+// a different volume serial and so is a different key anyway
+#[cfg(target_os = "windows")]
+pub fn changed() -> bool {
+    use windows::Win32::Storage::FileSystem::GetLogicalDrives;
+
+    static DRIVES: Mutex<Option<u32>> = Mutex::new(None);
+    let drives = unsafe { GetLogicalDrives() };
+    let mut last = DRIVES.lock().unwrap();
+    let changed = last.is_some_and(|last| last != drives);
+    *last = Some(drives);
+    changed
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+pub fn changed() -> bool {
+    false
+}
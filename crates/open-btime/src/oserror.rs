@@ -0,0 +1,170 @@
+// The text the OS gives for its error codes, so thrown errors say "Access is
+// denied" rather than leaving users to look up error 5. std only offers it
+// with " (os error 5)" appended.
+//
+// Errors also get a code from a fixed set of POSIX names, the same on every
+// platform, so JS handles ERROR_SHARING_VIOLATION on Windows and EBUSY
+// elsewhere with one check. The README lists the set
+use std::io;
+
+// The stable code of an error, EUNKNOWN for OS errors outside the set
+pub fn code(err: &io::Error) -> &'static str {
+    if let Some(code) = err.raw_os_error() {
+        return os_code(code).unwrap_or("EUNKNOWN");
+    }
+    // Errors of this crate's own making, such as malformed paths
+    if err.get_ref().is_some_and(|inner| inner.is::<crate::links::Shared>()) {
+        return "EMLINK";
+    }
+    match err.kind() {
+        io::ErrorKind::NotFound => "ENOENT",
+        io::ErrorKind::PermissionDenied => "EACCES",
+        io::ErrorKind::Unsupported => "ENOTSUP",
+        io::ErrorKind::TimedOut => "ETIMEDOUT",
+        io::ErrorKind::AlreadyExists => "EEXIST",
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => "EINVAL",
+        _ => "EUNKNOWN",
+    }
+}
+
+#[cfg(unix)]
+fn os_code(code: i32) -> Option<&'static str> {
+    Some(match code {
+        libc::ENOENT => "ENOENT",
+        libc::EACCES => "EACCES",
+        libc::EPERM => "EPERM",
+        // One number on Linux, two on macOS, which are the same to callers
+        code if code == libc::ENOTSUP || code == libc::EOPNOTSUPP => "ENOTSUP",
+        libc::ENOSYS => "ENOTSUP",
+        libc::EBUSY | libc::ETXTBSY => "EBUSY",
+        libc::EROFS => "EROFS",
+        libc::ETIMEDOUT => "ETIMEDOUT",
+        libc::EEXIST => "EEXIST",
+        libc::ENOTDIR => "ENOTDIR",
+        libc::EISDIR => "EISDIR",
+        libc::ENOTEMPTY => "ENOTEMPTY",
+        libc::EXDEV => "EXDEV",
+        libc::ELOOP => "ELOOP",
+        libc::ENAMETOOLONG => "ENAMETOOLONG",
+        libc::EINVAL | libc::EBADF => "EINVAL",
+        libc::ENOSPC | libc::EDQUOT => "ENOSPC",
+        libc::EIO => "EIO",
+        libc::EMFILE | libc::ENFILE => "EMFILE",
+        libc::EAGAIN | libc::EINTR => "EAGAIN",
+        libc::ECANCELED => "ECANCELED",
+        libc::EMLINK => "EMLINK",
+        _ => return None,
+    })
+}
+
+// Win32 error codes from winerror.h
+#[cfg(windows)]
+fn os_code(code: i32) -> Option<&'static str> {
+    Some(match code {
+        // ERROR_FILE_NOT_FOUND, ERROR_PATH_NOT_FOUND, ERROR_INVALID_DRIVE,
+        // ERROR_BAD_NETPATH, ERROR_BAD_NET_NAME
+        2 | 3 | 15 | 53 | 67 => "ENOENT",
+        // ERROR_ACCESS_DENIED, ERROR_NETWORK_ACCESS_DENIED
+        5 | 65 => "EACCES",
+        // ERROR_NOT_ALL_ASSIGNED, ERROR_PRIVILEGE_NOT_HELD
+        1300 | 1314 => "EPERM",
+        // ERROR_INVALID_FUNCTION, ERROR_NOT_SUPPORTED, ERROR_CALL_NOT_IMPLEMENTED
+        1 | 50 | 120 => "ENOTSUP",
+        // ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION, ERROR_BUSY,
+        // ERROR_USER_MAPPED_FILE
+        32 | 33 | 170 | 1224 => "EBUSY",
+        // ERROR_WRITE_PROTECT
+        19 => "EROFS",
+        // ERROR_SEM_TIMEOUT, WAIT_TIMEOUT, ERROR_TIMEOUT
+        121 | 258 | 1460 => "ETIMEDOUT",
+        // ERROR_FILE_EXISTS, ERROR_ALREADY_EXISTS
+        80 | 183 => "EEXIST",
+        // ERROR_DIRECTORY
+        267 => "ENOTDIR",
+        // ERROR_DIR_NOT_EMPTY
+        145 => "ENOTEMPTY",
+        // ERROR_NOT_SAME_DEVICE
+        17 => "EXDEV",
+        // ERROR_CANT_RESOLVE_FILENAME
+        1921 => "ELOOP",
+        // ERROR_FILENAME_EXCED_RANGE
+        206 => "ENAMETOOLONG",
+        // ERROR_INVALID_HANDLE, ERROR_INVALID_PARAMETER, ERROR_INVALID_NAME
+        6 | 87 | 123 => "EINVAL",
+        // ERROR_HANDLE_DISK_FULL, ERROR_DISK_FULL
+        39 | 112 => "ENOSPC",
+        // ERROR_CRC, ERROR_IO_DEVICE
+        23 | 1117 => "EIO",
+        // ERROR_TOO_MANY_OPEN_FILES
+        4 => "EMFILE",
+        // ERROR_IO_PENDING
+        997 => "EAGAIN",
+        // ERROR_OPERATION_ABORTED, ERROR_CANCELLED
+        995 | 1223 => "ECANCELED",
+        // ERROR_TOO_MANY_LINKS
+        1142 => "EMLINK",
+        _ => return None,
+    })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn os_code(_code: i32) -> Option<&'static str> {
+    None
+}
+
+// io::Error is not Clone; the copy keeps the code, kind and text
+pub fn copy(err: &io::Error) -> io::Error {
+    match err.raw_os_error() {
+        Some(code) => io::Error::from_raw_os_error(code),
+        None => io::Error::new(err.kind(), err.to_string()),
+    }
+}
+
+// What went wrong, in the words of the OS for OS errors
+pub fn describe(err: &io::Error) -> String {
+    err.raw_os_error().and_then(message).unwrap_or_else(|| err.to_string())
+}
+
+// strerror_r, the XSI version that fills the buffer
+#[cfg(unix)]
+fn message(code: i32) -> Option<String> {
+    let mut buffer = [0 as libc::c_char; 256];
+    if unsafe { libc::strerror_r(code, buffer.as_mut_ptr(), buffer.len()) } != 0 {
+        return None;
+    }
+    let text = unsafe { std::ffi::CStr::from_ptr(buffer.as_ptr()) }.to_string_lossy();
+    Some(text.into_owned())
+}
+
+// FormatMessageW in the user's language, without the trailing period and
+// line break of system messages
+#[cfg(windows)]
+fn message(code: i32) -> Option<String> {
+    use windows::core::PWSTR;
+    use windows::Win32::System::Diagnostics::Debug::{
+        FormatMessageW, FORMAT_MESSAGE_FROM_SYSTEM, FORMAT_MESSAGE_IGNORE_INSERTS,
+    };
+
+    let mut buffer = [0u16; 512];
+    let length = unsafe {
+        FormatMessageW(
+            FORMAT_MESSAGE_FROM_SYSTEM | FORMAT_MESSAGE_IGNORE_INSERTS,
+            None,
+            code as u32,
+            0,
+            PWSTR(buffer.as_mut_ptr()),
+            buffer.len() as u32,
+            None,
+        )
+    };
+    if length == 0 {
+        return None;
+    }
+    let text = String::from_utf16_lossy(&buffer[..length as usize]);
+    Some(text.trim_end().trim_end_matches('.').to_string())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn message(_code: i32) -> Option<String> {
+    None
+}
@@ -0,0 +1,338 @@
+use std::ffi::OsString;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Component, Path, PathBuf};
+
+use crate::config;
+
+// Resolve a relative path against an explicit base directory instead of the
+// process-wide working directory
+pub fn resolve(path: PathBuf, cwd: Option<&Path>) -> PathBuf {
+    match cwd {
+        Some(cwd) if path.is_relative() => cwd.join(path),
+        _ => path,
+    }
+}
+
+// The absolute path with every symlink, `.` and `..` resolved, from realpath
+// or GetFinalPathNameByHandleW. Without follow the last component is only
+// made absolute, so a symlink itself is still the one addressed
+pub fn canonicalize(path: &Path, follow: bool) -> Result<PathBuf> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !follow => {
+            let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            Ok(strip_verbatim(std::fs::canonicalize(parent)?).join(name))
+        }
+        _ => std::fs::canonicalize(path).map(strip_verbatim),
+    }
+}
+
+// GetFinalPathNameByHandleW gives \\?\C:\x and \\?\UNC\server\share\x,
+// reported as C:\x and \\server\share\x like every other path
+#[cfg(target_os = "windows")]
+pub fn strip_verbatim(path: PathBuf) -> PathBuf {
+    let Some(text) = path.to_str() else {
+        return path;
+    };
+    if let Some(rest) = text.strip_prefix(r"\\?\UNC\") {
+        return PathBuf::from(format!(r"\\{}", rest));
+    }
+    match text.strip_prefix(r"\\?\") {
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => PathBuf::from(rest),
+        _ => path,
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn strip_verbatim(path: PathBuf) -> PathBuf {
+    path
+}
+
+// The checks of the path layer, run right before each timestamp call
+pub fn check(path: &Path, follow: bool) -> Result<()> {
+    #[cfg(target_os = "windows")]
+    check_device_name(path)?;
+    check_link_depth(path, follow)
+}
+
+// Win32 maps these names to a device in any directory and whatever the
+// extension, so C:\logs\aux.txt is the AUX device rather than a file.
+// Opening one can block on a port or console, or write times to nothing
+#[cfg(target_os = "windows")]
+fn device_name(name: &str) -> bool {
+    // Trailing spaces of the base name are ignored as well
+    let base = name.split('.').next().unwrap_or(name).trim_end_matches(' ').to_ascii_uppercase();
+    match base.as_str() {
+        "CON" | "PRN" | "AUX" | "NUL" | "CONIN$" | "CONOUT$" => true,
+        _ => match base.strip_prefix("COM").or_else(|| base.strip_prefix("LPT")) {
+            Some(number) => matches!(number, "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" | "¹" | "²" | "³"),
+            None => false,
+        },
+    }
+}
+
+// Reject a path whose last component is a reserved device name. The \\?\
+// form skips the mapping and addresses a file of that name, and \\.\
+// names a device on purpose, so both pass
+#[cfg(target_os = "windows")]
+fn check_device_name(path: &Path) -> Result<()> {
+    use std::path::Prefix;
+
+    if let Some(Component::Prefix(prefix)) = path.components().next() {
+        if prefix.kind().is_verbatim() || matches!(prefix.kind(), Prefix::DeviceNS(_)) {
+            return Ok(());
+        }
+    }
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if device_name(name) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is a reserved device name, use the \\\\?\\ form for a file of that name", name),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// The path to open for path. Win32 strips trailing dots and spaces from
+// every component, so a file named "notes." created from WSL or by a
+// network client would be taken for "notes". Paths with such names are
+// made absolute and turned into the \\?\ form, which is passed on as it
+// is; `.` and `..` are resolved first, as that form leaves them alone too
+#[cfg(target_os = "windows")]
+pub fn literal(path: &Path) -> std::borrow::Cow<'_, Path> {
+    use std::borrow::Cow;
+    use std::path::Prefix;
+
+    let trailing = |name: &std::ffi::OsStr| name.to_str().is_some_and(|name| name.ends_with(['.', ' ']));
+    let stripped = path.components().any(|component| matches!(component, Component::Normal(name) if trailing(name)));
+    if !stripped {
+        return Cow::Borrowed(path);
+    }
+    let absolute = match std::env::current_dir() {
+        Ok(cwd) if path.is_relative() => cwd.join(path),
+        _ => path.to_path_buf(),
+    };
+    // A drive-relative path such as C:notes. is relative to that drive's
+    // own working directory, which only the OS knows
+    if !absolute.has_root() {
+        return Cow::Borrowed(path);
+    }
+
+    let mut literal = OsString::new();
+    let mut names: Vec<&std::ffi::OsStr> = Vec::new();
+    for component in absolute.components() {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::Disk(drive) => literal.push(format!(r"\\?\{}:", drive as char)),
+                Prefix::UNC(server, share) => {
+                    literal.push(r"\\?\UNC\");
+                    literal.push(server);
+                    literal.push(r"\");
+                    literal.push(share);
+                }
+                // Already verbatim, or a device
+                _ => return Cow::Borrowed(path),
+            },
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir => {
+                names.pop();
+            }
+            Component::Normal(name) => names.push(name),
+        }
+    }
+    for name in names {
+        literal.push(r"\");
+        literal.push(name);
+    }
+    Cow::Owned(PathBuf::from(literal))
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn literal(path: &Path) -> std::borrow::Cow<'_, Path> {
+    std::borrow::Cow::Borrowed(path)
+}
+
+// The error for a path with more symlinks than allowed, as the OS reports it
+#[cfg(unix)]
+pub fn too_many_links() -> Error {
+    Error::from_raw_os_error(libc::ELOOP)
+}
+
+// ERROR_CANT_RESOLVE_FILENAME
+#[cfg(not(unix))]
+pub fn too_many_links() -> Error {
+    Error::from_raw_os_error(1921)
+}
+
+// Fail with too_many_links when resolving path takes more symlinks than
+// configure({ symlinkDepth }) allows, counting those in the directories
+// above it and those their targets lead through. Without follow a symlink
+// in the last component is not resolved. Without a configured depth the
+// OS's own limit applies and nothing is checked here, as each check costs
+// an lstat per component. Components that cannot be looked up are left to
+// the call that follows to report
+pub fn check_link_depth(path: &Path, follow: bool) -> Result<()> {
+    let Some(limit) = config::get().symlink_depth else {
+        return Ok(());
+    };
+    let mut current = PathBuf::new();
+    let mut pending = Vec::new();
+    let absolute = if path.is_relative() { std::env::current_dir()?.join(path) } else { path.to_path_buf() };
+    queue_components(&mut current, &mut pending, &absolute);
+
+    let mut links = 0;
+    while let Some(name) = pending.pop() {
+        if name == ".." {
+            current.pop();
+            continue;
+        }
+        let next = current.join(&name);
+        let is_link = std::fs::symlink_metadata(&next).is_ok_and(|metadata| metadata.file_type().is_symlink());
+        if !is_link || (pending.is_empty() && !follow) {
+            current = next;
+            continue;
+        }
+
+        links += 1;
+        if links > limit {
+            return Err(too_many_links());
+        }
+        let Ok(target) = std::fs::read_link(&next) else {
+            return Ok(());
+        };
+        queue_components(&mut current, &mut pending, &target);
+    }
+    Ok(())
+}
+
+// Queue the components of path so the first is popped first. The root or
+// drive of an absolute path replaces current
+fn queue_components(current: &mut PathBuf, pending: &mut Vec<OsString>, path: &Path) {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            // Pushing a root or drive replaces what it does not keep
+            Component::Prefix(_) | Component::RootDir => current.push(component.as_os_str()),
+            Component::CurDir => {}
+            Component::ParentDir => components.push(OsString::from("..")),
+            Component::Normal(name) => components.push(name.to_os_string()),
+        }
+    }
+    pending.extend(components.into_iter().rev());
+}
+
+// Convert a file:// URL into a native path, following the same rules as
+// Node's url.fileURLToPath()
+pub fn file_url_to_path(url: &str) -> Result<PathBuf> {
+    let rest = match url.get(..7) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("file://") => &url[7..],
+        _ => return Err(invalid_url("The URL must be of scheme file")),
+    };
+
+    // Drop any query string or fragment, they are never part of the path
+    let rest = rest.split(['?', '#']).next().unwrap_or("");
+
+    // Split the authority (host) from the path component
+    let (host, pathname) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+
+    // Encoded separators would silently change which file is addressed
+    if has_encoded_separator(pathname) {
+        return Err(invalid_url("File URL path must not include encoded \\ or / characters"));
+    }
+
+    url_parts_to_path(host, pathname)
+}
+
+#[cfg(target_os = "windows")]
+fn url_parts_to_path(host: &str, pathname: &str) -> Result<PathBuf> {
+    let decoded = percent_decode(pathname)?;
+    let decoded = String::from_utf8(decoded)
+        .map_err(|_| invalid_url("File URL path must be valid UTF-8"))?
+        .replace('/', "\\");
+
+    // A non-local host maps onto a UNC path: file://server/share/x -> \\server\share\x
+    if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+        return Ok(PathBuf::from(format!("\\\\{}{}", host, decoded)));
+    }
+
+    // Otherwise the path has to start with a drive letter: /C:/x -> C:\x
+    let bytes = decoded.as_bytes();
+    if bytes.len() < 3 || !bytes[1].is_ascii_alphabetic() || bytes[2] != b':' {
+        return Err(invalid_url("File URL path must be absolute"));
+    }
+    Ok(PathBuf::from(&decoded[1..]))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn url_parts_to_path(host: &str, pathname: &str) -> Result<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+        return Err(invalid_url("File URL host must be \"localhost\" or empty"));
+    }
+
+    let decoded = percent_decode(pathname)?;
+    Ok(PathBuf::from(OsString::from_vec(decoded)))
+}
+
+// Check for %2F (/) and, on Windows, %5C (\) in a URL path
+fn has_encoded_separator(pathname: &str) -> bool {
+    let lower = pathname.to_ascii_lowercase();
+    lower.contains("%2f") || (cfg!(target_os = "windows") && lower.contains("%5c"))
+}
+
+// Decode %XX escapes into raw bytes
+fn percent_decode(input: &str) -> Result<Vec<u8>> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hi = bytes.get(i + 1).and_then(|b| (*b as char).to_digit(16));
+            let lo = bytes.get(i + 2).and_then(|b| (*b as char).to_digit(16));
+            match (hi, lo) {
+                (Some(hi), Some(lo)) => {
+                    out.push((hi * 16 + lo) as u8);
+                    i += 3;
+                    continue;
+                }
+                _ => return Err(invalid_url("Invalid percent-encoding in file URL")),
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    Ok(out)
+}
+
+fn invalid_url(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("/a%20b/c").unwrap(), b"/a b/c");
+        assert_eq!(percent_decode("%C3%a9%ff").unwrap(), [0xc3, 0xa9, 0xff]);
+        assert_eq!(percent_decode("100%25").unwrap(), b"100%");
+        assert_eq!(percent_decode("").unwrap(), b"");
+        for input in ["%", "%4", "%zz", "a%g1"] {
+            assert_eq!(percent_decode(input).unwrap_err().kind(), ErrorKind::InvalidInput, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn finds_encoded_separators() {
+        assert!(has_encoded_separator("/a%2Fb"));
+        assert!(has_encoded_separator("/a%2fb"));
+        assert!(!has_encoded_separator("/a%20b"));
+        assert_eq!(has_encoded_separator("/a%5Cb"), cfg!(target_os = "windows"));
+    }
+}
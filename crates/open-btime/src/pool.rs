@@ -0,0 +1,42 @@
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+// Run f over every item on `threads` worker threads. The items are produced on
+// the calling thread while the workers run, and at most `threads` items wait
+// in the queue, so a fast producer cannot run arbitrarily far ahead
+pub fn for_each_bounded<T, I, F>(items: I, threads: usize, f: F)
+where
+    T: Send,
+    I: Iterator<Item = T>,
+    F: Fn(T) + Sync,
+{
+    let threads = threads.max(1);
+    if threads == 1 {
+        items.for_each(f);
+        return;
+    }
+
+    let (sender, receiver) = mpsc::sync_channel::<T>(threads);
+    let receiver = Mutex::new(receiver);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                // Hold the lock only while taking the next item
+                let item = receiver.lock().unwrap().recv();
+                match item {
+                    Ok(item) => f(item),
+                    Err(_) => break,
+                }
+            });
+        }
+
+        for item in items {
+            if sender.send(item).is_err() {
+                break;
+            }
+        }
+        drop(sender);
+    });
+}
@@ -0,0 +1,65 @@
+// Structured conditions on walked entries, such as "JPEGs modified before
+// 2020", evaluated by the walker so a tree of millions of entries needs no
+// pass over them in JS. A condition object requires all of its conditions;
+// any and not combine them further
+use crate::glob::Glob;
+use crate::times::{FileTimes, Timestamp};
+use crate::walk::{Entry, EntryType};
+
+#[derive(Clone, Copy, Debug)]
+pub enum TimeField {
+    Btime,
+    Mtime,
+    Atime,
+}
+
+impl TimeField {
+    fn of(self, times: &FileTimes) -> Option<Timestamp> {
+        match self {
+            TimeField::Btime => times.btime,
+            TimeField::Mtime => times.mtime,
+            TimeField::Atime => times.atime,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Predicate {
+    All(Vec<Predicate>),
+    Any(Vec<Predicate>),
+    Not(Box<Predicate>),
+    // Lowercase extensions without the leading dot
+    Extension(Vec<String>),
+    Type(Vec<EntryType>),
+    // Any of the patterns, matched like exclude patterns
+    Name(Vec<Glob>),
+    // Strictly before or after; an entry without the time matches neither
+    Before(TimeField, Timestamp),
+    After(TimeField, Timestamp),
+    SizeOver(u64),
+    SizeUnder(u64),
+}
+
+impl Predicate {
+    // relative is the /-separated path of the entry below the walk root
+    pub fn matches(&self, entry: &Entry, relative: &str) -> bool {
+        match self {
+            Predicate::All(predicates) => predicates.iter().all(|predicate| predicate.matches(entry, relative)),
+            Predicate::Any(predicates) => predicates.iter().any(|predicate| predicate.matches(entry, relative)),
+            Predicate::Not(predicate) => !predicate.matches(entry, relative),
+            Predicate::Extension(extensions) => entry
+                .path
+                .extension()
+                .is_some_and(|extension| extensions.contains(&extension.to_string_lossy().to_lowercase())),
+            Predicate::Type(types) => types.contains(&entry.file_type),
+            Predicate::Name(globs) => {
+                let name = entry.path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+                globs.iter().any(|glob| glob.matches(relative, &name))
+            }
+            Predicate::Before(field, time) => field.of(&entry.times).is_some_and(|value| value < *time),
+            Predicate::After(field, time) => field.of(&entry.times).is_some_and(|value| value > *time),
+            Predicate::SizeOver(size) => entry.size > *size,
+            Predicate::SizeUnder(size) => entry.size < *size,
+        }
+    }
+}
@@ -0,0 +1,137 @@
+// Windows privileges that let backup and restore tools bypass file ACLs
+use std::io;
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{CloseHandle, GetLastError, HANDLE, LUID};
+use windows::Win32::Security::{
+    AdjustTokenPrivileges, GetTokenInformation, ImpersonateSelf, LookupPrivilegeValueW, RevertToSelf,
+    SecurityImpersonation, TokenPrivileges, LUID_AND_ATTRIBUTES, SE_BACKUP_NAME, SE_PRIVILEGE_ENABLED,
+    SE_RESTORE_NAME, TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES, TOKEN_QUERY,
+};
+use windows::Win32::System::Threading::{GetCurrentProcess, GetCurrentThread, OpenProcessToken, OpenThreadToken};
+
+use crate::win::io_error;
+
+const ERROR_ACCESS_DENIED: i32 = 5;
+const ERROR_NOT_ALL_ASSIGNED: i32 = 1300;
+
+struct Token(HANDLE);
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        let _ = unsafe { CloseHandle(self.0) };
+    }
+}
+
+fn lookup(name: PCWSTR) -> io::Result<LUID> {
+    let mut luid = LUID::default();
+    unsafe { LookupPrivilegeValueW(PCWSTR::null(), name, &mut luid) }.map_err(io_error)?;
+    Ok(luid)
+}
+
+fn process_token(access: windows::Win32::Security::TOKEN_ACCESS_MASK) -> io::Result<Token> {
+    let mut handle = HANDLE::default();
+    unsafe { OpenProcessToken(GetCurrentProcess(), access, &mut handle) }.map_err(io_error)?;
+    Ok(Token(handle))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrivilegeState {
+    NotHeld,
+    // Held by the account but disabled, the default for most privileges
+    Disabled,
+    Enabled,
+}
+
+// The state of a privilege in a token, read from its privilege list
+fn privilege_state(token: &Token, name: PCWSTR) -> io::Result<PrivilegeState> {
+    let luid = lookup(name)?;
+    let mut length = 0u32;
+    let _ = unsafe { GetTokenInformation(token.0, TokenPrivileges, None, 0, &mut length) };
+    let mut buf = vec![0u32; (length as usize).div_ceil(4)];
+    unsafe {
+        GetTokenInformation(token.0, TokenPrivileges, Some(buf.as_mut_ptr() as *mut _), length, &mut length)
+    }
+    .map_err(io_error)?;
+
+    // TOKEN_PRIVILEGES: a count followed by LUID_AND_ATTRIBUTES { u32 low, i32 high, u32 attributes }
+    let count = buf[0] as usize;
+    for privilege in buf[1..].chunks_exact(3).take(count) {
+        if privilege[0] == luid.LowPart && privilege[1] as i32 == luid.HighPart {
+            return Ok(if privilege[2] & SE_PRIVILEGE_ENABLED.0 != 0 {
+                PrivilegeState::Enabled
+            } else {
+                PrivilegeState::Disabled
+            });
+        }
+    }
+    Ok(PrivilegeState::NotHeld)
+}
+
+// Enable SeRestorePrivilege for the whole process, which lets restores write
+// files owned by other accounts. Returns the resulting state
+pub fn enable_restore_privilege() -> io::Result<PrivilegeState> {
+    let token = process_token(TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY)?;
+    match enable(&token, SE_RESTORE_NAME) {
+        Err(err) if err.raw_os_error() == Some(ERROR_NOT_ALL_ASSIGNED) => {}
+        result => result?,
+    }
+    privilege_state(&token, SE_RESTORE_NAME)
+}
+
+// Explain an access denied error by the state of SeRestorePrivilege
+pub fn access_denied_hint(err: &io::Error) -> Option<&'static str> {
+    if err.raw_os_error() != Some(ERROR_ACCESS_DENIED) {
+        return None;
+    }
+    let state = process_token(TOKEN_QUERY).and_then(|token| privilege_state(&token, SE_RESTORE_NAME));
+    match state {
+        Ok(PrivilegeState::NotHeld) => Some("the account does not hold SeRestorePrivilege"),
+        Ok(PrivilegeState::Disabled) => Some("SeRestorePrivilege is held but not enabled, see enableRestorePrivilege()"),
+        _ => None,
+    }
+}
+
+// Enable one privilege on a token. Fails with ERROR_NOT_ALL_ASSIGNED when
+// the account does not hold it
+fn enable(token: &Token, name: PCWSTR) -> io::Result<()> {
+    let luid = lookup(name)?;
+    let privileges = TOKEN_PRIVILEGES {
+        PrivilegeCount: 1,
+        Privileges: [LUID_AND_ATTRIBUTES { Luid: luid, Attributes: SE_PRIVILEGE_ENABLED }],
+    };
+    unsafe { AdjustTokenPrivileges(token.0, false, Some(&privileges), 0, None, None) }.map_err(io_error)?;
+    // AdjustTokenPrivileges succeeds for privileges the token lacks and only
+    // reports it through the last error
+    unsafe { GetLastError() }.map_err(io_error)
+}
+
+// Undoes ImpersonateSelf when dropped
+struct Impersonation;
+
+impl Drop for Impersonation {
+    fn drop(&mut self) {
+        let _ = unsafe { RevertToSelf() };
+    }
+}
+
+// Run f with SeBackupPrivilege enabled on a thread token, so opens with
+// FILE_FLAG_BACKUP_SEMANTICS bypass ACLs that deny attribute access.
+// SeRestorePrivilege, which covers writes under backup semantics, is enabled
+// as well when the account holds it. The process token is left untouched
+pub fn with_backup_privileges<T>(f: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    unsafe { ImpersonateSelf(SecurityImpersonation) }.map_err(io_error)?;
+    let _impersonation = Impersonation;
+
+    let mut handle = HANDLE::default();
+    unsafe { OpenThreadToken(GetCurrentThread(), TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY, false, &mut handle) }
+        .map_err(io_error)?;
+    let token = Token(handle);
+
+    enable(&token, SE_BACKUP_NAME)?;
+    match enable(&token, SE_RESTORE_NAME) {
+        Err(err) if err.raw_os_error() == Some(ERROR_NOT_ALL_ASSIGNED) => {}
+        result => result?,
+    }
+    f()
+}
@@ -0,0 +1,45 @@
+// Probe results by volume. A probe creates, writes and removes a file, which
+// is slow on network shares, and its answer holds for the whole volume, so
+// the last CAPACITY volumes probed are remembered, the least recently used
+// going first. The cache is emptied when the mount table changes
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::mounts;
+use crate::volume::{self, Probe};
+
+const CAPACITY: usize = 64;
+
+// Most recently used last
+static CACHE: Mutex<VecDeque<(u64, Probe)>> = Mutex::new(VecDeque::new());
+
+// The probe of the volume dir lives on, from the cache when it holds one
+// and fresh is not set. Probes that fail are not kept
+pub fn probe(dir: &Path, fresh: bool) -> io::Result<Probe> {
+    let Ok(id) = volume::id(dir) else {
+        return volume::probe(dir);
+    };
+    if !fresh {
+        let mut cache = CACHE.lock().unwrap();
+        if mounts::changed() {
+            cache.clear();
+        }
+        if let Some(i) = cache.iter().position(|(key, _)| *key == id) {
+            let entry = cache.remove(i).unwrap();
+            let probe = entry.1.clone();
+            cache.push_back(entry);
+            return Ok(probe);
+        }
+    }
+
+    let probe = volume::probe(dir)?;
+    let mut cache = CACHE.lock().unwrap();
+    cache.retain(|(key, _)| *key != id);
+    if cache.len() == CAPACITY {
+        cache.pop_front();
+    }
+    cache.push_back((id, probe.clone()));
+    Ok(probe)
+}
@@ -0,0 +1,78 @@
+// Per-file progress of background work, delivered to a JS callback as each
+// file completes. Posting to the JS thread once per file would flood it on
+// fast trees, so completions are collected here and posted in batches: at
+// most one batch waits for the JS thread at a time, and a new one is posted
+// no sooner than INTERVAL after the last. A batch picks up everything that
+// completed before it runs, however long the JS thread was busy
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use neon::prelude::*;
+
+use crate::oserror;
+use crate::walk::WalkError;
+
+const INTERVAL: Duration = Duration::from_millis(20);
+
+pub struct Progress {
+    channel: Channel,
+    callback: Root<JsFunction>,
+    pending: Mutex<Pending>,
+}
+
+#[derive(Default)]
+struct Pending {
+    completed: Vec<Completed>,
+    // A batch is posted and has not run yet
+    posted: bool,
+    last: Option<Instant>,
+}
+
+struct Completed {
+    path: String,
+    error: Option<WalkError>,
+}
+
+impl Progress {
+    pub fn new<'a>(cx: &mut impl Context<'a>, callback: Handle<'a, JsFunction>) -> Arc<Progress> {
+        Arc::new(Progress { channel: cx.channel(), callback: callback.root(cx), pending: Mutex::default() })
+    }
+
+    // Record a file as done, with the error it failed with. Called from any thread
+    pub fn complete(self: &Arc<Progress>, path: &Path, error: Option<&io::Error>) {
+        let error = error.map(|error| WalkError { path: path.to_path_buf(), error: oserror::copy(error) });
+        let mut pending = self.pending.lock().unwrap();
+        pending.completed.push(Completed { path: path.to_string_lossy().into_owned(), error });
+        if pending.posted || pending.last.is_some_and(|last| last.elapsed() < INTERVAL) {
+            return;
+        }
+        pending.posted = true;
+        pending.last = Some(Instant::now());
+        let progress = Arc::clone(self);
+        // Fails only once the environment is torn down, when nobody is listening
+        let _ = self.channel.try_send(move |mut cx| progress.deliver(&mut cx));
+    }
+
+    // Call the callback for every file completed so far, on the JS thread.
+    // Called once more after the work ends, before its result, for the files
+    // that completed after the last batch was posted
+    pub fn deliver<'a>(&self, cx: &mut impl Context<'a>) -> NeonResult<()> {
+        let completed = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.posted = false;
+            std::mem::take(&mut pending.completed)
+        };
+        let callback = self.callback.to_inner(cx);
+        for Completed { path, error } in completed {
+            let path = cx.string(path);
+            let error = match error {
+                Some(error) => crate::walk_error_to_js(cx, &error)?.upcast::<JsValue>(),
+                None => cx.null().upcast(),
+            };
+            callback.call_with(cx).arg(path).arg(error).exec(cx)?;
+        }
+        Ok(())
+    }
+}
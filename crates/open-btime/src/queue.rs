@@ -0,0 +1,73 @@
+// Timestamps applied while an archive is extracted, as each entry finishes
+// writing. Files are set in batches spread over worker threads. Directories
+// are held until the queue finishes, since extracting their entries changes
+// their times again, and are then set deepest first. Entries whose data may
+// still be written when they are queued, as with node-tar's onReadEntry,
+// are held the same way
+use std::mem;
+use std::path::PathBuf;
+
+use crate::times::FileTimes;
+use crate::tree::{self, TreeResult};
+use crate::walk::WalkError;
+
+pub struct TimesQueue {
+    files: Vec<(PathBuf, FileTimes)>,
+    // Directories and other entries set when the queue finishes
+    deferred: Vec<(PathBuf, FileTimes)>,
+    batch_size: usize,
+    concurrency: usize,
+    result: TreeResult,
+    finished: bool,
+}
+
+impl TimesQueue {
+    pub fn new(batch_size: usize, concurrency: usize) -> TimesQueue {
+        TimesQueue {
+            files: Vec::with_capacity(batch_size),
+            deferred: Vec::new(),
+            batch_size,
+            concurrency,
+            result: TreeResult::default(),
+            finished: false,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn push_file(&mut self, path: PathBuf, times: FileTimes) {
+        self.files.push((path, times));
+        if self.files.len() >= self.batch_size {
+            self.flush();
+        }
+    }
+
+    pub fn push_deferred(&mut self, path: PathBuf, times: FileTimes) {
+        self.deferred.push((path, times));
+    }
+
+    // Apply the files queued so far
+    pub fn flush(&mut self) {
+        let batch = mem::replace(&mut self.files, Vec::with_capacity(self.batch_size));
+        self.result.merge(tree::set_paths(batch, self.concurrency));
+    }
+
+    // Apply the remaining files, then the deferred entries with the deepest
+    // first so a directory comes after everything inside it; entries at the
+    // same depth keep the order they were queued in
+    pub fn finish(&mut self) -> TreeResult {
+        self.flush();
+        let mut deferred = mem::take(&mut self.deferred);
+        deferred.sort_by_key(|(path, _)| std::cmp::Reverse(path.components().count()));
+        for (path, times) in deferred {
+            match crate::set_times(&path, &times) {
+                Ok(backend) => self.result.written(backend),
+                Err(error) => self.result.errors.push(WalkError { path, error }),
+            }
+        }
+        self.finished = true;
+        mem::take(&mut self.result)
+    }
+}
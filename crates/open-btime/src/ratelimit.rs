@@ -0,0 +1,58 @@
+// A process-wide token bucket that paces timestamp writes, so a large tree
+// does not send tens of thousands of metadata writes a second to an SMB or
+// NFS server. All worker threads draw from the same bucket, which holds up
+// to one second's worth of writes
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct Bucket {
+    // Writes per second
+    rate: f64,
+    // Negative while writes wait for their turn
+    tokens: f64,
+    updated: Instant,
+}
+
+// One write an hour, below which a limit is a mistake rather than pacing
+pub const MIN_RATE: f64 = 1.0 / 3600.0;
+
+static BUCKET: Mutex<Option<Bucket>> = Mutex::new(None);
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+// Limit writes to `rate` a second, or lift the limit with None
+pub fn set(rate: Option<f64>) {
+    let mut bucket = BUCKET.lock().unwrap();
+    ACTIVE.store(rate.is_some(), Ordering::Relaxed);
+    *bucket = rate.map(|rate| Bucket { rate, tokens: rate.max(1.0), updated: Instant::now() });
+}
+
+pub fn rate() -> Option<f64> {
+    BUCKET.lock().unwrap().as_ref().map(|bucket| bucket.rate)
+}
+
+// Take a token, sleeping until it is due. The token is reserved before the
+// sleep, so waiting threads are served in the order they arrived
+pub fn acquire() {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    let seconds = {
+        let mut bucket = BUCKET.lock().unwrap();
+        let Some(bucket) = bucket.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * bucket.rate;
+        bucket.tokens = (bucket.tokens + refill).min(bucket.rate.max(1.0)) - 1.0;
+        bucket.updated = now;
+        if bucket.tokens >= 0.0 {
+            return;
+        }
+        -bucket.tokens / bucket.rate
+    };
+    // Computed with the bucket unlocked, so a wait too long to represent
+    // cannot poison it for every other thread
+    thread::sleep(Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX));
+}
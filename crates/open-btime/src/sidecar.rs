@@ -0,0 +1,320 @@
+// A backend for filesystems with neither settable birth times nor extended
+// attributes, such as FAT on USB sticks. The birth times written through
+// this library are recorded in a SQLite database at the root of the volume,
+// `.open-btime.sqlite`, keyed by the path relative to the root, so they
+// travel with the stick. The file's inode number is recorded alongside and
+// a time is only read back for the same number, so a file created under the
+// name of a deleted one does not inherit its time. FAT has no inode numbers
+// and Linux makes new ones up on every mount, so there the path alone
+// counts. SQLite is compiled in and called through its C API
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use libsqlite3_sys as ffi;
+
+use crate::backend::{self, BtimeBackend, Capabilities};
+use crate::mounts;
+use crate::path;
+use crate::snapshot;
+use crate::times::{FileTimes, Timestamp};
+
+const FILE_NAME: &str = ".open-btime.sqlite";
+
+const SCHEMA: &CStr = c"CREATE TABLE IF NOT EXISTS btimes (path TEXT PRIMARY KEY, inode INTEGER NOT NULL, sec INTEGER NOT NULL, nsec INTEGER NOT NULL)";
+const UPSERT: &CStr = c"INSERT OR REPLACE INTO btimes (path, inode, sec, nsec) VALUES (?1, ?2, ?3, ?4)";
+const SELECT: &CStr = c"SELECT inode, sec, nsec FROM btimes WHERE path = ?1";
+const SELECT_ALL: &CStr = c"SELECT path, inode, sec, nsec FROM btimes ORDER BY path";
+
+enum Param<'a> {
+    Text(&'a str),
+    Integer(i64),
+}
+
+struct Db(*mut ffi::sqlite3);
+
+// A connection is only used under the lock of its cache entry
+unsafe impl Send for Db {}
+
+impl Db {
+    fn open(path: &Path) -> io::Result<Db> {
+        let name = CString::new(path.to_string_lossy().into_owned()).map_err(io::Error::other)?;
+        let mut handle = std::ptr::null_mut();
+        let code = unsafe { ffi::sqlite3_open_v2(name.as_ptr(), &mut handle, ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE, std::ptr::null()) };
+        // SQLite hands out a handle to close even when opening fails
+        let db = Db(handle);
+        if code != ffi::SQLITE_OK {
+            return Err(db.error());
+        }
+        // Another process may be writing to the same stick
+        unsafe { ffi::sqlite3_busy_timeout(db.0, 5000) };
+        db.execute(SCHEMA, &[], |_| ())?;
+        Ok(db)
+    }
+
+    fn error(&self) -> io::Error {
+        let message = unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(self.0)) }.to_string_lossy();
+        io::Error::other(format!("sidecar database: {}", message))
+    }
+
+    // Run one statement with its parameters, passing each row it returns to
+    // `each`
+    fn execute(&self, sql: &CStr, params: &[Param], mut each: impl FnMut(&Row)) -> io::Result<()> {
+        let mut handle = std::ptr::null_mut();
+        if unsafe { ffi::sqlite3_prepare_v2(self.0, sql.as_ptr(), -1, &mut handle, std::ptr::null_mut()) } != ffi::SQLITE_OK {
+            return Err(self.error());
+        }
+        let statement = Statement(handle);
+        for (i, param) in params.iter().enumerate() {
+            let index = i as c_int + 1;
+            let code = match param {
+                Param::Text(text) => unsafe {
+                    ffi::sqlite3_bind_text(statement.0, index, text.as_ptr() as *const c_char, text.len() as c_int, ffi::SQLITE_TRANSIENT())
+                },
+                Param::Integer(value) => unsafe { ffi::sqlite3_bind_int64(statement.0, index, *value) },
+            };
+            if code != ffi::SQLITE_OK {
+                return Err(self.error());
+            }
+        }
+        loop {
+            match unsafe { ffi::sqlite3_step(statement.0) } {
+                ffi::SQLITE_ROW => each(&Row(&statement)),
+                ffi::SQLITE_DONE => return Ok(()),
+                _ => return Err(self.error()),
+            }
+        }
+    }
+}
+
+impl Drop for Db {
+    fn drop(&mut self) {
+        unsafe { ffi::sqlite3_close(self.0) };
+    }
+}
+
+struct Statement(*mut ffi::sqlite3_stmt);
+
+impl Drop for Statement {
+    fn drop(&mut self) {
+        unsafe { ffi::sqlite3_finalize(self.0) };
+    }
+}
+
+struct Row<'a>(&'a Statement);
+
+impl Row<'_> {
+    fn integer(&self, column: c_int) -> i64 {
+        unsafe { ffi::sqlite3_column_int64(self.0 .0, column) }
+    }
+
+    fn text(&self, column: c_int) -> String {
+        let text = unsafe { ffi::sqlite3_column_text(self.0 .0, column) };
+        if text.is_null() {
+            return String::new();
+        }
+        let len = unsafe { ffi::sqlite3_column_bytes(self.0 .0, column) } as usize;
+        String::from_utf8_lossy(unsafe { std::slice::from_raw_parts(text, len) }).into_owned()
+    }
+}
+
+// The device the root is on, so a connection to the database of a stick
+// that was pulled is not used for the next one mounted at the same place
+#[cfg(unix)]
+fn device(root: &Path) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    Ok(std::fs::metadata(root)?.dev())
+}
+
+#[cfg(not(unix))]
+fn device(root: &Path) -> io::Result<u64> {
+    std::fs::metadata(root).map(|_| 0)
+}
+
+type Connection = Arc<Mutex<Db>>;
+
+// Open connections by volume root, with the device each was opened on
+static CONNECTIONS: Mutex<Option<HashMap<PathBuf, (u64, Connection)>>> = Mutex::new(None);
+
+// The connection to the database of a volume root. Without create, None
+// when the volume has no database, so reads do not leave one behind
+fn connect(root: &Path, create: bool) -> io::Result<Option<Connection>> {
+    let device = device(root)?;
+    let mut connections = CONNECTIONS.lock().unwrap();
+    let connections = connections.get_or_insert_with(HashMap::new);
+    if let Some((opened_on, db)) = connections.get(root) {
+        if *opened_on == device {
+            return Ok(Some(Arc::clone(db)));
+        }
+    }
+    let file = root.join(FILE_NAME);
+    if !create && !file.exists() {
+        return Ok(None);
+    }
+    let db = Arc::new(Mutex::new(Db::open(&file)?));
+    connections.insert(root.to_path_buf(), (device, Arc::clone(&db)));
+    Ok(Some(db))
+}
+
+fn create(root: &Path) -> io::Result<Connection> {
+    connect(root, true)?.ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+}
+
+// Where a path is kept: the root of its volume, the path relative to it and
+// whether inode numbers identify files there
+pub struct Location {
+    pub root: PathBuf,
+    pub relative: String,
+    inodes: bool,
+}
+
+// FAT and exFAT have no inode numbers of their own
+fn keeps_inodes(filesystem: &str) -> bool {
+    !matches!(filesystem.to_ascii_lowercase().as_str(), "vfat" | "msdos" | "fat" | "fat32" | "exfat")
+}
+
+pub fn locate(path: &Path, follow: bool) -> io::Result<Location> {
+    let path = path::canonicalize(path, follow)?;
+    let mounts = mounts::list()?;
+    let mount = mounts
+        .iter()
+        .filter(|mount| path.starts_with(&mount.path))
+        .max_by_key(|mount| mount.path.as_os_str().len())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no mounted volume contains {}", path.display())))?;
+    Ok(Location { relative: snapshot::relative_path(&mount.path, &path), root: mount.path.clone(), inodes: keeps_inodes(&mount.filesystem) })
+}
+
+#[cfg(unix)]
+fn inode(path: &Path, follow: bool) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = if follow { std::fs::metadata(path)? } else { std::fs::symlink_metadata(path)? };
+    Ok(metadata.ino())
+}
+
+// Windows sets birth times on FAT itself, so the path is key enough there
+#[cfg(not(unix))]
+fn inode(path: &Path, follow: bool) -> io::Result<u64> {
+    if follow { std::fs::metadata(path) } else { std::fs::symlink_metadata(path) }.map(|_| 0)
+}
+
+fn store(location: &Location, inode: u64, btime: Timestamp) -> io::Result<()> {
+    let db = create(&location.root)?;
+    let db = db.lock().unwrap();
+    let params = [Param::Text(&location.relative), Param::Integer(inode as i64), Param::Integer(btime.sec), Param::Integer(btime.nsec as i64)];
+    db.execute(UPSERT, &params, |_| ())
+}
+
+// The birth time recorded for a path, None when there is none or it was
+// recorded for another file of the same name
+pub fn stored(path: &Path, follow: bool) -> io::Result<Option<Timestamp>> {
+    let location = locate(path, follow)?;
+    let Some(db) = connect(&location.root, false)? else {
+        return Ok(None);
+    };
+    let mut row = None;
+    db.lock().unwrap().execute(SELECT, &[Param::Text(&location.relative)], |found| {
+        row = Some((found.integer(0) as u64, Timestamp { sec: found.integer(1), nsec: found.integer(2) as u32 }));
+    })?;
+    let Some((recorded_inode, btime)) = row else {
+        return Ok(None);
+    };
+    if location.inodes && recorded_inode != inode(path, follow)? {
+        return Ok(None);
+    }
+    Ok(Some(btime))
+}
+
+pub struct Entry {
+    // Relative to the directory exported or imported, with `/` separators
+    pub path: String,
+    pub inode: u64,
+    pub btime: Timestamp,
+}
+
+// The recorded birth times of the entries below dir, from the database of
+// the volume it is on
+pub fn export(dir: &Path) -> io::Result<Vec<Entry>> {
+    let location = locate(dir, true)?;
+    let Some(db) = connect(&location.root, false)? else {
+        return Ok(Vec::new());
+    };
+    let mut entries = Vec::new();
+    db.lock().unwrap().execute(SELECT_ALL, &[], |row| {
+        let path = row.text(0);
+        let relative = if location.relative == "." {
+            Some(path.as_str())
+        } else if path == location.relative {
+            Some(".")
+        } else {
+            path.strip_prefix(&location.relative).and_then(|rest| rest.strip_prefix('/'))
+        };
+        if let Some(relative) = relative {
+            let btime = Timestamp { sec: row.integer(2), nsec: row.integer(3) as u32 };
+            entries.push(Entry { path: relative.to_string(), inode: row.integer(1) as u64, btime });
+        }
+    })?;
+    Ok(entries)
+}
+
+// Record the birth times of entries below dir, e.g. exported from another
+// volume alongside the files, in one transaction. Inode numbers are taken
+// from the files as they are now. Returns the paths of entries whose file
+// does not exist, which are left out
+pub fn import(dir: &Path, entries: &[(String, Timestamp)]) -> io::Result<Vec<String>> {
+    let dir = path::canonicalize(dir, true)?;
+    let location = locate(&dir, true)?;
+    let mut found = Vec::new();
+    let mut missing = Vec::new();
+    for (relative, btime) in entries {
+        let path = snapshot::absolute_path(&dir, relative);
+        match path::canonicalize(&path, false).and_then(|path| Ok((inode(&path, false)?, path))) {
+            Ok((inode, path)) => found.push((snapshot::relative_path(&location.root, &path), inode, *btime)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => missing.push(relative.clone()),
+            Err(err) => return Err(err),
+        }
+    }
+    let db = create(&location.root)?;
+    let db = db.lock().unwrap();
+    db.execute(c"BEGIN", &[], |_| ())?;
+    for (relative, inode, btime) in &found {
+        let params = [Param::Text(relative), Param::Integer(*inode as i64), Param::Integer(btime.sec), Param::Integer(btime.nsec as i64)];
+        if let Err(err) = db.execute(UPSERT, &params, |_| ()) {
+            let _ = db.execute(c"ROLLBACK", &[], |_| ());
+            return Err(err);
+        }
+    }
+    db.execute(c"COMMIT", &[], |_| ())?;
+    Ok(missing)
+}
+
+pub struct Sidecar;
+
+impl BtimeBackend for Sidecar {
+    fn name(&self) -> &'static str {
+        "sidecar"
+    }
+
+    fn set_times(&self, path: &Path, times: &FileTimes, follow: bool) -> io::Result<()> {
+        if let Some(btime) = times.btime {
+            store(&locate(path, follow)?, inode(path, follow)?, btime)?;
+        }
+        backend::Platform.set_times(path, &FileTimes { btime: None, ..*times }, follow)
+    }
+
+    fn get_times(&self, path: &Path, follow: bool) -> io::Result<FileTimes> {
+        let times = backend::Platform.get_times(path, follow)?;
+        Ok(FileTimes { btime: stored(path, follow)?.or(times.btime), ..times })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { set_btime: true, get_btime: true, emulated: true }
+    }
+
+    fn keeps_btime(&self, path: &Path, follow: bool) -> bool {
+        stored(path, follow).is_ok_and(|stored| stored.is_some())
+    }
+}
@@ -0,0 +1,74 @@
+// Simulated writes, for planning tools that need to know what a call would
+// leave on disk without it touching anything. A simulation goes through the
+// same steps as the write up to the syscall: the times are quantized under
+// the configured policy, cut down to the steps the filesystem keeps and
+// moved into the range it can hold, and the file is checked for what would
+// make the write fail
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::access::{self, Obstacle};
+use crate::config;
+use crate::links;
+use crate::pool;
+use crate::times::{FileTimes, Timestamp};
+use crate::tree::TreeResult;
+use crate::volume;
+use crate::walk::WalkError;
+
+pub struct Simulation {
+    pub path: PathBuf,
+    // The times the write would hand to the filesystem
+    pub times: FileTimes,
+    // What the filesystem would keep of them
+    pub stored: FileTimes,
+    // Whether a time was outside the filesystem's range and moved to its edge
+    pub clamped: bool,
+    // Why the write would fail, as canSetBtime reports it
+    pub obstacles: Vec<Obstacle>,
+    // The other names of the file, whose birth time the write changes too
+    pub shared_with: u64,
+}
+
+pub fn simulate(path: &Path, times: &FileTimes, follow: bool) -> io::Result<Simulation> {
+    // ctime cannot be set and is not part of a write
+    let times = FileTimes { ctime: None, ..times.quantize(config::get().quantize)? };
+    let obstacles = access::check(path, follow)?;
+    let shared_with = links::other_names(path, follow)?;
+    let Ok(filesystem) = volume::filesystem(path) else {
+        return Ok(Simulation { path: path.to_path_buf(), times, stored: times, clamped: false, obstacles, shared_with });
+    };
+    let mut stored = volume::stored_times(&filesystem, &times);
+    let unclamped = stored;
+    if let Some(limits) = volume::limits(&filesystem) {
+        let clamp = |time: Option<Timestamp>| time.map(|time| time.clamp(limits.earliest, limits.latest));
+        stored = FileTimes { btime: clamp(stored.btime), mtime: clamp(stored.mtime), atime: clamp(stored.atime), ctime: None };
+    }
+    Ok(Simulation { path: path.to_path_buf(), times, stored, clamped: stored != unclamped, obstacles, shared_with })
+}
+
+// Simulate setting a separate birth time on each path, on `concurrency`
+// workers. The simulations come back in the order of items; paths that
+// could not be examined are in the errors instead, and processed counts
+// the writes nothing stands in the way of
+pub fn simulate_paths(items: Vec<(PathBuf, Timestamp)>, concurrency: usize) -> (Vec<Simulation>, TreeResult) {
+    let simulations = Mutex::new(Vec::with_capacity(items.len()));
+    let errors = Mutex::new(Vec::new());
+    pool::for_each_bounded(items.into_iter().enumerate(), concurrency, |(i, (path, btime))| {
+        match simulate(&path, &FileTimes { btime: Some(btime), ..FileTimes::default() }, true) {
+            Ok(simulation) => simulations.lock().unwrap().push((i, simulation)),
+            Err(error) => errors.lock().unwrap().push(WalkError { path, error }),
+        }
+    });
+
+    let mut simulations = simulations.into_inner().unwrap();
+    simulations.sort_unstable_by_key(|(i, _)| *i);
+    let simulations: Vec<Simulation> = simulations.into_iter().map(|(_, simulation)| simulation).collect();
+    let result = TreeResult {
+        processed: simulations.iter().filter(|simulation| simulation.obstacles.is_empty()).count(),
+        errors: errors.into_inner().unwrap(),
+        ..TreeResult::default()
+    };
+    (simulations, result)
+}
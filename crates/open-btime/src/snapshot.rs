@@ -0,0 +1,1577 @@
+// Timestamp snapshots of a directory tree and their restoration.
+//
+// A manifest is a JSON document:
+//
+//   {
+//     "format": "open-btime-manifest",
+//     "version": 1,
+//     "minReaderVersion": 1,
+//     "features": ["nanoseconds"],
+//     "root": "/data",
+//     "entries": [
+//       {"path": ".", "type": "dir", "btime": "1700000000123456789", ...},
+//       {"path": "notes/a.md", "type": "file", ...}
+//     ]
+//   }
+//
+// version is the format revision the writer implemented, minReaderVersion the
+// oldest reader revision that can still restore it. Writers only raise
+// minReaderVersion for changes older readers would misinterpret; fields a
+// reader does not know are ignored. features lists optional parts of the
+// format the manifest uses, and a reader refuses manifests with features it
+// does not know instead of silently restoring them wrong:
+//
+//   nanoseconds  times are decimal strings of nanoseconds since the epoch;
+//                without it they are plain numbers of seconds
+//   xattrs       entries may carry an "xattrs" object; restoring times does
+//                not depend on it
+//   checksum     the entries are followed by a footer with their checksum,
+//                which does not cover the header fields, see checksum.rs
+//
+// The NDJSON variant holds the same data as one JSON object per line: the
+// header fields without "entries" on the first line, then one entry per
+// line, so huge manifests can be written and restored without holding them.
+// A document manifest larger than the configured memory limit is restored the
+// same way: its entries are first moved to a temporary file one per line.
+// Either encoding may be gzip or zstd compressed, see compress.rs
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::access::{self, Obstacle};
+use crate::backend;
+use crate::checkpoint::{self, Checkpoint};
+use crate::checksum::{self, Checker, Crc32};
+use crate::compress::{self, Compression, ManifestFile};
+use crate::config;
+use crate::hash;
+use crate::json::{self, Value};
+use crate::links;
+use crate::times::{FileTimes, Timestamp};
+use crate::tree::TreeResult;
+use crate::volume;
+use crate::walk::{EntryType, WalkError, WalkOptions, Walker};
+
+pub const FORMAT: &str = "open-btime-manifest";
+pub const VERSION: u64 = 1;
+pub const MIN_READER_VERSION: u64 = 1;
+
+pub const FEATURE_NANOSECONDS: &str = "nanoseconds";
+pub const FEATURE_XATTRS: &str = "xattrs";
+pub const FEATURE_CHECKSUM: &str = "checksum";
+const KNOWN_FEATURES: [&str; 3] = [FEATURE_NANOSECONDS, FEATURE_XATTRS, FEATURE_CHECKSUM];
+
+#[derive(Clone, Debug)]
+pub struct ManifestEntry {
+    // Relative to the manifest root with / separators, "." for the root itself
+    pub path: String,
+    pub file_type: EntryType,
+    pub times: FileTimes,
+    pub size: Option<u64>,
+    // Content hash of a file, when the snapshot recorded them
+    pub hash: Option<u64>,
+    // Identity of a file with several hard links, shared by all its names
+    pub link: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ManifestHeader {
+    // Times were recorded with full precision rather than in seconds
+    pub nanoseconds: bool,
+    // Footers with the checksum of the entries follow them
+    pub checksum: bool,
+    pub root: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub struct Manifest {
+    pub header: ManifestHeader,
+    pub entries: Vec<ManifestEntry>,
+}
+
+// The header fields shared by both encodings, `separator` following each comma
+fn header_fields(root: &Path, checksums: Checksums, separator: &str) -> String {
+    let mut header = String::from("\"format\": ");
+    json::write_string(&mut header, FORMAT);
+    header.push_str(&format!(
+        ",{0}\"version\": {1},{0}\"minReaderVersion\": {2},{0}\"features\": [",
+        separator, VERSION, MIN_READER_VERSION
+    ));
+    json::write_string(&mut header, FEATURE_NANOSECONDS);
+    if checksums.footer {
+        header.push_str(", ");
+        json::write_string(&mut header, FEATURE_CHECKSUM);
+    }
+    header.push_str(&format!("],{}\"root\": ", separator));
+    json::write_string(&mut header, &root.to_string_lossy());
+    header
+}
+
+// An entry as a single-line JSON object
+fn entry_json(entry: &ManifestEntry) -> String {
+    let mut line = String::from("{\"path\": ");
+    json::write_string(&mut line, &entry.path);
+    line.push_str(", \"type\": ");
+    json::write_string(&mut line, entry.file_type.name());
+    for (key, timestamp) in [("btime", entry.times.btime), ("mtime", entry.times.mtime), ("atime", entry.times.atime)] {
+        if let Some(timestamp) = timestamp {
+            line.push_str(&format!(", \"{}\": \"{}\"", key, timestamp.as_nanos()));
+        }
+    }
+    if let Some(size) = entry.size {
+        line.push_str(&format!(", \"size\": {}", size));
+    }
+    if let Some(content_hash) = entry.hash {
+        line.push_str(", \"hash\": ");
+        json::write_string(&mut line, &hash::format(content_hash));
+    }
+    if let Some(link) = &entry.link {
+        line.push_str(", \"link\": ");
+        json::write_string(&mut line, link);
+    }
+    line.push('}');
+    line
+}
+
+// The integrity checks a manifest is written with
+#[derive(Clone, Copy, Debug)]
+pub struct Checksums {
+    // A footer with the checksum of all entries
+    pub footer: bool,
+    // A CRC in every entry
+    pub entries: bool,
+}
+
+impl Default for Checksums {
+    fn default() -> Checksums {
+        Checksums { footer: true, entries: false }
+    }
+}
+
+// Formats entries with the checks they are written with, keeping the
+// checksum of the footer
+struct Summer {
+    checksums: Checksums,
+    crc: Crc32,
+    count: u64,
+}
+
+impl Summer {
+    fn new(checksums: Checksums) -> Summer {
+        Summer { checksums, crc: Crc32::default(), count: 0 }
+    }
+
+    fn entry(&mut self, entry: &ManifestEntry) -> String {
+        let mut line = entry_json(entry);
+        if self.checksums.entries {
+            line.pop();
+            let crc = checksum::entry_crc(&checksum::canonical(line.as_bytes()));
+            line.push_str(&format!(", \"crc\": \"{:08x}\"}}", crc));
+        }
+        if self.checksums.footer {
+            self.crc.update(&checksum::canonical(line.as_bytes()));
+            self.crc.update(b"\n");
+            self.count += 1;
+        }
+        line
+    }
+
+    // The footer fields, `separator` following each comma
+    fn footer(&self, separator: &str) -> Option<String> {
+        let checksum = checksum::footer_checksum(self.crc.value());
+        let footer = format!("\"checksum\": \"{}\",{}\"count\": {}", checksum, separator, self.count);
+        self.checksums.footer.then_some(footer)
+    }
+}
+
+// Writes a manifest entry by entry, so a snapshot never holds the whole tree
+pub struct ManifestWriter<W: Write> {
+    out: W,
+    summer: Summer,
+}
+
+impl<W: Write> ManifestWriter<W> {
+    pub fn new(mut out: W, root: &Path, checksums: Checksums) -> io::Result<ManifestWriter<W>> {
+        let header = format!("{{\n  {},\n  \"entries\": [", header_fields(root, checksums, "\n  "));
+        out.write_all(header.as_bytes())?;
+        Ok(ManifestWriter { out, summer: Summer::new(checksums) })
+    }
+
+    pub fn write_entry(&mut self, entry: &ManifestEntry) -> io::Result<()> {
+        let separator = if self.summer.count == 0 { "\n    " } else { ",\n    " };
+        let line = self.summer.entry(entry);
+        self.out.write_all(format!("{}{}", separator, line).as_bytes())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(if self.summer.count == 0 { b"]" } else { b"\n  ]" })?;
+        if let Some(footer) = self.summer.footer("\n  ") {
+            self.out.write_all(format!(",\n  {}", footer).as_bytes())?;
+        }
+        self.out.write_all(b"\n}\n")?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// The NDJSON encoding: a header line, then one line per entry, then the
+// footer line
+pub struct NdjsonWriter<W: Write> {
+    out: W,
+    summer: Summer,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub fn new(mut out: W, root: &Path, checksums: Checksums) -> io::Result<NdjsonWriter<W>> {
+        out.write_all(format!("{{{}}}\n", header_fields(root, checksums, " ")).as_bytes())?;
+        Ok(NdjsonWriter { out, summer: Summer::new(checksums) })
+    }
+
+    // Continue a manifest whose header is already written
+    pub fn appending(out: W, checksums: Checksums) -> NdjsonWriter<W> {
+        NdjsonWriter { out, summer: Summer::new(checksums) }
+    }
+
+    pub fn write_entry(&mut self, entry: &ManifestEntry) -> io::Result<()> {
+        let line = self.summer.entry(entry);
+        self.out.write_all(format!("{}\n", line).as_bytes())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(footer) = self.summer.footer(" ") {
+            self.out.write_all(format!("{{{}}}\n", footer).as_bytes())?;
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// Spreadsheet-friendly export: one row per entry with path, btime, mtime,
+// atime in ISO 8601 UTC and size. Times the filesystem does not record are
+// left empty. Only the JSON format can be restored
+pub struct CsvWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(mut out: W) -> io::Result<CsvWriter<W>> {
+        out.write_all(b"path,btime,mtime,atime,size\r\n")?;
+        Ok(CsvWriter { out })
+    }
+
+    pub fn write_entry(&mut self, entry: &ManifestEntry) -> io::Result<()> {
+        let time = |timestamp: Option<Timestamp>| timestamp.map(Timestamp::to_iso8601).unwrap_or_default();
+        let row = format!(
+            "{},{},{},{},{}\r\n",
+            csv_field(&entry.path),
+            time(entry.times.btime),
+            time(entry.times.mtime),
+            time(entry.times.atime),
+            entry.size.map(|size| size.to_string()).unwrap_or_default()
+        );
+        self.out.write_all(row.as_bytes())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// Quote a field when it contains a separator, quote or line break (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Json,
+    Ndjson,
+    Csv,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "json" => Some(Format::Json),
+            "ndjson" => Some(Format::Ndjson),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+
+    // The format implied by a manifest's extension, JSON unless it is
+    // .ndjson, .jsonl or .csv, looking past a .gz or .zst extension
+    pub fn from_path(path: &Path) -> Format {
+        let (path, _) = Compression::split_path(path);
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("ndjson") || extension.eq_ignore_ascii_case("jsonl") => {
+                Format::Ndjson
+            }
+            Some(extension) if extension.eq_ignore_ascii_case("csv") => Format::Csv,
+            _ => Format::Json,
+        }
+    }
+}
+
+enum Writer<W: Write> {
+    Json(ManifestWriter<W>),
+    Ndjson(NdjsonWriter<W>),
+    Csv(CsvWriter<W>),
+}
+
+impl<W: Write> Writer<W> {
+    fn write_entry(&mut self, entry: &ManifestEntry) -> io::Result<()> {
+        match self {
+            Writer::Json(writer) => writer.write_entry(entry),
+            Writer::Ndjson(writer) => writer.write_entry(entry),
+            Writer::Csv(writer) => writer.write_entry(entry),
+        }
+    }
+
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Writer::Json(writer) => writer.finish(),
+            Writer::Ndjson(writer) => writer.finish(),
+            Writer::Csv(writer) => writer.finish(),
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Entries of an open manifest in file order. Whole-document manifests are
+// parsed up front, NDJSON ones and spilled documents one line at a time
+pub enum ManifestReader {
+    Document(std::vec::IntoIter<ManifestEntry>),
+    Lines {
+        lines: io::Lines<Box<dyn BufRead + Send>>,
+        nanoseconds: bool,
+        line: usize,
+        // Dropped after lines, which closes the file before it is removed
+        spilled: Option<Spilled>,
+    },
+}
+
+// The temporary file holding the entries of a spilled document, removed
+// once the reader is done with it
+pub struct Spilled(PathBuf);
+
+impl Drop for Spilled {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+impl Iterator for ManifestReader {
+    type Item = io::Result<ManifestEntry>;
+
+    fn next(&mut self) -> Option<io::Result<ManifestEntry>> {
+        match self {
+            ManifestReader::Document(entries) => entries.next().map(Ok),
+            ManifestReader::Lines { lines, nanoseconds, line, spilled } => loop {
+                *line += 1;
+                let text = match lines.next()? {
+                    Ok(text) => text,
+                    Err(err) => return Some(Err(err)),
+                };
+                if text.trim().is_empty() {
+                    continue;
+                }
+                let item = json::parse(&text);
+                if item.as_ref().is_ok_and(is_footer) {
+                    continue;
+                }
+                let entry = item.and_then(|item| parse_entry(&item, *nanoseconds));
+                // Spilled entries are numbered from 0 like those of a parsed document
+                let position = match spilled {
+                    Some(_) => format!("entry {}", *line - 1),
+                    None => format!("line {}", line),
+                };
+                return Some(entry.map_err(|message| invalid(format!("{}: {}", position, message))));
+            },
+        }
+    }
+}
+
+// Open a manifest in either encoding for reading its entries one by one.
+// Document manifests larger than memory_limit bytes are spilled rather than
+// parsed in memory. The size of compressed ones is not known up front, so
+// with a limit they are always spilled
+pub fn open_manifest(path: &Path, memory_limit: Option<u64>) -> io::Result<(ManifestHeader, ManifestReader)> {
+    let size = fs::metadata(path)?.len();
+    let (mut input, compression) = compress::open(path)?;
+    let first_line = read_first_line(&mut input, memory_limit)?;
+
+    // An NDJSON manifest starts with a complete header object, the document
+    // encoding with an object spanning the whole file
+    if let Some(header) = ndjson_header(&first_line) {
+        if header.get("entries").is_none() {
+            let header = parse_header(&header)?;
+            let nanoseconds = header.nanoseconds;
+            return Ok((header, ManifestReader::Lines { lines: input.lines(), nanoseconds, line: 1, spilled: None }));
+        }
+    }
+
+    if memory_limit.is_some_and(|limit| compression != Compression::None || size > limit) {
+        let chained = BufReader::new(io::Cursor::new(first_line).chain(input));
+        return spill(path, chained);
+    }
+    let mut text = String::from_utf8(first_line).map_err(|err| invalid(err.to_string()))?;
+    input.read_to_string(&mut text)?;
+    let manifest = parse_manifest(&text)?;
+    Ok((manifest.header, ManifestReader::Document(manifest.entries.into_iter())))
+}
+
+// The first line of a manifest, or its first memory_limit bytes when the
+// line is longer. The bytes are handed back so a document manifest can be
+// read on from them
+fn read_first_line(input: &mut impl BufRead, memory_limit: Option<u64>) -> io::Result<Vec<u8>> {
+    let mut line = Vec::new();
+    match memory_limit {
+        Some(limit) => input.take(limit).read_until(b'\n', &mut line)?,
+        None => input.read_until(b'\n', &mut line)?,
+    };
+    Ok(line)
+}
+
+// The header object a first line holds, or None when it is not valid JSON,
+// such as a line cut short by the memory limit
+fn ndjson_header(line: &[u8]) -> Option<Value> {
+    json::parse(std::str::from_utf8(line).ok()?).ok()
+}
+
+// Move the entries of a document manifest to a temporary file, one per line,
+// and read them from there. Only the header fields and one entry are held at
+// a time
+fn spill(path: &Path, input: impl BufRead) -> io::Result<(ManifestHeader, ManifestReader)> {
+    let name = path.file_name().unwrap_or(path.as_os_str());
+    let spill_path = crate::atomic::temporary_path(&std::env::temp_dir().join(name))?;
+    let spilled = Spilled(spill_path.clone());
+    let mut out = BufWriter::new(File::create(&spill_path)?);
+    let mut scanner = Scanner { input, offset: 0 };
+    let header = scanner.document(&mut |entry| {
+        out.write_all(entry)?;
+        out.write_all(b"\n")
+    })?;
+    out.flush()?;
+    drop(out);
+
+    let header = parse_header(&json::parse(&header).map_err(invalid)?)?;
+    let input: Box<dyn BufRead + Send> = Box::new(BufReader::new(File::open(&spill_path)?));
+    let lines = input.lines();
+    let nanoseconds = header.nanoseconds;
+    Ok((header, ManifestReader::Lines { lines, nanoseconds, line: 0, spilled: Some(spilled) }))
+}
+
+// Splits a document manifest without parsing it into values: the fields of
+// the top-level object other than "entries" are collected as JSON text, and
+// each element of "entries" is handed on in canonical form
+struct Scanner<R: BufRead> {
+    input: R,
+    offset: u64,
+}
+
+impl<R: BufRead> Scanner<R> {
+    fn error(&self, message: &str) -> io::Error {
+        invalid(format!("invalid JSON at byte {}: {}", self.offset, message))
+    }
+
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.input.fill_buf()?.first().copied())
+    }
+
+    fn bump(&mut self) {
+        self.input.consume(1);
+        self.offset += 1;
+    }
+
+    // The next byte that is not whitespace, left unread
+    fn token(&mut self) -> io::Result<Option<u8>> {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek()? {
+            self.bump();
+        }
+        self.peek()
+    }
+
+    fn expect(&mut self, byte: u8) -> io::Result<()> {
+        if self.token()? != Some(byte) {
+            return Err(self.error(&format!("expected {}", byte as char)));
+        }
+        self.bump();
+        Ok(())
+    }
+
+    // Copy one value as it is, without the whitespace between its tokens
+    fn copy_value(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        self.token()?;
+        let start = out.len();
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        loop {
+            let Some(byte) = self.peek()? else {
+                return Err(self.error("unexpected end of input"));
+            };
+            if in_string {
+                self.bump();
+                out.push(byte);
+                if escaped {
+                    escaped = false;
+                } else if byte == b'\\' {
+                    escaped = true;
+                } else if byte == b'"' {
+                    in_string = false;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                continue;
+            }
+            match byte {
+                // A number or literal at the top ends at the next delimiter
+                b' ' | b'\t' | b'\n' | b'\r' | b',' | b'}' | b']' if depth == 0 => {
+                    if out.len() == start {
+                        return Err(self.error("unexpected character"));
+                    }
+                    return Ok(());
+                }
+                b' ' | b'\t' | b'\n' | b'\r' => {
+                    self.bump();
+                    continue;
+                }
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' => depth -= 1,
+                b'"' => in_string = true,
+                _ => {}
+            }
+            self.bump();
+            out.push(byte);
+            if depth == 0 && matches!(byte, b'}' | b']') {
+                return Ok(());
+            }
+        }
+    }
+
+    // Scan the whole document, returning the header fields as a JSON object
+    fn document(&mut self, entry: &mut impl FnMut(&[u8]) -> io::Result<()>) -> io::Result<String> {
+        let mut header = vec![b'{'];
+        let mut has_entries = false;
+        self.expect(b'{')?;
+        let mut first = true;
+        while self.token()? != Some(b'}') {
+            if !first {
+                self.expect(b',')?;
+            }
+            first = false;
+            let mut key = Vec::new();
+            self.copy_value(&mut key)?;
+            self.expect(b':')?;
+            if key == b"\"entries\"" {
+                has_entries = true;
+                self.entries(entry)?;
+                continue;
+            }
+            if header.len() > 1 {
+                header.push(b',');
+            }
+            header.extend_from_slice(&key);
+            header.push(b':');
+            self.copy_value(&mut header)?;
+        }
+        self.bump();
+        if self.token()?.is_some() {
+            return Err(self.error("trailing characters"));
+        }
+        if !has_entries {
+            return Err(invalid("manifest has no entries".to_string()));
+        }
+        header.push(b'}');
+        String::from_utf8(header).map_err(|_| invalid("manifest is not UTF-8".to_string()))
+    }
+
+    fn entries(&mut self, entry: &mut impl FnMut(&[u8]) -> io::Result<()>) -> io::Result<()> {
+        self.expect(b'[')?;
+        let mut line = Vec::new();
+        let mut first = true;
+        while self.token()? != Some(b']') {
+            if !first {
+                self.expect(b',')?;
+            }
+            first = false;
+            line.clear();
+            self.copy_value(&mut line)?;
+            entry(&line)?;
+        }
+        self.bump();
+        Ok(())
+    }
+}
+
+// A footer line of an NDJSON manifest
+fn is_footer(item: &Value) -> bool {
+    item.get("path").is_none() && item.get("checksum").is_some()
+}
+
+// Check a manifest written with checksums against them before anything is
+// restored from it, reading it through once. Manifests without checksums
+// pass. As with open_manifest, no more than memory_limit bytes of the first
+// line are held
+pub fn verify(path: &Path, memory_limit: Option<u64>) -> io::Result<()> {
+    let (mut input, _) = compress::open(path)?;
+    let first_line = read_first_line(&mut input, memory_limit)?;
+    let mut checker = Checker::default();
+
+    if let Some(header) = ndjson_header(&first_line) {
+        if header.get("entries").is_none() {
+            if !parse_header(&header)?.checksum {
+                return Ok(());
+            }
+            for (i, text) in input.lines().enumerate() {
+                let text = text?;
+                let line = i + 2;
+                if text.trim().is_empty() {
+                    continue;
+                }
+                let checked = match json::parse(&text) {
+                    Ok(item) if is_footer(&item) => checker.footer(&item),
+                    // Malformed entries are reported when they are restored
+                    _ => checker.add(&checksum::canonical(text.as_bytes())),
+                };
+                checked.map_err(|message| invalid(format!("line {}: {}", line, message)))?;
+            }
+            return finish_check(&checker);
+        }
+    }
+
+    let chained = BufReader::new(io::Cursor::new(first_line).chain(input));
+    let mut scanner = Scanner { input: chained, offset: 0 };
+    let header = scanner.document(&mut |entry| checker.add(entry).map_err(invalid))?;
+    let header = json::parse(&header).map_err(invalid)?;
+    if !parse_header(&header)?.checksum {
+        return Ok(());
+    }
+    if header.get("checksum").is_some() {
+        checker.footer(&header).map_err(invalid)?;
+    }
+    finish_check(&checker)
+}
+
+// Entries no footer covered are what is left of a manifest cut short
+fn finish_check(checker: &Checker) -> io::Result<()> {
+    if checker.footers == 0 || checker.unchecked() > 0 {
+        return Err(invalid(format!(
+            "{} entries are not covered by a checksum, the manifest may be truncated",
+            checker.unchecked()
+        )));
+    }
+    Ok(())
+}
+
+// Read a whole manifest in either encoding
+pub fn read_manifest(path: &Path) -> io::Result<Manifest> {
+    let (header, reader) = open_manifest(path, None)?;
+    let entries = reader.collect::<io::Result<_>>()?;
+    Ok(Manifest { header, entries })
+}
+
+fn parse_header(document: &Value) -> io::Result<ManifestHeader> {
+    if document.get("format").and_then(Value::as_str) != Some(FORMAT) {
+        return Err(invalid("not an open-btime manifest".to_string()));
+    }
+    let version = document
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid("manifest has no version".to_string()))?;
+    // Manifests from before minReaderVersion existed have to be readable by their own version
+    let min_reader_version = document.get("minReaderVersion").and_then(Value::as_u64).unwrap_or(version);
+    if min_reader_version > VERSION {
+        return Err(invalid(format!(
+            "manifest version {} needs a reader of version {}, this one reads up to {}",
+            version, min_reader_version, VERSION
+        )));
+    }
+
+    let mut nanoseconds = false;
+    let mut checksum = false;
+    for feature in document.get("features").and_then(Value::as_array).unwrap_or_default() {
+        let feature = feature.as_str().ok_or_else(|| invalid("malformed features list".to_string()))?;
+        if !KNOWN_FEATURES.contains(&feature) {
+            return Err(invalid(format!("manifest uses unsupported feature {:?}", feature)));
+        }
+        nanoseconds |= feature == FEATURE_NANOSECONDS;
+        checksum |= feature == FEATURE_CHECKSUM;
+    }
+
+    let root = document
+        .get("root")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("manifest has no root".to_string()))?;
+    Ok(ManifestHeader { nanoseconds, checksum, root: PathBuf::from(root) })
+}
+
+pub fn parse_manifest(text: &str) -> io::Result<Manifest> {
+    let document = json::parse(text).map_err(invalid)?;
+    let header = parse_header(&document)?;
+    let items = document
+        .get("entries")
+        .and_then(Value::as_array)
+        .ok_or_else(|| invalid("manifest has no entries".to_string()))?;
+
+    let mut entries = Vec::with_capacity(items.len());
+    for (i, item) in items.iter().enumerate() {
+        entries.push(parse_entry(item, header.nanoseconds).map_err(|message| invalid(format!("entry {}: {}", i, message)))?);
+    }
+
+    Ok(Manifest { header, entries })
+}
+
+fn parse_entry(item: &Value, nanoseconds: bool) -> Result<ManifestEntry, String> {
+    let path = item.get("path").and_then(Value::as_str).ok_or("missing path")?;
+    // A checksum does not vouch for the paths, whoever wrote the manifest
+    // could write its footer as well
+    if !is_below_root(path) {
+        return Err(format!("path {:?} leads outside the root", path));
+    }
+    let file_type = item
+        .get("type")
+        .and_then(Value::as_str)
+        .and_then(EntryType::from_name)
+        .ok_or("missing or unknown type")?;
+
+    let time = |key: &str| -> Result<Option<Timestamp>, String> {
+        let parsed = match item.get(key) {
+            None | Some(Value::Null) => return Ok(None),
+            Some(Value::String(nanos)) if nanoseconds => nanos.parse::<i128>().ok().and_then(Timestamp::from_nanos),
+            Some(Value::Number(seconds)) if !nanoseconds => Timestamp::from_secs_f64(*seconds),
+            Some(_) => None,
+        };
+        parsed.map(Some).ok_or_else(|| format!("invalid {}", key))
+    };
+
+    let size = match item.get("size") {
+        None | Some(Value::Null) => None,
+        Some(size) => Some(size.as_u64().ok_or("invalid size")?),
+    };
+
+    // Hashes of unknown algorithms are left out
+    let content_hash = item.get("hash").and_then(Value::as_str).and_then(hash::parse);
+    let link = match item.get("link") {
+        None | Some(Value::Null) => None,
+        Some(link) => Some(link.as_str().ok_or("invalid link")?.to_string()),
+    };
+
+    Ok(ManifestEntry {
+        path: path.to_string(),
+        file_type,
+        size,
+        hash: content_hash,
+        link,
+        times: FileTimes {
+            btime: time("btime")?,
+            mtime: time("mtime")?,
+            atime: time("atime")?,
+            ctime: None,
+        },
+    })
+}
+
+// Path of an entry relative to the walk root, as stored in manifests
+pub fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    if relative.as_os_str().is_empty() {
+        return ".".to_string();
+    }
+    let parts: Vec<_> = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+    parts.join("/")
+}
+
+// relative_path for an entry to record. Manifests hold paths as text, so a
+// name that is not valid Unicode is refused rather than recorded with U+FFFD
+// in its place, which would name another file when the manifest is restored
+fn recorded_relative_path(root: &Path, path: &Path) -> io::Result<String> {
+    match path.strip_prefix(root).unwrap_or(path).to_str() {
+        Some(_) => Ok(relative_path(root, path)),
+        None => Err(not_unicode()),
+    }
+}
+
+fn not_unicode() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "the path is not valid Unicode and cannot be recorded in a manifest")
+}
+
+// Whether a manifest path is the root, ".", or names below it separated by
+// /, none of them empty, `.`, `..` or a part the platform takes for a root
+// or drive, so resolving it cannot leave the root
+fn is_below_root(path: &str) -> bool {
+    path == "."
+        || path.split('/').all(|part| matches!(Path::new(part).components().collect::<Vec<_>>()[..], [Component::Normal(name)] if name == part))
+}
+
+// Resolve a manifest path against a root directory
+pub fn absolute_path(root: &Path, relative: &str) -> PathBuf {
+    if relative == "." {
+        return root.to_path_buf();
+    }
+    relative.split('/').fold(root.to_path_buf(), |path, part| path.join(part))
+}
+
+// The header of an NDJSON manifest entries are appended to, whose root has to
+// contain `root`, with the compression the manifest was written with
+fn append_base(manifest_path: &Path, root: &Path) -> io::Result<(ManifestHeader, Compression)> {
+    let unsupported = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message.to_string());
+    let (header, reader) = open_manifest(manifest_path, None)?;
+    if !matches!(reader, ManifestReader::Lines { .. }) {
+        return Err(unsupported("only NDJSON manifests can be appended to"));
+    }
+    if !header.nanoseconds {
+        return Err(unsupported("manifests recorded in seconds cannot be appended to"));
+    }
+    if !root.starts_with(&header.root) {
+        return Err(unsupported("root is not inside the root of the manifest"));
+    }
+    drop(reader);
+    let (_, compression) = compress::open(manifest_path)?;
+    Ok((header, compression))
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SnapshotOptions {
+    pub format: Format,
+    pub compression: Compression,
+    // Add to an existing NDJSON manifest instead of replacing it
+    pub append: bool,
+    pub checksums: Checksums,
+    // Record a content hash of every file
+    pub hash: bool,
+    // Record which files are hard links of each other
+    pub links: bool,
+}
+
+// Record the timestamps of root and everything the walker yields below it.
+// With `append`, the entries are added to an existing NDJSON manifest whose
+// root contains `root`, in its compression, with paths relative to its root
+// and a footer of their own when it has checksums; a manifest that does not
+// exist yet or is empty is created as usual
+pub fn snapshot(
+    root: &Path,
+    manifest_path: &Path,
+    snapshot_options: &SnapshotOptions,
+    options: WalkOptions,
+) -> io::Result<TreeResult> {
+    let SnapshotOptions { format, compression, append, checksums, hash, links } = *snapshot_options;
+    if root.to_str().is_none() {
+        return Err(not_unicode());
+    }
+    let root_metadata = std::fs::metadata(root)?;
+    let existing = append && fs::metadata(manifest_path).is_ok_and(|metadata| metadata.len() > 0);
+    let (base, mut writer) = if existing {
+        if format != Format::Ndjson {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "only NDJSON manifests can be appended to"));
+        }
+        let (header, compression) = append_base(manifest_path, root)?;
+        let out = ManifestFile::append(manifest_path, compression)?;
+        let checksums = Checksums { footer: header.checksum, ..checksums };
+        (header.root, Writer::Ndjson(NdjsonWriter::appending(out, checksums)))
+    } else {
+        let out = ManifestFile::create(manifest_path, compression)?;
+        let writer = match format {
+            Format::Json => Writer::Json(ManifestWriter::new(out, root, checksums)?),
+            Format::Ndjson => Writer::Ndjson(NdjsonWriter::new(out, root, checksums)?),
+            Format::Csv => Writer::Csv(CsvWriter::new(out)?),
+        };
+        (root.to_path_buf(), writer)
+    };
+    writer.write_entry(&ManifestEntry {
+        path: recorded_relative_path(&base, root)?,
+        file_type: EntryType::Dir,
+        times: FileTimes::from_metadata(&root_metadata),
+        size: Some(root_metadata.len()),
+        hash: None,
+        link: None,
+    })?;
+
+    let mut result = TreeResult { processed: 1, ..TreeResult::default() };
+    let mut walker = Walker::new(root, options);
+    for item in walker.by_ref() {
+        match item {
+            Ok(entry) => {
+                let path = match recorded_relative_path(&base, &entry.path) {
+                    Ok(path) => path,
+                    Err(error) => {
+                        result.errors.push(WalkError { path: entry.path, error });
+                        continue;
+                    }
+                };
+                // A file that cannot be read is recorded without its hash
+                let content_hash = match entry.file_type {
+                    EntryType::File if hash => match hash::hash_file(&entry.path) {
+                        Ok(content_hash) => Some(content_hash),
+                        Err(error) => {
+                            result.errors.push(WalkError { path: entry.path.clone(), error });
+                            None
+                        }
+                    },
+                    _ => None,
+                };
+                // Likewise a file whose links cannot be counted is recorded as a single one
+                let link = match entry.file_type {
+                    EntryType::File if links => match links::link_key(&entry.path) {
+                        Ok(link) => link,
+                        Err(error) => {
+                            result.errors.push(WalkError { path: entry.path.clone(), error });
+                            None
+                        }
+                    },
+                    _ => None,
+                };
+                writer.write_entry(&ManifestEntry {
+                    path,
+                    file_type: entry.file_type,
+                    times: entry.times,
+                    size: Some(entry.size),
+                    hash: content_hash,
+                    link,
+                })?;
+                result.processed += 1;
+            }
+            Err(err) => result.errors.push(err),
+        }
+    }
+    writer.finish()?.finish()?;
+
+    result.truncated = walker.truncated().to_vec();
+    Ok(result)
+}
+
+// Rewrites recorded paths starting with one prefix to another directory, so
+// a manifest taken as D:\data can be restored below /mnt/data. Prefixes are
+// compared by whole components, with \ and / treated alike, and on Windows
+// without regard to case
+#[derive(Clone, Debug)]
+pub struct Remap {
+    from: String,
+    to: PathBuf,
+}
+
+impl Remap {
+    pub fn new(from: &str, to: PathBuf) -> Result<Remap, String> {
+        let from = normalize_separators(from);
+        if from.is_empty() {
+            return Err("remap from must not be empty".to_string());
+        }
+        Ok(Remap { from, to })
+    }
+
+    fn apply(&self, recorded: &str) -> Option<PathBuf> {
+        let rest = strip_path_prefix(recorded, &self.from)?;
+        if rest.is_empty() {
+            return Some(self.to.clone());
+        }
+        let rest = if self.from.ends_with('/') { rest } else { rest.strip_prefix('/')? };
+        // The recorded root is part of what is matched, and comes from the
+        // manifest like the entry paths do
+        is_below_root(rest).then(|| absolute_path(&self.to, rest))
+    }
+}
+
+impl std::fmt::Display for Remap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} -> {}", self.from, self.to.display())
+    }
+}
+
+// What follows prefix in path. Windows looks names up without regard to
+// case, so D:\Data and d:\data name the same directory there
+fn strip_path_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    if !cfg!(windows) {
+        return path.strip_prefix(prefix);
+    }
+    let mut rest = path.chars();
+    for expected in prefix.chars() {
+        if !rest.next()?.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(rest.as_str())
+}
+
+// Use / as the separator and drop trailing ones, except for a root "/"
+fn normalize_separators(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    match path.trim_end_matches('/') {
+        "" if !path.is_empty() => "/".to_string(),
+        trimmed if trimmed.ends_with(':') => format!("{}/", trimmed),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+// The full path an entry was recorded at, with / separators
+fn recorded_path(root: &Path, relative: &str) -> String {
+    let root = normalize_separators(&root.to_string_lossy());
+    if relative == "." {
+        root
+    } else if root.ends_with('/') {
+        format!("{}{}", root, relative)
+    } else {
+        format!("{}/{}", root, relative)
+    }
+}
+
+// Which of the recorded timestamps an operation looks at
+#[derive(Clone, Copy, Debug)]
+pub struct TimeFields {
+    pub btime: bool,
+    pub mtime: bool,
+    pub atime: bool,
+}
+
+impl TimeFields {
+    pub const ALL: TimeFields = TimeFields { btime: true, mtime: true, atime: true };
+
+    pub fn from_names(names: &[String]) -> Result<TimeFields, String> {
+        let mut fields = TimeFields { btime: false, mtime: false, atime: false };
+        for name in names {
+            match name.as_str() {
+                "btime" => fields.btime = true,
+                "mtime" => fields.mtime = true,
+                "atime" => fields.atime = true,
+                _ => return Err(format!("Unknown timestamp in times: {:?}", name)),
+            }
+        }
+        Ok(fields)
+    }
+
+    // The selected times as (name, value) pairs
+    fn select(self, times: &FileTimes) -> impl Iterator<Item = (&'static str, Option<Timestamp>)> {
+        [
+            ("btime", times.btime, self.btime),
+            ("mtime", times.mtime, self.mtime),
+            ("atime", times.atime, self.atime),
+        ]
+        .into_iter()
+        .filter(|(_, _, selected)| *selected)
+        .map(|(name, timestamp, _)| (name, timestamp))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RestoreOptions {
+    // Restore below this directory instead of the recorded root
+    pub root: Option<PathBuf>,
+    // Tried in order, the first matching prefix wins
+    pub remap: Vec<Remap>,
+    pub fields: TimeFields,
+    // Look for files that are gone by their content hash
+    pub detect_moves: bool,
+    // Report what a restore would do instead of writing anything
+    pub dry_run: bool,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> RestoreOptions {
+        RestoreOptions { root: None, remap: Vec::new(), fields: TimeFields::ALL, detect_moves: false, dry_run: false }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RestoreResult {
+    pub result: TreeResult,
+    // Recorded paths none of the remap rules matched, left untouched
+    pub unmatched: Vec<String>,
+    // Recorded paths of files found elsewhere by their content, and where
+    pub moved: Vec<(String, PathBuf)>,
+    // Recorded paths of each file with several hard links, by file
+    pub links: Vec<Vec<String>>,
+    // What a dry run found, in place of writes
+    pub dry_run: DryRun,
+}
+
+#[derive(Debug, Default)]
+pub struct DryRun {
+    // Entries whose times would change, with the times they would get
+    pub changes: Vec<Change>,
+    // Recorded paths with nothing at them
+    pub missing: Vec<String>,
+    // Recorded paths whose write would fail, and why
+    pub blocked: Vec<(String, Vec<Obstacle>)>,
+}
+
+impl DryRun {
+    // Look at the path an entry would be written to, true when the write
+    // would go through. Birth times count as changes only where the backend
+    // can set them, as elsewhere the write leaves them alone
+    fn check(&mut self, entry: &ManifestEntry, path: &Path, times: &FileTimes) -> io::Result<bool> {
+        let backend = backend::current();
+        let times = times.quantize(config::get().quantize)?;
+        let set_btime = backend.capabilities().set_btime;
+        let current = match backend.get_times(path, true) {
+            Ok(current) => current,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                self.missing.push(entry.path.clone());
+                return Ok(false);
+            }
+            Err(error) => return Err(error),
+        };
+        let fields: Vec<&'static str> = TimeFields::ALL
+            .select(&times)
+            .zip(TimeFields::ALL.select(&current))
+            .filter(|((name, wanted), (_, found))| wanted.is_some() && wanted != found && (set_btime || *name != "btime"))
+            .map(|((name, _), _)| name)
+            .collect();
+        if !fields.is_empty() {
+            self.changes.push(Change { path: entry.path.clone(), fields, recorded: times, current });
+        }
+        let mut obstacles = access::check(path, true)?;
+        obstacles.retain(|obstacle| *obstacle != Obstacle::Unsupported);
+        if obstacles.is_empty() {
+            return Ok(true);
+        }
+        self.blocked.push((entry.path.clone(), obstacles));
+        Ok(false)
+    }
+}
+
+// The names of one file with several hard links, and where its times were set
+#[derive(Default)]
+struct LinkGroup {
+    applied: Option<PathBuf>,
+    names: Vec<String>,
+}
+
+// A hashed file that was not found at its recorded path
+struct Lost {
+    entry: ManifestEntry,
+    path: PathBuf,
+    times: FileTimes,
+    error: io::Error,
+}
+
+// Apply recorded timestamps as the entries are read. A directory is written
+// once the entries below it are done, deepest first, so only the directories
+// on the path to the current entry are held; this relies on the parents-first
+// order snapshots are written in. Symlinks are skipped since the setters
+// follow links. A malformed entry stops the restore with an error.
+//
+// With a checkpoint, the count of manifest entries handled is saved as the
+// restore goes. On resume the leading entries are read again without
+// applying them, which rebuilds the same held directories, and directories an
+// earlier run wrote are not written again.
+//
+// With detect_moves, hashed files missing from their recorded paths are
+// looked for below the restored root once all entries are done, which keeps
+// the paths of all entries in memory until then.
+//
+// Entries recorded as hard links of one file are written once: later names
+// that still lead to the file written are counted without writing it again.
+//
+// A dry run reads the current times of each entry instead of writing them
+// and reports the entries that would change, are missing or would fail
+// their write. It cannot be resumed and does not look for moved files
+pub fn restore(
+    header: &ManifestHeader,
+    entries: impl Iterator<Item = io::Result<ManifestEntry>>,
+    options: &RestoreOptions,
+    mut checkpoint: Option<Checkpoint>,
+) -> io::Result<RestoreResult> {
+    let root = options.root.as_deref().unwrap_or(&header.root);
+    let mut result = TreeResult::default();
+    let mut unmatched = Vec::new();
+    let mut recorded_paths = HashSet::new();
+    let mut lost = Vec::new();
+    let mut link_groups: Vec<LinkGroup> = Vec::new();
+    let mut link_index: HashMap<String, usize> = HashMap::new();
+    let mut dry_run = DryRun::default();
+
+    // Where an entry is restored, or its full recorded path when no remap rule matches
+    let resolve = |relative: &str| -> Result<PathBuf, String> {
+        if options.remap.is_empty() {
+            return Ok(absolute_path(root, relative));
+        }
+        let recorded = recorded_path(root, relative);
+        options.remap.iter().find_map(|remap| remap.apply(&recorded)).ok_or(recorded)
+    };
+
+    let mut apply = |entry: &ManifestEntry, replaying: bool| {
+        let group = entry.link.as_ref().map(|link| {
+            *link_index.entry(link.clone()).or_insert_with(|| {
+                link_groups.push(LinkGroup::default());
+                link_groups.len() - 1
+            })
+        });
+        if let Some(group) = group {
+            link_groups[group].names.push(entry.path.clone());
+        }
+        let path = match resolve(&entry.path) {
+            Ok(path) => path,
+            Err(recorded) => {
+                if !replaying {
+                    unmatched.push(recorded);
+                }
+                return;
+            }
+        };
+        if options.detect_moves {
+            recorded_paths.insert(path.clone());
+        }
+        if replaying {
+            if let Some(group) = group {
+                link_groups[group].applied.get_or_insert(path);
+            }
+            return;
+        }
+        if let Some(applied) = group.and_then(|group| link_groups[group].applied.as_ref()) {
+            // A name split off into a file of its own since is written after all
+            if links::same_file(applied, &path).unwrap_or(false) {
+                result.processed += 1;
+                return;
+            }
+        }
+        let times = FileTimes {
+            btime: entry.times.btime.filter(|_| options.fields.btime),
+            mtime: entry.times.mtime.filter(|_| options.fields.mtime),
+            atime: entry.times.atime.filter(|_| options.fields.atime),
+            ctime: None,
+        };
+        // The backend that did the write, None for a dry run
+        let mut backend = None;
+        let written = if options.dry_run {
+            dry_run.check(entry, &path, &times)
+        } else {
+            crate::set_times(&path, &times).map(|name| {
+                backend = Some(name);
+                true
+            })
+        };
+        match written {
+            Ok(false) => {}
+            Ok(true) => {
+                match backend {
+                    Some(backend) => result.written(backend),
+                    None => result.processed += 1,
+                }
+                if let Some(group) = group {
+                    link_groups[group].applied.get_or_insert(path);
+                }
+            }
+            Err(error) if options.detect_moves && !options.dry_run && error.kind() == io::ErrorKind::NotFound && entry.hash.is_some() => {
+                lost.push(Lost { entry: entry.clone(), path, times, error })
+            }
+            Err(error) => result.errors.push(WalkError { path, error }),
+        }
+    };
+
+    let resumed = checkpoint.as_ref().map_or(0, Checkpoint::resume_from);
+    let mut checkpoint_errors = Vec::new();
+    // Directories whose entries may still follow, innermost last
+    let mut open_dirs: Vec<ManifestEntry> = Vec::new();
+    for (i, entry) in entries.enumerate() {
+        let entry = entry?;
+        let replaying = i < resumed;
+        while open_dirs.last().is_some_and(|dir| !is_below(&entry.path, &dir.path)) {
+            let dir = open_dirs.pop().unwrap();
+            apply(&dir, replaying);
+        }
+        match entry.file_type {
+            EntryType::Dir => open_dirs.push(entry),
+            EntryType::Symlink | EntryType::Junction => {}
+            _ => apply(&entry, replaying),
+        }
+        checkpoint_errors.extend(checkpoint::update(&mut checkpoint, i + 1));
+    }
+    while let Some(dir) = open_dirs.pop() {
+        apply(&dir, false);
+    }
+    checkpoint_errors.extend(checkpoint::finish(checkpoint));
+
+    let mut moved = Vec::new();
+    if !lost.is_empty() {
+        match resolve(".") {
+            Ok(search_root) => moved = find_moves(&search_root, lost, &recorded_paths, &mut result),
+            Err(_) => result.errors.extend(lost.into_iter().map(|lost| WalkError { path: lost.path, error: lost.error })),
+        }
+    }
+
+    let links = link_groups.into_iter().map(|group| group.names).filter(|names| names.len() > 1).collect();
+    result.errors.append(&mut checkpoint_errors);
+    result.resumed = resumed;
+    Ok(RestoreResult { result, unmatched, moved, links, dry_run })
+}
+
+// Find the files that are gone below root by their size and content hash and
+// give each the times of its entry, when exactly one file that is not itself
+// in the manifest has its content and no other lost file shares it. The rest
+// are reported as the errors their writes failed with
+fn find_moves(root: &Path, lost: Vec<Lost>, recorded_paths: &HashSet<PathBuf>, result: &mut TreeResult) -> Vec<(String, PathBuf)> {
+    let key = |lost: &Lost| (lost.entry.size, lost.entry.hash);
+    let mut wanted: HashMap<(Option<u64>, Option<u64>), usize> = HashMap::new();
+    for lost in &lost {
+        *wanted.entry(key(lost)).or_default() += 1;
+    }
+    let sizes: HashSet<Option<u64>> = wanted.keys().map(|(size, _)| *size).collect();
+
+    let mut found: HashMap<(Option<u64>, Option<u64>), Vec<PathBuf>> = HashMap::new();
+    for entry in Walker::new(root, WalkOptions::default()).flatten() {
+        if entry.file_type != EntryType::File || !sizes.contains(&Some(entry.size)) || recorded_paths.contains(&entry.path) {
+            continue;
+        }
+        // Files that cannot be read cannot be matched either
+        if let Ok(content_hash) = hash::hash_file(&entry.path) {
+            found.entry((Some(entry.size), Some(content_hash))).or_default().push(entry.path);
+        }
+    }
+
+    let mut moved = Vec::new();
+    for lost in lost {
+        let candidates = found.get(&key(&lost)).map(Vec::as_slice).unwrap_or_default();
+        match candidates {
+            [path] if wanted[&key(&lost)] == 1 => match crate::set_times(path, &lost.times) {
+                Ok(backend) => {
+                    result.written(backend);
+                    moved.push((lost.entry.path, path.clone()));
+                }
+                Err(error) => result.errors.push(WalkError { path: path.clone(), error }),
+            },
+            _ => result.errors.push(WalkError { path: lost.path, error: lost.error }),
+        }
+    }
+    moved
+}
+
+// Whether a manifest path lies below a directory's manifest path
+fn is_below(path: &str, dir: &str) -> bool {
+    if dir == "." {
+        return path != ".";
+    }
+    path.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'))
+}
+
+// An entry present both in the manifest and on disk whose type or selected
+// timestamps no longer match
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub path: String,
+    // Names of what differs: "type", "btime", "mtime", "atime"
+    pub fields: Vec<&'static str>,
+    pub recorded: FileTimes,
+    pub current: FileTimes,
+}
+
+#[derive(Debug, Default)]
+pub struct Diff {
+    pub changed: Vec<Change>,
+    // Recorded entries no longer found on disk, in manifest order
+    pub missing: Vec<String>,
+    // Entries on disk the manifest does not know, in walk order
+    pub added: Vec<String>,
+    pub errors: Vec<WalkError>,
+    pub truncated: Vec<PathBuf>,
+}
+
+// Compare the tree below root with a manifest. Times of manifests recorded
+// in seconds are compared to the second. With case_fold, paths that differ
+// only in case are the same entry
+pub fn diff(
+    manifest: &Manifest,
+    root: &Path,
+    options: WalkOptions,
+    fields: TimeFields,
+    case_fold: bool,
+) -> io::Result<Diff> {
+    let key = |path: &str| if case_fold { volume::fold_case(path) } else { path.to_string() };
+    let mut recorded: HashMap<String, &ManifestEntry> = manifest.entries.iter().map(|e| (key(&e.path), e)).collect();
+    let mut result = Diff::default();
+    let precision = |timestamp: Option<Timestamp>| -> Option<Timestamp> {
+        match timestamp {
+            Some(timestamp) if !manifest.header.nanoseconds => Some(Timestamp { sec: timestamp.sec, nsec: 0 }),
+            timestamp => timestamp,
+        }
+    };
+
+    let mut compare = |path: String, file_type: EntryType, current: FileTimes| {
+        let Some(entry) = recorded.remove(&key(&path)) else {
+            result.added.push(path);
+            return;
+        };
+        let mut changed_fields = Vec::new();
+        if entry.file_type != file_type {
+            changed_fields.push("type");
+        }
+        for ((name, before), (_, after)) in fields.select(&entry.times).zip(fields.select(&current)) {
+            if precision(before) != precision(after) {
+                changed_fields.push(name);
+            }
+        }
+        if !changed_fields.is_empty() {
+            result.changed.push(Change { path, fields: changed_fields, recorded: entry.times, current });
+        }
+    };
+
+    let root_metadata = std::fs::metadata(root)?;
+    compare(".".to_string(), EntryType::Dir, FileTimes::from_metadata(&root_metadata));
+
+    let mut walker = Walker::new(root, options);
+    let mut errors = Vec::new();
+    for item in walker.by_ref() {
+        match item {
+            Ok(entry) => compare(relative_path(root, &entry.path), entry.file_type, entry.times),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    // Entries below unreadable or truncated directories are unknown, not missing
+    let unknown: Vec<String> = errors
+        .iter()
+        .map(|err| &err.path)
+        .chain(walker.truncated())
+        .map(|path| key(&relative_path(root, path)))
+        .collect();
+    let below = |path: &str| unknown.iter().any(|dir| path.strip_prefix(dir.as_str()).is_some_and(|rest| rest.starts_with('/')));
+    result.missing = manifest
+        .entries
+        .iter()
+        .filter(|e| recorded.contains_key(&key(&e.path)) && !below(&key(&e.path)))
+        .map(|e| e.path.clone())
+        .collect();
+
+    result.errors = errors;
+    result.truncated = walker.truncated().to_vec();
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_relative_paths() {
+        let root = Path::new("/data");
+        assert_eq!(relative_path(root, Path::new("/data")), ".");
+        assert_eq!(relative_path(root, Path::new("/data/a/b.txt")), "a/b.txt");
+        assert_eq!(absolute_path(root, "a/b.txt"), Path::new("/data/a/b.txt"));
+        assert_eq!(absolute_path(root, "."), root);
+    }
+
+    fn scan(text: &str) -> io::Result<(String, Vec<String>)> {
+        let mut scanner = Scanner { input: text.as_bytes(), offset: 0 };
+        let mut entries = Vec::new();
+        let header = scanner.document(&mut |entry| {
+            entries.push(String::from_utf8(entry.to_vec()).unwrap());
+            Ok(())
+        })?;
+        Ok((header, entries))
+    }
+
+    #[test]
+    fn copies_values_without_whitespace() {
+        let mut scanner = Scanner { input: &b" { \"a b\" : [ 1 , \"x\\\" ]\" ] } , 2]"[..], offset: 0 };
+        let mut out = Vec::new();
+        scanner.copy_value(&mut out).unwrap();
+        assert_eq!(out, b"{\"a b\":[1,\"x\\\" ]\"]}");
+        scanner.expect(b',').unwrap();
+        out.clear();
+        scanner.copy_value(&mut out).unwrap();
+        assert_eq!(out, b"2");
+    }
+
+    #[test]
+    fn splits_documents() {
+        let (header, entries) =
+            scan(r#"{"version": 1, "entries": [ {"path": "a b", "x": [1, 2]} , {"path":"c"} ], "root": "/r"}"#).unwrap();
+        assert_eq!(header, r#"{"version":1,"root":"/r"}"#);
+        assert_eq!(entries, [r#"{"path":"a b","x":[1,2]}"#, r#"{"path":"c"}"#]);
+        assert_eq!(scan(r#"{"entries": []}"#).unwrap(), ("{}".to_string(), Vec::new()));
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        for text in [r#"{"version": 1}"#, r#"{"entries": [] } x"#, r#"{"entries": [1 2]}"#, r#"{"entries": [{"a": 1}"#, "[]", ""] {
+            assert!(scan(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_root() {
+        let entry = |path: &str| parse_entry(&json::parse(&format!("{{\"path\": {:?}, \"type\": \"file\"}}", path)).unwrap(), true);
+        for path in ["", "..", "../x", "a/../../x", "./a", "a/.", "a//b", "a/", "/etc/passwd"] {
+            assert!(entry(path).unwrap_err().contains("leads outside the root"), "{:?}", path);
+        }
+        #[cfg(target_os = "windows")]
+        for path in ["C:", "C:/x", r"a\..\..\x", r"\\server\share"] {
+            assert!(entry(path).is_err(), "{:?}", path);
+        }
+        for path in [".", "a", "a/b.txt", "a..b/.c"] {
+            assert_eq!(entry(path).unwrap().path, path);
+        }
+    }
+
+    #[test]
+    fn restores_nothing_outside_the_root() {
+        let dir = std::env::temp_dir().join(format!("open-btime-hostile-{}", std::process::id()));
+        let root = dir.join("root");
+        fs::create_dir_all(&root).unwrap();
+        let outside = dir.join("outside");
+        fs::write(&outside, b"").unwrap();
+        let before = crate::times::get_times(&outside, true).unwrap().mtime;
+        let manifest = dir.join("hostile.ndjson");
+        let header = format!("{{\"format\": \"{}\", \"version\": 1, \"root\": {:?}}}", FORMAT, root.to_str().unwrap());
+        let entries = ["{\"path\": \".\", \"type\": \"dir\", \"mtime\": 1000}", "{\"path\": \"../outside\", \"type\": \"file\", \"mtime\": 1000}"];
+        fs::write(&manifest, format!("{}\n{}\n", header, entries.join("\n"))).unwrap();
+
+        let (header, reader) = open_manifest(&manifest, None).unwrap();
+        let error = restore(&header, reader, &RestoreOptions::default(), None).unwrap_err();
+        assert!(error.to_string().contains("leads outside the root"), "{}", error);
+        assert_eq!(crate::times::get_times(&outside, true).unwrap().mtime, before);
+        assert_eq!(Remap::new("/", root.clone()).unwrap().apply("/../outside"), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_no_more_than_the_limit_of_a_one_line_manifest() {
+        let entries: Vec<String> = (0..100).map(|i| format!("{{\"path\": \"f{}\", \"type\": \"file\", \"mtime\": 1000}}", i)).collect();
+        let text = format!("{{\"format\": \"{}\", \"version\": 1, \"root\": \"/data\", \"entries\": [{}]}}", FORMAT, entries.join(", "));
+        let limit = 256;
+        assert!(text.len() > limit);
+        let first_line = read_first_line(&mut text.as_bytes(), Some(limit as u64)).unwrap();
+        assert_eq!(first_line.len(), limit);
+        assert!(ndjson_header(&first_line).is_none());
+
+        let manifest = std::env::temp_dir().join(format!("open-btime-one-line-{}.json", std::process::id()));
+        fs::write(&manifest, &text).unwrap();
+        verify(&manifest, Some(limit as u64)).unwrap();
+        let (header, reader) = open_manifest(&manifest, Some(limit as u64)).unwrap();
+        assert_eq!(header.root, Path::new("/data"));
+        assert!(matches!(reader, ManifestReader::Lines { spilled: Some(_), .. }));
+        assert_eq!(reader.map(|entry| entry.unwrap().path).last().unwrap(), "f99");
+        fs::remove_file(&manifest).unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn remaps_without_regard_to_case() {
+        let remap = Remap::new("D:\\Data", PathBuf::from("E:\\restored")).unwrap();
+        assert_eq!(remap.apply("d:/data/a.txt"), Some(PathBuf::from("E:\\restored\\a.txt")));
+        assert_eq!(remap.apply("D:/DATA"), Some(PathBuf::from("E:\\restored")));
+        assert_eq!(remap.apply("D:/Database/a.txt"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_names_that_are_not_unicode() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = Path::new("/data");
+        let path = root.join(OsStr::from_bytes(b"bad\xff"));
+        assert_eq!(recorded_relative_path(root, &path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(recorded_relative_path(root, &root.join("good")).unwrap(), "good");
+    }
+}
@@ -0,0 +1,180 @@
+// Metadata dates Finder shows besides the filesystem times, such as
+// kMDItemContentCreationDate. They are read from and written to the
+// com.apple.metadata:<name> extended attribute as a binary property list
+// holding a date, which Spotlight imports when it indexes the file. Reads
+// fall back to what Spotlight has indexed when the attribute is absent
+use std::ffi::{c_void, CString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::times::Timestamp;
+
+// Core Foundation dates count seconds from 2001-01-01
+const CF_EPOCH_OFFSET: f64 = 978_307_200.0;
+
+fn check_name(name: &str) -> io::Result<()> {
+    if !name.starts_with("kMDItem") || name.contains('\0') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("not a metadata attribute name: {:?}", name)));
+    }
+    Ok(())
+}
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains null bytes"))
+}
+
+fn xattr_name(name: &str) -> CString {
+    CString::new(format!("com.apple.metadata:{}", name)).unwrap()
+}
+
+// bplist00 with a single date object: header, the date marker and a big
+// endian f64, a one-entry offset table and the 32-byte trailer
+fn encode_date(absolute_time: f64) -> Vec<u8> {
+    let mut plist = b"bplist00".to_vec();
+    plist.push(0x33);
+    plist.extend_from_slice(&absolute_time.to_be_bytes());
+    let offset_table = plist.len() as u64;
+    plist.push(8);
+    plist.extend_from_slice(&[0; 6]);
+    plist.extend_from_slice(&[1, 1]);
+    plist.extend_from_slice(&1u64.to_be_bytes());
+    plist.extend_from_slice(&0u64.to_be_bytes());
+    plist.extend_from_slice(&offset_table.to_be_bytes());
+    plist
+}
+
+fn decode_date(plist: &[u8]) -> Option<f64> {
+    if !plist.starts_with(b"bplist00") || plist.len() < 8 + 32 {
+        return None;
+    }
+    let trailer = &plist[plist.len() - 32..];
+    let read_be = |bytes: &[u8]| bytes.iter().fold(0u64, |n, &b| (n << 8) | b as u64);
+    let offset_size = trailer[6] as usize;
+    let top = read_be(&trailer[16..24]) as usize;
+    let offset_table = read_be(&trailer[24..32]) as usize;
+
+    let entry = offset_table.checked_add(top.checked_mul(offset_size)?)?;
+    let offset = read_be(plist.get(entry..entry + offset_size)?) as usize;
+    match plist.get(offset..offset + 9)? {
+        [0x33, date @ ..] => Some(f64::from_be_bytes(date.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+fn read_xattr(path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+    let c_path = c_path(path)?;
+    let c_name = xattr_name(name);
+    let mut buf = vec![0u8; 256];
+    let read = unsafe {
+        libc::getxattr(c_path.as_ptr(), c_name.as_ptr(), buf.as_mut_ptr() as *mut c_void, buf.len(), 0, 0)
+    };
+    if read < 0 {
+        let err = io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::ENOATTR) => Ok(None),
+            _ => Err(err),
+        };
+    }
+    buf.truncate(read as usize);
+    Ok(Some(buf))
+}
+
+mod cf {
+    use std::ffi::c_void;
+
+    pub type CFTypeRef = *const c_void;
+    pub const UTF8: u32 = 0x0800_0100;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        pub fn CFStringCreateWithBytes(
+            allocator: CFTypeRef,
+            bytes: *const u8,
+            length: isize,
+            encoding: u32,
+            external: u8,
+        ) -> CFTypeRef;
+        pub fn CFGetTypeID(value: CFTypeRef) -> usize;
+        pub fn CFDateGetTypeID() -> usize;
+        pub fn CFDateGetAbsoluteTime(date: CFTypeRef) -> f64;
+        pub fn CFRelease(value: CFTypeRef);
+    }
+
+    #[link(name = "CoreServices", kind = "framework")]
+    extern "C" {
+        pub fn MDItemCreate(allocator: CFTypeRef, path: CFTypeRef) -> CFTypeRef;
+        pub fn MDItemCopyAttribute(item: CFTypeRef, name: CFTypeRef) -> CFTypeRef;
+    }
+
+    // Releases a Core Foundation object when dropped
+    pub struct Owned(pub CFTypeRef);
+
+    impl Owned {
+        pub fn string(s: &str) -> Option<Owned> {
+            let string = unsafe { CFStringCreateWithBytes(std::ptr::null(), s.as_ptr(), s.len() as isize, UTF8, 0) };
+            (!string.is_null()).then_some(Owned(string))
+        }
+    }
+
+    impl Drop for Owned {
+        fn drop(&mut self) {
+            unsafe { CFRelease(self.0) };
+        }
+    }
+}
+
+// What Spotlight has indexed for the file, None if it has nothing or the
+// attribute is not a date
+fn indexed_date(path: &Path, name: &str) -> Option<f64> {
+    let path = cf::Owned::string(&path.to_string_lossy())?;
+    let name = cf::Owned::string(name)?;
+    let item = unsafe { cf::MDItemCreate(std::ptr::null(), path.0) };
+    if item.is_null() {
+        return None;
+    }
+    let item = cf::Owned(item);
+    let value = unsafe { cf::MDItemCopyAttribute(item.0, name.0) };
+    if value.is_null() {
+        return None;
+    }
+    let value = cf::Owned(value);
+    unsafe { (cf::CFGetTypeID(value.0) == cf::CFDateGetTypeID()).then(|| cf::CFDateGetAbsoluteTime(value.0)) }
+}
+
+pub fn get_date(path: &Path, name: &str) -> io::Result<Option<Timestamp>> {
+    check_name(name)?;
+    // Fail like the other readers for missing files instead of returning null
+    std::fs::metadata(path)?;
+    let absolute_time = match read_xattr(path, name)? {
+        Some(plist) => decode_date(&plist),
+        None => indexed_date(path, name),
+    };
+    Ok(absolute_time.and_then(|t| Timestamp::from_secs_f64(t + CF_EPOCH_OFFSET)))
+}
+
+pub fn set_date(path: &Path, name: &str, date: Timestamp) -> io::Result<()> {
+    check_name(name)?;
+    let c_path = c_path(path)?;
+    let c_name = xattr_name(name);
+    let absolute_time = date.sec as f64 - CF_EPOCH_OFFSET + date.nsec as f64 / 1e9;
+    let plist = encode_date(absolute_time);
+    if unsafe { libc::setxattr(c_path.as_ptr(), c_name.as_ptr(), plist.as_ptr() as *const c_void, plist.len(), 0, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn remove_date(path: &Path, name: &str) -> io::Result<()> {
+    check_name(name)?;
+    let c_path = c_path(path)?;
+    let c_name = xattr_name(name);
+    if unsafe { libc::removexattr(c_path.as_ptr(), c_name.as_ptr(), 0) } != 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ENOATTR) {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
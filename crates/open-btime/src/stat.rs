@@ -0,0 +1,77 @@
+// One normalized stat of a file across platforms: its timestamps, size,
+// identity and the filesystem it lives on, in place of stat, statx and
+// GetFileInformationByHandleEx each giving part of it
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::times::{self, FileTimes};
+use crate::volume;
+use crate::walk::EntryType;
+
+#[derive(Clone, Debug)]
+pub struct FileStat {
+    pub times: FileTimes,
+    pub size: u64,
+    pub file_type: EntryType,
+    // The inode, or the NTFS file index
+    pub ino: u64,
+    // The device, or the volume serial number
+    pub dev: u64,
+    pub nlink: u64,
+    // None when the filesystem could not be queried
+    pub filesystem: Option<String>,
+}
+
+#[cfg(unix)]
+pub fn stat(path: &Path, follow: bool) -> io::Result<FileStat> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = if follow { fs::metadata(path)? } else { fs::symlink_metadata(path)? };
+    Ok(FileStat {
+        times: times::get_times(path, follow)?,
+        size: metadata.len(),
+        file_type: EntryType::from_file_type(metadata.file_type()),
+        ino: metadata.ino(),
+        dev: metadata.dev(),
+        nlink: metadata.nlink(),
+        filesystem: volume::filesystem(path).ok().map(|filesystem| filesystem.name),
+    })
+}
+
+#[cfg(windows)]
+pub fn stat(path: &Path, follow: bool) -> io::Result<FileStat> {
+    use crate::win;
+
+    let file = win::open_query(path, follow)?;
+    let metadata = file.metadata()?;
+    let info = win::file_information(&file)?;
+    let mut times = times::get_times(path, follow)?;
+    if times.ctime.is_none() {
+        times.ctime = win::change_time(&file)?;
+    }
+    Ok(FileStat {
+        times,
+        size: metadata.len(),
+        file_type: EntryType::from_file_type(metadata.file_type()),
+        ino: win::file_index(&info),
+        dev: info.dwVolumeSerialNumber as u64,
+        nlink: info.nNumberOfLinks as u64,
+        filesystem: volume::filesystem(path).ok().map(|filesystem| filesystem.name),
+    })
+}
+
+// Other platforms have no file identity in std
+#[cfg(not(any(unix, windows)))]
+pub fn stat(path: &Path, follow: bool) -> io::Result<FileStat> {
+    let metadata = if follow { fs::metadata(path)? } else { fs::symlink_metadata(path)? };
+    Ok(FileStat {
+        times: times::get_times(path, follow)?,
+        size: metadata.len(),
+        file_type: EntryType::from_file_type(metadata.file_type()),
+        ino: 0,
+        dev: 0,
+        nlink: 1,
+        filesystem: volume::filesystem(path).ok().map(|filesystem| filesystem.name),
+    })
+}
@@ -0,0 +1,122 @@
+// Process-wide counters of the filesystem operations behind every export,
+// read with getStats() by services that embed the addon
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    GetTimes,
+    SetTimes,
+    ReadDir,
+    CopyFile,
+}
+
+pub const OPS: [Op; 4] = [Op::GetTimes, Op::SetTimes, Op::ReadDir, Op::CopyFile];
+
+impl Op {
+    pub fn from_name(name: &str) -> Option<Op> {
+        OPS.into_iter().find(|op| op.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::GetTimes => "getTimes",
+            Op::SetTimes => "setTimes",
+            Op::ReadDir => "readDir",
+            Op::CopyFile => "copyFile",
+        }
+    }
+}
+
+struct Counters {
+    count: AtomicU64,
+    failures: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl Counters {
+    const fn new() -> Counters {
+        Counters { count: AtomicU64::new(0), failures: AtomicU64::new(0), nanos: AtomicU64::new(0) }
+    }
+}
+
+static COUNTERS: [Counters; OPS.len()] = [const { Counters::new() }; OPS.len()];
+// Failures of all operations by OS error code, -1 for errors without one
+static ERRORS: Mutex<BTreeMap<i32, u64>> = Mutex::new(BTreeMap::new());
+
+// Writes by the backend of a chain that handled them
+static HANDLED: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+
+// Count `count` operations that took `started.elapsed()` together, `errors`
+// being the codes of those that failed
+fn add(op: Op, started: Instant, count: u64, errors: impl Iterator<Item = i32>) {
+    let counters = &COUNTERS[op as usize];
+    counters.count.fetch_add(count, Ordering::Relaxed);
+    counters.nanos.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    let mut errors = errors.peekable();
+    if errors.peek().is_none() {
+        return;
+    }
+    let mut by_code = ERRORS.lock().unwrap();
+    for code in errors {
+        counters.failures.fetch_add(1, Ordering::Relaxed);
+        *by_code.entry(code).or_default() += 1;
+    }
+}
+
+pub fn record<T>(op: Op, started: Instant, result: &io::Result<T>) {
+    record_error(op, started, result.as_ref().err());
+}
+
+pub fn record_error(op: Op, started: Instant, error: Option<&io::Error>) {
+    let code = error.map(|err| err.raw_os_error().unwrap_or(-1));
+    add(op, started, 1, code.into_iter());
+}
+
+// A batch of operations issued at once, such as one io_uring submission
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub fn record_batch<T>(op: Op, started: Instant, results: &[io::Result<T>]) {
+    let errors = results.iter().filter_map(|result| result.as_ref().err().map(|err| err.raw_os_error().unwrap_or(-1)));
+    add(op, started, results.len() as u64, errors);
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OpStats {
+    pub count: u64,
+    pub failures: u64,
+    pub total_nanos: u64,
+}
+
+pub fn op_stats(op: Op) -> OpStats {
+    let counters = &COUNTERS[op as usize];
+    OpStats {
+        count: counters.count.load(Ordering::Relaxed),
+        failures: counters.failures.load(Ordering::Relaxed),
+        total_nanos: counters.nanos.load(Ordering::Relaxed),
+    }
+}
+
+pub fn handled(backend: &'static str) {
+    *HANDLED.lock().unwrap().entry(backend).or_default() += 1;
+}
+
+pub fn handled_by() -> Vec<(&'static str, u64)> {
+    HANDLED.lock().unwrap().iter().map(|(&backend, &count)| (backend, count)).collect()
+}
+
+pub fn errors() -> Vec<(i32, u64)> {
+    ERRORS.lock().unwrap().iter().map(|(&code, &count)| (code, count)).collect()
+}
+
+pub fn reset() {
+    for counters in &COUNTERS {
+        counters.count.store(0, Ordering::Relaxed);
+        counters.failures.store(0, Ordering::Relaxed);
+        counters.nanos.store(0, Ordering::Relaxed);
+    }
+    ERRORS.lock().unwrap().clear();
+    HANDLED.lock().unwrap().clear();
+}
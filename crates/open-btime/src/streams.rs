@@ -0,0 +1,97 @@
+// Alternate data streams of NTFS files, for duplication tools that copy
+// every stream of a file. NTFS keeps one set of timestamps per file rather
+// than per stream, so times set through a stream's handle apply to the file
+// and all of its streams, the same as times set through the file
+use std::ffi::OsString;
+use std::fs::OpenOptions;
+use std::io;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::fs::OpenOptionsExt;
+use std::path::Path;
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Storage::FileSystem::{
+    FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard, FILE_FLAG_BACKUP_SEMANTICS, FILE_WRITE_ATTRIBUTES,
+    WIN32_FIND_STREAM_DATA,
+};
+
+use crate::times::FileTimes;
+use crate::win::{self, io_error};
+
+const ERROR_HANDLE_EOF: i32 = 38;
+
+pub struct Stream {
+    // Without the leading colon and the :$DATA type, empty for the unnamed stream
+    pub name: OsString,
+    pub size: u64,
+}
+
+struct FindHandle(HANDLE);
+
+impl Drop for FindHandle {
+    fn drop(&mut self) {
+        let _ = unsafe { FindClose(self.0) };
+    }
+}
+
+// ":name:$DATA" as FindFirstStreamW reports it
+fn stream_name(data: &WIN32_FIND_STREAM_DATA) -> OsString {
+    const DATA_TYPE: [u16; 6] = [b':' as u16, b'$' as u16, b'D' as u16, b'A' as u16, b'T' as u16, b'A' as u16];
+    let length = data.cStreamName.iter().position(|&c| c == 0).unwrap_or(data.cStreamName.len());
+    let name = &data.cStreamName[..length];
+    let name = name.strip_prefix(&[b':' as u16]).unwrap_or(name);
+    let name = name.strip_suffix(&DATA_TYPE).unwrap_or(name);
+    OsString::from_wide(name)
+}
+
+// The data streams of a file, the unnamed one first. Directories have none
+// unless streams were added to them
+pub fn list(path: &Path) -> io::Result<Vec<Stream>> {
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut data = WIN32_FIND_STREAM_DATA::default();
+    let handle = unsafe {
+        FindFirstStreamW(PCWSTR(wide.as_ptr()), FindStreamInfoStandard, &mut data as *mut WIN32_FIND_STREAM_DATA as *mut core::ffi::c_void, 0)
+    };
+    let handle = match handle.map_err(io_error) {
+        Ok(handle) => FindHandle(handle),
+        Err(err) if err.raw_os_error() == Some(ERROR_HANDLE_EOF) => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut streams = Vec::new();
+    loop {
+        streams.push(Stream { name: stream_name(&data), size: data.StreamSize as u64 });
+        if let Err(err) = unsafe { FindNextStreamW(handle.0, &mut data as *mut WIN32_FIND_STREAM_DATA as *mut core::ffi::c_void) } {
+            let err = io_error(err);
+            if err.raw_os_error() == Some(ERROR_HANDLE_EOF) {
+                break;
+            }
+            return Err(err);
+        }
+    }
+    Ok(streams)
+}
+
+// The path of a stream, path:name, or the file itself for the unnamed stream
+pub fn stream_path(path: &Path, name: &str) -> Result<OsString, String> {
+    if name.contains(['\\', '/', ':']) {
+        return Err(format!("invalid stream name: {:?}", name));
+    }
+    let mut stream = path.as_os_str().to_os_string();
+    if !name.is_empty() {
+        stream.push(":");
+        stream.push(name);
+    }
+    Ok(stream)
+}
+
+// Set the timestamps of a file through one of its streams. Fields that are
+// None are left unchanged
+pub fn set_times(stream: &Path, times: &FileTimes) -> io::Result<()> {
+    let file = OpenOptions::new()
+        .access_mode(FILE_WRITE_ATTRIBUTES.0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0)
+        .open(stream)?;
+    win::set_file_times(&file, times)
+}
@@ -0,0 +1,78 @@
+// Unloading the addon while work is in flight. Every instance of the addon,
+// one per thread that loads it (the main thread and each worker or Electron
+// renderer), keeps an Instance in its environment. Node drops it when the
+// environment is torn down, on worker exit or a window reload: background
+// work the instance started is told to stop and waited for. The last
+// instance to go applies the writes batching still holds, closes the undo
+// log and stops the threads shared by all instances: the worker pool, the
+// batch flusher and the timeout helpers, joining each. A timeout helper still
+// blocked past its limit cannot be joined and is left to exit on its own
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+use neon::prelude::*;
+use neon::thread::LocalKey;
+
+// The background work of one instance
+#[derive(Default)]
+pub struct Work {
+    cancelled: AtomicBool,
+    running: Mutex<usize>,
+    idle: Condvar,
+}
+
+impl Work {
+    // Set once the instance unloads; long operations stop at the next entry
+    pub fn cancelled(&self) -> &AtomicBool {
+        &self.cancelled
+    }
+
+    // Count a background job as running until the guard is dropped. Taken on
+    // the JS thread before the job is queued, so a job that never starts
+    // still releases it when its closure is dropped
+    pub fn start(self: &Arc<Work>) -> Running {
+        *self.running.lock().unwrap() += 1;
+        Running(Arc::clone(self))
+    }
+}
+
+pub struct Running(Arc<Work>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let mut running = self.0.running.lock().unwrap();
+        *running -= 1;
+        if *running == 0 {
+            self.0.idle.notify_all();
+        }
+    }
+}
+
+struct Instance(Arc<Work>);
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        self.0.cancelled.store(true, Ordering::Relaxed);
+        let running = self.0.running.lock().unwrap();
+        drop(self.0.idle.wait_while(running, |running| *running > 0).unwrap());
+        if INSTANCES.fetch_sub(1, Ordering::AcqRel) == 1 {
+            crate::batch::stop();
+            let _ = crate::undo::open(None);
+            crate::workers::shutdown();
+            crate::timeout::shutdown();
+        }
+    }
+}
+
+static INSTANCES: AtomicUsize = AtomicUsize::new(0);
+static INSTANCE: LocalKey<Instance> = LocalKey::new();
+
+// The background work of the calling instance, registering the instance
+// on first use
+pub fn work<'a>(cx: &mut impl Context<'a>) -> Arc<Work> {
+    let instance = INSTANCE.get_or_init(cx, || {
+        INSTANCES.fetch_add(1, Ordering::AcqRel);
+        Instance(Arc::default())
+    });
+    Arc::clone(&instance.0)
+}
@@ -0,0 +1,185 @@
+// A time limit for single filesystem operations. A hung NFS or SMB mount can
+// block an open forever, which would wedge a whole batch, so with a limit set
+// each operation runs on a helper thread and the caller stops waiting once
+// the limit passes. The operation then fails with ETIMEDOUT (ERROR_TIMEOUT
+// on Windows). On Windows the blocked call is cancelled as well; elsewhere
+// the helper stays blocked until the call returns on its own. The helpers
+// are a small pool that is reused from one operation to the next, and once
+// every one of them is stuck past the limit operations fail straight away
+// instead of piling up more threads behind the hung mount
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Milliseconds, 0 for no limit
+static LIMIT: AtomicU64 = AtomicU64::new(0);
+
+const MAX_HELPERS: usize = 32;
+
+pub fn set(limit: Option<Duration>) {
+    LIMIT.store(limit.map_or(0, |limit| (limit.as_millis() as u64).max(1)), Ordering::Relaxed);
+}
+
+pub fn get() -> Option<Duration> {
+    match LIMIT.load(Ordering::Relaxed) {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
+    }
+}
+
+#[cfg(target_os = "windows")]
+const TIMED_OUT: i32 = 1460;
+#[cfg(not(target_os = "windows"))]
+const TIMED_OUT: i32 = libc::ETIMEDOUT;
+
+enum State {
+    Queued,
+    // With the raw handle of the helper thread running it
+    Running(isize),
+    // Done, or given up on while still queued
+    Finished,
+}
+
+struct Job {
+    operation: Box<dyn FnOnce() + Send>,
+    state: Arc<Mutex<State>>,
+}
+
+struct Pool {
+    queue: VecDeque<Job>,
+    // When each helper started the job it runs, None while it is idle
+    busy_since: Vec<Option<Instant>>,
+    helpers: Vec<thread::JoinHandle<()>>,
+    // Bumped by shutdown(), which lets the helpers of earlier generations go
+    generation: u64,
+}
+
+static POOL: Mutex<Pool> =
+    Mutex::new(Pool { queue: VecDeque::new(), busy_since: Vec::new(), helpers: Vec::new(), generation: 0 });
+static WORK: Condvar = Condvar::new();
+
+fn helper(index: usize, generation: u64) {
+    let mut pool = POOL.lock().unwrap();
+    loop {
+        if pool.generation != generation {
+            return;
+        }
+        let Some(job) = pool.queue.pop_front() else {
+            pool = WORK.wait(pool).unwrap();
+            continue;
+        };
+        {
+            let mut state = job.state.lock().unwrap();
+            if matches!(*state, State::Finished) {
+                continue;
+            }
+            *state = State::Running(raw_handle(&pool.helpers[index]));
+        }
+        pool.busy_since[index] = Some(Instant::now());
+        drop(pool);
+        (job.operation)();
+        *job.state.lock().unwrap() = State::Finished;
+        pool = POOL.lock().unwrap();
+        if pool.generation != generation {
+            return;
+        }
+        pool.busy_since[index] = None;
+    }
+}
+
+// Let the helpers go and wait for the idle ones. Called when the last
+// instance of the addon unloads. A helper still blocked in a call cannot be
+// joined; it exits once the call returns
+pub fn shutdown() {
+    let idle: Vec<_> = {
+        let mut pool = POOL.lock().unwrap();
+        pool.generation += 1;
+        pool.queue.clear();
+        let busy_since = mem::take(&mut pool.busy_since);
+        let helpers = mem::take(&mut pool.helpers);
+        helpers.into_iter().zip(busy_since).filter(|(_, since)| since.is_none()).map(|(helper, _)| helper).collect()
+    };
+    WORK.notify_all();
+    for helper in idle {
+        let _ = helper.join();
+    }
+}
+
+fn timed_out() -> io::Error {
+    io::Error::from_raw_os_error(TIMED_OUT)
+}
+
+// Run an operation under the limit, or directly when there is none
+pub fn run<T: Send + 'static>(operation: impl FnOnce() -> io::Result<T> + Send + 'static) -> io::Result<T> {
+    let Some(limit) = get() else {
+        return operation();
+    };
+    let (sender, receiver) = mpsc::channel();
+    let state = Arc::new(Mutex::new(State::Queued));
+    {
+        let mut pool = POOL.lock().unwrap();
+        let now = Instant::now();
+        let stuck = pool.busy_since.iter().filter(|since| since.is_some_and(|since| now - since >= limit)).count();
+        if stuck == MAX_HELPERS {
+            return Err(timed_out());
+        }
+        let idle = pool.busy_since.iter().filter(|since| since.is_none()).count();
+        if idle <= pool.queue.len() && pool.helpers.len() < MAX_HELPERS {
+            let (index, generation) = (pool.helpers.len(), pool.generation);
+            let helper = thread::Builder::new()
+                .name("open-btime-timeout".to_string())
+                .spawn(move || helper(index, generation))?;
+            pool.helpers.push(helper);
+            pool.busy_since.push(None);
+        }
+        let operation = Box::new(move || {
+            let _ = sender.send(operation());
+        });
+        pool.queue.push_back(Job { operation, state: Arc::clone(&state) });
+    }
+    WORK.notify_one();
+    match receiver.recv_timeout(limit) {
+        Ok(result) => result,
+        Err(_) => {
+            let mut state = state.lock().unwrap();
+            match *state {
+                State::Queued => *state = State::Finished,
+                State::Running(handle) => cancel(handle),
+                State::Finished => {}
+            }
+            drop(state);
+            // The operation may have finished in the meantime
+            receiver.try_recv().unwrap_or_else(|_| Err(timed_out()))
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn raw_handle(helper: &thread::JoinHandle<()>) -> isize {
+    use std::os::windows::io::AsRawHandle;
+
+    helper.as_raw_handle() as isize
+}
+
+#[cfg(not(target_os = "windows"))]
+fn raw_handle(_helper: &thread::JoinHandle<()>) -> isize {
+    0
+}
+
+// Abort the synchronous I/O call the helper is blocked in, such as an open
+// waiting for an unreachable server. The job's state is locked, so the
+// helper is still running it
+#[cfg(target_os = "windows")]
+fn cancel(handle: isize) {
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::System::IO::CancelSynchronousIo;
+
+    let _ = unsafe { CancelSynchronousIo(HANDLE(handle)) };
+}
+
+#[cfg(not(target_os = "windows"))]
+fn cancel(_handle: isize) {}
@@ -0,0 +1,268 @@
+use std::fs::Metadata;
+use std::io::{self, Result};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::arena::PathArena;
+use crate::backend;
+use crate::config::Quantize;
+use crate::fault;
+use crate::path;
+use crate::stats;
+use crate::timeout;
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+// The finest step the platform stores timestamps in, a FILETIME counts
+// 100ns intervals
+pub const RESOLUTION_NANOS: i128 = if cfg!(target_os = "windows") { 100 } else { 1 };
+
+// 100ns intervals between 1601-01-01, the epoch of Windows FILETIMEs, and the Unix epoch
+const FILETIME_EPOCH_DIFFERENCE: i128 = 116_444_736_000_000_000;
+
+// A point in time as whole seconds plus nanoseconds since the Unix epoch.
+// nsec is always in 0..1_000_000_000, also for times before 1970
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp {
+    pub sec: i64,
+    pub nsec: u32,
+}
+
+impl Timestamp {
+    pub fn from_nanos(nanos: i128) -> Option<Timestamp> {
+        let sec = i64::try_from(nanos.div_euclid(NANOS_PER_SEC)).ok()?;
+        let nsec = nanos.rem_euclid(NANOS_PER_SEC) as u32;
+        Some(Timestamp { sec, nsec })
+    }
+
+    // Fractional seconds, as passed to btime() from JS
+    pub fn from_secs_f64(seconds: f64) -> Option<Timestamp> {
+        if !seconds.is_finite() {
+            return None;
+        }
+        let sec = seconds.floor();
+        if sec < i64::MIN as f64 || sec >= i64::MAX as f64 {
+            return None;
+        }
+        let nsec = (((seconds - sec) * 1e9) as u32).min(999_999_999);
+        Some(Timestamp { sec: sec as i64, nsec })
+    }
+
+    pub fn as_nanos(self) -> i128 {
+        self.sec as i128 * NANOS_PER_SEC + self.nsec as i128
+    }
+
+    pub fn as_millis_f64(self) -> f64 {
+        self.sec as f64 * 1e3 + self.nsec as f64 / 1e6
+    }
+
+    // UTC in ISO 8601 with nanoseconds, e.g. 2023-11-14T22:13:20.123456789Z
+    pub fn to_iso8601(self) -> String {
+        let days = self.sec.div_euclid(86_400);
+        let seconds = self.sec.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            self.nsec
+        )
+    }
+
+    // The value the platform stores for this timestamp under a policy, None
+    // when it falls between two steps and the policy is Quantize::Error
+    pub fn quantize(self, policy: Quantize) -> Option<Timestamp> {
+        let nanos = self.as_nanos();
+        let remainder = nanos.rem_euclid(RESOLUTION_NANOS);
+        match policy {
+            _ if remainder == 0 => Some(self),
+            Quantize::Truncate => Timestamp::from_nanos(nanos - remainder),
+            Quantize::Round if remainder * 2 >= RESOLUTION_NANOS => Timestamp::from_nanos(nanos - remainder + RESOLUTION_NANOS),
+            Quantize::Round => Timestamp::from_nanos(nanos - remainder),
+            Quantize::Error => None,
+        }
+    }
+
+    // A FILETIME count of 100ns intervals since 1601
+    pub fn from_filetime(intervals: u64) -> Timestamp {
+        Timestamp::from_nanos((intervals as i128 - FILETIME_EPOCH_DIFFERENCE) * 100).expect("FILETIME out of Timestamp range")
+    }
+
+    // The FILETIME of this time, less than 100ns dropped. None outside 1601
+    // to the largest signed 64-bit count, the range Windows accepts
+    pub fn to_filetime(self) -> Option<u64> {
+        let intervals = self.as_nanos().div_euclid(100) + FILETIME_EPOCH_DIFFERENCE;
+        (0..=i64::MAX as i128).contains(&intervals).then_some(intervals as u64)
+    }
+
+    pub fn from_system_time(time: SystemTime) -> Timestamp {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => Timestamp::from_nanos(after.as_nanos() as i128),
+            Err(err) => Timestamp::from_nanos(-(err.duration().as_nanos() as i128)),
+        }
+        .expect("SystemTime out of Timestamp range")
+    }
+}
+
+// Proleptic Gregorian (year, month, day) of a day count since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// Day count since 1970-01-01 of a proleptic Gregorian date, the inverse of civil_from_days
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * mp + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// The timestamps of a single file. A field is None when the platform or
+// filesystem does not record it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileTimes {
+    pub btime: Option<Timestamp>,
+    pub mtime: Option<Timestamp>,
+    pub atime: Option<Timestamp>,
+    pub ctime: Option<Timestamp>,
+}
+
+impl FileTimes {
+    pub fn from_metadata(metadata: &Metadata) -> FileTimes {
+        FileTimes {
+            btime: metadata.created().ok().map(Timestamp::from_system_time),
+            mtime: metadata.modified().ok().map(Timestamp::from_system_time),
+            atime: metadata.accessed().ok().map(Timestamp::from_system_time),
+            ctime: change_time(metadata),
+        }
+    }
+
+    // The times to write under a quantization policy, ctime is left as it is
+    pub fn quantize(&self, policy: Quantize) -> Result<FileTimes> {
+        let quantize = |timestamp: Option<Timestamp>| {
+            timestamp
+                .map(|timestamp| {
+                    timestamp.quantize(policy).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidInput, "timestamp is finer than the platform's 100ns resolution")
+                    })
+                })
+                .transpose()
+        };
+        Ok(FileTimes { btime: quantize(self.btime)?, mtime: quantize(self.mtime)?, atime: quantize(self.atime)?, ctime: self.ctime })
+    }
+}
+
+// Read all timestamps of a file through the installed backend. With follow
+// unset a symlink's own times are returned
+// (lstat/statx(AT_SYMLINK_NOFOLLOW)/FILE_FLAG_OPEN_REPARSE_POINT)
+pub fn get_times(path: &Path, follow: bool) -> Result<FileTimes> {
+    get_times_by(path, follow).map(|(times, _)| times)
+}
+
+// get_times, also returning the name of the backend that answered
+pub fn get_times_by(path: &Path, follow: bool) -> Result<(FileTimes, &'static str)> {
+    let started = Instant::now();
+    let times = fault::check(stats::Op::GetTimes, path).and_then(|_| {
+        let (backend, path) = (backend::current(), path.to_path_buf());
+        timeout::run(move || path::check(&path, follow).and_then(|_| backend.get_times_by(&path, follow)))
+    });
+    stats::record(stats::Op::GetTimes, started, &times);
+    times
+}
+
+// Read the timestamps of many files, batched through io_uring when the
+// io-uring feature is enabled and the kernel allows it
+pub fn get_times_many(paths: &PathArena, follow: bool) -> Vec<Result<FileTimes>> {
+    // Other backends, injected faults, a symlinkDepth to check and a timeout
+    // to run under take each path on its own
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    if backend::is_platform() && !fault::active() && crate::config::get().symlink_depth.is_none() && timeout::get().is_none() {
+        let started = Instant::now();
+        if let Some(results) = crate::dir::stat_paths(paths, follow) {
+            stats::record_batch(stats::Op::GetTimes, started, &results);
+            return results.into_iter().map(|stat| stat.map(|stat| stat.times)).collect();
+        }
+    }
+    paths.iter().map(|path| get_times(path, follow)).collect()
+}
+
+#[cfg(unix)]
+fn change_time(metadata: &Metadata) -> Option<Timestamp> {
+    use std::os::unix::fs::MetadataExt;
+    Some(Timestamp { sec: metadata.ctime(), nsec: metadata.ctime_nsec() as u32 })
+}
+
+// std does not expose the NTFS ChangeTime
+#[cfg(not(unix))]
+fn change_time(_metadata: &Metadata) -> Option<Timestamp> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_nanoseconds() {
+        assert_eq!(Timestamp::from_nanos(-1), Some(Timestamp { sec: -1, nsec: 999_999_999 }));
+        assert_eq!(Timestamp::from_nanos(i128::MAX), None);
+        let timestamp = Timestamp { sec: -5, nsec: 7 };
+        assert_eq!(Timestamp::from_nanos(timestamp.as_nanos()), Some(timestamp));
+    }
+
+    #[test]
+    fn converts_days_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(-135_140), (1600, 1, 1));
+    }
+
+    #[test]
+    fn formats_iso8601() {
+        assert_eq!(Timestamp { sec: 1_700_000_000, nsec: 123_456_789 }.to_iso8601(), "2023-11-14T22:13:20.123456789Z");
+        assert_eq!(Timestamp { sec: -1, nsec: 0 }.to_iso8601(), "1969-12-31T23:59:59.000000000Z");
+    }
+
+    #[test]
+    fn converts_dates_to_days() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(1900, 3, 1) - days_from_civil(1900, 2, 28), 1);
+        for days in (-1_000_000..1_000_000).step_by(997) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn converts_filetimes() {
+        let epoch = 116_444_736_000_000_000;
+        assert_eq!(Timestamp { sec: 0, nsec: 0 }.to_filetime(), Some(epoch));
+        assert_eq!(Timestamp::from_filetime(epoch), Timestamp { sec: 0, nsec: 0 });
+        assert_eq!(Timestamp::from_filetime(0), Timestamp { sec: -11_644_473_600, nsec: 0 });
+        // Less than 100ns is dropped, rounding towards 1601
+        assert_eq!(Timestamp { sec: 0, nsec: 150 }.to_filetime(), Some(epoch + 1));
+        assert_eq!(Timestamp { sec: -1, nsec: 999_999_950 }.to_filetime(), Some(epoch - 1));
+        assert_eq!(Timestamp { sec: -11_644_473_601, nsec: 0 }.to_filetime(), None);
+        assert_eq!(Timestamp { sec: i64::MAX, nsec: 0 }.to_filetime(), None);
+        let timestamp = Timestamp { sec: 1_700_000_000, nsec: 123_456_700 };
+        assert_eq!(timestamp.to_filetime().map(Timestamp::from_filetime), Some(timestamp));
+    }
+}
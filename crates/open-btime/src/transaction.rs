@@ -0,0 +1,76 @@
+// All-or-nothing batches. The current times of every path are read before
+// anything is written; once a write fails no further writes start and the
+// paths already written get their previous times back
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::pool;
+use crate::times::{self, FileTimes};
+use crate::tree::TreeResult;
+use crate::walk::WalkError;
+
+// Why a batch was given up, and how far the rollback got
+#[derive(Debug, Default)]
+pub struct Aborted {
+    // The writes that failed, or the reads that kept the batch from starting
+    pub errors: Vec<WalkError>,
+    pub rolled_back: usize,
+    // Paths whose previous times could not be put back
+    pub rollback_errors: Vec<WalkError>,
+}
+
+// tree::set_paths as a transaction
+pub fn set_paths(items: Vec<(PathBuf, FileTimes)>, concurrency: usize) -> Result<TreeResult, Aborted> {
+    // Only the fields a write changes are restored
+    let mut previous = Vec::with_capacity(items.len());
+    let mut errors = Vec::new();
+    for (path, times) in &items {
+        match times::get_times(path, true) {
+            Ok(current) => previous.push(FileTimes {
+                btime: times.btime.and(current.btime),
+                mtime: times.mtime.and(current.mtime),
+                atime: times.atime.and(current.atime),
+                ctime: None,
+            }),
+            Err(error) => errors.push(WalkError { path: path.clone(), error }),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(Aborted { errors, ..Aborted::default() });
+    }
+
+    let failed = AtomicBool::new(false);
+    let written = Mutex::new(Vec::new());
+    let errors = Mutex::new(Vec::new());
+    pool::for_each_bounded(items.iter().enumerate(), concurrency, |(i, (path, times))| {
+        if failed.load(Ordering::Relaxed) {
+            return;
+        }
+        match crate::set_times(path, times) {
+            Ok(backend) => written.lock().unwrap().push((i, backend)),
+            Err(error) => {
+                failed.store(true, Ordering::Relaxed);
+                errors.lock().unwrap().push(WalkError { path: path.clone(), error });
+            }
+        }
+    });
+    let written = written.into_inner().unwrap();
+    let errors = errors.into_inner().unwrap();
+    if errors.is_empty() {
+        let mut result = TreeResult::default();
+        for &(_, backend) in &written {
+            result.written(backend);
+        }
+        return Ok(result);
+    }
+
+    let mut rollback_errors = Vec::new();
+    for &(i, _) in &written {
+        let path = &items[i].0;
+        if let Err(error) = crate::set_times(path, &previous[i]) {
+            rollback_errors.push(WalkError { path: path.clone(), error });
+        }
+    }
+    Err(Aborted { errors, rolled_back: written.len() - rollback_errors.len(), rollback_errors })
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::checkpoint::{self, Checkpoint, Watermark};
+use crate::pool;
+use crate::times::{FileTimes, Timestamp};
+use crate::walk::{EntryType, WalkError, WalkOptions, Walker};
+
+// Outcome of a recursive operation
+#[derive(Debug, Default)]
+pub struct TreeResult {
+    pub processed: usize,
+    pub errors: Vec<WalkError>,
+    pub truncated: Vec<PathBuf>,
+    // Entries an earlier run recorded in the checkpoint as done
+    pub resumed: usize,
+    // How many of the writes each backend did, by name
+    pub backends: BTreeMap<&'static str, usize>,
+}
+
+impl TreeResult {
+    // Count an entry written by `backend`
+    pub fn written(&mut self, backend: &'static str) {
+        self.processed += 1;
+        *self.backends.entry(backend).or_default() += 1;
+    }
+
+    // Add the counts and errors of another result, e.g. of a batch
+    pub fn merge(&mut self, mut other: TreeResult) {
+        self.processed += other.processed;
+        self.errors.append(&mut other.errors);
+        self.truncated.append(&mut other.truncated);
+        self.resumed += other.resumed;
+        for (backend, count) in other.backends {
+            *self.backends.entry(backend).or_default() += count;
+        }
+    }
+}
+
+// Set the birth time of root and of everything the walker yields below it.
+// Files are stamped by `concurrency` workers while the walk goes on;
+// directories are stamped once all files are done, deepest first, so the
+// order of directory writes does not depend on thread scheduling. Links that
+// are not followed are skipped.
+//
+// With a checkpoint, walk entries are counted in walk order and the number of
+// leading entries done is saved as files complete. Files an earlier run
+// finished are skipped; directories are always stamped again, which is cheap
+// and leaves the same result.
+//
+// Once `cancelled` is set no further entries are stamped, directories
+// included, and the checkpoint is kept for a later run to resume from.
+//
+// `completed` is called on the worker for each entry stamped, with the error
+// it failed with
+pub fn stamp_tree(
+    root: &Path,
+    btime: Timestamp,
+    options: WalkOptions,
+    concurrency: usize,
+    checkpoint: Option<Checkpoint>,
+    cancelled: &AtomicBool,
+    completed: &(dyn Fn(&Path, Option<&io::Error>) + Sync),
+) -> TreeResult {
+    let written = Mutex::new(TreeResult::default());
+    let errors = Mutex::new(Vec::new());
+    let stamp = |path: PathBuf| match crate::set_btime(&path, btime) {
+        Ok(backend) => {
+            written.lock().unwrap().written(backend);
+            completed(&path, None);
+        }
+        Err(error) => {
+            completed(&path, Some(&error));
+            errors.lock().unwrap().push(WalkError { path, error });
+        }
+    };
+
+    let resumed = checkpoint.as_ref().map_or(0, Checkpoint::resume_from);
+    let progress = Mutex::new((Watermark::starting_at(resumed), checkpoint));
+    let complete = |i: usize| {
+        let mut progress = progress.lock().unwrap();
+        let (watermark, checkpoint) = &mut *progress;
+        if let Some(err) = checkpoint::update(checkpoint, watermark.complete(i)) {
+            errors.lock().unwrap().push(err);
+        }
+    };
+
+    let mut walker = Walker::new(root, options);
+    let mut dirs = vec![root.to_path_buf()];
+    let files = walker.by_ref().take_while(|_| !cancelled.load(Ordering::Relaxed)).enumerate().filter_map(|(i, result)| {
+        let file = match result {
+            Ok(entry) if entry.file_type == EntryType::Dir => {
+                dirs.push(entry.path);
+                None
+            }
+            Ok(entry) if entry.file_type.is_link() => None,
+            Ok(entry) if i >= resumed => Some(entry.path),
+            Ok(_) => return None,
+            Err(err) => {
+                errors.lock().unwrap().push(err);
+                None
+            }
+        };
+        if file.is_none() && i >= resumed {
+            complete(i);
+        }
+        file.map(|path| (i, path))
+    });
+    pool::for_each_bounded(files, concurrency, |(i, path)| {
+        stamp(path);
+        complete(i);
+    });
+
+    // Directories come out of the walk parents first
+    if !cancelled.load(Ordering::Relaxed) {
+        for dir in dirs.into_iter().rev() {
+            stamp(dir);
+        }
+        if let Some(err) = checkpoint::finish(progress.into_inner().unwrap().1) {
+            errors.lock().unwrap().push(err);
+        }
+    }
+
+    let written = written.into_inner().unwrap();
+    TreeResult {
+        processed: written.processed,
+        errors: errors.into_inner().unwrap(),
+        truncated: walker.truncated().to_vec(),
+        resumed,
+        backends: written.backends,
+    }
+}
+
+// Set a separate birth time on each path, on `concurrency` workers
+pub fn stamp_paths(items: Vec<(PathBuf, Timestamp)>, concurrency: usize) -> TreeResult {
+    let items = items.into_iter().map(|(path, btime)| (path, FileTimes { btime: Some(btime), ..FileTimes::default() }));
+    set_paths(items.collect(), concurrency)
+}
+
+// Set separate timestamps on each path, on `concurrency` workers
+pub fn set_paths(items: Vec<(PathBuf, FileTimes)>, concurrency: usize) -> TreeResult {
+    let result = Mutex::new(TreeResult::default());
+    pool::for_each_bounded(items.into_iter(), concurrency, |(path, times)| match crate::set_times(&path, &times) {
+        Ok(backend) => result.lock().unwrap().written(backend),
+        Err(error) => result.lock().unwrap().errors.push(WalkError { path, error }),
+    });
+    result.into_inner().unwrap()
+}
@@ -0,0 +1,144 @@
+// Undo logs. While one is open every timestamp write is appended to it as a
+// line of JSON with the times it replaced, and undo() replays a log in
+// reverse to put them back:
+//
+//   {"path": "/photos/a.jpg", "follow": true, "time": "1700000000000000000",
+//    "old": {"btime": "1600000000000000000"}, "new": {"btime": "1500000000000000000"}}
+//
+// Times are nanoseconds since the Unix epoch as strings, like in NDJSON
+// manifests. Only the fields a write set are recorded
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::beneath;
+use crate::config;
+use crate::json::{self, Value};
+use crate::times::{self, FileTimes, Timestamp};
+use crate::tree::TreeResult;
+use crate::walk::WalkError;
+
+struct Log {
+    path: PathBuf,
+    file: File,
+}
+
+static LOG: Mutex<Option<Log>> = Mutex::new(None);
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+// Start appending to the log at path, or stop logging with None
+pub fn open(path: Option<&Path>) -> io::Result<()> {
+    let log = match path {
+        Some(path) => Some(Log { path: path.to_path_buf(), file: OpenOptions::new().create(true).append(true).open(path)? }),
+        None => None,
+    };
+    let mut current = LOG.lock().unwrap();
+    ACTIVE.store(log.is_some(), Ordering::Relaxed);
+    *current = log;
+    Ok(())
+}
+
+pub fn path() -> Option<PathBuf> {
+    LOG.lock().unwrap().as_ref().map(|log| log.path.clone())
+}
+
+fn write_fields(line: &mut String, times: &FileTimes) {
+    line.push('{');
+    let mut separator = "";
+    for (key, timestamp) in [("btime", times.btime), ("mtime", times.mtime), ("atime", times.atime)] {
+        if let Some(timestamp) = timestamp {
+            line.push_str(&format!("{}\"{}\": \"{}\"", separator, key, timestamp.as_nanos()));
+            separator = ", ";
+        }
+    }
+    line.push('}');
+}
+
+// Run a write and log it when a log is open. The times it replaces are read
+// right before; a write that fails is not logged, one that cannot be logged
+// is reported as failed although the times were changed. A path that is not
+// valid Unicode cannot be logged as text and is refused before the write
+pub fn record<T>(path: &Path, times: &FileTimes, follow: bool, write: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return write();
+    }
+    // A file beneath keeps open is logged under the path it was found at
+    let found = beneath::found_at(path);
+    let Some(text) = found.to_str() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the path is not valid Unicode and cannot be recorded in the undo log"));
+    };
+    let current = times::get_times(path, follow)?;
+    let written = write()?;
+
+    let old = FileTimes {
+        btime: times.btime.and(current.btime),
+        mtime: times.mtime.and(current.mtime),
+        atime: times.atime.and(current.atime),
+        ctime: None,
+    };
+    let new = times.quantize(config::get().quantize).unwrap_or(*times);
+    let mut line = String::from("{\"path\": ");
+    json::write_string(&mut line, text);
+    line.push_str(&format!(
+        ", \"follow\": {}, \"time\": \"{}\", \"old\": ",
+        follow,
+        Timestamp::from_system_time(SystemTime::now()).as_nanos()
+    ));
+    write_fields(&mut line, &old);
+    line.push_str(", \"new\": ");
+    write_fields(&mut line, &new);
+    line.push_str("}\n");
+
+    if let Some(log) = LOG.lock().unwrap().as_mut() {
+        log.file.write_all(line.as_bytes())?;
+    }
+    Ok(written)
+}
+
+// One logged write: the path, whether it followed links, and the old times
+fn parse_line(line: &str) -> Result<(PathBuf, bool, FileTimes), String> {
+    let item = json::parse(line)?;
+    let path = item.get("path").and_then(Value::as_str).ok_or("missing path")?;
+    let follow = !matches!(item.get("follow"), Some(Value::Bool(false)));
+    let old = item.get("old").ok_or("missing old")?;
+    let time = |key: &str| -> Result<Option<Timestamp>, String> {
+        match old.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(nanos)) => {
+                nanos.parse::<i128>().ok().and_then(Timestamp::from_nanos).map(Some).ok_or_else(|| format!("invalid {}", key))
+            }
+            Some(_) => Err(format!("invalid {}", key)),
+        }
+    };
+    let times = FileTimes { btime: time("btime")?, mtime: time("mtime")?, atime: time("atime")?, ctime: None };
+    Ok((PathBuf::from(path), follow, times))
+}
+
+// Put back the times of every logged write, the last one first, so a path
+// written several times ends with the times it had before the first write.
+// The writes go through `write`, which does not log them
+pub fn replay(log_path: &Path, write: impl Fn(&Path, &FileTimes, bool) -> io::Result<&'static str>) -> io::Result<TreeResult> {
+    let text = fs::read_to_string(log_path)?;
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_line(line) {
+            Ok(entry) => entries.push(entry),
+            Err(message) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, message))),
+        }
+    }
+
+    let mut result = TreeResult::default();
+    for (path, follow, times) in entries.into_iter().rev() {
+        match write(&path, &times, follow) {
+            Ok(backend) => result.written(backend),
+            Err(error) => result.errors.push(WalkError { path, error }),
+        }
+    }
+    Ok(result)
+}
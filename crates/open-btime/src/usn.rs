@@ -0,0 +1,189 @@
+// Reading the NTFS change journal (USN journal) to learn which watched files
+// had their basic information, which includes the timestamps, rewritten since
+// the last read, without polling every file
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::{Path, PathBuf};
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{ERROR_NOT_SUPPORTED, FILETIME, HANDLE};
+use windows::Win32::Storage::FileSystem::{GetVolumeNameForVolumeMountPointW, GetVolumePathNameW};
+use windows::Win32::System::Ioctl::{
+    FSCTL_QUERY_USN_JOURNAL, FSCTL_READ_USN_JOURNAL, READ_USN_JOURNAL_DATA_V0, USN_JOURNAL_DATA_V0,
+    USN_REASON_BASIC_INFO_CHANGE,
+};
+use windows::Win32::System::IO::DeviceIoControl;
+
+use crate::times::Timestamp;
+use crate::volume;
+use crate::walk::file_id;
+use crate::win::{filetime_to_timestamp, io_error};
+
+// Where a previous read stopped, as returned by read_changes
+#[derive(Clone, Copy, Debug)]
+pub struct Cursor {
+    pub journal_id: u64,
+    pub next_usn: i64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub path: PathBuf,
+    // The latest record for the path and when it was written
+    pub usn: i64,
+    pub time: Option<Timestamp>,
+}
+
+#[derive(Debug)]
+pub struct ReadResult {
+    pub cursor: Cursor,
+    // Records since the previous cursor were lost because the journal was
+    // recreated or has wrapped around; watched files have to be checked directly
+    pub lost: bool,
+    pub changes: Vec<Change>,
+}
+
+// Open the volume that contains path, e.g. \\?\Volume{guid} for C:\data
+fn open_volume(path: &Path) -> io::Result<File> {
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut mount_point = [0u16; 1024];
+    unsafe { GetVolumePathNameW(PCWSTR(wide.as_ptr()), &mut mount_point) }.map_err(io_error)?;
+    let mut volume_name = [0u16; 64];
+    unsafe { GetVolumeNameForVolumeMountPointW(PCWSTR(mount_point.as_ptr()), &mut volume_name) }.map_err(io_error)?;
+
+    // With the trailing backslash the name is the root directory, without it the volume
+    let length = volume_name.iter().position(|&c| c == 0).unwrap_or(volume_name.len());
+    let name = String::from_utf16_lossy(&volume_name[..length]);
+    OpenOptions::new().read(true).open(name.trim_end_matches('\\'))
+}
+
+fn handle(file: &File) -> HANDLE {
+    HANDLE(file.as_raw_handle() as isize)
+}
+
+fn query_journal(volume: &File) -> io::Result<USN_JOURNAL_DATA_V0> {
+    let mut data = USN_JOURNAL_DATA_V0::default();
+    unsafe {
+        DeviceIoControl(
+            handle(volume),
+            FSCTL_QUERY_USN_JOURNAL,
+            None,
+            0,
+            Some(&mut data as *mut USN_JOURNAL_DATA_V0 as *mut c_void),
+            std::mem::size_of::<USN_JOURNAL_DATA_V0>() as u32,
+            None,
+            None,
+        )
+    }
+    .map_err(io_error)?;
+    Ok(data)
+}
+
+// Report basic-info changes of the watched paths since `since`. Without a
+// cursor reading starts at the end of the journal, which only establishes
+// the cursor for the next call. All watched paths must be on the volume of
+// the first one. Opening the volume needs administrator rights. ReFS
+// journals only hold V3 records with 128-bit ids, which the 64-bit ids of
+// the watched files cannot be matched against, so they are not read
+pub fn read_changes(watched: &[PathBuf], since: Option<Cursor>) -> io::Result<ReadResult> {
+    let Some(first) = watched.first() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no paths to watch"));
+    };
+    if volume::is_refs(first) {
+        return Err(io::Error::from_raw_os_error(ERROR_NOT_SUPPORTED.0 as i32));
+    }
+    let volume = open_volume(first)?;
+    let journal = query_journal(&volume)?;
+
+    let mut ids = HashMap::new();
+    for path in watched {
+        let (_, index) = file_id(path)?;
+        ids.insert(index, path);
+    }
+
+    let (mut start, lost) = match since {
+        None => (journal.NextUsn, false),
+        Some(cursor) if cursor.journal_id != journal.UsnJournalID => (journal.NextUsn, true),
+        Some(cursor) if cursor.next_usn < journal.FirstUsn => (journal.FirstUsn, true),
+        Some(cursor) => (cursor.next_usn, false),
+    };
+
+    let mut latest: HashMap<u64, Change> = HashMap::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let request = READ_USN_JOURNAL_DATA_V0 {
+            StartUsn: start,
+            ReasonMask: USN_REASON_BASIC_INFO_CHANGE,
+            ReturnOnlyOnClose: 0,
+            Timeout: 0,
+            BytesToWaitFor: 0,
+            UsnJournalID: journal.UsnJournalID,
+        };
+        let mut returned = 0u32;
+        unsafe {
+            DeviceIoControl(
+                handle(&volume),
+                FSCTL_READ_USN_JOURNAL,
+                Some(&request as *const READ_USN_JOURNAL_DATA_V0 as *const c_void),
+                std::mem::size_of::<READ_USN_JOURNAL_DATA_V0>() as u32,
+                Some(buf.as_mut_ptr() as *mut c_void),
+                buf.len() as u32,
+                Some(&mut returned),
+                None,
+            )
+        }
+        .map_err(io_error)?;
+
+        // The output starts with the USN to continue from, followed by records
+        let returned = returned as usize;
+        if returned < 8 {
+            break;
+        }
+        let next = i64::from_ne_bytes(buf[..8].try_into().unwrap());
+
+        let mut offset = 8;
+        while offset + 60 <= returned {
+            let record = &buf[offset..returned];
+            let read_u64 = |at: usize| u64::from_ne_bytes(record[at..at + 8].try_into().unwrap());
+            let length = u32::from_ne_bytes(record[..4].try_into().unwrap()) as usize;
+            let major_version = u16::from_ne_bytes([record[4], record[5]]);
+            if length == 0 {
+                break;
+            }
+
+            // struct USN_RECORD_V2 { u32 RecordLength; u16 MajorVersion, MinorVersion;
+            // u64 FileReferenceNumber, ParentFileReferenceNumber; i64 Usn, TimeStamp; u32 Reason; ... }
+            // V3 records with 128-bit ids only come from ReFS
+            if major_version == 2 {
+                let file_reference = read_u64(8);
+                if let Some(path) = ids.get(&file_reference) {
+                    let usn = read_u64(24) as i64;
+                    let stamp = read_u64(32);
+                    let time = filetime_to_timestamp(FILETIME {
+                        dwLowDateTime: stamp as u32,
+                        dwHighDateTime: (stamp >> 32) as u32,
+                    });
+                    latest.insert(file_reference, Change { path: (*path).clone(), usn, time });
+                }
+            }
+            offset += length;
+        }
+
+        if next == start {
+            break;
+        }
+        start = next;
+    }
+
+    let mut changes: Vec<Change> = latest.into_values().collect();
+    changes.sort_by_key(|change| change.usn);
+    Ok(ReadResult {
+        cursor: Cursor { journal_id: journal.UsnJournalID, next_usn: start },
+        lost,
+        changes,
+    })
+}
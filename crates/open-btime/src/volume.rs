@@ -0,0 +1,382 @@
+// What is known about the filesystem a path lives on, and a probe that
+// checks whether birth time writes there actually stick. SMB and NFS
+// servers differ in that: some store the time, some accept the write and
+// drop it, some reject it
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::oserror;
+use crate::times::{self, FileTimes, Timestamp};
+
+#[derive(Clone, Debug)]
+pub struct Filesystem {
+    // e.g. "ntfs", "apfs", "ext4", "nfs", "smb", or the raw type when unknown
+    pub name: String,
+    // Served over the network rather than backed by a local device
+    pub remote: bool,
+}
+
+#[cfg(target_os = "linux")]
+pub fn filesystem(path: &Path) -> io::Result<Filesystem> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Magic numbers from linux/magic.h
+    let (name, remote) = match stat.f_type as u32 {
+        0xEF53 => ("ext4", false),
+        0x9123_683E => ("btrfs", false),
+        0x5846_5342 => ("xfs", false),
+        0x2FC1_2FC1 => ("zfs", false),
+        0x0102_1994 => ("tmpfs", false),
+        0x794C_7630 => ("overlayfs", false),
+        0x4D44 => ("vfat", false),
+        0x2011_BAB0 => ("exfat", false),
+        0x5346_544E => ("ntfs", false),
+        0x6573_5546 => ("fuse", false),
+        0x6969 => ("nfs", true),
+        0x517B => ("smb", true),
+        0xFF53_4D42 => ("cifs", true),
+        0xFE53_4D42 => ("smb2", true),
+        0x00C3_6400 => ("ceph", true),
+        other => return Ok(Filesystem { name: format!("0x{:x}", other), remote: false }),
+    };
+    Ok(Filesystem { name: name.to_string(), remote })
+}
+
+#[cfg(target_os = "macos")]
+pub fn filesystem(path: &Path) -> io::Result<Filesystem> {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let name = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) }.to_string_lossy().into_owned();
+    Ok(Filesystem { name, remote: stat.f_flags & libc::MNT_LOCAL as u32 == 0 })
+}
+
+#[cfg(target_os = "windows")]
+pub fn filesystem(path: &Path) -> io::Result<Filesystem> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Storage::FileSystem::{
+        GetDriveTypeW, GetVolumeInformationByHandleW, GetVolumePathNameW, FILE_FLAG_BACKUP_SEMANTICS,
+    };
+
+    use crate::win;
+
+    // From WinBase.h, the constant lives in a windows crate feature not otherwise needed
+    const DRIVE_REMOTE: u32 = 4;
+
+    let file = fs::OpenOptions::new().access_mode(0).custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0).open(path)?;
+    let mut name = [0u16; 64];
+    unsafe { GetVolumeInformationByHandleW(HANDLE(file.as_raw_handle() as isize), None, None, None, None, Some(&mut name)) }
+        .map_err(win::io_error)?;
+    let length = name.iter().position(|&unit| unit == 0).unwrap_or(name.len());
+    let name = String::from_utf16_lossy(&name[..length]).to_lowercase();
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut root = [0u16; 1024];
+    unsafe { GetVolumePathNameW(PCWSTR(wide.as_ptr()), &mut root) }.map_err(win::io_error)?;
+    let remote = unsafe { GetDriveTypeW(PCWSTR(root.as_ptr())) } == DRIVE_REMOTE;
+    Ok(Filesystem { name, remote })
+}
+
+// ReFS stores and reports birth times like NTFS but leaves out NTFS
+// features, the 64-bit change journal records among them
+#[cfg(target_os = "windows")]
+pub fn is_refs(path: &Path) -> bool {
+    filesystem(path).is_ok_and(|filesystem| filesystem.name == "refs")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+pub fn filesystem(_path: &Path) -> io::Result<Filesystem> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "filesystem type not available"))
+}
+
+// The volume a path lives on, as its device number or, on Windows, its
+// volume serial number
+#[cfg(unix)]
+pub fn id(path: &Path) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(fs::metadata(path)?.dev())
+}
+
+#[cfg(target_os = "windows")]
+pub fn id(path: &Path) -> io::Result<u64> {
+    let info = crate::win::file_information(&crate::win::open_query(path, true)?)?;
+    Ok(info.dwVolumeSerialNumber as u64)
+}
+
+#[cfg(not(any(unix, target_os = "windows")))]
+pub fn id(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "volume identity not available"))
+}
+
+// exFAT keeps creation and modification times in 10ms steps and access
+// times in 2s steps, as local times with their offset from UTC in 15 minute
+// steps. The Windows, macOS and Linux drivers fill the offset in on write
+// and take it back out on read, so what they write reads back as the same
+// UTC time in any timezone, cut down to those steps. Times on volumes from
+// cameras and other devices that leave the offset unset are taken as local
+// times of whoever reads them, which no write through the driver fixes
+const EXFAT_STEP_NANOS: i128 = 10_000_000;
+const EXFAT_ACCESS_STEP_NANOS: i128 = 2_000_000_000;
+
+fn floor_to(timestamp: Option<Timestamp>, step: i128) -> Option<Timestamp> {
+    let nanos = timestamp?.as_nanos();
+    Timestamp::from_nanos(nanos - nanos.rem_euclid(step))
+}
+
+// The times a filesystem keeps when it is given these, cut down to the steps
+// it keeps them in. Filesystems whose limits are unknown are taken to keep
+// what they are given. Times outside the range are left to the write to
+// refuse or clamp
+pub fn stored_times(filesystem: &Filesystem, times: &FileTimes) -> FileTimes {
+    let Some(Limits { granularity, .. }) = limits(filesystem) else {
+        return *times;
+    };
+    FileTimes {
+        btime: floor_to(times.btime, granularity.btime),
+        mtime: floor_to(times.mtime, granularity.mtime),
+        atime: floor_to(times.atime, granularity.atime),
+        ctime: times.ctime,
+    }
+}
+
+// How finely a filesystem keeps each of the times, in nanoseconds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Granularity {
+    pub btime: i128,
+    pub mtime: i128,
+    pub atime: i128,
+    pub ctime: i128,
+}
+
+impl Granularity {
+    const fn all(step: i128) -> Granularity {
+        Granularity { btime: step, mtime: step, atime: step, ctime: step }
+    }
+}
+
+// The steps a filesystem keeps times in and the range it can hold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    pub granularity: Granularity,
+    pub earliest: Timestamp,
+    pub latest: Timestamp,
+}
+
+const SECOND: i128 = 1_000_000_000;
+
+// FAT dates count from 1980 to 2107 in local time. FAT keeps creation times
+// in 10ms steps, modification times in 2s steps and access times as a date;
+// it has no change time, which drivers report as the modification time
+const FAT: Limits = Limits {
+    granularity: Granularity { btime: EXFAT_STEP_NANOS, mtime: 2 * SECOND, atime: 86_400 * SECOND, ctime: 2 * SECOND },
+    earliest: Timestamp { sec: 315_532_800, nsec: 0 },
+    latest: Timestamp { sec: 4_354_819_198, nsec: 0 },
+};
+const EXFAT: Limits = Limits {
+    granularity: Granularity { btime: EXFAT_STEP_NANOS, mtime: EXFAT_STEP_NANOS, atime: EXFAT_ACCESS_STEP_NANOS, ctime: EXFAT_STEP_NANOS },
+    earliest: Timestamp { sec: 315_532_800, nsec: 0 },
+    latest: Timestamp { sec: 4_354_819_199, nsec: 990_000_000 },
+};
+// FILETIME, 100ns steps from 1601 in a signed 64-bit count
+const NTFS: Limits = Limits {
+    granularity: Granularity::all(100),
+    earliest: Timestamp { sec: -11_644_473_600, nsec: 0 },
+    latest: Timestamp { sec: 910_692_730_085, nsec: 477_580_700 },
+};
+// Unsigned 32-bit seconds from 1904
+const HFS: Limits = Limits {
+    granularity: Granularity::all(SECOND),
+    earliest: Timestamp { sec: -2_082_844_800, nsec: 0 },
+    latest: Timestamp { sec: 2_212_122_495, nsec: 0 },
+};
+// Signed 64-bit nanoseconds from 1970
+const APFS: Limits = Limits {
+    granularity: Granularity::all(1),
+    earliest: Timestamp { sec: -9_223_372_037, nsec: 145_224_192 },
+    latest: Timestamp { sec: 9_223_372_036, nsec: 854_775_807 },
+};
+// 32-bit seconds with two epoch bits borrowed from the nanoseconds, in
+// inodes of 256 bytes, the default since ext4 came out
+const EXT4: Limits = Limits {
+    granularity: Granularity::all(1),
+    earliest: Timestamp { sec: -2_147_483_648, nsec: 0 },
+    latest: Timestamp { sec: 15_032_385_535, nsec: 0 },
+};
+// With the bigtime feature, the default since xfsprogs 5.15
+const XFS: Limits = Limits {
+    granularity: Granularity::all(1),
+    earliest: Timestamp { sec: -2_147_483_648, nsec: 0 },
+    latest: Timestamp { sec: 16_299_260_415, nsec: 0 },
+};
+// Signed 64-bit seconds and nanoseconds
+const WIDE: Limits = Limits {
+    granularity: Granularity::all(1),
+    earliest: Timestamp { sec: i64::MIN, nsec: 0 },
+    latest: Timestamp { sec: i64::MAX, nsec: 999_999_999 },
+};
+
+// What a filesystem can keep, by the names filesystem() gives on each
+// platform. None for network and unknown filesystems, where it depends on
+// what is behind them
+pub fn limits(filesystem: &Filesystem) -> Option<Limits> {
+    let mut limits = match filesystem.name.as_str() {
+        "vfat" | "msdos" | "fat" | "fat32" => FAT,
+        "exfat" => EXFAT,
+        "ntfs" | "refs" => NTFS,
+        "hfs" => HFS,
+        "apfs" => APFS,
+        "ext4" => EXT4,
+        "xfs" => XFS,
+        "btrfs" | "zfs" | "tmpfs" => WIDE,
+        _ => return None,
+    };
+    // Windows hands times over in 100ns steps whatever the filesystem keeps
+    let step = |step: i128| step.max(times::RESOLUTION_NANOS);
+    let Granularity { btime, mtime, atime, ctime } = limits.granularity;
+    limits.granularity = Granularity { btime: step(btime), mtime: step(mtime), atime: step(atime), ctime: step(ctime) };
+    Some(limits)
+}
+
+// Whether two paths name the same file
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (crate::walk::file_id(a), crate::walk::file_id(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn swap_case(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_lowercase() { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() })
+        .collect()
+}
+
+// Whether names are matched case-sensitively where path is, found by looking
+// up path with the case of its last component that has letters swapped.
+// None when no component has ASCII letters. Case sensitivity can differ
+// per directory, as with case-folding ext4 directories or directories
+// flagged case-sensitive on NTFS
+pub fn case_sensitive(path: &Path) -> Option<bool> {
+    let mut current = path;
+    loop {
+        if let Some(name) = current.file_name()?.to_str() {
+            let swapped = swap_case(name);
+            if swapped != name {
+                return Some(!same_file(current, &current.with_file_name(swapped)));
+            }
+        }
+        current = current.parent()?;
+    }
+}
+
+// The key paths are matched by on case-insensitive volumes
+pub fn fold_case(path: &str) -> String {
+    path.to_lowercase()
+}
+
+// How a birth time write on the probed filesystem went
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BtimeWrites {
+    // The time read back is the one written
+    Stored,
+    // The write succeeded but the time read back is different
+    Ignored,
+    // The write failed
+    Rejected,
+    // The backend has no way to set birth times, as on Linux
+    Unsupported,
+}
+
+impl BtimeWrites {
+    pub fn name(self) -> &'static str {
+        match self {
+            BtimeWrites::Stored => "stored",
+            BtimeWrites::Ignored => "ignored",
+            BtimeWrites::Rejected => "rejected",
+            BtimeWrites::Unsupported => "unsupported",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Probe {
+    pub filesystem: Filesystem,
+    // Whether the filesystem reports birth times at all
+    pub get_btime: bool,
+    pub set_btime: BtimeWrites,
+    pub case_sensitive: bool,
+    // Why a write was rejected
+    pub error: Option<io::Error>,
+}
+
+impl Clone for Probe {
+    fn clone(&self) -> Probe {
+        Probe { filesystem: self.filesystem.clone(), error: self.error.as_ref().map(oserror::copy), ..*self }
+    }
+}
+
+static PROBES: AtomicU64 = AtomicU64::new(0);
+
+fn probe_path(dir: &Path) -> PathBuf {
+    dir.join(format!(".open-btime-probe-{}-{}", process::id(), PROBES.fetch_add(1, Ordering::Relaxed)))
+}
+
+// Create a temporary file in dir, set its birth time to a fixed value and
+// read it back, and look the file up in a different case. The file is
+// removed afterwards
+pub fn probe(dir: &Path) -> io::Result<Probe> {
+    let filesystem = filesystem(dir)?;
+    let path = probe_path(dir);
+    fs::File::create_new(&path)?;
+    let result = probe_file(&path);
+    let case_sensitive = case_sensitive(&path).unwrap_or(true);
+    let _ = fs::remove_file(&path);
+    let (get_btime, set_btime, error) = result?;
+    Ok(Probe { filesystem, get_btime, set_btime, case_sensitive, error })
+}
+
+fn probe_file(path: &Path) -> io::Result<(bool, BtimeWrites, Option<io::Error>)> {
+    let get_btime = times::get_times(path, true)?.btime.is_some();
+    if !crate::backend::current().capabilities().set_btime {
+        return Ok((get_btime, BtimeWrites::Unsupported, None));
+    }
+
+    // 2001-02-03T04:05:06Z, far from the file's real creation time and
+    // representable on every filesystem that stores birth times
+    let btime = Timestamp { sec: 981_173_106, nsec: 0 };
+    if let Err(err) = crate::set_times(path, &FileTimes { btime: Some(btime), ..FileTimes::default() }) {
+        return Ok((get_btime, BtimeWrites::Rejected, Some(err)));
+    }
+    let read_back = times::get_times(path, true)?.btime;
+    let set_btime = if read_back == Some(btime) { BtimeWrites::Stored } else { BtimeWrites::Ignored };
+    Ok((get_btime, set_btime, None))
+}
@@ -0,0 +1,368 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::dir::{self, Stat};
+use crate::glob::Glob;
+use crate::predicate::Predicate;
+use crate::times::{FileTimes, Timestamp};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryType {
+    File,
+    Dir,
+    Symlink,
+    // An NTFS junction, a directory reparse point that redirects to another directory
+    Junction,
+    Other,
+}
+
+impl EntryType {
+    pub fn from_file_type(file_type: fs::FileType) -> EntryType {
+        if file_type.is_symlink() {
+            EntryType::Symlink
+        } else if file_type.is_dir() {
+            EntryType::Dir
+        } else if file_type.is_file() {
+            EntryType::File
+        } else {
+            EntryType::Other
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EntryType::File => "file",
+            EntryType::Dir => "dir",
+            EntryType::Symlink => "symlink",
+            EntryType::Junction => "junction",
+            EntryType::Other => "other",
+        }
+    }
+
+    // Entries that point at another path, which the setters would follow
+    pub fn is_link(self) -> bool {
+        matches!(self, EntryType::Symlink | EntryType::Junction)
+    }
+
+    pub fn from_name(name: &str) -> Option<EntryType> {
+        match name {
+            "file" => Some(EntryType::File),
+            "dir" => Some(EntryType::Dir),
+            "symlink" => Some(EntryType::Symlink),
+            "junction" => Some(EntryType::Junction),
+            "other" => Some(EntryType::Other),
+            _ => None,
+        }
+    }
+}
+
+// Whether the walker resolves symlinks it finds below the root
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FollowSymlinks {
+    // Report links as links
+    #[default]
+    Never,
+    // Descend into links to directories, report links to anything else as links
+    Dirs,
+    // Report every link as its target
+    Always,
+}
+
+impl FollowSymlinks {
+    pub fn from_name(name: &str) -> Option<FollowSymlinks> {
+        match name {
+            "never" => Some(FollowSymlinks::Never),
+            "dirs" => Some(FollowSymlinks::Dirs),
+            "always" => Some(FollowSymlinks::Always),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FollowSymlinks::Never => "never",
+            FollowSymlinks::Dirs => "dirs",
+            FollowSymlinks::Always => "always",
+        }
+    }
+}
+
+// A file or directory found below the walk root, with its own timestamps.
+// For a followed symlink file_type and times are those of the target and
+// symlink is set, so callers can decide whether to stamp the link or the target.
+// placeholder marks an evicted iCloud Drive item on macOS
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub file_type: EntryType,
+    pub times: FileTimes,
+    pub size: u64,
+    pub symlink: bool,
+    pub placeholder: bool,
+}
+
+// A directory that could not be listed or an entry that could not be stat'ed
+#[derive(Debug)]
+pub struct WalkError {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+// Which of the walked entries are yielded. Directories are always descended
+// into, the filter only decides what is reported
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    // Lowercase extensions without the leading dot
+    pub extensions: Option<Vec<String>>,
+    pub min_mtime: Option<Timestamp>,
+    pub max_mtime: Option<Timestamp>,
+    pub types: Option<Vec<EntryType>>,
+    // Conditions given as an object, checked after the fields above
+    pub predicate: Option<Predicate>,
+}
+
+impl Filter {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        if let Some(types) = &self.types {
+            if !types.contains(&entry.file_type) {
+                return false;
+            }
+        }
+
+        if let Some(extensions) = &self.extensions {
+            let extension = entry.path.extension().map(|e| e.to_string_lossy().to_lowercase());
+            match extension {
+                Some(extension) if extensions.contains(&extension) => {}
+                _ => return false,
+            }
+        }
+
+        if self.min_mtime.is_some() || self.max_mtime.is_some() {
+            let Some(mtime) = entry.times.mtime else {
+                return false;
+            };
+            if self.min_mtime.is_some_and(|min| mtime < min) || self.max_mtime.is_some_and(|max| mtime > max) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WalkOptions {
+    pub filter: Filter,
+    // Entries matching any of these are skipped, directories are not descended into
+    pub exclude: Vec<Glob>,
+    pub follow_symlinks: FollowSymlinks,
+    // Descend into junctions, which are reported as junctions and left alone otherwise
+    pub follow_junctions: bool,
+    // Directories at this depth are reported but not descended into
+    pub max_depth: Option<usize>,
+    // Report iCloud placeholders under the path of the item they stand in for
+    pub icloud_names: bool,
+}
+
+// Depth-first walk below a root directory. Entries of each directory are
+// visited in name order so repeated walks over the same tree agree
+pub struct Walker {
+    options: WalkOptions,
+    root: PathBuf,
+    started: bool,
+    // Entries still to be visited with what the listing told about them, next one last
+    stack: Vec<(PathBuf, usize, io::Result<Stat>)>,
+    // Listing failure of the directory yielded last, reported right after it
+    deferred: Option<WalkError>,
+    // (device, inode) of every directory entered, to stop symlink loops
+    visited: HashSet<(u64, u64)>,
+    truncated: Vec<PathBuf>,
+}
+
+impl Walker {
+    pub fn new(root: &Path, options: WalkOptions) -> Walker {
+        Walker {
+            options,
+            root: root.to_path_buf(),
+            started: false,
+            stack: Vec::new(),
+            deferred: None,
+            visited: HashSet::new(),
+            truncated: Vec::new(),
+        }
+    }
+
+    // Directories that were not descended into because of max_depth
+    pub fn truncated(&self) -> &[PathBuf] {
+        &self.truncated
+    }
+
+    // Queue the children of a directory so they are visited before its siblings
+    fn push_children(&mut self, dir: &Path, depth: usize) -> Result<(), WalkError> {
+        let mut children = dir::read_dir(dir).map_err(|error| WalkError { path: dir.to_path_buf(), error })?;
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+        self.stack.extend(children.into_iter().rev().map(|child| (dir.join(child.name), depth + 1, child.stat)));
+        Ok(())
+    }
+
+    // Record a directory about to be descended into, false if it was seen before
+    fn enter(&mut self, dir: &Path, stat: &Stat) -> io::Result<bool> {
+        if !self.follows_links() {
+            return Ok(true);
+        }
+        let id = match stat.id {
+            Some(id) => id,
+            None => file_id(dir)?,
+        };
+        Ok(self.visited.insert(id))
+    }
+
+    fn follows_links(&self) -> bool {
+        self.options.follow_symlinks != FollowSymlinks::Never || self.options.follow_junctions
+    }
+
+    // The /-separated path below the root that glob patterns are matched against
+    fn relative(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path).to_string_lossy();
+        if cfg!(target_os = "windows") { relative.replace('\\', "/") } else { relative.into_owned() }
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        if self.options.exclude.is_empty() {
+            return false;
+        }
+        let relative = self.relative(path);
+        let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        self.options.exclude.iter().any(|glob| glob.matches(&relative, &name))
+    }
+
+    fn is_reported(&self, entry: &Entry) -> bool {
+        let filter = &self.options.filter;
+        filter.matches(entry) && filter.predicate.as_ref().is_none_or(|predicate| predicate.matches(entry, &self.relative(&entry.path)))
+    }
+}
+
+impl Iterator for Walker {
+    type Item = Result<Entry, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            let root = self.root.clone();
+            if self.follows_links() {
+                if let Ok(metadata) = fs::metadata(&root) {
+                    let _ = self.enter(&root, &Stat::from_metadata(&metadata));
+                }
+            }
+            if self.options.max_depth == Some(0) {
+                self.truncated.push(root);
+                return None;
+            }
+            if let Err(err) = self.push_children(&root, 0) {
+                return Some(Err(err));
+            }
+        }
+
+        loop {
+            if let Some(err) = self.deferred.take() {
+                return Some(Err(err));
+            }
+
+            let (path, depth, stat) = self.stack.pop()?;
+            if self.is_excluded(&path) {
+                continue;
+            }
+            let mut stat = match stat {
+                Ok(stat) => stat,
+                Err(error) => return Some(Err(WalkError { path, error })),
+            };
+
+            // Resolve the link if the policy allows it for the target's type,
+            // dangling links stay links
+            let mut followed = false;
+            let follow = match stat.file_type {
+                EntryType::Symlink => self.options.follow_symlinks != FollowSymlinks::Never,
+                EntryType::Junction => self.options.follow_junctions,
+                _ => false,
+            };
+            if follow {
+                if let Ok(target) = fs::metadata(&path) {
+                    if target.is_dir() || self.options.follow_symlinks == FollowSymlinks::Always {
+                        stat = Stat::from_metadata(&target);
+                        followed = true;
+                    }
+                }
+            }
+
+            #[cfg(target_os = "macos")]
+            let placeholder = stat.file_type == EntryType::File && crate::icloud::is_placeholder(&path);
+            #[cfg(target_os = "macos")]
+            let path = if placeholder && self.options.icloud_names {
+                crate::icloud::item_path(&path).unwrap_or(path)
+            } else {
+                path
+            };
+            #[cfg(not(target_os = "macos"))]
+            let placeholder = false;
+
+            let entry = Entry {
+                file_type: stat.file_type,
+                times: stat.times,
+                size: stat.size,
+                symlink: followed,
+                placeholder,
+                path,
+                depth,
+            };
+
+            if entry.file_type == EntryType::Dir && self.options.max_depth.is_some_and(|max| depth >= max) {
+                self.truncated.push(entry.path.clone());
+            } else if entry.file_type == EntryType::Dir {
+                self.deferred = match self.enter(&entry.path, &stat) {
+                    Ok(true) => self.push_children(&entry.path, depth).err(),
+                    Ok(false) => Some(WalkError {
+                        path: entry.path.clone(),
+                        error: io::Error::other("symlink loop detected, not descending"),
+                    }),
+                    Err(error) => Some(WalkError { path: entry.path.clone(), error }),
+                };
+            }
+
+            if self.is_reported(&entry) {
+                return Some(Ok(entry));
+            }
+        }
+    }
+}
+
+// Identify a file by (volume serial, file index) when the listing did not
+// provide an id, which FindFirstFileEx does not
+#[cfg(target_os = "windows")]
+pub fn file_id(path: &Path) -> io::Result<(u64, u64)> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION, FILE_FLAG_BACKUP_SEMANTICS,
+    };
+
+    // Directories can only be opened with backup semantics
+    let file = fs::OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0)
+        .open(path)?;
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    unsafe { GetFileInformationByHandle(HANDLE(file.as_raw_handle() as isize), &mut info) }
+        .map_err(crate::win::io_error)?;
+
+    let index = ((info.nFileIndexHigh as u64) << 32) | info.nFileIndexLow as u64;
+    Ok((info.dwVolumeSerialNumber as u64, index))
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn file_id(_path: &Path) -> io::Result<(u64, u64)> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "file id not available"))
+}
@@ -0,0 +1,104 @@
+// Helpers shared by the Windows-specific code paths
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::mem;
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::Path;
+
+use windows::Win32::Foundation::{FILETIME, HANDLE};
+use windows::Win32::Storage::FileSystem::{
+    FileBasicInfo, GetFileInformationByHandle, GetFileInformationByHandleEx, SetFileTime, BY_HANDLE_FILE_INFORMATION,
+    FILE_BASIC_INFO, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT,
+};
+
+use crate::path;
+use crate::times::{FileTimes, Timestamp};
+
+// Convert a Unix timestamp to a FILETIME (100ns intervals since 1601)
+pub fn timestamp_to_filetime(timestamp: Timestamp) -> io::Result<FILETIME> {
+    let intervals = timestamp
+        .to_filetime()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Time not representable as FILETIME"))?;
+    Ok(FILETIME {
+        dwLowDateTime: (intervals & 0xFFFFFFFF) as u32,
+        dwHighDateTime: (intervals >> 32) as u32,
+    })
+}
+
+// Convert a FILETIME to a Unix timestamp, None for the "not set" value 0
+pub fn filetime_to_timestamp(filetime: FILETIME) -> Option<Timestamp> {
+    let intervals = ((filetime.dwHighDateTime as u64) << 32) | filetime.dwLowDateTime as u64;
+    if intervals == 0 {
+        return None;
+    }
+    Some(Timestamp::from_filetime(intervals))
+}
+
+// Set the times of a file opened with FILE_WRITE_ATTRIBUTES. Fields that are
+// None are left unchanged
+pub fn set_file_times(file: &File, times: &FileTimes) -> io::Result<()> {
+    let convert = |t: Option<Timestamp>| t.map(timestamp_to_filetime).transpose();
+    let creation = convert(times.btime)?;
+    let access = convert(times.atime)?;
+    let write = convert(times.mtime)?;
+
+    let handle = HANDLE(file.as_raw_handle() as isize);
+    let pointer = |ft: &Option<FILETIME>| ft.as_ref().map(|ft| ft as *const FILETIME);
+    unsafe { SetFileTime(handle, pointer(&creation), pointer(&access), pointer(&write)) }.map_err(io_error)
+}
+
+// Open a file or directory without any access to its data, for querying
+// it. With follow unset a symlink or junction is opened itself
+pub fn open_query(path: &Path, follow: bool) -> io::Result<File> {
+    let mut flags = FILE_FLAG_BACKUP_SEMANTICS;
+    if !follow {
+        flags |= FILE_FLAG_OPEN_REPARSE_POINT;
+    }
+    OpenOptions::new().access_mode(0).custom_flags(flags.0).open(path::literal(path))
+}
+
+// Volume serial, file index and link count among others
+pub fn file_information(file: &File) -> io::Result<BY_HANDLE_FILE_INFORMATION> {
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    unsafe { GetFileInformationByHandle(HANDLE(file.as_raw_handle() as isize), &mut info) }.map_err(io_error)?;
+    Ok(info)
+}
+
+pub fn file_index(info: &BY_HANDLE_FILE_INFORMATION) -> u64 {
+    ((info.nFileIndexHigh as u64) << 32) | info.nFileIndexLow as u64
+}
+
+// The NTFS ChangeTime, when the metadata of the file last changed, which
+// std does not expose
+pub fn change_time(file: &File) -> io::Result<Option<Timestamp>> {
+    let mut info = FILE_BASIC_INFO::default();
+    unsafe {
+        GetFileInformationByHandleEx(
+            HANDLE(file.as_raw_handle() as isize),
+            FileBasicInfo,
+            &mut info as *mut FILE_BASIC_INFO as *mut core::ffi::c_void,
+            mem::size_of::<FILE_BASIC_INFO>() as u32,
+        )
+    }
+    .map_err(io_error)?;
+    Ok((info.ChangeTime > 0).then(|| Timestamp::from_filetime(info.ChangeTime as u64)))
+}
+
+// Attributes of cloud files placeholders whose data is fetched on access
+const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x0004_0000;
+const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x0040_0000;
+
+pub fn is_placeholder(attributes: u32) -> bool {
+    attributes & (FILE_ATTRIBUTE_RECALL_ON_OPEN | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS) != 0
+}
+
+// Turn a windows-rs error back into the Win32 error code it was created from
+pub fn io_error(err: windows::core::Error) -> io::Error {
+    let code = err.code().0 as u32;
+    if code & 0xFFFF_0000 == 0x8007_0000 {
+        io::Error::from_raw_os_error((code & 0xFFFF) as i32)
+    } else {
+        io::Error::from_raw_os_error(code as i32)
+    }
+}
@@ -0,0 +1,142 @@
+// The threads the async exports run on. Node's libuv pool has four threads
+// by default and is shared with fs, dns and zlib, so a long btimeRecursiveAsync
+// there holds up unrelated work, and busy fs calls hold up ours. The addon
+// keeps a pool of its own instead, sized with configure({ threads }), whose
+// threads start on demand and then wait for the next job
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use neon::prelude::*;
+
+pub const DEFAULT_THREADS: usize = 4;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct State {
+    jobs: VecDeque<Job>,
+    size: usize,
+    // Threads started and not yet exited, and those waiting for a job
+    spawned: usize,
+    idle: usize,
+    threads: Vec<thread::JoinHandle<()>>,
+    // Set while the pool shuts down, so threads exit once the queue is empty
+    exiting: bool,
+}
+
+static STATE: Mutex<State> =
+    Mutex::new(State { jobs: VecDeque::new(), size: DEFAULT_THREADS, spawned: 0, idle: 0, threads: Vec::new(), exiting: false });
+static READY: Condvar = Condvar::new();
+
+pub fn size() -> usize {
+    STATE.lock().unwrap().size
+}
+
+// Threads over the new size exit once they finish their current job
+pub fn resize(size: usize) {
+    STATE.lock().unwrap().size = size.max(1);
+    READY.notify_all();
+}
+
+// Queue a job, starting a thread for it when none is idle and the pool has room
+pub fn spawn(job: impl FnOnce() + Send + 'static) {
+    let mut state = STATE.lock().unwrap();
+    state.jobs.push_back(Box::new(job));
+    if state.idle > 0 || state.spawned >= state.size {
+        READY.notify_one();
+        return;
+    }
+    state.spawned += 1;
+    match thread::Builder::new().name("open-btime".to_string()).spawn(work) {
+        Ok(thread) => state.threads.push(thread),
+        Err(_) => {
+            // Left for the threads already running; with none the job runs here
+            state.spawned -= 1;
+            if state.spawned == 0 {
+                let job = state.jobs.pop_back().unwrap();
+                drop(state);
+                job();
+            }
+        }
+    }
+}
+
+// Let the threads finish the queued jobs and exit, and wait for them. Called
+// when the last instance of the addon unloads; jobs queued afterwards start
+// new threads
+pub fn shutdown() {
+    let threads = {
+        let mut state = STATE.lock().unwrap();
+        state.exiting = true;
+        mem::take(&mut state.threads)
+    };
+    READY.notify_all();
+    for thread in threads {
+        let _ = thread.join();
+    }
+    STATE.lock().unwrap().exiting = false;
+}
+
+fn work() {
+    let mut state = STATE.lock().unwrap();
+    loop {
+        if state.spawned > state.size {
+            state.spawned -= 1;
+            return;
+        }
+        match state.jobs.pop_front() {
+            Some(job) => {
+                drop(state);
+                job();
+                state = STATE.lock().unwrap();
+            }
+            None if state.exiting => {
+                state.spawned -= 1;
+                return;
+            }
+            None => {
+                state.idle += 1;
+                state = READY.wait(state).unwrap();
+                state.idle -= 1;
+            }
+        }
+    }
+}
+
+// Run job on the pool and settle the returned promise with complete on the
+// JS thread, the pool's counterpart of cx.task(job).promise(complete)
+pub fn promise<'a, T, V>(
+    cx: &mut impl Context<'a>,
+    job: impl FnOnce() -> T + Send + 'static,
+    complete: impl FnOnce(TaskContext, T) -> JsResult<V> + Send + 'static,
+) -> Handle<'a, JsPromise>
+where
+    T: Send + 'static,
+    V: Value,
+{
+    let channel = cx.channel();
+    let (deferred, promise) = cx.promise();
+    spawn(move || {
+        let result = job();
+        // Fails only once the environment is torn down, when nobody is waiting
+        let _ = deferred.try_settle_with(&channel, move |cx| complete(cx, result));
+    });
+    promise
+}
+
+// Run job on the pool, then complete on the JS thread, the pool's
+// counterpart of cx.task(job).and_then(complete)
+pub fn and_then<'a, T>(
+    cx: &mut impl Context<'a>,
+    job: impl FnOnce() -> T + Send + 'static,
+    complete: impl FnOnce(TaskContext, T) -> NeonResult<()> + Send + 'static,
+) where
+    T: Send + 'static,
+{
+    let channel = cx.channel();
+    spawn(move || {
+        let result = job();
+        let _ = channel.try_send(move |cx| complete(cx, result));
+    });
+}
@@ -0,0 +1,112 @@
+// Extended attribute access over the Linux and macOS variants of the calls,
+// which differ in their trailing position and options arguments. Symlinks
+// are followed
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use libc::{c_char, c_void, ssize_t};
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains null bytes"))
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn list_raw(path: *const c_char, buf: *mut c_char, size: usize) -> ssize_t {
+    libc::listxattr(path, buf, size)
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn list_raw(path: *const c_char, buf: *mut c_char, size: usize) -> ssize_t {
+    libc::listxattr(path, buf, size, 0)
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn get_raw(path: *const c_char, name: *const c_char, value: *mut c_void, size: usize) -> ssize_t {
+    libc::getxattr(path, name, value, size)
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn get_raw(path: *const c_char, name: *const c_char, value: *mut c_void, size: usize) -> ssize_t {
+    libc::getxattr(path, name, value, size, 0, 0)
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn set_raw(path: *const c_char, name: *const c_char, value: *const c_void, size: usize) -> i32 {
+    libc::setxattr(path, name, value, size, 0)
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn set_raw(path: *const c_char, name: *const c_char, value: *const c_void, size: usize) -> i32 {
+    libc::setxattr(path, name, value, size, 0, 0)
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn remove_raw(path: *const c_char, name: *const c_char) -> i32 {
+    libc::removexattr(path, name)
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn remove_raw(path: *const c_char, name: *const c_char) -> i32 {
+    libc::removexattr(path, name, 0)
+}
+
+// Call a size-then-fill function until the buffer was large enough, as the
+// value can grow between the two calls
+fn read_sized(mut call: impl FnMut(*mut c_void, usize) -> ssize_t) -> io::Result<Vec<u8>> {
+    loop {
+        let size = call(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; size as usize];
+        let read = call(buf.as_mut_ptr() as *mut c_void, buf.len());
+        if read >= 0 {
+            buf.truncate(read as usize);
+            return Ok(buf);
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+// Names of the attributes of path, an empty list where the filesystem has none
+pub fn list(path: &Path) -> io::Result<Vec<CString>> {
+    let c_path = c_path(path)?;
+    let names = match read_sized(|buf, size| unsafe { list_raw(c_path.as_ptr(), buf as *mut c_char, size) }) {
+        Ok(names) => names,
+        Err(err) if err.raw_os_error() == Some(libc::ENOTSUP) => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    // The list is a sequence of NUL-terminated names
+    Ok(names
+        .split_inclusive(|&b| b == 0)
+        .filter_map(|name| CStr::from_bytes_with_nul(name).ok())
+        .map(CStr::to_owned)
+        .collect())
+}
+
+pub fn get(path: &Path, name: &CStr) -> io::Result<Vec<u8>> {
+    let c_path = c_path(path)?;
+    read_sized(|buf, size| unsafe { get_raw(c_path.as_ptr(), name.as_ptr(), buf, size) })
+}
+
+pub fn set(path: &Path, name: &CStr, value: &[u8]) -> io::Result<()> {
+    let c_path = c_path(path)?;
+    if unsafe { set_raw(c_path.as_ptr(), name.as_ptr(), value.as_ptr() as *const c_void, value.len()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn remove(path: &Path, name: &CStr) -> io::Result<()> {
+    let c_path = c_path(path)?;
+    if unsafe { remove_raw(c_path.as_ptr(), name.as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}